@@ -0,0 +1,64 @@
+//! 压缩包内搜索 (zip/jar/wheel)
+//!
+//! 默认关闭，设置环境变量 ARCHIVE_SEARCH=1 开启。
+
+use std::io::Read;
+use std::path::Path;
+
+const ENTRY_MAX_BYTES: u64 = 1024 * 1024;
+
+/// 是否开启压缩包搜索
+pub fn enabled() -> bool {
+    matches!(
+        std::env::var("ARCHIVE_SEARCH").unwrap_or_default().to_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// 是否为支持的压缩包格式
+pub fn is_archive(path: &Path) -> bool {
+    let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    matches!(ext.as_str(), "zip" | "jar" | "war" | "ear" | "aar" | "whl" | "egg" | "nupkg")
+}
+
+fn open(path: &Path) -> anyhow::Result<zip::ZipArchive<std::fs::File>> {
+    let file = std::fs::File::open(path)?;
+    Ok(zip::ZipArchive::new(file)?)
+}
+
+/// 列出压缩包条目 (名称, 解压后大小)
+pub fn list_entries(path: &Path) -> anyhow::Result<Vec<(String, u64)>> {
+    let mut zip = open(path)?;
+    let mut out = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let entry = zip.by_index(i)?;
+        if entry.is_dir() { continue; }
+        out.push((entry.name().to_string(), entry.size()));
+    }
+    Ok(out)
+}
+
+/// 在文本条目中逐行匹配，返回 "条目:行号:内容"
+pub fn grep_entries(path: &Path, re: &regex_lite::Regex, max_hits: usize) -> anyhow::Result<Vec<String>> {
+    let mut zip = open(path)?;
+    let mut hits = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        if entry.is_dir() || entry.size() > ENTRY_MAX_BYTES { continue; }
+
+        let name = entry.name().to_string();
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        if entry.by_ref().take(ENTRY_MAX_BYTES).read_to_end(&mut data).is_err() { continue; }
+        // 跳过二进制条目（如 .class）
+        if data.contains(&0) { continue; }
+
+        let text = String::from_utf8_lossy(&data);
+        for (n, line) in text.lines().enumerate() {
+            if re.is_match(line) {
+                hits.push(format!("{}:{}:{}", name, n + 1, line));
+                if hits.len() >= max_hits { return Ok(hits); }
+            }
+        }
+    }
+    Ok(hits)
+}
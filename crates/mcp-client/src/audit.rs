@@ -0,0 +1,81 @@
+//! 执行器文件访问审计日志
+//!
+//! 配置 `audit_log`（JSONL 文件路径，默认关闭）后，执行器每次读取、列出或搜索路径时追加一条记录，
+//! 带上所属的 MCP 请求 id 和工具名，便于事后核查 AI 在仓库中访问过哪些内容。越界被拒绝的访问同样记录。
+//!
+//! 请求上下文通过 task-local 传入：handle_tools_call 在 scope 中运行，ToolExecutor::new 时取出 current()
+//! 并随执行器克隆，执行器在其他任务 / 阻塞线程中执行命令时也能带上请求 id。
+
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde_json::json;
+
+tokio::task_local! {
+    static CONTEXT: Context;
+}
+
+/// 同一进程内串行追加审计文件
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 一次工具调用的审计上下文
+#[derive(Debug, Clone)]
+pub struct Context {
+    /// 审计文件
+    pub path: PathBuf,
+    /// JSON-RPC 请求 id
+    pub request_id: String,
+    pub tool: String,
+}
+
+/// 在审计上下文中运行 fut；未开启审计时直接运行
+pub async fn scope<F: Future>(context: Option<Context>, fut: F) -> F::Output {
+    match context {
+        Some(c) => CONTEXT.scope(c, fut).await,
+        None => fut.await,
+    }
+}
+
+/// 当前任务的审计上下文
+pub fn current() -> Option<Context> {
+    CONTEXT.try_with(Context::clone).ok()
+}
+
+/// 命令的访问类型
+fn access_kind(command: &str) -> &'static str {
+    match command {
+        "rg" | "rg_files" | "count" | "entrypoints" => "search",
+        "files" | "tree" | "ls" | "glob" | "stats" => "list",
+        _ => "read",
+    }
+}
+
+/// 追加一条记录；path 为 /codebase 虚拟路径（被拒绝时为请求的原始路径）
+pub fn record(context: &Context, project: &str, command: &str, path: &str, denied: bool) {
+    let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let line = json!({
+        "time": time,
+        "request_id": context.request_id,
+        "tool": context.tool,
+        "project": project,
+        "command": command,
+        "access": access_kind(command),
+        "path": path,
+        "denied": denied,
+    });
+
+    let _guard = FILE_LOCK.lock().unwrap();
+    if let Some(dir) = context.path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&context.path)
+        .and_then(|mut f| writeln!(f, "{}", line));
+    if let Err(e) = result {
+        log_warn!("cannot write audit log {}: {}", context.path.display(), e);
+    }
+}
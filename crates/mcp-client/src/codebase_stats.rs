@@ -0,0 +1,117 @@
+//! codebase_stats 工具：代码库规模概览
+//!
+//! 遍历目录树（跳过隐藏条目和 node_modules/target 等），汇总各语言文件数和行数、测试文件数，
+//! 以及行数最多的子目录，供 agent 和用户在大仓库中估计搜索范围。不调用 AI，也不依赖 rg。
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::executor::{self, StatsSummary};
+use crate::i18n::Locale;
+
+const DEFAULT_DEPTH: usize = 1;
+const DEFAULT_MAX_DIRS: usize = 10;
+const MAX_DIRS: usize = 50;
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "codebase_stats",
+        "description": l.pick(
+            "Summarize the size of a codebase without an AI search: language breakdown, file and line counts, test file count and the largest directories. Useful to calibrate search scope in large repos and monorepos.",
+            "不经 AI 搜索，汇总代码库规模：各语言占比、文件数和行数、测试文件数以及最大的目录，便于在大仓库和 monorepo 中估计搜索范围。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                },
+                "path": {
+                    "type": "string",
+                    "description": l.pick("Directory to summarize, relative to project_path (default: whole project)", "要统计的目录，相对 project_path（默认整个项目）"),
+                    "default": ""
+                },
+                "depth": {
+                    "type": "integer",
+                    "description": l.pick("Depth of the directories ranked by size (1-3, default 1 = top-level directories)", "按大小排名的目录深度（1-3，默认 1 = 顶层目录）"),
+                    "default": DEFAULT_DEPTH,
+                    "minimum": 1,
+                    "maximum": executor::STATS_DIR_DEPTH
+                },
+                "max_dirs": {
+                    "type": "integer",
+                    "description": l.pick("Max directories to list (1-50, default 10)", "最多列出的目录数（1-50，默认 10）"),
+                    "default": DEFAULT_MAX_DIRS,
+                    "minimum": 1,
+                    "maximum": MAX_DIRS
+                }
+            }
+        }
+    })
+}
+
+fn render(label: &str, summary: &StatsSummary, depth: usize, max_dirs: usize) -> String {
+    let mut out = vec![
+        format!("{}: {} files, {} lines, {} test files", label, summary.total_files, summary.total_lines, summary.test_files),
+        String::new(),
+        format!("{:<14} {:>8} {:>10} {:>6}", "Language", "Files", "Lines", "Lines%"),
+    ];
+    for s in &summary.languages {
+        let share = if summary.total_lines == 0 { 0.0 } else { s.lines as f64 * 100.0 / summary.total_lines as f64 };
+        out.push(format!("{:<14} {:>8} {:>10} {:>5.1}%", s.language, s.files, s.lines, share));
+    }
+
+    let dirs: Vec<_> = summary.dirs.iter().filter(|d| d.depth == depth).take(max_dirs).collect();
+    if !dirs.is_empty() {
+        out.push(String::new());
+        out.push("Largest directories (by lines):".into());
+        let width = dirs.iter().map(|d| d.path.len() + 1).max().unwrap_or(0);
+        for d in dirs {
+            out.push(format!("  {:<width$} {:>8} files {:>10} lines", format!("{}/", d.path), d.files, d.lines, width = width));
+        }
+    }
+    if summary.partial {
+        out.push(String::new());
+        out.push(format!("(partial: stopped after {} files)", executor::STATS_MAX_FILES));
+    }
+    out.join("\n")
+}
+
+/// 执行 codebase_stats
+pub async fn call(args: &Value) -> Result<String, String> {
+    let root = crate::direct_tools::project_root(args)?;
+    let depth = match args.get("depth").and_then(|d| d.as_u64()) {
+        Some(d) if (1..=executor::STATS_DIR_DEPTH as u64).contains(&d) => d as usize,
+        Some(d) => return Err(format!("depth must be between 1 and {}, got {}", executor::STATS_DIR_DEPTH, d)),
+        None => DEFAULT_DEPTH,
+    };
+    let max_dirs = args.get("max_dirs").and_then(|m| m.as_u64()).map(|m| m as usize)
+        .unwrap_or(DEFAULT_MAX_DIRS)
+        .clamp(1, MAX_DIRS);
+
+    let path = args.get("path").and_then(|p| p.as_str()).map(str::trim).unwrap_or("");
+    let (dir, label) = if path.is_empty() {
+        (std::path::PathBuf::from(&root), root.clone())
+    } else {
+        let virtual_path = crate::direct_tools::virtual_path(Path::new(&root), path)?;
+        let rel = virtual_path.trim_start_matches("/codebase").trim_start_matches('/');
+        let dir = Path::new(&root).join(rel);
+        let label = dir.to_string_lossy().to_string();
+        (dir, label)
+    };
+    if !dir.is_dir() {
+        return Err(format!("directory not found: {}", path));
+    }
+
+    let summary = tokio::task::spawn_blocking(move || executor::language_stats(&dir))
+        .await
+        .map_err(|e| e.to_string())?;
+    if summary.total_files == 0 {
+        return Ok(format!("{}: no files", label));
+    }
+    Ok(render(&label, &summary, depth, max_dirs))
+}
@@ -0,0 +1,106 @@
+//! completion/complete：工具参数补全
+//!
+//! `project_path` 候选为客户端根目录、本会话用过的项目，以及输入值所在目录下匹配的子目录；
+//! `query`/`question` 候选为本会话最近的查询和问题。引用类型不限（ref/tool、ref/prompt 等），按参数名补全。
+
+use std::collections::VecDeque;
+
+use serde_json::{json, Value};
+
+/// 每类最多记住的条目
+const RECENT_MAX: usize = 50;
+/// 协议规定单次最多返回 100 个候选
+const MAX_VALUES: usize = 100;
+
+/// 本会话最近使用的项目和查询，新的在前
+#[derive(Default)]
+pub struct Recent {
+    projects: VecDeque<String>,
+    queries: VecDeque<String>,
+}
+
+fn push_front(list: &mut VecDeque<String>, value: &str) {
+    let value = value.trim();
+    if value.is_empty() {
+        return;
+    }
+    list.retain(|v| v != value);
+    list.push_front(value.to_string());
+    list.truncate(RECENT_MAX);
+}
+
+impl Recent {
+    /// 记录 tools/call 的 project_path 与 query/question
+    pub fn record(&mut self, request: &Value) {
+        let args = request.pointer("/params/arguments");
+        if let Some(p) = args.and_then(|a| a.get("project_path")).and_then(|p| p.as_str()) {
+            push_front(&mut self.projects, p);
+        }
+        for key in ["query", "question"] {
+            if let Some(q) = args.and_then(|a| a.get(key)).and_then(|q| q.as_str()) {
+                push_front(&mut self.queries, q);
+            }
+        }
+    }
+}
+
+/// 输入值所在目录下、以剩余部分开头的子目录（不含隐藏目录，除非输入以 . 开头）
+fn matching_dirs(value: &str) -> Vec<String> {
+    let Some(split) = value.rfind(['/', '\\']) else { return Vec::new() };
+    let (dir, prefix) = (&value[..=split], &value[split + 1..]);
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut dirs: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|e| e.file_name().to_str().map(str::to_string))
+        .filter(|name| name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.')))
+        .map(|name| format!("{}{}", dir, name))
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+fn project_candidates(value: &str, roots: &[String], recent: &Recent) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    let known = roots.iter().chain(recent.projects.iter()).filter(|p| p.starts_with(value)).cloned();
+    for candidate in known.chain(matching_dirs(value)) {
+        if !out.contains(&candidate) {
+            out.push(candidate);
+        }
+    }
+    out
+}
+
+fn query_candidates(value: &str, recent: &Recent) -> Vec<String> {
+    let needle = value.to_lowercase();
+    // 前缀匹配在前，其余包含匹配在后
+    let (mut prefix, contains): (Vec<String>, Vec<String>) = recent
+        .queries
+        .iter()
+        .filter(|q| q.to_lowercase().contains(&needle))
+        .cloned()
+        .partition(|q| q.to_lowercase().starts_with(&needle));
+    prefix.extend(contains);
+    prefix
+}
+
+/// completion/complete 响应
+pub fn complete(msg: &Value, roots: &[String], recent: &Recent) -> Value {
+    let id = msg.get("id").cloned().unwrap_or(json!(null));
+    let name = msg.pointer("/params/argument/name").and_then(|n| n.as_str()).unwrap_or("");
+    let value = msg.pointer("/params/argument/value").and_then(|v| v.as_str()).unwrap_or("");
+    let candidates = match name {
+        "project_path" => project_candidates(value, roots, recent),
+        "query" | "question" => query_candidates(value, recent),
+        _ => Vec::new(),
+    };
+    let total = candidates.len();
+    let values: Vec<String> = candidates.into_iter().take(MAX_VALUES).collect();
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": {
+            "completion": { "values": values, "total": total, "hasMore": total > MAX_VALUES }
+        }
+    })
+}
@@ -0,0 +1,423 @@
+//! 配置文件 (JSON)
+//!
+//! 路径：命令行 --config > 环境变量 WINDSURF_MCP_CONFIG > ~/.config/windsurf-mcp/config.json。
+//! 运行时定期检查修改时间，内容变化后重新加载；工具定义变化时发送 notifications/tools/list_changed。
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::logging::LogConfig;
+use crate::provider::ProviderConfig;
+use crate::quota::QuotaPolicy;
+use crate::windsurf::{CallMode, CallOptions, Framing};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 内置默认值：tree_depth / max_turns / max_results
+const DEFAULT_TREE_DEPTH: u32 = 3;
+const DEFAULT_MAX_TURNS: u32 = 5;
+const DEFAULT_MAX_RESULTS: u32 = 10;
+
+/// repo map 格式：tree = 目录树，files = 扁平文件列表（带大小分级）
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapFormat {
+    #[default]
+    Tree,
+    Files,
+}
+
+impl MapFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "tree" => Some(MapFormat::Tree),
+            "files" => Some(MapFormat::Files),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MapFormat::Tree => "tree",
+            MapFormat::Files => "files",
+        }
+    }
+}
+
+/// 一组搜索参数，未设置的项沿用上一层
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub tree_depth: Option<u32>,
+    pub max_turns: Option<u32>,
+    pub max_results: Option<u32>,
+    pub map_format: Option<MapFormat>,
+}
+
+/// 运维设置的默认参数
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    #[serde(flatten)]
+    pub preset: Preset,
+    /// 默认使用的 profile
+    pub profile: Option<String>,
+}
+
+/// 合并后的搜索参数默认值
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchLimits {
+    pub tree_depth: u32,
+    pub max_turns: u32,
+    pub max_results: u32,
+    pub map_format: MapFormat,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 对外暴露的工具，空 = 全部
+    pub tools: Vec<String>,
+    /// 保活 ping 间隔（秒），0 = 关闭
+    pub keepalive_secs: Option<u64>,
+    /// 工具描述语言（en / zh-CN）
+    pub language: Option<String>,
+    /// 额外的 OpenAI 兼容后端，名称 → 配置
+    pub providers: BTreeMap<String, ProviderConfig>,
+    /// 未指定 provider 时使用，默认 windsurf
+    pub default_provider: Option<String>,
+    /// 默认参数
+    pub defaults: Defaults,
+    /// 命名参数预设，工具参数 profile 选择
+    pub profiles: BTreeMap<String, Preset>,
+    /// multi_repo_search 仓库集合：集合名 → (仓库名 → 本地路径或 git URL)
+    pub repo_sets: BTreeMap<String, BTreeMap<String, String>>,
+    /// multi_repo_search 并发数
+    pub multi_repo_parallelism: Option<usize>,
+    /// Windsurf 调用方式
+    pub transport: TransportConfig,
+    /// 向 relay 发送客户端版本/平台/传输方式，默认关闭（需显式设为 true）；环境变量 WINDSURF_MCP_NO_CLIENT_INFO=1 强制关闭
+    pub client_info: Option<bool>,
+    /// 低额度时限流/拒绝
+    pub quota: QuotaPolicy,
+    /// 单次工具结果的最大字节数，超出部分通过 fetch_result_page 分页取回；0 = 不分页
+    pub result_page_bytes: Option<usize>,
+    /// 文件数超过该值时 repo map 改为抽样模式，默认 20000；0 = 关闭
+    pub map_sample_threshold: Option<usize>,
+    /// 额外暴露 rg / readfile / tree / glob 直接工具，默认关闭
+    pub direct_tools: Option<bool>,
+    /// 不遵守 .gitignore / .ignore / .rgignore（同命令行 --no-ignore，仅启动时读取）
+    pub no_ignore: Option<bool>,
+    /// 单条入站消息（LSP Content-Length、HTTP 请求体、WebSocket 消息）的最大字节数，默认 4 MiB
+    pub max_message_bytes: Option<usize>,
+    /// 日志目标（仅启动时读取）
+    pub log: LogConfig,
+    /// 搜索历史（search_history 工具与 history:// 资源）
+    pub history: HistoryConfig,
+    /// 执行器命令超时
+    pub timeouts: TimeoutConfig,
+    /// 执行器命令输出的截断上限
+    pub output: OutputConfig,
+    /// 一轮中同时执行的命令数（1-16），默认 4
+    pub command_parallelism: Option<usize>,
+    /// rg / count 跳过超过该字节数的文件（生成文件、锁文件），默认 1 MiB；0 = 不限制
+    pub search_max_filesize: Option<u64>,
+    /// 执行器文件访问审计日志（JSONL），默认关闭
+    pub audit_log: Option<PathBuf>,
+    /// 执行器禁止访问的路径 glob（`secrets`、`.env*`、`*.pem`），与环境变量 WINDSURF_MCP_DENY_PATHS 合并（仅启动时读取）
+    pub deny_paths: Vec<String>,
+    /// 命令结果发往模型前替换其中的密钥和高熵字符串，默认开启（同命令行 --no-redact，仅启动时读取）
+    pub redact_secrets: Option<bool>,
+    /// 替换内置系统提示的模板文件，未设置时取环境变量 PROMPT_TEMPLATE_PATH；
+    /// 支持 {max_turns} / {max_commands} / {max_results} 占位符，每次搜索时重新读取
+    pub prompt_template: Option<PathBuf>,
+}
+
+/// 执行器输出上限；未设置的项依次取环境变量 WINDSURF_MCP_RESULT_MAX_LINES /
+/// WINDSURF_MCP_LINE_MAX_CHARS / WINDSURF_MCP_TURN_MAX_BYTES 和内置默认值
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// 每个命令结果的最大行数，默认 50
+    pub max_lines: Option<usize>,
+    /// 每行最多字符数，默认 250
+    pub line_max_chars: Option<usize>,
+    /// 一轮全部命令输出的总字节数，按需均分给各命令，默认 64 KiB；0 = 不限制
+    pub turn_max_bytes: Option<usize>,
+}
+
+impl OutputConfig {
+    pub fn resolve(&self) -> crate::executor::OutputLimits {
+        let env = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<usize>().ok());
+        let d = crate::executor::OutputLimits::default();
+        let turn_max_bytes = match self.turn_max_bytes.or_else(|| env("WINDSURF_MCP_TURN_MAX_BYTES")) {
+            Some(0) => None,
+            Some(n) => Some(n.max(crate::executor::MIN_TURN_BYTES)),
+            None => d.turn_max_bytes,
+        };
+        crate::executor::OutputLimits {
+            max_lines: self.max_lines.or_else(|| env("WINDSURF_MCP_RESULT_MAX_LINES")).map_or(d.max_lines, |n| n.max(5)),
+            line_max_chars: self.line_max_chars.or_else(|| env("WINDSURF_MCP_LINE_MAX_CHARS")).map_or(d.line_max_chars, |n| n.max(40)),
+            turn_max_bytes,
+        }
+    }
+}
+
+/// 执行器超时（秒），0 = 不限制
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    /// 单个 rg/readfile/tree/... 命令，默认 30
+    pub command_secs: Option<u64>,
+    /// 一轮的全部命令，默认 60；超时后已完成的命令照常返回
+    pub turn_secs: Option<u64>,
+}
+
+impl TimeoutConfig {
+    pub fn resolve(&self) -> crate::executor::Timeouts {
+        let pick = |secs: Option<u64>, default: Option<Duration>| match secs {
+            Some(0) => None,
+            Some(n) => Some(Duration::from_secs(n)),
+            None => default,
+        };
+        let d = crate::executor::Timeouts::default();
+        crate::executor::Timeouts { command: pick(self.command_secs, d.command), turn: pick(self.turn_secs, d.turn) }
+    }
+}
+
+/// 搜索历史的保存位置与条数
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// 保留的条数，默认 200；0 = 不记录
+    pub max_entries: Option<usize>,
+    /// 历史文件，默认 $XDG_STATE_HOME/windsurf-mcp/history.jsonl 或 ~/.local/state/windsurf-mcp/history.jsonl
+    pub path: Option<PathBuf>,
+}
+
+/// Windsurf 调用方式：stream / unary / auto，帧格式 connect / grpc
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransportConfig {
+    pub mode: Option<CallMode>,
+    /// connect（默认）/ grpc
+    pub framing: Option<Framing>,
+    /// unary 方法名，默认 GetDevstral
+    pub unary_method: Option<String>,
+    /// auto 模式下连续流式失败多少次后切换到 unary，默认 2
+    pub fallback_after: Option<u32>,
+}
+
+impl TransportConfig {
+    pub fn call_options(&self) -> CallOptions {
+        let mut opts = CallOptions::default();
+        if let Some(mode) = self.mode {
+            opts.mode = mode;
+        }
+        if let Some(f) = self.framing {
+            opts.framing = f;
+        }
+        if let Some(m) = self.unary_method.as_ref().filter(|m| !m.is_empty()) {
+            opts.unary_method = m.clone();
+        }
+        if let Some(n) = self.fallback_after {
+            opts.fallback_after = n;
+        }
+        opts
+    }
+}
+
+impl Config {
+    pub fn send_client_info(&self) -> bool {
+        let opted_out = matches!(
+            std::env::var("WINDSURF_MCP_NO_CLIENT_INFO").unwrap_or_default().to_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        );
+        !opted_out && self.client_info.unwrap_or(false)
+    }
+
+    /// 默认值优先级：profile > defaults > 内置；profile 为空时用 defaults.profile
+    pub fn limits(&self, profile: Option<&str>) -> Result<SearchLimits, String> {
+        let name = profile.filter(|p| !p.is_empty()).or(self.defaults.profile.as_deref());
+        let preset = match name {
+            Some(n) => Some(self.profiles.get(n).ok_or_else(|| {
+                let names: Vec<&str> = self.profiles.keys().map(|k| k.as_str()).collect();
+                format!("unknown profile: {} (available: {})", n, if names.is_empty() { "none".into() } else { names.join(", ") })
+            })?),
+            None => None,
+        };
+        let d = &self.defaults.preset;
+        let pick = |f: fn(&Preset) -> Option<u32>, fallback: u32| preset.and_then(f).or(f(d)).unwrap_or(fallback);
+        Ok(SearchLimits {
+            tree_depth: pick(|p| p.tree_depth, DEFAULT_TREE_DEPTH).clamp(1, 6),
+            max_turns: pick(|p| p.max_turns, DEFAULT_MAX_TURNS).clamp(1, 5),
+            max_results: pick(|p| p.max_results, DEFAULT_MAX_RESULTS).clamp(1, 30),
+            map_format: preset.and_then(|p| p.map_format).or(d.map_format).unwrap_or_default(),
+        })
+    }
+
+    /// 分页大小，None = 不分页
+    pub fn page_bytes(&self) -> Option<usize> {
+        match self.result_page_bytes {
+            Some(0) => None,
+            Some(n) => Some(n.max(crate::pages::MIN_PAGE_BYTES)),
+            None => Some(crate::pages::DEFAULT_PAGE_BYTES),
+        }
+    }
+
+    /// 抽样阈值，None = 不抽样
+    pub fn sample_threshold(&self) -> Option<usize> {
+        match self.map_sample_threshold {
+            Some(0) => None,
+            Some(n) => Some(n),
+            None => Some(crate::repo_sample::DEFAULT_THRESHOLD),
+        }
+    }
+
+    /// 系统提示模板路径：配置文件 > 环境变量 PROMPT_TEMPLATE_PATH
+    pub fn prompt_template(&self) -> Option<PathBuf> {
+        self.prompt_template
+            .clone()
+            .or_else(|| std::env::var("PROMPT_TEMPLATE_PATH").ok().filter(|p| !p.is_empty()).map(PathBuf::from))
+    }
+
+    /// 入站消息上限；0 视为未设置
+    pub fn max_message_bytes(&self) -> usize {
+        self.max_message_bytes.filter(|n| *n > 0).unwrap_or(crate::sse::MAX_MESSAGE_BYTES)
+    }
+
+    pub fn tool_enabled(&self, name: &str) -> bool {
+        self.tools.is_empty() || self.tools.iter().any(|t| t == name)
+    }
+}
+
+/// 命令行 --config 指定的路径
+static CLI_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_cli_path(p: PathBuf) {
+    let _ = CLI_PATH.set(p);
+}
+
+/// 配置文件路径的来源
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Env,
+    File,
+    Cli,
+}
+
+impl Source {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Source::Default => "default",
+            Source::Env => "env",
+            Source::File => "file",
+            Source::Cli => "cli",
+        }
+    }
+}
+
+/// 配置文件路径及来源：--config > WINDSURF_MCP_CONFIG > ~/.config/windsurf-mcp/config.json
+pub fn path_with_source() -> Option<(PathBuf, Source)> {
+    if let Some(p) = CLI_PATH.get() {
+        return Some((p.clone(), Source::Cli));
+    }
+    if let Ok(p) = std::env::var("WINDSURF_MCP_CONFIG") {
+        if !p.is_empty() {
+            return Some((PathBuf::from(p), Source::Env));
+        }
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some((PathBuf::from(home).join(".config").join("windsurf-mcp").join("config.json"), Source::Default))
+}
+
+/// 配置文件路径
+pub fn path() -> Option<PathBuf> {
+    path_with_source().map(|(p, _)| p)
+}
+
+/// 读取并解析配置文件，文件不存在时返回默认配置
+pub fn load_from(path: &std::path::Path) -> anyhow::Result<Config> {
+    match std::fs::read_to_string(path) {
+        Ok(text) if text.trim().is_empty() => Ok(Config::default()),
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 加载配置，出错时打印警告并使用默认配置
+pub fn load() -> Config {
+    let Some(p) = path() else { return Config::default() };
+    load_from(&p).unwrap_or_else(|e| {
+        log_warn!("config {}: {}, using defaults", p.display(), e);
+        Config::default()
+    })
+}
+
+fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 运行时共享的配置
+#[derive(Clone, Default)]
+pub struct SharedConfig(Arc<RwLock<Config>>);
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(RwLock::new(config)))
+    }
+
+    pub fn get(&self) -> Config {
+        self.0.read().unwrap().clone()
+    }
+
+    fn replace(&self, config: Config) {
+        *self.0.write().unwrap() = config;
+    }
+}
+
+/// 轮询配置文件，重新加载后若工具定义变化则通过 `out` 发送 tools/list_changed
+pub fn spawn_reloader(shared: SharedConfig, out: mpsc::UnboundedSender<String>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(p) = path() else { return };
+        let mut last_mtime = modified(&p);
+        let mut ticker = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let mtime = modified(&p);
+            if mtime == last_mtime {
+                continue;
+            }
+            last_mtime = mtime;
+
+            let new = match load_from(&p) {
+                Ok(c) => c,
+                Err(e) => {
+                    // 保留旧配置，避免编辑到一半的文件清空设置
+                    log_warn!("config reload {}: {}, keeping previous config", p.display(), e);
+                    continue;
+                }
+            };
+            let old = shared.get();
+            if new == old {
+                continue;
+            }
+            let changed = crate::tool_definitions(&old) != crate::tool_definitions(&new);
+            shared.replace(new);
+            log_info!("config reloaded from {}", p.display());
+
+            if changed {
+                let note = json!({ "jsonrpc": "2.0", "method": "notifications/tools/list_changed" });
+                if out.send(note.to_string()).is_err() { return; }
+            }
+        }
+    })
+}
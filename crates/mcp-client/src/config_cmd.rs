@@ -0,0 +1,456 @@
+//! `config show` / `config validate` / `doctor` 子命令
+//!
+//! show 打印合并后的生效配置及每项来源 (default/env/file/cli)，密钥只显示前 4 位；
+//! validate 检查配置文件、URL、路径和取值范围，有错误时退出码为 1；
+//! doctor 额外检查运行环境 (ripgrep 版本与特性)，`doctor --install-rg` 在没有可用的 rg 时下载一份。
+
+use reqwest::Url;
+
+use crate::config::{self, Config, Source};
+use crate::{i18n, keepalive, provider, remote};
+
+/// 密钥脱敏：前 4 位 + 长度
+fn mask(secret: &str) -> String {
+    if secret.is_empty() {
+        return "(not set)".into();
+    }
+    let head: String = secret.chars().take(4).collect();
+    format!("{}**** ({} chars)", head, secret.chars().count())
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// 配置文件 > 环境变量 > 默认值
+fn layered<T: ToString>(file: Option<T>, env_name: &str, default: &str) -> (String, Source) {
+    match (file, env(env_name)) {
+        (Some(v), _) => (v.to_string(), Source::File),
+        (None, Some(v)) => (v, Source::Env),
+        (None, None) => (default.to_string(), Source::Default),
+    }
+}
+
+struct Report(Vec<(String, String, Source)>);
+
+impl Report {
+    fn add(&mut self, key: &str, value: impl ToString, source: Source) {
+        self.0.push((key.to_string(), value.to_string(), source));
+    }
+
+    fn file(&mut self, key: &str, value: Option<impl ToString>, default: impl ToString) {
+        match value {
+            Some(v) => self.add(key, v, Source::File),
+            None => self.add(key, default, Source::Default),
+        }
+    }
+
+    fn render(&self) -> String {
+        let width = self.0.iter().map(|(k, _, _)| k.len()).max().unwrap_or(0);
+        self.0
+            .iter()
+            .map(|(k, v, s)| format!("{:width$}  {}  [{}]", k, v, s.as_str(), width = width))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// 读取配置文件；不存在时为默认配置
+fn load() -> (Option<(std::path::PathBuf, Source)>, Result<Config, String>) {
+    let located = config::path_with_source();
+    let cfg = match &located {
+        Some((p, _)) => config::load_from(p).map_err(|e| format!("{}: {}", p.display(), e)),
+        None => Ok(Config::default()),
+    };
+    (located, cfg)
+}
+
+fn show() -> Result<String, String> {
+    let (located, cfg) = load();
+    let cfg = cfg?;
+    let mut r = Report(Vec::new());
+
+    match &located {
+        Some((p, s)) => {
+            let state = if p.is_file() { "" } else { " (not found, using defaults)" };
+            r.add("config_file", format!("{}{}", p.display(), state), *s);
+        }
+        None => r.add("config_file", "(none: HOME not set)", Source::Default),
+    }
+
+    let (relay_url, src) = match env("RELAY_URL") {
+        Some(u) => (u, Source::Env),
+        None => ("http://localhost:3000".into(), Source::Default),
+    };
+    r.add("relay_url", relay_url, src);
+    match env("ACCESS_TOKEN").map(|t| ("ACCESS_TOKEN", t)).or_else(|| env("WINDSURF_API_KEY").map(|t| ("WINDSURF_API_KEY", t))) {
+        Some((name, t)) => r.add("access_token", format!("{} (from {})", mask(&t), name), Source::Env),
+        None => r.add("access_token", mask(""), Source::Default),
+    }
+
+    let (lang, src) = layered(cfg.language.as_deref(), "WINDSURF_MCP_LANG", "en");
+    let resolved = match i18n::Locale::resolve(cfg.language.as_deref()) {
+        i18n::Locale::En => "en",
+        i18n::Locale::ZhCn => "zh-CN",
+    };
+    r.add("language", format!("{} -> {}", lang, resolved), src);
+
+    let (_, src) = layered(cfg.keepalive_secs, "KEEPALIVE_SECS", "");
+    let show = |transport: &str| keepalive::interval(cfg.keepalive_secs, transport).map(|d| format!("{}s", d.as_secs())).unwrap_or_else(|| "off".into());
+    r.add("keepalive", format!("{} (stdio), {} (network)", show("stdio"), show("sse")), src);
+
+    let client_info_src = if env("WINDSURF_MCP_NO_CLIENT_INFO").is_some() {
+        Source::Env
+    } else if cfg.client_info.is_some() {
+        Source::File
+    } else {
+        Source::Default
+    };
+    // 涉及隐私：开启后向 relay 上报本机信息
+    let client_info = if cfg.send_client_info() { "on (sends version/platform/transport to the relay)" } else { "off" };
+    r.add("client_info [privacy]", client_info, client_info_src);
+    r.add(
+        "archive_search",
+        crate::archive::enabled(),
+        if env("ARCHIVE_SEARCH").is_some() { Source::Env } else { Source::Default },
+    );
+
+    match cfg.limits(None) {
+        Ok(l) => {
+            let src = if cfg.defaults != Default::default() { Source::File } else { Source::Default };
+            r.add(
+                "defaults",
+                format!("tree_depth={} max_turns={} max_results={} map_format={}", l.tree_depth, l.max_turns, l.max_results, l.map_format.as_str()),
+                src,
+            );
+        }
+        Err(e) => r.add("defaults", format!("ERROR: {}", e), Source::File),
+    }
+    for (name, p) in &cfg.profiles {
+        let fmt = |v: Option<u32>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+        r.add(
+            &format!("profiles.{}", name),
+            format!(
+                "tree_depth={} max_turns={} max_results={} map_format={}",
+                fmt(p.tree_depth),
+                fmt(p.max_turns),
+                fmt(p.max_results),
+                p.map_format.map(|f| f.as_str()).unwrap_or("-")
+            ),
+            Source::File,
+        );
+    }
+
+    r.file("tools", (!cfg.tools.is_empty()).then(|| cfg.tools.join(", ")), "(all)");
+    r.file("default_provider", cfg.default_provider.as_deref(), provider::WINDSURF);
+    for (name, p) in &cfg.providers {
+        let key = match p.api_key_env.as_deref() {
+            Some(var) => format!(", api_key={} (from {})", mask(&env(var).unwrap_or_default()), var),
+            None => String::new(),
+        };
+        r.add(&format!("providers.{}", name), format!("{} model={}{}", p.base_url, p.model, key), Source::File);
+    }
+
+    let opts = cfg.transport.call_options();
+    let transport_src = if cfg.transport != Default::default() { Source::File } else { Source::Default };
+    r.add(
+        "transport",
+        format!(
+            "mode={} framing={} unary_method={} fallback_after={}",
+            format!("{:?}", opts.mode).to_lowercase(),
+            format!("{:?}", opts.framing).to_lowercase(),
+            opts.unary_method,
+            opts.fallback_after
+        ),
+        transport_src,
+    );
+
+    match cfg.quota.min_remaining {
+        Some(min) => r.add("quota", format!("min_remaining={} action={:?}", min, cfg.quota.action).to_lowercase(), Source::File),
+        None => r.add("quota", "(no low-quota policy)", Source::Default),
+    }
+    r.file(
+        "result_page_bytes",
+        cfg.result_page_bytes.map(|_| cfg.page_bytes().map(|n| n.to_string()).unwrap_or_else(|| "off".into())),
+        crate::pages::DEFAULT_PAGE_BYTES,
+    );
+    r.file("direct_tools", cfg.direct_tools, false);
+    r.file("no_ignore", cfg.no_ignore, false);
+    r.file("redact_secrets", cfg.redact_secrets, true);
+    r.file("max_message_bytes", cfg.max_message_bytes.map(|_| cfg.max_message_bytes()), crate::sse::MAX_MESSAGE_BYTES);
+    r.file(
+        "map_sample_threshold",
+        cfg.map_sample_threshold.map(|_| cfg.sample_threshold().map(|n| n.to_string()).unwrap_or_else(|| "off".into())),
+        crate::repo_sample::DEFAULT_THRESHOLD,
+    );
+    match cfg.log.effective() {
+        Ok(log) => {
+            let src = if env("WINDSURF_MCP_LOG").is_some() {
+                Source::Env
+            } else if cfg.log != Default::default() {
+                Source::File
+            } else {
+                Source::Default
+            };
+            let target = log.target.unwrap_or_default();
+            let detail = match (target, log.path.as_deref()) {
+                (crate::logging::LogTarget::File, Some(p)) => format!("file {}", p),
+                (t, _) => t.as_str().to_string(),
+            };
+            r.add("log", detail, src);
+        }
+        Err(e) => r.add("log", format!("ERROR: {}", e), Source::Env),
+    }
+    r.file("multi_repo_parallelism", cfg.multi_repo_parallelism, crate::MULTI_REPO_DEFAULT_PARALLEL);
+    r.file("command_parallelism", cfg.command_parallelism, crate::executor::DEFAULT_COMMAND_PARALLELISM);
+    let max_filesize = match cfg.search_max_filesize {
+        Some(0) => "off".to_string(),
+        n => crate::repo_sample::human_size(n.unwrap_or(crate::executor::DEFAULT_SEARCH_MAX_FILESIZE)),
+    };
+    r.add("search_max_filesize", max_filesize, if cfg.search_max_filesize.is_some() { Source::File } else { Source::Default });
+    match &cfg.audit_log {
+        Some(p) => r.add("audit_log", p.display(), Source::File),
+        None => r.add("audit_log", "off", Source::Default),
+    }
+    let (template, src) = layered(cfg.prompt_template.as_ref().map(|p| p.display()), "PROMPT_TEMPLATE_PATH", "built-in");
+    r.add("prompt_template", template, src);
+    let deny_env: Vec<String> = env("WINDSURF_MCP_DENY_PATHS").map(|v| v.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect()).unwrap_or_default();
+    match (cfg.deny_paths.is_empty(), deny_env.is_empty()) {
+        (true, true) => r.add("deny_paths", "none", Source::Default),
+        (false, _) => r.add("deny_paths", cfg.deny_paths.iter().chain(&deny_env).cloned().collect::<Vec<_>>().join(", "), Source::File),
+        (true, false) => r.add("deny_paths", deny_env.join(", "), Source::Env),
+    }
+    let history_src = if cfg.history != Default::default() { Source::File } else { Source::Default };
+    match crate::history::path(&cfg.history) {
+        Some(p) => r.add("history", format!("{} (max_entries={})", p.display(), cfg.history.max_entries.unwrap_or(crate::history::DEFAULT_MAX_ENTRIES)), history_src),
+        None => r.add("history", "off", history_src),
+    }
+    let timeouts = cfg.timeouts.resolve();
+    let secs = |d: Option<std::time::Duration>| d.map(|d| format!("{}s", d.as_secs())).unwrap_or_else(|| "off".into());
+    r.add(
+        "timeouts",
+        format!("command={} turn={}", secs(timeouts.command), secs(timeouts.turn)),
+        if cfg.timeouts != Default::default() { Source::File } else { Source::Default },
+    );
+    let output = cfg.output.resolve();
+    let output_env = ["WINDSURF_MCP_RESULT_MAX_LINES", "WINDSURF_MCP_LINE_MAX_CHARS", "WINDSURF_MCP_TURN_MAX_BYTES"].iter().any(|n| env(n).is_some());
+    r.add(
+        "output",
+        format!(
+            "max_lines={} line_max_chars={} turn_max_bytes={}",
+            output.max_lines,
+            output.line_max_chars,
+            output.turn_max_bytes.map(|n| n.to_string()).unwrap_or_else(|| "off".into())
+        ),
+        if cfg.output != Default::default() { Source::File } else if output_env { Source::Env } else { Source::Default },
+    );
+    for (set, repos) in &cfg.repo_sets {
+        let list: Vec<String> = repos.iter().map(|(n, p)| format!("{}={}", n, p)).collect();
+        r.add(&format!("repo_sets.{}", set), list.join(", "), Source::File);
+    }
+
+    Ok(r.render())
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    let u = Url::parse(url).map_err(|e| e.to_string())?;
+    if !matches!(u.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme {}", u.scheme()));
+    }
+    Ok(())
+}
+
+/// 返回 (错误, 警告)
+fn validate(cfg: &Config) -> (Vec<String>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let relay_url = env("RELAY_URL").unwrap_or_else(|| "http://localhost:3000".into());
+    if let Err(e) = check_url(&relay_url) {
+        errors.push(format!("RELAY_URL {}: {}", relay_url, e));
+    }
+    if env("ACCESS_TOKEN").is_none() && env("WINDSURF_API_KEY").is_none() {
+        warnings.push("no ACCESS_TOKEN / WINDSURF_API_KEY set; windsurf searches will fail".into());
+    }
+
+    if let Some(lang) = cfg.language.as_deref() {
+        if i18n::Locale::parse(lang).is_none() {
+            errors.push(format!("language {}: expected en or zh-CN", lang));
+        }
+    }
+    for t in &cfg.tools {
+        if !crate::TOOL_NAMES.contains(&t.as_str()) {
+            errors.push(format!("tools: unknown tool {} (known: {})", t, crate::TOOL_NAMES.join(", ")));
+        }
+    }
+
+    if let Err(e) = cfg.limits(None) {
+        errors.push(format!("defaults.profile: {}", e));
+    }
+    let presets = std::iter::once(("defaults".to_string(), &cfg.defaults.preset))
+        .chain(cfg.profiles.iter().map(|(n, p)| (format!("profiles.{}", n), p)));
+    for (name, p) in presets {
+        for (field, value, max) in [("tree_depth", p.tree_depth, 6), ("max_turns", p.max_turns, 5), ("max_results", p.max_results, 30)] {
+            if let Some(v) = value.filter(|v| !(1..=max).contains(v)) {
+                warnings.push(format!("{}.{} = {} is outside 1-{} and will be clamped", name, field, v, max));
+            }
+        }
+    }
+
+    if let Err(e) = provider::resolve(&cfg.providers, cfg.default_provider.as_deref(), "") {
+        errors.push(format!("default_provider: {}", e));
+    }
+    for (name, p) in &cfg.providers {
+        if let Err(e) = check_url(&p.base_url) {
+            errors.push(format!("providers.{}.base_url {}: {}", name, p.base_url, e));
+        }
+        if p.model.trim().is_empty() {
+            errors.push(format!("providers.{}.model is empty", name));
+        }
+        if let Some(var) = p.api_key_env.as_deref() {
+            if env(var).is_none() {
+                warnings.push(format!("providers.{}.api_key_env: {} is not set", name, var));
+            }
+        }
+    }
+
+    for (set, repos) in &cfg.repo_sets {
+        if repos.is_empty() {
+            warnings.push(format!("repo_sets.{} is empty", set));
+        }
+        for (name, path) in repos {
+            if remote::parse(path).is_none() && !std::path::Path::new(path).is_dir() {
+                errors.push(format!("repo_sets.{}.{}: {} is not a directory or git URL", set, name, path));
+            }
+        }
+    }
+    if let Some(n) = cfg.multi_repo_parallelism.filter(|n| !(1..=crate::MULTI_REPO_MAX_PARALLEL).contains(n)) {
+        warnings.push(format!("multi_repo_parallelism = {} is outside 1-{} and will be clamped", n, crate::MULTI_REPO_MAX_PARALLEL));
+    }
+    if let Some(n) = cfg.command_parallelism.filter(|n| !(1..=crate::executor::MAX_COMMAND_PARALLELISM).contains(n)) {
+        warnings.push(format!("command_parallelism = {} is outside 1-{} and will be clamped", n, crate::executor::MAX_COMMAND_PARALLELISM));
+    }
+    if let Err(e) = crate::deny_paths::validate(&cfg.deny_paths) {
+        errors.push(e);
+    }
+    if let Some(path) = cfg.prompt_template() {
+        match std::fs::read_to_string(&path) {
+            Ok(t) if t.trim().is_empty() => errors.push(format!("prompt_template {}: file is empty", path.display())),
+            Ok(t) if !t.contains("{max_turns}") || !t.contains("{max_commands}") => {
+                warnings.push(format!("prompt_template {}: no {{max_turns}} / {{max_commands}} placeholder; the model is not told its limits", path.display()))
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(format!("prompt_template {}: {}", path.display(), e)),
+        }
+    }
+
+    if cfg.transport.unary_method.as_deref().is_some_and(|m| m.trim().is_empty()) {
+        warnings.push("transport.unary_method is empty; the default is used".into());
+    }
+    if cfg.transport.fallback_after == Some(0) {
+        warnings.push("transport.fallback_after = 0 behaves like 1".into());
+    }
+    match cfg.log.effective() {
+        Ok(log) => {
+            use crate::logging::LogTarget;
+            match (log.target.unwrap_or_default(), log.path.as_deref()) {
+                (LogTarget::File, None) => errors.push("log.target = file requires log.path".into()),
+                (LogTarget::File, Some(p)) => {
+                    let parent = std::path::Path::new(p).parent().filter(|d| !d.as_os_str().is_empty());
+                    if parent.is_some_and(|d| !d.is_dir()) {
+                        errors.push(format!("log.path {}: directory does not exist", p));
+                    }
+                }
+                (LogTarget::Syslog | LogTarget::Journald, _) if cfg!(not(unix)) => {
+                    errors.push("log.target syslog/journald is only available on Unix".into());
+                }
+                (LogTarget::Eventlog, _) if cfg!(not(windows)) => {
+                    errors.push("log.target eventlog is only available on Windows".into());
+                }
+                (LogTarget::Journald, _) if !std::path::Path::new("/run/systemd/journal/socket").exists() => {
+                    warnings.push("log.target journald: /run/systemd/journal/socket not found, stderr will be used".into());
+                }
+                _ => {}
+            }
+        }
+        Err(e) => errors.push(e),
+    }
+    if cfg.quota.min_remaining.is_some_and(|m| m < 0.0) {
+        errors.push("quota.min_remaining must not be negative".into());
+    }
+    if let Some(n) = cfg.result_page_bytes.filter(|n| *n > 0 && *n < crate::pages::MIN_PAGE_BYTES) {
+        warnings.push(format!("result_page_bytes = {} is raised to the minimum {}", n, crate::pages::MIN_PAGE_BYTES));
+    }
+
+    (errors, warnings)
+}
+
+/// `doctor`：ripgrep 探测 + 配置校验，返回进程退出码（由配置校验和 rg 下载结果决定）
+pub async fn doctor(install_rg: bool) -> i32 {
+    // 必须在首次探测之前下载，caps() 的结果会被缓存
+    let mut install_ok = true;
+    if install_rg && crate::ripgrep::probe().unusable().is_some() {
+        match crate::rg_install::install().await {
+            Ok(path) => println!("installed ripgrep {} to {}", crate::rg_install::RG_VERSION, path.display()),
+            Err(e) => {
+                println!("error: ripgrep download failed: {:#}", e);
+                install_ok = false;
+            }
+        }
+    }
+    let rg = crate::ripgrep::caps();
+    // 缺少 rg 不算失败：rg / files 命令改用内置搜索
+    match rg.unusable() {
+        Some(why) => println!("warning: {}; searches use the built-in engine (slower, no PCRE2), `doctor --install-rg` downloads ripgrep", why),
+        None => {
+            println!("ok: {}", rg.summary());
+            if !rg.pcre2 {
+                println!("warning: ripgrep built without PCRE2, look-around and backreference patterns will fail");
+            }
+        }
+    }
+    if run(Some("validate")) == 0 && install_ok { 0 } else { 1 }
+}
+
+/// `config <show|validate>`，返回进程退出码
+pub fn run(sub: Option<&str>) -> i32 {
+    match sub {
+        Some("show") => match show() {
+            Ok(text) => {
+                println!("{}", text);
+                0
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                1
+            }
+        },
+        Some("validate") => {
+            let (located, cfg) = load();
+            if let Some((p, s)) = &located {
+                println!("config file: {} [{}]{}", p.display(), s.as_str(), if p.is_file() { "" } else { " (not found)" });
+            }
+            let (errors, warnings) = match cfg {
+                Ok(cfg) => validate(&cfg),
+                Err(e) => (vec![e], Vec::new()),
+            };
+            for w in &warnings {
+                println!("warning: {}", w);
+            }
+            for e in &errors {
+                println!("error: {}", e);
+            }
+            if errors.is_empty() {
+                println!("config OK ({} warning(s))", warnings.len());
+                0
+            } else {
+                println!("{} error(s), {} warning(s)", errors.len(), warnings.len());
+                1
+            }
+        }
+        _ => {
+            eprintln!("usage: windsurf-mcp-client [--config <path>] config <show|validate>");
+            2
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! 搜索对话续接（fast_context_refine）
+//!
+//! AI 搜索结束后保存完整对话（消息、已读文件、grep 关键词）和原始参数，结果中返回 session_id；
+//! fast_context_refine 取回对话、追加新的要求后继续同一轮搜索，已收集的上下文不必重新获取。
+//! 对话保存在会话内存中，每个会话只保留最近 MAX_STORED 个。
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+use crate::windsurf::ChatMessage;
+
+pub const MAX_STORED: usize = 16;
+
+/// 一次搜索结束时的对话状态
+#[derive(Clone)]
+pub struct Transcript {
+    pub messages: Vec<ChatMessage>,
+    pub rg_patterns: Vec<String>,
+    pub matches: Vec<crate::executor::RgMatch>,
+    pub files: Vec<String>,
+}
+
+/// 可续接的搜索：工具名 + 原始参数 + 对话
+#[derive(Clone)]
+pub struct Conversation {
+    pub tool: String,
+    pub args: Value,
+    pub transcript: Transcript,
+}
+
+#[derive(Default)]
+pub struct Store(Mutex<VecDeque<(String, Conversation)>>);
+
+impl Store {
+    /// 保存对话；`id` 为 None 时分配新 id，否则覆盖同 id 的旧对话。返回 id
+    pub fn save(&self, id: Option<&str>, conversation: Conversation) -> String {
+        let id = id.map(str::to_string).unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+        let mut store = self.0.lock().unwrap();
+        store.retain(|(i, _)| *i != id);
+        if store.len() >= MAX_STORED {
+            store.pop_front();
+        }
+        store.push_back((id.clone(), conversation));
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Conversation> {
+        let store = self.0.lock().unwrap();
+        store.iter().find(|(i, _)| i == id).map(|(_, c)| c.clone())
+    }
+}
@@ -0,0 +1,216 @@
+//! 执行器禁止访问的路径
+//!
+//! 配置 `deny_paths` 与环境变量 WINDSURF_MCP_DENY_PATHS（逗号分隔）中的 glob，启动时读取，与模型传入的参数无关。
+//! 不含 `/` 的模式匹配任意一层的名称（`.env*`、`*.pem`、`secrets`），含 `/` 的模式匹配路径末尾的若干层
+//! （`config/keys` 匹配任意位置的 config/keys）；末尾的 `/` 忽略。
+//! 匹配的文件和目录（连同其下的一切）：real_path 拒绝访问，遍历（tree / glob / stats / 内置搜索 / repo map）和 ls 中隐藏，
+//! rg / count / files / files 格式的 repo map 通过排除 glob 跳过并过滤结果，git 通过排除 pathspec 跳过；
+//! 结果校验（verify_ranges）和 resources/read 同样不读取。
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+static DENY: OnceLock<DenyList> = OnceLock::new();
+
+#[derive(Default)]
+pub struct DenyList {
+    /// 规范化后的模式（去掉末尾 `/`）
+    patterns: Vec<String>,
+    /// 各模式加上 `**/` 前缀
+    set: GlobSet,
+}
+
+impl DenyList {
+    fn new(patterns: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut normalized: Vec<String> = Vec::new();
+        let mut builder = GlobSetBuilder::new();
+        for p in patterns {
+            let p = p.trim().trim_matches('/').to_string();
+            if p.is_empty() || normalized.contains(&p) {
+                continue;
+            }
+            let glob = GlobBuilder::new(&format!("**/{}", p)).literal_separator(true).build().map_err(|e| format!("deny_paths {}: {}", p, e))?;
+            builder.add(glob);
+            normalized.push(p);
+        }
+        let set = builder.build().map_err(|e| e.to_string())?;
+        Ok(Self { patterns: normalized, set })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// path 本身（按末尾的若干层）是否被禁止；不检查祖先目录
+    pub fn matches(&self, path: &Path) -> bool {
+        !self.is_empty() && self.set.is_match(path)
+    }
+
+    /// root 下的 path 或它在 root 内的任一祖先目录被禁止；按消去 `.`/`..` 后的路径和解析符号链接后的路径各检查一次
+    pub fn denies(&self, root: &Path, path: &Path) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let check = |p: &Path| p.ancestors().take_while(|a| a.starts_with(root) && *a != root).any(|a| self.matches(a));
+        check(&lexical(path)) || path.canonicalize().is_ok_and(|real| check(&real))
+    }
+
+    /// rg / 内置搜索的排除 glob
+    pub fn rg_globs(&self) -> Vec<String> {
+        self.patterns.iter().map(|p| if p.contains('/') { format!("!**/{}", p) } else { format!("!{}", p) }).collect()
+    }
+
+    /// git 的排除 pathspec（文件本身及目录下的一切）
+    pub fn git_pathspecs(&self) -> Vec<String> {
+        self.patterns.iter().flat_map(|p| [format!(":(exclude,glob)**/{}", p), format!(":(exclude,glob)**/{}/**", p)]).collect()
+    }
+}
+
+/// 不访问文件系统地消去 `.` 和 `..`
+fn lexical(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// 配置与环境变量中的模式
+fn load(configured: &[String]) -> Result<DenyList, String> {
+    let from_env = std::env::var("WINDSURF_MCP_DENY_PATHS").unwrap_or_default();
+    DenyList::new(configured.iter().cloned().chain(from_env.split(',').map(str::to_string)))
+}
+
+/// 只在启动时调用一次；模式无效时返回错误，由调用方拒绝启动
+pub fn init(configured: &[String]) -> Result<(), String> {
+    let _ = DENY.set(load(configured)?);
+    Ok(())
+}
+
+/// 当前的禁止列表；未初始化时为空（单元测试中为 TEST_DENIED）
+pub fn get() -> &'static DenyList {
+    DENY.get_or_init(uninitialized)
+}
+
+#[cfg(not(test))]
+fn uninitialized() -> DenyList {
+    DenyList::default()
+}
+
+/// 单元测试共用的全局禁止列表：名为 TEST_DENIED 的文件或目录
+#[cfg(test)]
+pub const TEST_DENIED: &str = "denied-by-test";
+
+#[cfg(test)]
+fn uninitialized() -> DenyList {
+    DenyList::new([TEST_DENIED.to_string()]).unwrap()
+}
+
+/// 检查配置与环境变量中的模式是否有效（config doctor）
+pub fn validate(configured: &[String]) -> Result<(), String> {
+    load(configured).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(patterns: &[&str]) -> DenyList {
+        DenyList::new(patterns.iter().map(|p| p.to_string())).unwrap()
+    }
+
+    #[test]
+    fn name_patterns_match_at_any_depth() {
+        let deny = list(&[".env*", "*.pem"]);
+        let root = Path::new("/repo");
+        assert!(deny.denies(root, Path::new("/repo/.env")));
+        assert!(deny.denies(root, Path::new("/repo/app/.env.local")));
+        assert!(deny.denies(root, Path::new("/repo/certs/server.pem")));
+        assert!(!deny.denies(root, Path::new("/repo/src/env.rs")));
+        assert!(!deny.denies(root, Path::new("/repo/certs/server.pem.md")));
+    }
+
+    #[test]
+    fn slash_patterns_match_trailing_components() {
+        let deny = list(&["config/keys/"]);
+        let root = Path::new("/repo");
+        assert!(deny.denies(root, Path::new("/repo/config/keys")));
+        assert!(deny.denies(root, Path::new("/repo/svc/config/keys")));
+        assert!(!deny.denies(root, Path::new("/repo/config")));
+        assert!(!deny.denies(root, Path::new("/repo/keys")));
+        assert!(!deny.denies(root, Path::new("/repo/config/keys.md")));
+    }
+
+    #[test]
+    fn ancestor_directories_deny_everything_below() {
+        let deny = list(&["secrets", "config/keys"]);
+        let root = Path::new("/repo");
+        assert!(deny.denies(root, Path::new("/repo/secrets/prod/db.yaml")));
+        assert!(deny.denies(root, Path::new("/repo/config/keys/id_rsa")));
+        // 只看 root 以内的层级
+        assert!(!deny.denies(Path::new("/secrets/repo"), Path::new("/secrets/repo/a.rs")));
+    }
+
+    #[test]
+    fn dot_dot_is_resolved() {
+        let deny = list(&["config/keys", ".env"]);
+        let root = Path::new("/repo");
+        assert!(deny.denies(root, Path::new("/repo/config/tmp/../keys/id_rsa")));
+        assert!(deny.denies(root, Path::new("/repo/src/./../.env")));
+        assert!(!deny.denies(root, Path::new("/repo/.env/../src/a.rs")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_resolved() {
+        let root = std::env::temp_dir().join(format!("windsurf-mcp-deny-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("config/keys")).unwrap();
+        std::fs::write(root.join("config/keys/id_rsa"), "").unwrap();
+        std::os::unix::fs::symlink(root.join("config/keys"), root.join("public")).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let deny = list(&["config/keys"]);
+        assert!(deny.denies(&root, &root.join("public/id_rsa")));
+        assert!(deny.denies(&root, &root.join("public")));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn rg_globs_and_git_pathspecs() {
+        let deny = list(&[" .env* ", "/config/keys/", "*.pem", ".env*"]);
+        assert_eq!(deny.rg_globs(), ["!.env*", "!**/config/keys", "!*.pem"]);
+        assert_eq!(
+            deny.git_pathspecs(),
+            [
+                ":(exclude,glob)**/.env*",
+                ":(exclude,glob)**/.env*/**",
+                ":(exclude,glob)**/config/keys",
+                ":(exclude,glob)**/config/keys/**",
+                ":(exclude,glob)**/*.pem",
+                ":(exclude,glob)**/*.pem/**",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_list_denies_nothing() {
+        let deny = list(&["", " / "]);
+        assert!(deny.is_empty());
+        assert!(!deny.denies(Path::new("/repo"), Path::new("/repo/.env")));
+        assert!(deny.rg_globs().is_empty());
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        assert!(DenyList::new(["src/[".to_string()]).is_err());
+    }
+}
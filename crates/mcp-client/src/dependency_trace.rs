@@ -0,0 +1,443 @@
+//! dependency_trace 工具：沿 import/include/use 语句追踪依赖
+//!
+//! 从起始文件出发，按语言解析依赖并映射到项目内的真实文件，最多追踪 N 跳，输出缩进的依赖树
+//! （路径为 /codebase/...）。解析规则：
+//! - Rust：`mod x;`、`use crate::/self::/super::`（及当前模块下的路径），按 x.rs / x/mod.rs 查找
+//! - Python：`import a.b`、`from .x import y`，按 a/b.py / a/b/__init__.py 查找（项目根、src/、相对目录）
+//! - JS/TS：相对路径的 import/require/export from，补全扩展名和 index 文件
+//! - Go：go.mod 模块前缀下的包，映射为包目录
+//! - C/C++：`#include "x.h"`，相对当前文件、项目根或 include/
+//!
+//! 其余依赖（第三方包、标准库、无法解析的路径）按文件汇总列出，不再展开。
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::i18n::Locale;
+
+const DEFAULT_DEPTH: usize = 2;
+const MAX_DEPTH: usize = 5;
+/// 树中最多列出的节点数
+const MAX_NODES: usize = 300;
+/// 每个文件最多列出的外部依赖名
+const MAX_EXTERNAL: usize = 20;
+/// 超过此大小的文件不解析
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+const JS_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs", "mts", "cts"];
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "dependency_trace",
+        "description": l.pick(
+            "Trace a file's dependencies by following its import/include/use statements (Rust, Python, JS/TS, Go, C/C++), up to N hops. Returns the dependency tree with module names mapped to /codebase/... paths; third-party and unresolved imports are listed per file.",
+            "沿 import/include/use 语句追踪文件的依赖（Rust、Python、JS/TS、Go、C/C++），最多 N 跳。返回依赖树，模块名映射为 /codebase/... 路径；第三方和无法解析的依赖按文件列出。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "file": {
+                    "type": "string",
+                    "description": l.pick("Starting file: absolute, relative to project_path, or /codebase/...", "起始文件：绝对路径、相对 project_path 的路径或 /codebase/...")
+                },
+                "depth": {
+                    "type": "integer",
+                    "description": l.pick("Hops to follow (1-5, default 2)", "追踪的跳数（1-5，默认 2）"),
+                    "default": DEFAULT_DEPTH,
+                    "minimum": 1,
+                    "maximum": MAX_DEPTH
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            },
+            "required": ["file"]
+        }
+    })
+}
+
+/// 一条依赖：模块名 + 解析到的项目内路径（文件，Go 为包目录）
+struct Dep {
+    name: String,
+    path: Option<PathBuf>,
+}
+
+/// 词法规范化（处理 . 和 ..），不访问文件系统
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => {}
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn first_file(candidates: impl IntoIterator<Item = PathBuf>) -> Option<PathBuf> {
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+// ---------- Rust ----------
+
+/// 文件对应模块的子模块目录：mod.rs/lib.rs/main.rs 为所在目录，其余为 目录/文件名
+fn rust_module_dir(file: &Path) -> PathBuf {
+    let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+    match file.file_stem().and_then(|s| s.to_str()) {
+        Some("mod" | "lib" | "main") | None => dir,
+        Some(stem) => dir.join(stem),
+    }
+}
+
+/// crate 根模块所在目录：向上找到 Cargo.toml 后取 src/
+fn rust_crate_dir(file: &Path, root: &Path) -> PathBuf {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        if d.join("Cargo.toml").is_file() {
+            return d.join("src");
+        }
+        if d == root {
+            break;
+        }
+        dir = d.parent();
+    }
+    file.parent().unwrap_or(root).to_path_buf()
+}
+
+/// 目录 dir 对应的模块文件：dir.rs、dir/mod.rs，或 crate 根的 lib.rs/main.rs
+fn rust_module_file(dir: &Path) -> Option<PathBuf> {
+    first_file([dir.with_extension("rs"), dir.join("mod.rs"), dir.join("lib.rs"), dir.join("main.rs")])
+}
+
+/// base 下最长可解析的模块路径：a::b::C → a/b.rs、a/b/mod.rs、a.rs、a/mod.rs
+fn rust_resolve(base: &Path, segs: &[&str]) -> Option<PathBuf> {
+    (1..=segs.len()).rev().find_map(|n| {
+        let p = base.join(segs[..n].join("/"));
+        first_file([p.with_extension("rs"), p.join("mod.rs")])
+    })
+}
+
+/// 展开 use 树：a::{b, c::{d, e}} → a::b, a::c::d, a::c::e
+fn expand_use(tree: &str) -> Vec<String> {
+    let tree = tree.trim();
+    let Some(open) = tree.find('{') else {
+        let path = tree.split(" as ").next().unwrap_or(tree).trim();
+        return if path.is_empty() { Vec::new() } else { vec![path.to_string()] };
+    };
+    let prefix = &tree[..open];
+    let Some(close) = tree.rfind('}') else { return Vec::new() };
+    let inner = &tree[open + 1..close];
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0usize, 0usize);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&inner[start..]);
+    parts
+        .into_iter()
+        .flat_map(expand_use)
+        .map(|p| if p == "self" { prefix.trim_end_matches("::").to_string() } else { format!("{}{}", prefix, p) })
+        .collect()
+}
+
+fn rust_deps(file: &Path, source: &str, root: &Path) -> Vec<Dep> {
+    let mut deps = Vec::new();
+    let mod_re = regex_lite::Regex::new(r"^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+(\w+)\s*;").unwrap();
+    for cap in source.lines().filter_map(|l| mod_re.captures(l)) {
+        let name = cap[1].to_string();
+        let path = rust_resolve(&rust_module_dir(file), &[&name]);
+        deps.push(Dep { name: format!("mod {}", name), path });
+    }
+    for (_, stmt) in crate::outline::imports(file, source).unwrap_or_default() {
+        let Some(tree) = stmt.split_once("use ").map(|(_, t)| t.trim_end_matches(';')) else { continue };
+        for path in expand_use(tree) {
+            let segs: Vec<&str> = path.split("::").map(str::trim).filter(|s| !s.is_empty() && *s != "*").collect();
+            let Some((&head, rest)) = segs.split_first() else { continue };
+            let resolved = match head {
+                "crate" => rust_resolve(&rust_crate_dir(file, root), rest),
+                "self" => rust_resolve(&rust_module_dir(file), rest),
+                "super" => {
+                    let mut base = rust_module_dir(file);
+                    let mut rest = rest;
+                    base.pop();
+                    while let Some((&"super", r)) = rest.split_first() {
+                        base.pop();
+                        rest = r;
+                    }
+                    if rest.is_empty() { rust_module_file(&base) } else { rust_resolve(&base, rest) }
+                }
+                // 2018 版起可直接写当前模块的子模块；否则是外部 crate
+                _ => match rust_resolve(&rust_module_dir(file), &segs) {
+                    Some(p) => Some(p),
+                    None => {
+                        deps.push(Dep { name: head.to_string(), path: None });
+                        continue;
+                    }
+                },
+            };
+            deps.push(Dep { name: path.clone(), path: resolved });
+        }
+    }
+    deps
+}
+
+// ---------- Python ----------
+
+fn python_module(base: &Path, dotted: &str) -> Option<PathBuf> {
+    let p = base.join(dotted.replace('.', "/"));
+    first_file([p.with_extension("py"), p.join("__init__.py")])
+}
+
+fn python_deps(file: &Path, source: &str, root: &Path) -> Vec<Dep> {
+    let from_re = regex_lite::Regex::new(r"^\s*from\s+(\.*)([\w.]*)\s+import\s+\(?([^#)]*)").unwrap();
+    let import_re = regex_lite::Regex::new(r"^\s*import\s+([^#]+)").unwrap();
+    let dir = file.parent().unwrap_or(root);
+    let absolute_bases = [root.to_path_buf(), root.join("src"), dir.to_path_buf()];
+    let mut deps = Vec::new();
+    for line in source.lines() {
+        if let Some(cap) = from_re.captures(line) {
+            let (dots, module) = (cap[1].len(), &cap[2]);
+            let bases: Vec<PathBuf> = if dots > 0 {
+                let mut base = dir.to_path_buf();
+                for _ in 1..dots {
+                    base.pop();
+                }
+                vec![base]
+            } else {
+                absolute_bases.to_vec()
+            };
+            let label = format!("{}{}", &cap[1], module);
+            let sep = if module.is_empty() { "" } else { "." };
+            // from pkg import submodule
+            let names: Vec<&str> = cap[3].split(',').map(|n| n.split(" as ").next().unwrap_or("").trim()).filter(|n| !n.is_empty()).collect();
+            let mut found_sub = false;
+            for name in &names {
+                let dotted = if module.is_empty() { name.to_string() } else { format!("{}.{}", module, name) };
+                if let Some(p) = bases.iter().find_map(|b| python_module(b, &dotted)) {
+                    deps.push(Dep { name: format!("{}{}{}", label, sep, name), path: Some(p) });
+                    found_sub = true;
+                }
+            }
+            if !found_sub {
+                let path = if module.is_empty() { None } else { bases.iter().find_map(|b| python_module(b, module)) };
+                deps.push(Dep { name: label, path });
+            }
+        } else if let Some(cap) = import_re.captures(line) {
+            for module in cap[1].split(',').map(|m| m.split(" as ").next().unwrap_or("").trim()).filter(|m| !m.is_empty()) {
+                let path = absolute_bases.iter().find_map(|b| python_module(b, module));
+                deps.push(Dep { name: module.to_string(), path });
+            }
+        }
+    }
+    deps
+}
+
+// ---------- JS/TS ----------
+
+fn js_resolve(dir: &Path, spec: &str) -> Option<PathBuf> {
+    let base = normalize(&dir.join(spec));
+    if base.is_file() {
+        return Some(base);
+    }
+    let name = base.file_name()?.to_string_lossy().to_string();
+    let with_ext = JS_EXTENSIONS.iter().map(|e| base.with_file_name(format!("{}.{}", name, e)));
+    let index = JS_EXTENSIONS.iter().map(|e| base.join(format!("index.{}", e)));
+    first_file(with_ext.chain(index))
+}
+
+fn js_deps(file: &Path, source: &str) -> Vec<Dep> {
+    let spec_re = regex_lite::Regex::new(
+        r#"(?:\bfrom\s*|\bimport\s*\(?\s*|\brequire\s*\(\s*|^\s*import\s+)['"]([^'"]+)['"]"#,
+    )
+    .unwrap();
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut deps = Vec::new();
+    for cap in source.lines().flat_map(|l| spec_re.captures_iter(l).collect::<Vec<_>>()) {
+        let spec = cap[1].to_string();
+        let path = if spec.starts_with('.') { js_resolve(dir, &spec) } else { None };
+        deps.push(Dep { name: spec, path });
+    }
+    deps
+}
+
+// ---------- Go ----------
+
+/// go.mod 所在目录及模块名
+fn go_module(file: &Path, root: &Path) -> Option<(PathBuf, String)> {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        if let Ok(text) = std::fs::read_to_string(d.join("go.mod")) {
+            let module = text.lines().find_map(|l| l.trim().strip_prefix("module "))?.trim().to_string();
+            return Some((d.to_path_buf(), module));
+        }
+        if d == root {
+            break;
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+fn go_deps(file: &Path, source: &str, root: &Path) -> Vec<Dep> {
+    let quoted = regex_lite::Regex::new(r#""([^"]+)""#).unwrap();
+    let module = go_module(file, root);
+    let mut deps = Vec::new();
+    for (_, stmt) in crate::outline::imports(file, source).unwrap_or_default() {
+        for cap in quoted.captures_iter(&stmt) {
+            let import = cap[1].to_string();
+            let path = module.as_ref().and_then(|(dir, name)| {
+                let rest = import.strip_prefix(name.as_str())?;
+                let pkg = dir.join(rest.trim_start_matches('/'));
+                pkg.is_dir().then_some(pkg)
+            });
+            deps.push(Dep { name: import, path });
+        }
+    }
+    deps
+}
+
+// ---------- C/C++ ----------
+
+fn c_deps(file: &Path, source: &str, root: &Path) -> Vec<Dep> {
+    let include_re = regex_lite::Regex::new(r#"^\s*#\s*include\s*([<"])([^>"]+)[>"]"#).unwrap();
+    let dir = file.parent().unwrap_or(root);
+    source
+        .lines()
+        .filter_map(|l| include_re.captures(l))
+        .map(|cap| {
+            let header = cap[2].to_string();
+            let path = if &cap[1] == "\"" {
+                first_file([dir.join(&header), root.join(&header), root.join("include").join(&header)].map(|p| normalize(&p)))
+            } else {
+                None
+            };
+            Dep { name: header, path }
+        })
+        .collect()
+}
+
+/// 一个节点（文件或 Go 包目录）的依赖
+fn deps_of(node: &Path, root: &Path) -> Vec<Dep> {
+    // Go 包：目录下所有非测试 .go 文件
+    if node.is_dir() {
+        let Ok(entries) = std::fs::read_dir(node) else { return Vec::new() };
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "go") && !p.to_string_lossy().ends_with("_test.go"))
+            .collect();
+        files.sort();
+        let mut seen = HashSet::new();
+        return files.iter().flat_map(|f| deps_of(f, root)).filter(|d| seen.insert(d.name.clone())).collect();
+    }
+    if std::fs::metadata(node).map(|m| m.len() > MAX_FILE_BYTES).unwrap_or(true) {
+        return Vec::new();
+    }
+    let Ok(source) = std::fs::read_to_string(node) else { return Vec::new() };
+    match crate::lang::detect_language(node) {
+        Some("Rust") => rust_deps(node, &source, root),
+        Some("Python") => python_deps(node, &source, root),
+        Some("JavaScript" | "TypeScript" | "Vue" | "Svelte") => js_deps(node, &source),
+        Some("Go") => go_deps(node, &source, root),
+        Some("C" | "C++") => c_deps(node, &source, root),
+        _ => Vec::new(),
+    }
+}
+
+fn display(root: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+    let suffix = if path.is_dir() { "/" } else { "" };
+    format!("/codebase/{}{}", rel, suffix)
+}
+
+struct Walk<'a> {
+    root: &'a Path,
+    max_depth: usize,
+    expanded: HashSet<PathBuf>,
+    lines: Vec<String>,
+    truncated: bool,
+}
+
+impl Walk<'_> {
+    fn visit(&mut self, node: &Path, depth: usize) {
+        self.expanded.insert(node.to_path_buf());
+        let indent = "  ".repeat(depth + 1);
+        let mut external: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
+        for dep in deps_of(node, self.root) {
+            let Some(path) = dep.path.map(|p| normalize(&p)).filter(|p| p.starts_with(self.root) && p != node) else {
+                if !external.contains(&dep.name) {
+                    external.push(dep.name);
+                }
+                continue;
+            };
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            if self.lines.len() >= MAX_NODES {
+                self.truncated = true;
+                return;
+            }
+            let again = self.expanded.contains(&path);
+            let note = if again && depth + 1 < self.max_depth { ", see above" } else { "" };
+            self.lines.push(format!("{}{} ({}{})", indent, display(self.root, &path), dep.name, note));
+            if !again && depth + 1 < self.max_depth {
+                self.visit(&path, depth + 1);
+            }
+        }
+        if !external.is_empty() {
+            let more = external.len().saturating_sub(MAX_EXTERNAL);
+            external.truncate(MAX_EXTERNAL);
+            let more = if more > 0 { format!(" (+{} more)", more) } else { String::new() };
+            self.lines.push(format!("{}not in project: {}{}", indent, external.join(", "), more));
+        }
+    }
+}
+
+/// 执行 dependency_trace
+pub async fn call(args: &Value) -> Result<String, String> {
+    let root = PathBuf::from(crate::direct_tools::project_root(args)?);
+    let file = args.get("file").and_then(|f| f.as_str()).map(str::trim).filter(|f| !f.is_empty())
+        .ok_or_else(|| "missing required argument: file".to_string())?;
+    let virtual_path = crate::direct_tools::virtual_path(&root, file)?;
+    let root = normalize(&root.canonicalize().unwrap_or(root));
+    let start = root.join(virtual_path.trim_start_matches("/codebase").trim_start_matches('/'));
+    if !start.is_file() {
+        return Err(format!("file not found: {}", file));
+    }
+    let depth = args.get("depth").and_then(|d| d.as_u64()).map(|d| d as usize)
+        .unwrap_or(DEFAULT_DEPTH)
+        .clamp(1, MAX_DEPTH);
+
+    tokio::task::spawn_blocking(move || {
+        let mut walk = Walk { root: &root, max_depth: depth, expanded: HashSet::new(), lines: Vec::new(), truncated: false };
+        walk.visit(&start, 0);
+        let mut out = vec![format!("{} (depth {})", display(&root, &start), depth)];
+        if walk.lines.is_empty() {
+            out.push("  (no imports found)".into());
+        }
+        out.append(&mut walk.lines);
+        if walk.truncated {
+            out.push(format!("... (truncated at {} entries)", MAX_NODES));
+        }
+        out.join("\n")
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
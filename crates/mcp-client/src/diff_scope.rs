@@ -0,0 +1,162 @@
+//! diff_context_search 的搜索范围：git diff 改动的文件及直接依赖它们的文件
+//!
+//! 改动文件来自 `git diff --name-only`（`staged` 时加 `--cached`），已删除的文件不计入；
+//! 直接依赖为 import/use 语句引用了改动文件模块名的同语言源文件。
+//! repo map、rg/files 命令和最终结果都限定在这些文件内。
+
+use std::path::Path;
+
+/// 改动文件上限，超出部分忽略
+const MAX_CHANGED: usize = 200;
+/// 直接依赖文件上限
+const MAX_DEPENDENTS: usize = 100;
+/// 超过此大小的文件不检查 import
+const IMPORT_SCAN_BYTES: u64 = 512 * 1024;
+/// 这些文件名代表所在目录这个模块
+const MODULE_INDEX_STEMS: &[&str] = &["mod", "lib", "main", "index", "__init__"];
+
+#[derive(Debug, Clone)]
+pub struct Scope {
+    /// 对比对象，如 "HEAD"、"staged"、"main"
+    pub label: String,
+    /// 改动文件（项目相对路径）
+    pub changed: Vec<String>,
+    /// (依赖文件, 它引用的改动文件)
+    pub dependents: Vec<(String, String)>,
+}
+
+impl Scope {
+    /// 范围内的全部文件
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.changed.iter().map(String::as_str).chain(self.dependents.iter().map(|(f, _)| f.as_str()))
+    }
+
+    pub fn contains(&self, rel: &str) -> bool {
+        self.files().any(|f| f == rel)
+    }
+
+    /// repo map 标题
+    pub fn title(&self) -> String {
+        format!("git diff --name-only {} + direct dependents", self.label)
+    }
+
+    /// 代替目录树提供给模型的 repo map
+    pub fn map(&self) -> String {
+        let changed = self.changed.iter().map(|f| format!("/codebase/{} [changed]", f));
+        let dependents = self.dependents.iter().map(|(f, dep)| format!("/codebase/{} [imports {}]", f, dep));
+        changed.chain(dependents).collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// 收集改动文件（相对 `base`，默认 HEAD；`staged` 时只看暂存区）及其直接依赖
+pub async fn collect(root: &Path, base: Option<&str>, staged: bool) -> Result<Scope, String> {
+    let base = base.map(str::trim).filter(|b| !b.is_empty());
+    if let Some(b) = base {
+        crate::git::check_ref(b)?;
+    }
+    let mut args = vec!["diff", "--name-only", "--relative"];
+    if staged {
+        args.push("--cached");
+    }
+    let label = match (base, staged) {
+        (Some(b), true) => format!("--cached {}", b),
+        (Some(b), false) => b.to_string(),
+        (None, true) => "--cached".to_string(),
+        (None, false) => "HEAD".to_string(),
+    };
+    args.push(base.unwrap_or("HEAD"));
+    args.push("--");
+
+    let out = crate::git::run(root, &args).await?;
+    let changed: Vec<String> = out
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && root.join(l).is_file())
+        .take(MAX_CHANGED)
+        .map(str::to_string)
+        .collect();
+    if changed.is_empty() {
+        return Err(format!("no changed files (git diff --name-only {})", label));
+    }
+
+    let root_buf = root.to_path_buf();
+    let changed_for_scan = changed.clone();
+    let dependents = tokio::task::spawn_blocking(move || dependents(&root_buf, &changed_for_scan))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Scope { label, changed, dependents })
+}
+
+/// 引用该文件时使用的模块名：文件名去扩展名，mod.rs/index.ts/__init__.py 等取目录名
+pub fn module_key(rel: &str) -> Option<String> {
+    let path = Path::new(rel);
+    let stem = path.file_stem()?.to_str()?;
+    let key = if MODULE_INDEX_STEMS.contains(&stem) {
+        path.parent()?.file_name()?.to_str()?
+    } else {
+        stem
+    };
+    (key.len() >= 2).then(|| key.to_string())
+}
+
+/// JS/TS 互相 import，其余按语言相同判断
+pub fn same_family(a: &str, b: &str) -> bool {
+    let js = |l: &str| matches!(l, "JavaScript" | "TypeScript");
+    a == b || (js(a) && js(b))
+}
+
+/// import/use 语句中的标识符和路径片段
+pub fn import_words(path: &Path, language: &str, source: &str) -> Vec<String> {
+    import_lines(path, language, source)
+        .iter()
+        .flat_map(|l| l.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).map(str::to_string).collect::<Vec<_>>())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// 文件的 import/use 语句文本
+fn import_lines(path: &Path, language: &str, source: &str) -> Vec<String> {
+    if let Some(imports) = crate::outline::imports(path, source) {
+        return imports.into_iter().map(|(_, text)| text).collect();
+    }
+    let Some(re) = crate::lang::import_pattern(language).and_then(|p| regex_lite::Regex::new(p).ok()) else {
+        return Vec::new();
+    };
+    source.lines().filter(|l| re.is_match(l)).map(str::to_string).collect()
+}
+
+/// import 语句引用了某个改动文件模块名的同语言文件
+fn dependents(root: &Path, changed: &[String]) -> Vec<(String, String)> {
+    let targets: Vec<(&str, String, &'static str)> = changed
+        .iter()
+        .filter_map(|rel| {
+            let language = crate::lang::detect_language(Path::new(rel))?;
+            Some((rel.as_str(), module_key(rel)?, language))
+        })
+        .collect();
+    let mut out = Vec::new();
+    if targets.is_empty() {
+        return out;
+    }
+    crate::executor::walk_files(root, |path, meta| {
+        let Ok(rel) = path.strip_prefix(root) else { return true };
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if meta.len() > IMPORT_SCAN_BYTES || changed.contains(&rel) {
+            return true;
+        }
+        let Some(language) = crate::lang::detect_language(path) else { return true };
+        if !targets.iter().any(|(_, _, l)| same_family(l, language)) {
+            return true;
+        }
+        let Ok(source) = std::fs::read_to_string(path) else { return true };
+        let words = import_words(path, language, &source);
+        let hit = targets
+            .iter()
+            .find(|(_, key, l)| same_family(l, language) && words.iter().any(|w| w == key));
+        if let Some((target, _, _)) = hit {
+            out.push((rel, target.to_string()));
+        }
+        out.len() < MAX_DEPENDENTS
+    });
+    out
+}
@@ -0,0 +1,175 @@
+//! 直接暴露执行器命令的 MCP 工具：rg / readfile / tree / glob
+//!
+//! 不经过模型，由调用方自行探索；参数与 restricted_exec 中的同名命令一致，
+//! 路径使用虚拟路径 /codebase/...，截断和路径映射与 AI 搜索时完全相同。
+//! 配置 `direct_tools: true` 时启用。
+
+use std::path::{Component, Path};
+
+use serde_json::{json, Value};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Config;
+use crate::executor::ToolExecutor;
+use crate::i18n::Locale;
+
+pub const NAMES: &[&str] = &["rg", "readfile", "tree", "glob"];
+
+/// 工具定义
+pub fn definitions(l: Locale) -> Vec<Value> {
+    let project_path = json!({
+        "type": "string",
+        "description": l.pick("Absolute path to project root, mounted as /codebase. Empty = first client root, else cwd.", "项目根目录的绝对路径，挂载为 /codebase，留空 = 客户端第一个根目录，否则为当前工作目录"),
+        "default": ""
+    });
+    let path = |en: &'static str, zh: &'static str| json!({ "type": "string", "description": l.pick(en, zh), "default": "/codebase" });
+    let mut tools = vec![
+        json!({
+            "name": "rg",
+            "description": l.pick(
+                "Search file contents with ripgrep under /codebase (max 50 matches per file, long output truncated).",
+                "在 /codebase 下用 ripgrep 搜索文件内容（每个文件最多 50 处匹配，过长输出会截断）。"
+            ),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "pattern": { "type": "string", "description": l.pick("Regex pattern", "正则表达式") },
+                    "path": path("Directory or file to search, e.g. /codebase/src", "搜索的目录或文件，如 /codebase/src"),
+                    "include": { "type": "array", "items": { "type": "string" }, "description": l.pick("Globs of files to include", "包含的文件 glob") },
+                    "exclude": { "type": "array", "items": { "type": "string" }, "description": l.pick("Globs of files to exclude", "排除的文件 glob") },
+                    "context": { "type": "integer", "description": l.pick("Lines of context around each match (max 10)", "每处匹配前后的上下文行数（最多 10）"), "minimum": 0 },
+                    "before": { "type": "integer", "description": l.pick("Lines of context before each match", "每处匹配之前的上下文行数"), "minimum": 0 },
+                    "after": { "type": "integer", "description": l.pick("Lines of context after each match", "每处匹配之后的上下文行数"), "minimum": 0 },
+                    "case_insensitive": { "type": "boolean", "description": l.pick("Ignore case", "忽略大小写") },
+                    "fixed_strings": { "type": "boolean", "description": l.pick("Treat the pattern as a literal string", "把 pattern 当作字面量") },
+                    "word_boundary": { "type": "boolean", "description": l.pick("Only match whole words", "只匹配整个单词") },
+                    "multiline": { "type": "boolean", "description": l.pick("Allow matches to span lines", "允许跨行匹配") }
+                },
+                "required": ["pattern"]
+            }
+        }),
+        json!({
+            "name": "readfile",
+            "description": l.pick(
+                "Read a file under /codebase with line numbers, optionally a line range.",
+                "读取 /codebase 下的文件（带行号），可指定行范围。"
+            ),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "file": { "type": "string", "description": l.pick("File path, e.g. /codebase/src/main.rs", "文件路径，如 /codebase/src/main.rs") },
+                    "start_line": { "type": "integer", "description": l.pick("First line (1-based)", "起始行（从 1 开始）"), "minimum": 1 },
+                    "end_line": { "type": "integer", "description": l.pick("Last line (inclusive)", "结束行（含）"), "minimum": 1 },
+                    "ranges": {
+                        "type": "array",
+                        "description": l.pick("Several line ranges to read at once (up to 20)", "一次读取多个行范围（最多 20 个）"),
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "start_line": { "type": "integer", "minimum": 1 },
+                                "end_line": { "type": "integer", "minimum": 1 }
+                            }
+                        }
+                    },
+                    "around_symbol": { "type": "string", "description": l.pick("Also read the complete definition of this function/class", "同时读取该函数/类的完整定义") }
+                },
+                "required": ["file"]
+            }
+        }),
+        json!({
+            "name": "tree",
+            "description": l.pick("Show the directory tree under /codebase.", "显示 /codebase 下的目录树。"),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": path("Directory, e.g. /codebase/src", "目录，如 /codebase/src"),
+                    "levels": { "type": "integer", "description": l.pick("Depth (default 3)", "深度（默认 3）"), "minimum": 1 },
+                    "sort": { "type": "string", "enum": ["name", "mtime", "size"], "default": "name" },
+                    "max_entries": { "type": "integer", "description": l.pick("Maximum entries shown per directory", "每个目录最多显示的条目数"), "minimum": 1 }
+                }
+            }
+        }),
+        json!({
+            "name": "glob",
+            "description": l.pick(
+                "Find files or directories by a glob relative to path under /codebase (* stays within one directory, ** recurses; max 100 matches).",
+                "按相对 path 的 glob 查找 /codebase 下的文件或目录（* 不跨目录，** 表示递归，最多 100 个）。"
+            ),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "pattern": { "type": "string", "description": l.pick("Glob, e.g. src/*.rs or **/*.rs", "glob，如 src/*.rs 或 **/*.rs") },
+                    "path": path("Directory to search", "搜索的目录"),
+                    "type_filter": { "type": "string", "enum": ["file", "directory", "all"], "default": "all" }
+                },
+                "required": ["pattern"]
+            }
+        }),
+    ];
+    for tool in &mut tools {
+        tool["inputSchema"]["properties"]["project_path"] = project_path.clone();
+    }
+    tools
+}
+
+/// 参数中的 project_path，留空为当前工作目录
+pub fn project_root(args: &Value) -> Result<String, String> {
+    let project_path = args.get("project_path").and_then(|p| p.as_str()).unwrap_or("");
+    if project_path.is_empty() {
+        Ok(std::env::current_dir().map_err(|e| e.to_string())?.to_string_lossy().to_string())
+    } else if std::path::Path::new(project_path).is_dir() {
+        Ok(project_path.to_string())
+    } else {
+        Err(format!("project_path is not a directory: {}", project_path))
+    }
+}
+
+/// 绝对路径、相对路径或 /codebase/... → /codebase 下的虚拟路径；不允许离开项目根目录
+pub fn virtual_path(root: &Path, path: &str) -> Result<String, String> {
+    if let Some(rel) = path.strip_prefix("/codebase") {
+        if rel.is_empty() || rel.starts_with('/') {
+            return virtual_path(root, rel.trim_start_matches('/'));
+        }
+    }
+    let p = Path::new(path);
+    let rel = if p.is_absolute() {
+        let real = p.canonicalize().map_err(|_| format!("file not found: {}", path))?;
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        real.strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .map_err(|_| format!("{} is outside the project {} (pass project_path)", path, root.display()))?
+    } else {
+        p.to_path_buf()
+    };
+    if rel.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("{} is outside the project", path));
+    }
+    Ok(format!("/codebase/{}", rel.to_string_lossy().replace('\\', "/")))
+}
+
+/// 执行一个直接工具；参数转换为 restricted_exec 命令交给执行器
+pub async fn call(name: &str, args: &Value, config: &Config, cancel: &CancellationToken) -> Result<String, String> {
+    let root = project_root(args)?;
+    let required = match name {
+        "readfile" => Some("file"),
+        "rg" | "glob" => Some("pattern"),
+        _ => None,
+    };
+    if let Some(key) = required.filter(|k| args.get(*k).and_then(|v| v.as_str()).is_none_or(|v| v.is_empty())) {
+        return Err(format!("missing required argument: {}", key));
+    }
+
+    let mut cmd = args.as_object().cloned().unwrap_or_default();
+    cmd.remove("project_path");
+    cmd.insert("type".into(), json!(name));
+    let mut executor = ToolExecutor::new(&root);
+    executor.cancel = cancel.clone();
+    executor.timeouts = config.timeouts.resolve();
+    executor.limits = config.output.resolve();
+    let output = executor.exec_command(&Value::Object(cmd)).await;
+    match output.strip_prefix("Error: ") {
+        Some(e) => Err(e.to_string()),
+        None => Ok(output),
+    }
+}
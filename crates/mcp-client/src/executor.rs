@@ -1,231 +1,1418 @@
 //! 本地工具执行器
 //!
-//! 在用户机器上执行 rg/readfile/tree/ls/glob 命令。
+//! 在用户机器上执行 rg/count/readfile/head/tail/readmatch/enclosing/imports/tree/ls/glob/files/stat/stats/archive/git 命令。
 //! 移植自 Node.js 版本的 executor.mjs
 
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use tokio::task;
+use std::process::Output;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::path_remap::PathRemapper;
+use crate::result_cache::{self, ResultCache, Stamp};
+use crate::search_fallback::Skipped;
+use crate::{archive, git, lang, outline};
 
 const RESULT_MAX_LINES: usize = 50;
 const LINE_MAX_CHARS: usize = 250;
+/// rg --max-count：每个文件最多的匹配行数
+const RG_MAX_COUNT: usize = 50;
+/// rg --max-filesize 的默认值：更大的文件（多为生成文件、锁文件）不搜索
+pub const DEFAULT_SEARCH_MAX_FILESIZE: u64 = 1024 * 1024;
+/// rg 上下文行数上限
+pub const MAX_CONTEXT_LINES: usize = 10;
+/// files 默认列出的文件数；低于 RESULT_MAX_LINES 减去说明行后的上限，保证默认值不被截断
+const FILES_DEFAULT_MAX: usize = 40;
+const READMATCH_DEFAULT_CONTEXT: usize = 3;
+/// readfile 一次最多读取的范围数
+const READFILE_MAX_RANGES: usize = 20;
+/// rg_files 一次最多搜索的文件数
+const RG_FILES_MAX: usize = 500;
+/// head / tail 默认行数
+const HEAD_TAIL_DEFAULT_LINES: usize = 20;
+/// git log 默认 / 最多列出的提交数
+const GIT_LOG_DEFAULT: usize = 20;
+const GIT_LOG_MAX: usize = 100;
+/// git blame 一次最多的行数
+const GIT_BLAME_MAX_LINES: usize = 200;
+const READMATCH_MAX_CONTEXT: usize = 20;
+pub const STATS_MAX_FILES: usize = 20000;
+const STATS_MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// 按目录汇总时统计的最大目录深度
+pub const STATS_DIR_DEPTH: usize = 3;
+
+/// 一轮命令输出的默认总字节数
+const DEFAULT_TURN_MAX_BYTES: usize = 64 * 1024;
+/// turn_max_bytes 的下限
+pub const MIN_TURN_BYTES: usize = 4096;
+
+/// 命令输出的截断上限
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputLimits {
+    /// 每个命令结果的最大行数
+    pub max_lines: usize,
+    /// 每行最多字符数
+    pub line_max_chars: usize,
+    /// 一次 exec_tool_call 全部命令输出的总字节数；None = 不限制
+    pub turn_max_bytes: Option<usize>,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self { max_lines: RESULT_MAX_LINES, line_max_chars: LINE_MAX_CHARS, turn_max_bytes: Some(DEFAULT_TURN_MAX_BYTES) }
+    }
+}
+
+/// 单个命令的默认超时
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// 一次 exec_tool_call（一轮的全部命令）的默认总时长
+pub const DEFAULT_TURN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 一轮中默认同时执行的命令数
+pub const DEFAULT_COMMAND_PARALLELISM: usize = 4;
+/// command_parallelism 的上限
+pub const MAX_COMMAND_PARALLELISM: usize = 16;
+
+/// 命令超时；None = 不限制
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    pub command: Option<Duration>,
+    pub turn: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self { command: Some(DEFAULT_COMMAND_TIMEOUT), turn: Some(DEFAULT_TURN_TIMEOUT) }
+    }
+}
+
+/// rg 命令的可选参数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RgOptions {
+    /// 前后上下文行数（-C）
+    pub context: Option<usize>,
+    /// 之前的上下文行数（-B），覆盖 context
+    pub before: Option<usize>,
+    /// 之后的上下文行数（-A），覆盖 context
+    pub after: Option<usize>,
+    /// 忽略大小写（-i）
+    pub case_insensitive: bool,
+    /// 按字面量搜索（-F）
+    pub fixed_strings: bool,
+    /// 只匹配整个单词（-w）
+    pub word_boundary: bool,
+    /// 允许跨行匹配（-U）
+    pub multiline: bool,
+}
+
+impl RgOptions {
+    /// 从 rg 命令的 JSON 参数读取
+    pub fn from_json(cmd: &serde_json::Value) -> Self {
+        let num = |key: &str| cmd.get(key).and_then(|v| v.as_u64()).map(|v| v as usize);
+        let flag = |key: &str| cmd.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        Self {
+            context: num("context"),
+            before: num("before"),
+            after: num("after"),
+            case_insensitive: flag("case_insensitive"),
+            fixed_strings: flag("fixed_strings"),
+            word_boundary: flag("word_boundary"),
+            multiline: flag("multiline"),
+        }
+    }
+
+    /// 匹配前、后的上下文行数（不超过 MAX_CONTEXT_LINES）
+    pub fn around(&self) -> (usize, usize) {
+        let pick = |n: Option<usize>| n.or(self.context).unwrap_or(0).min(MAX_CONTEXT_LINES);
+        (pick(self.before), pick(self.after))
+    }
+}
+
+/// rg 结果中的一个匹配（structuredContent 使用）
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgMatch {
+    /// 虚拟路径 /codebase/...
+    pub path: String,
+    pub line: usize,
+    /// 列号（1 起，按字节）
+    pub column: usize,
+    /// 匹配的文本
+    pub text: String,
+}
+
+/// ls/tree 排序方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey { Name, Mtime, Size }
+
+impl SortKey {
+    pub fn parse(s: Option<&str>) -> Self {
+        match s {
+            Some("mtime") => SortKey::Mtime,
+            Some("size") => SortKey::Size,
+            _ => SortKey::Name,
+        }
+    }
+}
+
+struct TreeOptions {
+    max_depth: usize,
+    sort: SortKey,
+    max_entries: Option<usize>,
+}
+
+/// 设置 WINDSURF_MCP_NO_SANDBOX=1 时允许读取项目外的路径
+fn sandbox_disabled() -> bool {
+    matches!(
+        std::env::var("WINDSURF_MCP_NO_SANDBOX").unwrap_or_default().to_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// 遍历时跳过的目录
+pub const SKIP_DIRS: [&str; 9] = ["node_modules", ".git", "dist", "build", "target", ".venv", "__pycache__", "vendor", ".cache"];
+
+/// 不读取 .gitignore/.ignore/.rgignore（命令行 --no-ignore 或配置 no_ignore）
+static NO_IGNORE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_no_ignore(on: bool) {
+    NO_IGNORE.store(on, Ordering::Relaxed);
+}
+
+pub fn no_ignore() -> bool {
+    NO_IGNORE.load(Ordering::Relaxed)
+}
+
+/// 遍历 root 的 WalkBuilder：遵守 .gitignore、.ignore、.rgignore（no_ignore 时不读取），
+/// 跳过隐藏项、SKIP_DIRS、名称匹配 exclude 的条目、deny_paths 禁止的路径和（不含子模块时的）子模块，同一层按名称排序
+pub fn walk_builder(root: &Path, exclude: &[String], subs: Option<&Submodules>) -> ignore::WalkBuilder {
+    let respect = !no_ignore();
+    let mut builder = ignore::WalkBuilder::new(root);
+    builder.standard_filters(respect).hidden(true).sort_by_file_name(|a, b| a.cmp(b));
+    if respect {
+        builder.add_custom_ignore_filename(".rgignore");
+    }
+    let exclude = exclude.to_vec();
+    let opaque = subs.filter(|s| !s.include).cloned();
+    builder.filter_entry(move |e| {
+        let name = e.file_name().to_string_lossy();
+        e.depth() == 0
+            || !(SKIP_DIRS.contains(&name.as_ref())
+                || excluded(&name, &exclude)
+                || crate::deny_paths::get().matches(e.path())
+                || opaque.as_ref().is_some_and(|s| s.is_submodule(e.path())))
+    });
+    builder
+}
+
+/// dir 下一层的条目（遵守忽略规则，跳过隐藏项和 SKIP_DIRS），按名称排序
+pub fn list_dir(dir: &Path, exclude: &[String]) -> Vec<PathBuf> {
+    walk_builder(dir, exclude, None)
+        .max_depth(Some(1))
+        .build()
+        .flatten()
+        .filter(|e| e.depth() == 1)
+        .map(ignore::DirEntry::into_path)
+        .collect()
+}
+
+/// 遍历 root 下的文件（按名称排序，遵守忽略规则，跳过隐藏项和 SKIP_DIRS），`visit` 返回 false 时停止
+pub fn walk_files(root: &Path, mut visit: impl FnMut(&Path, &std::fs::Metadata) -> bool) {
+    for entry in walk_builder(root, &[], None).build().flatten() {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        if !visit(entry.path(), &meta) {
+            return;
+        }
+    }
+}
+
+/// 递归遍历目录时的符号链接保护：解析到 root 之外的符号链接跳过，
+/// 同一真实目录只进入一次（符号链接环、指向祖先目录的链接）
+pub struct WalkGuard {
+    root: PathBuf,
+    seen: HashSet<PathBuf>,
+}
+
+impl WalkGuard {
+    pub fn new(root: &Path) -> Self {
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        Self { seen: HashSet::from([root.clone()]), root }
+    }
+
+    /// 是否列出该条目：非符号链接总是列出，符号链接须解析到 root 内
+    pub fn allows(&self, path: &Path) -> bool {
+        !path.is_symlink() || path.canonicalize().is_ok_and(|real| real.starts_with(&self.root))
+    }
+
+    /// list_dir，去掉指向 root 之外的符号链接
+    pub fn list_dir(&self, dir: &Path, exclude: &[String]) -> Vec<PathBuf> {
+        let mut entries = list_dir(dir, exclude);
+        entries.retain(|p| self.allows(p));
+        entries
+    }
+
+    /// 是否递归进入目录：解析后在 root 内且之前未进入过
+    pub fn enter(&mut self, dir: &Path) -> bool {
+        match dir.canonicalize() {
+            Ok(real) => real.starts_with(&self.root) && self.seen.insert(real),
+            Err(_) => false,
+        }
+    }
+}
+
+/// 已初始化的 git 子模块
+#[derive(Clone)]
+pub struct Submodules {
+    paths: Vec<PathBuf>,
+    pub include: bool,
+}
+
+impl Submodules {
+    /// 读取 .gitmodules（递归处理嵌套子模块），只保留已初始化的子模块
+    pub fn discover(root: &Path, include: bool) -> Self {
+        let mut paths = Vec::new();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let content = match std::fs::read_to_string(dir.join(".gitmodules")) {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+            for line in content.lines() {
+                let Some((key, value)) = line.split_once('=') else { continue };
+                if key.trim() != "path" { continue; }
+                let sub = dir.join(value.trim());
+                if sub.join(".git").exists() {
+                    pending.push(sub.clone());
+                    paths.push(sub);
+                }
+            }
+        }
+        Self { paths, include }
+    }
+
+    pub fn is_submodule(&self, path: &Path) -> bool {
+        self.paths.iter().any(|p| p == path)
+    }
+
+    /// 路径位于某个子模块内
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.iter().any(|p| path.starts_with(p))
+    }
+}
+
+/// 搜索过程中收集的信息
+#[derive(Debug, Clone, Default)]
+pub struct Collected {
+    /// rg / count 的模式
+    pub rg_patterns: Vec<String>,
+    /// readfile/head/tail/readmatch/enclosing 读取过的文件（虚拟路径）
+    pub files: Vec<String>,
+    /// rg 命令输出中的匹配，按 (path, line) 去重
+    pub matches: Vec<RgMatch>,
+}
 
+/// 执行器克隆之间共享的状态：exec_tool_call 的并行任务和同一次搜索的各轮都写到这里
+#[derive(Default)]
+struct Shared {
+    collected: Mutex<Collected>,
+    /// 文件读取和目录列表的结果
+    cache: Mutex<ResultCache>,
+}
+
+/// 克隆开销很小：根目录解析和子模块发现只在 new 时做一次，共享状态通过 Arc 共用
+#[derive(Clone)]
 pub struct ToolExecutor {
     root: PathBuf,
-    pub collected_rg_patterns: Vec<String>,
-    pub collected_files: Vec<String>,
+    /// 根目录的所有真实路径写法（规范化路径、原始路径、符号链接别名）→ /codebase
+    remapper: PathRemapper,
+    pub submodules: Submodules,
+    shared: Arc<Shared>,
+    /// readfile/readmatch/enclosing 单文件输出字节上限
+    pub max_file_bytes: Option<usize>,
+    /// rg / files 默认排除测试文件
+    pub exclude_tests: bool,
+    /// 客户端取消请求时终止正在运行的 rg
+    pub cancel: CancellationToken,
+    /// rg / files 只在这些文件内搜索（diff_context_search）
+    pub scope: Option<Vec<PathBuf>>,
+    /// 单个命令 / 一轮命令的超时
+    pub timeouts: Timeouts,
+    /// 输出截断上限
+    pub limits: OutputLimits,
+    /// exec_tool_call 同时执行的命令数
+    pub parallelism: usize,
+    /// rg / count 跳过超过该字节数的文件；None = 不限制
+    pub search_max_filesize: Option<u64>,
+    /// 文件访问审计（创建时所在工具调用的上下文）
+    audit: Option<crate::audit::Context>,
 }
 
 impl ToolExecutor {
     pub fn new(project_root: &str) -> Self {
+        let root = PathBuf::from(project_root).canonicalize().unwrap_or_else(|_| PathBuf::from(project_root));
         Self {
-            root: PathBuf::from(project_root).canonicalize().unwrap_or_else(|_| PathBuf::from(project_root)),
-            collected_rg_patterns: Vec::new(),
-            collected_files: Vec::new(),
+            submodules: Submodules::discover(&root, false),
+            remapper: PathRemapper::new(project_root, &root),
+            root,
+            shared: Arc::default(),
+            max_file_bytes: None,
+            exclude_tests: false,
+            cancel: CancellationToken::new(),
+            scope: None,
+            timeouts: Timeouts::default(),
+            limits: OutputLimits::default(),
+            parallelism: DEFAULT_COMMAND_PARALLELISM,
+            search_max_filesize: Some(DEFAULT_SEARCH_MAX_FILESIZE),
+            audit: crate::audit::current(),
+        }
+    }
+
+    /// 目前收集到的模式、文件和匹配
+    pub fn collected(&self) -> Collected {
+        self.shared.collected.lock().unwrap().clone()
+    }
+
+    /// 续接搜索时恢复之前收集的信息
+    pub fn restore(&self, collected: Collected) {
+        *self.shared.collected.lock().unwrap() = collected;
+    }
+
+    /// 限定 rg / files 的搜索范围（项目相对路径）
+    pub fn set_scope<'a>(&mut self, files: impl Iterator<Item = &'a str>) {
+        self.scope = Some(files.map(|f| self.root.join(f)).collect());
+    }
+
+    /// 范围内、位于 dir 下的文件；未限定范围时为 None
+    fn scoped_files(&self, dir: &Path) -> Option<Vec<&PathBuf>> {
+        self.scope.as_ref().map(|files| files.iter().filter(|f| f.starts_with(dir) && !self.denied(f)).collect())
+    }
+
+    /// 运行 rg；取消时丢弃 future，子进程随之被终止
+    async fn run_rg(&self, args: &[String]) -> Result<Output, String> {
+        let mut cmd = tokio::process::Command::new(find_rg_binary());
+        if no_ignore() {
+            cmd.arg("--no-ignore");
+        }
+        let output = cmd.args(args).kill_on_drop(true).output();
+        tokio::select! {
+            out = output => out.map_err(|e| e.to_string()),
+            _ = self.cancel.cancelled() => Err("cancelled".into()),
         }
     }
 
-    /// 虚拟路径 /codebase → 真实路径
-    fn real_path(&self, virtual_path: &str) -> PathBuf {
-        if virtual_path.starts_with("/codebase") {
-            let rel = virtual_path.strip_prefix("/codebase").unwrap_or("").trim_start_matches('/');
-            self.root.join(rel)
+    /// 虚拟路径 /codebase → 真实路径；相对路径按项目根目录解析。
+    /// 沙箱：解析符号链接和 `..` 后不在项目内的路径返回 Err("Error: path outside project root: ...")；
+    /// deny_paths 禁止的路径（不受沙箱开关影响）返回 Err("Error: path denied by policy: ...")
+    fn real_path(&self, virtual_path: &str) -> Result<PathBuf, String> {
+        let path = match virtual_path.strip_prefix("/codebase") {
+            Some(rel) if rel.is_empty() || rel.starts_with('/') => self.root.join(rel.trim_start_matches('/')),
+            _ => self.root.join(virtual_path),
+        };
+        if !sandbox_disabled() && !self.contains(&path) {
+            Err(format!("Error: path outside project root: {}", virtual_path))
+        } else if self.denied(&path) {
+            Err(format!("Error: path denied by policy: {}", virtual_path))
         } else {
-            PathBuf::from(virtual_path)
+            Ok(path)
+        }
+    }
+
+    /// path 或它在项目内的祖先目录被 deny_paths 禁止（含 `..` 与符号链接解析后的路径）
+    fn denied(&self, path: &Path) -> bool {
+        crate::deny_paths::get().denies(&self.root, path)
+    }
+
+    /// real_path，并把命令对该路径的访问记入审计日志（开启时）；越界被拒绝的访问同样记录
+    fn access(&self, command: &str, virtual_path: &str) -> Result<PathBuf, String> {
+        let resolved = self.real_path(virtual_path);
+        if let Some(audit) = &self.audit {
+            let path = match &resolved {
+                Ok(rp) => self.remap(&rp.to_string_lossy()).trim_end_matches('/').to_string(),
+                Err(_) => virtual_path.to_string(),
+            };
+            crate::audit::record(audit, &self.root.to_string_lossy(), command, &path, resolved.is_err());
         }
+        resolved
+    }
+
+    /// access 加上目标的元信息（不存在时为 None），在阻塞线程上执行，慢速文件系统不会卡住运行时
+    async fn locate(&self, command: &'static str, virtual_path: &str) -> Result<(PathBuf, Option<std::fs::Metadata>), String> {
+        let (this, path) = (self.clone(), virtual_path.to_string());
+        tokio::task::spawn_blocking(move || {
+            let rp = this.access(command, &path)?;
+            let meta = std::fs::metadata(&rp).ok();
+            Ok((rp, meta))
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Error: {}", e)))
+    }
+
+    /// path 解析后位于项目根目录内；不存在的部分按最近的已存在祖先判断
+    fn contains(&self, path: &Path) -> bool {
+        let mut existing = path;
+        while !existing.exists() {
+            match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(_)) => existing = parent,
+                // 以 `..` 结尾或到达根仍不存在：无法确认，按越界处理
+                _ => return false,
+            }
+        }
+        existing.canonicalize().is_ok_and(|real| real.starts_with(&self.root))
     }
 
     /// 真实路径 → 虚拟路径
     fn remap(&self, text: &str) -> String {
-        text.replace(&self.root.to_string_lossy().to_string(), "/codebase")
+        self.remapper.remap(text)
     }
 
-    /// 截断输出
-    fn truncate(text: &str) -> String {
-        let lines: Vec<&str> = text.lines().collect();
-        let limit = lines.len().min(RESULT_MAX_LINES);
-        let mut result: Vec<String> = lines[..limit]
-            .iter()
-            .map(|line| {
-                if line.len() > LINE_MAX_CHARS {
-                    line[..LINE_MAX_CHARS].to_string()
-                } else {
-                    line.to_string()
+    /// 压缩输出：去行尾空白、合并连续空行、连续重复行折叠为 "×N"
+    fn compact(text: &str) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        let mut last: Option<&str> = None;
+        let mut repeat = 0usize;
+
+        let flush = |out: &mut Vec<String>, repeat: usize| {
+            if repeat > 1 {
+                if let Some(l) = out.last_mut() {
+                    l.push_str(&format!(" ×{}", repeat));
                 }
-            })
-            .collect();
+            }
+        };
 
-        if lines.len() > RESULT_MAX_LINES {
-            result.push("... (lines truncated) ...".into());
+        for line in text.lines().map(|l| l.trim_end()) {
+            if last == Some(line) {
+                // 连续空行直接合并，不加标记
+                if !line.is_empty() { repeat += 1; }
+                continue;
+            }
+            flush(&mut out, repeat);
+            repeat = 1;
+            last = Some(line);
+            out.push(line.to_string());
+        }
+        flush(&mut out, repeat);
+        out
+    }
+
+    /// 截断输出：最多 limits.max_lines 行，每行最多 limits.line_max_chars 个字符
+    fn truncate(&self, text: &str) -> String {
+        let OutputLimits { max_lines, line_max_chars, .. } = self.limits;
+        let compacted = Self::compact(text);
+        let mut result: Vec<&str> = compacted.iter().take(max_lines).map(|l| truncate_chars(l, line_max_chars)).collect();
+        if compacted.len() > max_lines {
+            result.push("... (lines truncated) ...");
         }
         result.join("\n")
     }
 
     /// ripgrep 搜索
     pub async fn rg(
-        &mut self,
+        &self,
         pattern: &str,
         path: &str,
         include: Option<&[String]>,
         exclude: Option<&[String]>,
+        opts: &RgOptions,
     ) -> String {
-        self.collected_rg_patterns.push(pattern.to_string());
-        let rp = self.real_path(path);
+        self.shared.collected.lock().unwrap().rg_patterns.push(pattern.to_string());
+        let rp = match self.locate("rg", path).await {
+            Ok((p, Some(_))) => p,
+            Ok((_, None)) => return format!("Error: path does not exist: {}", path),
+            Err(e) => return e,
+        };
+        let paths: Vec<PathBuf> = match self.scoped_files(&rp) {
+            Some(files) if files.is_empty() => return "(no matches: outside the searched files)".into(),
+            Some(files) => files.into_iter().cloned().collect(),
+            None => vec![rp],
+        };
+        self.search(pattern, paths, include, exclude, opts).await
+    }
 
-        if !rp.exists() {
-            return format!("Error: path does not exist: {}", path);
+    /// 只在给定的文件（例如之前 glob / files 的结果）中搜索，后续轮次不必再扫描整个目录树。
+    /// 不存在或不允许访问的文件跳过并在结果首行注明
+    pub async fn rg_files(
+        &self,
+        pattern: &str,
+        files: &[String],
+        include: Option<&[String]>,
+        exclude: Option<&[String]>,
+        opts: &RgOptions,
+    ) -> String {
+        if files.is_empty() {
+            return "Error: rg_files requires a non-empty files array".into();
+        }
+        if files.len() > RG_FILES_MAX {
+            return format!("Error: at most {} files per rg_files", RG_FILES_MAX);
+        }
+        self.shared.collected.lock().unwrap().rg_patterns.push(pattern.to_string());
+        let mut paths: Vec<PathBuf> = Vec::new();
+        let mut skipped: Vec<&str> = Vec::new();
+        for f in files {
+            let rp = match self.locate("rg_files", f).await {
+                Ok((p, Some(_))) => p,
+                _ => {
+                    skipped.push(f);
+                    continue;
+                }
+            };
+            let found = match self.scoped_files(&rp) {
+                Some(scoped) => scoped.into_iter().cloned().collect(),
+                None => vec![rp],
+            };
+            for p in found {
+                if !paths.contains(&p) {
+                    paths.push(p);
+                }
+            }
+        }
+        if paths.is_empty() {
+            return format!("Error: none of the files exist or are searchable: {}", skipped.join(", "));
+        }
+        let text = self.search(pattern, paths, include, exclude, opts).await;
+        if skipped.is_empty() {
+            return text;
+        }
+        format!("(skipped {} missing or unsearchable files: {})\n{}", skipped.len(), skipped.join(", "), text)
+    }
+
+    /// 在 paths（文件或目录）中搜索：rg 可用时运行 rg，否则用内置搜索
+    async fn search(
+        &self,
+        pattern: &str,
+        paths: Vec<PathBuf>,
+        include: Option<&[String]>,
+        exclude: Option<&[String]>,
+        opts: &RgOptions,
+    ) -> String {
+        let caps = crate::ripgrep::caps();
+        if let Some(why) = caps.unusable() {
+            return self.builtin_rg(&why, pattern, paths, include, exclude, opts).await;
         }
 
         let mut args = vec![
-            "--no-heading".to_string(),
-            "-n".to_string(),
+            "--json".to_string(),
             "--max-count".to_string(),
-            "50".to_string(),
-            pattern.to_string(),
-            rp.to_string_lossy().to_string(),
+            RG_MAX_COUNT.to_string(),
         ];
+        let (before, after) = opts.around();
+        for (flag, n) in [("-B", before), ("-A", after)] {
+            if n > 0 {
+                args.push(flag.into());
+                args.push(n.to_string());
+            }
+        }
+        for (flag, on) in [("-i", opts.case_insensitive), ("-F", opts.fixed_strings), ("-w", opts.word_boundary)] {
+            if on {
+                args.push(flag.into());
+            }
+        }
+        // 字面量搜索不需要按模式内容补充 -U / -P
+        let mut flags = if opts.fixed_strings { Vec::new() } else { caps.pattern_flags(pattern) };
+        if opts.multiline && caps.multiline && !flags.contains(&"-U") {
+            flags.push("-U");
+        }
+        args.extend(flags.into_iter().map(String::from));
+        args.extend(self.max_filesize_args());
+        args.push("-e".into());
+        args.push(pattern.to_string());
+        args.extend(paths.iter().map(|p| p.to_string_lossy().to_string()));
+        let globs = self.rg_globs(include, exclude);
+        for g in &globs {
+            args.push("--glob".into());
+            args.push(g.clone());
+        }
+
+        let (out, skipped) = tokio::join!(self.run_rg(&args), self.skipped(paths, globs));
+        match out {
+            Ok(out) => {
+                let stdout = String::from_utf8_lossy(&out.stdout);
+                let stderr = String::from_utf8_lossy(&out.stderr);
+
+                if out.status.success() || out.status.code() == Some(0) {
+                    let mut lines = crate::rg_results::parse(&stdout);
+                    lines.retain(|l| !self.denied(Path::new(&l.path)));
+                    if lines.is_empty() {
+                        return self.with_skipped("(no matches)", skipped);
+                    }
+                    let text = self.render_rg(lines, opts);
+                    self.truncate(&self.with_skipped(&self.remap(&text), skipped))
+                } else if out.status.code() == Some(1) {
+                    self.with_skipped("(no matches)", skipped)
+                } else if !stderr.is_empty() {
+                    self.truncate(&self.remap(&stderr))
+                } else {
+                    self.with_skipped("(no matches)", skipped)
+                }
+            }
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    /// rg --max-filesize
+    fn max_filesize_args(&self) -> Vec<String> {
+        match self.search_max_filesize {
+            Some(max) => vec!["--max-filesize".into(), max.to_string()],
+            None => Vec::new(),
+        }
+    }
+
+    /// rg 跳过的大文件和二进制文件数（rg 本身不报告），在阻塞线程上与 rg 并行统计
+    async fn skipped(&self, paths: Vec<PathBuf>, globs: Vec<String>) -> Skipped {
+        let (max_filesize, cancel) = (self.search_max_filesize, self.cancel.clone());
+        tokio::task::spawn_blocking(move || crate::search_fallback::skipped(&paths, &globs, max_filesize, &cancel))
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .unwrap_or_default()
+    }
 
-        if let Some(inc) = include {
-            for g in inc {
+    /// 有文件被跳过时在结果前加一行说明
+    fn with_skipped(&self, text: &str, skipped: Skipped) -> String {
+        match skipped.note(self.search_max_filesize) {
+            Some(note) => format!("{}\n{}", note, text),
+            None => text.to_string(),
+        }
+    }
+
+    /// 列出文件（rg --files，遵守 .gitignore 等忽略规则）
+    pub async fn files(&self, path: &str, glob: Option<&str>, max_count: Option<usize>) -> String {
+        let rp = match self.locate("files", path).await {
+            Ok((p, Some(meta))) if meta.is_dir() => p,
+            Ok(_) => return format!("Error: dir not found: {}", path),
+            Err(e) => return e,
+        };
+        if let Some(files) = self.scoped_files(&rp) {
+            // 与 rg --glob 一致：不含 `/` 的模式匹配文件名，否则匹配相对 path 的路径
+            let glob = match glob.map(PathGlob::new).transpose() {
+                Ok(g) => g,
+                Err(e) => return format!("Error: invalid glob pattern {}: {}", glob.unwrap_or_default(), e),
+            };
+            let all: Vec<&PathBuf> = files
+                .into_iter()
+                .filter(|f| match &glob {
+                    Some(g) if g.segments.len() > 1 => f.strip_prefix(&rp).is_ok_and(|rel| g.is_match(rel)),
+                    Some(g) => f.file_name().is_some_and(|n| g.full.is_match(n)),
+                    None => true,
+                })
+                .collect();
+            if all.is_empty() {
+                return "(no files)".into();
+            }
+            let cap = max_count.unwrap_or(FILES_DEFAULT_MAX).clamp(1, self.limits.max_lines.saturating_sub(1).max(1));
+            let mut lines: Vec<String> = all.iter().take(cap).map(|f| self.remap(&f.to_string_lossy())).collect();
+            if all.len() > cap {
+                lines.push(format!("... ({} of {} files shown)", cap, all.len()));
+            }
+            return self.truncate(&lines.join("\n"));
+        }
+        if let Some(why) = crate::ripgrep::caps().unusable() {
+            return self.builtin_files(&why, &rp, glob, max_count).await;
+        }
+
+        let mut args = vec!["--files".to_string(), "--sort".to_string(), "path".to_string()];
+        let include: Vec<String> = glob.map(str::to_string).into_iter().collect();
+        for g in self.rg_globs(Some(&include), None) {
+            args.push("--glob".into());
+            args.push(g);
+        }
+        args.push(rp.to_string_lossy().to_string());
+
+        let cap = max_count.unwrap_or(FILES_DEFAULT_MAX).clamp(1, self.limits.max_lines.saturating_sub(1).max(1));
+        let out = match self.run_rg(&args).await {
+            Ok(out) => out,
+            Err(e) => return format!("Error: {}", e),
+        };
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let all: Vec<&str> = stdout.lines().filter(|l| !l.is_empty() && !self.denied(Path::new(l))).collect();
+        if all.is_empty() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            if out.status.code() != Some(1) && !stderr.is_empty() {
+                return self.truncate(&self.remap(&stderr));
+            }
+            return "(no files)".into();
+        }
+
+        let mut lines: Vec<String> = all.iter().take(cap).map(|l| self.remap(l)).collect();
+        if all.len() > cap {
+            lines.push(format!("... ({} of {} files shown)", cap, all.len()));
+        }
+        self.truncate(&lines.join("\n"))
+    }
+
+    /// 统计各文件的匹配行数（rg --count），比 rg 便宜得多
+    pub async fn count(
+        &self,
+        pattern: &str,
+        path: &str,
+        include: Option<&[String]>,
+        exclude: Option<&[String]>,
+        opts: &RgOptions,
+    ) -> String {
+        self.shared.collected.lock().unwrap().rg_patterns.push(pattern.to_string());
+        let rp = match self.locate("count", path).await {
+            Ok((p, Some(_))) => p,
+            Ok((_, None)) => return format!("Error: path does not exist: {}", path),
+            Err(e) => return e,
+        };
+        let paths: Vec<PathBuf> = match self.scoped_files(&rp) {
+            Some(files) if files.is_empty() => return "(no matches: outside the searched files)".into(),
+            Some(files) => files.into_iter().cloned().collect(),
+            None => vec![rp.clone()],
+        };
+        let globs = self.rg_globs(include, exclude);
+
+        let caps = crate::ripgrep::caps();
+        let max_filesize = self.search_max_filesize;
+        let (counts, note, skipped) = if let Some(why) = caps.unusable() {
+            let (pattern, search_opts, cancel) = (pattern.to_string(), opts.clone(), self.cancel.clone());
+            let found = tokio::task::spawn_blocking(move || {
+                crate::search_fallback::count(&paths, &pattern, &globs, &search_opts, max_filesize, &cancel)
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+            match found {
+                Ok((counts, skipped)) => (counts, Some(crate::search_fallback::note(&why)), skipped),
+                Err(e) => return format!("Error: {}", e),
+            }
+        } else {
+            let mut args = vec!["--count".to_string(), "--with-filename".to_string(), "--sort".to_string(), "path".to_string()];
+            for (flag, on) in [("-i", opts.case_insensitive), ("-F", opts.fixed_strings), ("-w", opts.word_boundary)] {
+                if on {
+                    args.push(flag.into());
+                }
+            }
+            let mut flags = if opts.fixed_strings { Vec::new() } else { caps.pattern_flags(pattern) };
+            if opts.multiline && caps.multiline && !flags.contains(&"-U") {
+                flags.push("-U");
+            }
+            args.extend(flags.into_iter().map(String::from));
+            args.extend(self.max_filesize_args());
+            for g in &globs {
                 args.push("--glob".into());
                 args.push(g.clone());
             }
+            args.push("-e".into());
+            args.push(pattern.to_string());
+            args.extend(paths.iter().map(|p| p.to_string_lossy().to_string()));
+            let (out, skipped) = tokio::join!(self.run_rg(&args), self.skipped(paths, globs));
+            let out = match out {
+                Ok(out) => out,
+                Err(e) => return format!("Error: {}", e),
+            };
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            if out.status.code() != Some(0) && stdout.is_empty() {
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                if out.status.code() != Some(1) && !stderr.is_empty() {
+                    return self.truncate(&self.remap(&stderr));
+                }
+                return self.with_skipped("(no matches)", skipped);
+            }
+            // "path:N"，路径本身可能含冒号，从右边拆
+            let counts = stdout
+                .lines()
+                .filter_map(|l| l.rsplit_once(':'))
+                .filter_map(|(p, n)| Some((PathBuf::from(p), n.parse().ok()?)))
+                .filter(|(p, _)| !self.denied(p))
+                .collect();
+            (counts, None, skipped)
+        };
+        if counts.is_empty() {
+            return self.with_skipped("(no matches)", skipped);
+        }
+
+        let total: usize = counts.iter().map(|(_, n)| n).sum();
+        let mut lines: Vec<String> = note.into_iter().chain(skipped.note(max_filesize)).collect();
+        let plural = |n: usize, word: &str| format!("{} {}{}", n, word, if n == 1 { "" } else { "s" });
+        lines.push(format!("{} in {}", plural(total, "matching line"), plural(counts.len(), "file")));
+        lines.extend(counts.iter().map(|(p, n)| format!("{}:{}", self.remap(&p.to_string_lossy()), n)));
+        self.truncate(&lines.join("\n"))
+    }
+
+    /// 只读 git：log --oneline / diff --stat / show / blame -L，在项目根目录下执行
+    pub async fn git(
+        &self,
+        subcommand: &str,
+        rev: Option<&str>,
+        file: Option<&str>,
+        lines: Option<(usize, usize)>,
+        max_count: Option<usize>,
+    ) -> String {
+        if let Some(r) = rev {
+            // 只接受 ref / 范围 / rev:path 写法，不接受选项和空白
+            let valid = r.chars().all(|c| c.is_ascii_alphanumeric() || "._/~^@{}:-".contains(c));
+            if r.is_empty() || !valid || git::check_ref(r).is_err() {
+                return format!("Error: invalid git ref: {}", r);
+            }
+            // rev:path 直接读取对象内容，同样受 deny_paths 限制
+            if let Some((_, p)) = r.split_once(':').filter(|(_, p)| !p.is_empty()) {
+                if self.denied(&self.root.join(p)) {
+                    return format!("Error: path denied by policy: {}", p);
+                }
+            }
+        }
+        let rel = match file {
+            Some(f) => match self.locate("git", f).await.map(|(rp, _)| rp) {
+                Ok(rp) => match rp.strip_prefix(&self.root) {
+                    Ok(rel) => Some(rel.to_string_lossy().to_string()),
+                    Err(_) => return format!("Error: path outside project root: {}", f),
+                },
+                Err(e) => return e,
+            },
+            None => None,
+        };
+
+        let mut args: Vec<String> = match subcommand {
+            "log" => {
+                let n = max_count.unwrap_or(GIT_LOG_DEFAULT).clamp(1, GIT_LOG_MAX);
+                vec!["log".into(), "--oneline".into(), "--no-color".into(), format!("-n{}", n)]
+            }
+            "diff" => vec!["diff".into(), "--stat".into(), "--no-color".into()],
+            "show" => vec!["show".into(), "--stat".into(), "--patch".into(), "--no-color".into()],
+            "blame" => {
+                let Some(rel) = &rel else { return "Error: git blame requires file".into() };
+                let Some((start, end)) = lines.filter(|(s, e)| *s >= 1 && e >= s) else {
+                    return "Error: git blame requires start_line <= end_line (1-indexed)".into();
+                };
+                let end = end.min(start + GIT_BLAME_MAX_LINES - 1);
+                let mut args: Vec<String> = vec!["blame".into(), "--date=short".into(), "-L".into(), format!("{},{}", start, end)];
+                args.extend(rev.map(str::to_string));
+                args.extend(["--".to_string(), rel.clone()]);
+                return self.run_git(args).await;
+            }
+            _ => return format!("Error: unsupported git subcommand '{}' (log, diff, show, blame)", subcommand),
+        };
+        args.extend(rev.map(str::to_string));
+        let deny = crate::deny_paths::get();
+        if let Some(rel) = rel {
+            args.extend(["--".to_string(), rel]);
+        } else if !deny.is_empty() {
+            args.extend(["--".to_string(), ".".to_string()]);
+            args.extend(deny.git_pathspecs());
+        }
+        self.run_git(args).await
+    }
+
+    /// 运行 git::run；取消时放弃（子进程随 future 终止）
+    async fn run_git(&self, args: Vec<String>) -> String {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let out = tokio::select! {
+            out = git::run(&self.root, &args) => out,
+            _ = self.cancel.cancelled() => Err("cancelled".into()),
+        };
+        match out {
+            Ok(text) if text.is_empty() => "(no output)".into(),
+            Ok(text) => self.truncate(&self.remap(&text)),
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    /// rg / files 的 glob：包含项、"!" 排除项、exclude_tests 时的测试文件排除项，以及 deny_paths 的排除项
+    fn rg_globs(&self, include: Option<&[String]>, exclude: Option<&[String]>) -> Vec<String> {
+        let mut globs: Vec<String> = include.unwrap_or_default().to_vec();
+        globs.extend(exclude.unwrap_or_default().iter().map(|g| format!("!{}", g)));
+        if self.exclude_tests {
+            globs.extend(lang::TEST_GLOBS.iter().map(|g| format!("!{}", g)));
+        }
+        globs.extend(crate::deny_paths::get().rg_globs());
+        globs
+    }
+
+    /// rg 不可用时的内置搜索
+    async fn builtin_rg(
+        &self,
+        why: &str,
+        pattern: &str,
+        paths: Vec<PathBuf>,
+        include: Option<&[String]>,
+        exclude: Option<&[String]>,
+        opts: &RgOptions,
+    ) -> String {
+        let globs = self.rg_globs(include, exclude);
+        let (pattern, search_opts, cancel) = (pattern.to_string(), opts.clone(), self.cancel.clone());
+        let max_filesize = self.search_max_filesize;
+        let found = tokio::task::spawn_blocking(move || {
+            crate::search_fallback::search(&paths, &pattern, &globs, &search_opts, RG_MAX_COUNT, max_filesize, &cancel)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+        match found {
+            Ok((mut lines, skipped)) => {
+                lines.retain(|l| !self.denied(Path::new(&l.path)));
+                if lines.is_empty() {
+                    return self.with_skipped("(no matches)", skipped);
+                }
+                let text = self.with_skipped(&self.remap(&self.render_rg(lines, opts)), skipped);
+                self.truncate(&format!("{}\n{}", crate::search_fallback::note(why), text))
+            }
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    /// 渲染 rg / 内置搜索的结果，并记录输出中的匹配
+    fn render_rg(&self, lines: Vec<crate::rg_results::RgLine>, opts: &RgOptions) -> String {
+        let (before, after) = opts.around();
+        // 给截断说明、内置搜索说明和跳过文件说明留出位置，末尾的汇总行不会被截掉
+        let rendered = crate::rg_results::render(lines, before + after > 0, self.limits.max_lines.saturating_sub(3).max(1));
+        let matches = rendered.matches.into_iter().filter_map(|l| {
+            let (column, text) = l.submatch?;
+            Some(RgMatch { path: self.remap(&l.path), line: l.line, column, text })
+        });
+        let matches: Vec<RgMatch> = matches.collect();
+        self.collect_matches(matches);
+        rendered.text
+    }
+
+    /// 记录匹配，同一 (path, line) 只保留一个
+    fn collect_matches(&self, matches: impl IntoIterator<Item = RgMatch>) {
+        let mut collected = self.shared.collected.lock().unwrap();
+        for m in matches {
+            if !collected.matches.iter().any(|c| c.path == m.path && c.line == m.line) {
+                collected.matches.push(m);
+            }
+        }
+    }
+
+    /// rg 不可用时的内置文件列表
+    async fn builtin_files(&self, why: &str, rp: &Path, glob: Option<&str>, max_count: Option<usize>) -> String {
+        let include: Vec<String> = glob.map(str::to_string).into_iter().collect();
+        let globs = self.rg_globs(Some(&include), None);
+        let (dir, cancel) = (rp.to_path_buf(), self.cancel.clone());
+        let found = tokio::task::spawn_blocking(move || crate::search_fallback::files(&dir, &globs, &cancel))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        let all = match found {
+            Ok(all) if all.is_empty() => return "(no files)".into(),
+            Ok(all) => all,
+            Err(e) => return format!("Error: {}", e),
+        };
+        let cap = max_count.unwrap_or(FILES_DEFAULT_MAX).clamp(1, self.limits.max_lines.saturating_sub(2).max(1));
+        let mut lines = vec![crate::search_fallback::note(why)];
+        lines.extend(all.iter().take(cap).map(|f| self.remap(&f.to_string_lossy())));
+        if all.len() > cap {
+            lines.push(format!("... ({} of {} files shown)", cap, all.len()));
+        }
+        self.truncate(&lines.join("\n"))
+    }
+
+    /// 读取文件
+    pub fn readfile(&self, file: &str, start_line: Option<usize>, end_line: Option<usize>) -> String {
+        self.readfile_ranges(file, &[(start_line, end_line)], None)
+    }
+
+    /// 读取文件的多个行范围（1 起，闭区间），以及 around_symbol 指定的函数/类的完整定义。
+    /// 重叠或相邻的范围合并，不相邻的片段之间用 `--` 分隔；超出行数上限时各片段公平分配
+    pub fn readfile_ranges(&self, file: &str, ranges: &[(Option<usize>, Option<usize>)], around_symbol: Option<&str>) -> String {
+        if ranges.len() > READFILE_MAX_RANGES {
+            return format!("Error: at most {} ranges per readfile", READFILE_MAX_RANGES);
+        }
+        let rp = match self.access("readfile", file) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let decoded = match read_text(&rp, file) {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+        let content = &decoded.text;
+
+        let lines: Vec<&str> = content.lines().collect();
+        // 0 起的左闭右开区间；起始行超出文件末尾时为空，而不是越界
+        let mut spans: Vec<(usize, usize)> = ranges
+            .iter()
+            .map(|(start, end)| {
+                let e = end.unwrap_or(lines.len()).min(lines.len());
+                (start.unwrap_or(1).saturating_sub(1).min(e), e)
+            })
+            .collect();
+        if let Some(symbol) = around_symbol {
+            if !outline::is_supported(&rp) {
+                return format!("Error: unsupported language: {}", file);
+            }
+            // Type::method / Class.method 按最后一段匹配
+            let name = symbol.rsplit([':', '.']).next().unwrap_or(symbol);
+            let blocks = outline::symbols(&rp, content).unwrap_or_default();
+            let found: Vec<_> = blocks.iter().filter(|b| b.name.as_deref() == Some(name)).collect();
+            if found.is_empty() {
+                return format!("Error: symbol not found in {}: {}", file, symbol);
+            }
+            spans.extend(found.iter().map(|b| (b.start_line - 1, b.end_line.min(lines.len()))));
+        }
+
+        spans.sort();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (s, e) in spans {
+            match merged.last_mut() {
+                Some(last) if s <= last.1 => last.1 = last.1.max(e),
+                _ => merged.push((s, e)),
+            }
+        }
+        let numbered = |s: usize, e: usize| lines[s..e].iter().enumerate().map(move |(i, line)| format!("{}:{}", s + i + 1, line));
+        if let [(s, e)] = merged[..] {
+            return self.truncate(&with_note(decoded.note(), numbered(s, e).collect::<Vec<_>>().join("\n")));
+        }
+
+        // 给分隔行和截断说明留出位置
+        let sizes: Vec<usize> = merged.iter().map(|(s, e)| e - s).collect();
+        let shares = fair_shares(&sizes, self.limits.max_lines.saturating_sub(2 * merged.len()).max(merged.len()));
+        let mut out: Vec<String> = Vec::new();
+        for ((s, e), share) in merged.into_iter().zip(shares) {
+            if !out.is_empty() {
+                out.push("--".into());
+            }
+            let shown = share.max(1).min(e - s);
+            out.extend(numbered(s, s + shown));
+            if s + shown < e {
+                out.push(format!("... (lines {}-{} not shown)", s + shown + 1, e));
+            }
+        }
+        self.truncate(&with_note(decoded.note(), out.join("\n")))
+    }
+
+    /// 文件开头（head）或末尾（tail）的 n 行，注明在全文中的位置
+    pub fn head_tail(&self, file: &str, n: Option<usize>, from_end: bool) -> String {
+        let rp = match self.access(if from_end { "tail" } else { "head" }, file) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let decoded = match read_text(&rp, file) {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+        let lines: Vec<&str> = decoded.text.lines().collect();
+        if lines.is_empty() {
+            return "(empty file)".into();
+        }
+        // 位置说明和编码说明各占一行，留出余量，否则 truncate 会从窗口开头保留、把 tail 的末尾截掉
+        let reserved = 1 + usize::from(decoded.encoding.is_some());
+        let n = n.unwrap_or(HEAD_TAIL_DEFAULT_LINES).min(self.limits.max_lines.saturating_sub(reserved)).clamp(1, lines.len());
+        let s = if from_end { lines.len() - n } else { 0 };
+        let e = s + n;
+
+        let mut out: Vec<String> = lines[s..e].iter().enumerate().map(|(i, line)| format!("{}:{}", s + i + 1, line)).collect();
+        if e - s < lines.len() {
+            let position = format!("... (lines {}-{} of {})", s + 1, e, lines.len());
+            // 说明放在省略的一侧，截断时也不会丢失
+            if from_end { out.insert(0, position) } else { out.push(position) }
+        }
+        self.truncate(&with_note(decoded.note(), out.join("\n")))
+    }
+
+    /// 文件内过滤读取：只返回匹配行及其上下文
+    pub fn readmatch(&self, file: &str, pattern: &str, context: Option<usize>) -> String {
+        let rp = match self.access("readmatch", file) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let decoded = match read_text(&rp, file) {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+        let content = &decoded.text;
+
+        // 非法正则按字面量处理
+        let re = match regex_lite::Regex::new(pattern) {
+            Ok(r) => r,
+            Err(_) => match regex_lite::Regex::new(&regex_lite::escape(pattern)) {
+                Ok(r) => r,
+                Err(e) => return format!("Error: invalid pattern: {}", e),
+            },
+        };
+
+        let ctx = context.unwrap_or(READMATCH_DEFAULT_CONTEXT).min(READMATCH_MAX_CONTEXT);
+        let lines: Vec<&str> = content.lines().collect();
+        let hits: Vec<usize> = lines.iter().enumerate()
+            .filter(|(_, l)| re.is_match(l))
+            .map(|(i, _)| i)
+            .collect();
+        if hits.is_empty() {
+            return "(no matches)".into();
+        }
+
+        // 合并相邻/重叠的上下文窗口
+        let mut out = Vec::new();
+        let mut last_end: Option<usize> = None;
+        for &h in &hits {
+            let s = h.saturating_sub(ctx);
+            let e = (h + ctx + 1).min(lines.len());
+            let from = match last_end {
+                Some(le) if s <= le => le,
+                Some(_) => { out.push("--".to_string()); s }
+                None => s,
+            };
+            for (i, line) in lines.iter().enumerate().take(e).skip(from) {
+                let sep = if hits.binary_search(&i).is_ok() { ':' } else { '-' };
+                out.push(format!("{}{}{}", i + 1, sep, line));
+            }
+            last_end = Some(e.max(from));
+        }
+
+        self.truncate(&with_note(decoded.note(), out.join("\n")))
+    }
+
+    /// 读取包含某一行的完整函数/类/impl 块
+    pub fn enclosing(&self, file: &str, line: usize) -> String {
+        let rp = match self.access("enclosing", file) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let decoded = match read_text(&rp, file) {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+        let content = &decoded.text;
+        if !outline::is_supported(&rp) {
+            return format!("Error: unsupported language: {}", file);
+        }
+
+        let block = match outline::enclosing_block(&rp, content, line) {
+            Some(b) => b,
+            None => return format!("(no enclosing block at line {})", line),
+        };
+
+        let header = format!(
+            "{}:{}-{} ({}{})",
+            file, block.start_line, block.end_line, block.kind,
+            block.name.as_deref().map(|n| format!(" {}", n)).unwrap_or_default()
+        );
+        let body: Vec<String> = content.lines()
+            .enumerate()
+            .skip(block.start_line - 1)
+            .take(block.end_line - block.start_line + 1)
+            .map(|(i, l)| format!("{}:{}", i + 1, l))
+            .collect();
+
+        self.truncate(&with_note(decoded.note(), format!("{}\n{}", header, body.join("\n"))))
+    }
+
+    /// 提取文件的 import/include/use 语句
+    pub fn imports(&self, file: &str) -> String {
+        let rp = match self.access("imports", file) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+
+        let decoded = match read_text(&rp, file) {
+            Ok(d) => d,
+            Err(e) => return e,
+        };
+        let content = &decoded.text;
+
+        // 优先 tree-sitter，其次按语言正则逐行匹配
+        let found = match outline::imports(&rp, content) {
+            Some(v) => v,
+            None => {
+                let pat = match lang::detect_language(&rp).and_then(lang::import_pattern) {
+                    Some(p) => p,
+                    None => return format!("Error: unsupported language: {}", file),
+                };
+                let re = match regex_lite::Regex::new(pat) {
+                    Ok(r) => r,
+                    Err(e) => return format!("Error: {}", e),
+                };
+                content.lines().enumerate()
+                    .filter(|(_, l)| re.is_match(l))
+                    .map(|(i, l)| (i + 1, l.trim().to_string()))
+                    .collect()
+            }
+        };
+
+        if found.is_empty() {
+            return "(no imports)".into();
+        }
+        let lines: Vec<String> = found.iter().map(|(n, t)| format!("{}:{}", n, t)).collect();
+        self.truncate(&with_note(decoded.note(), lines.join("\n")))
+    }
+
+    /// 列出或搜索压缩包 (zip/jar/wheel) 内容
+    pub fn archive(&self, file: &str, pattern: Option<&str>) -> String {
+        if !archive::enabled() {
+            return "Error: archive command is disabled (set ARCHIVE_SEARCH=1)".into();
+        }
+        let rp = match self.access("archive", file) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        if !rp.is_file() {
+            return format!("Error: file not found: {}", file);
         }
-        if let Some(exc) = exclude {
-            for g in exc {
-                args.push("--glob".into());
-                args.push(format!("!{}", g));
-            }
+        if !archive::is_archive(&rp) {
+            return format!("Error: not a supported archive: {}", file);
         }
 
-        let root_str = self.root.to_string_lossy().to_string();
-        // 尝试找到 rg 二进制
-        let rg_bin = find_rg_binary();
-
-        let result = task::spawn_blocking(move || {
-            let output = Command::new(&rg_bin)
-                .args(&args)
-                .output();
-
-            match output {
-                Ok(out) => {
-                    let stdout = String::from_utf8_lossy(&out.stdout);
-                    let stderr = String::from_utf8_lossy(&out.stderr);
-
-                    if out.status.success() || out.status.code() == Some(0) {
-                        let text = if stdout.is_empty() { "(no matches)".into() } else { stdout.to_string() };
-                        Self::truncate(&text.replace(&root_str, "/codebase"))
-                    } else if out.status.code() == Some(1) {
-                        "(no matches)".into()
-                    } else if !stderr.is_empty() {
-                        Self::truncate(&stderr.replace(&root_str, "/codebase"))
-                    } else {
-                        "(no matches)".into()
-                    }
+        match pattern.filter(|p| !p.is_empty()) {
+            None => match archive::list_entries(&rp) {
+                Ok(entries) if entries.is_empty() => "(empty archive)".into(),
+                Ok(entries) => {
+                    let mut lines = vec![format!("{} ({} entries)", file, entries.len())];
+                    lines.extend(entries.iter().map(|(name, size)| format!("{:>10} {}", size, name)));
+                    self.truncate(&lines.join("\n"))
                 }
                 Err(e) => format!("Error: {}", e),
+            },
+            Some(p) => {
+                let re = match regex_lite::Regex::new(p) {
+                    Ok(r) => r,
+                    Err(_) => match regex_lite::Regex::new(&regex_lite::escape(p)) {
+                        Ok(r) => r,
+                        Err(e) => return format!("Error: invalid pattern: {}", e),
+                    },
+                };
+                match archive::grep_entries(&rp, &re, self.limits.max_lines + 1) {
+                    Ok(hits) if hits.is_empty() => "(no matches)".into(),
+                    Ok(hits) => self.truncate(&hits.join("\n")),
+                    Err(e) => format!("Error: {}", e),
+                }
             }
-        }).await.unwrap_or_else(|e| format!("Error: {}", e));
-
-        result
+        }
     }
 
-    /// 读取文件
-    pub fn readfile(&self, file: &str, start_line: Option<usize>, end_line: Option<usize>) -> String {
-        let rp = self.real_path(file);
-
-        let content = match std::fs::read_to_string(&rp) {
-            Ok(c) => c,
-            Err(_) => return format!("Error: file not found: {}", file),
+    /// 文件元信息
+    pub fn stat(&self, file: &str) -> String {
+        let rp = match self.access("stat", file) {
+            Ok(p) => p,
+            Err(e) => return e,
         };
+        let meta = match std::fs::metadata(&rp) {
+            Ok(m) if m.is_file() => m,
+            _ => return format!("Error: file not found: {}", file),
+        };
+        // 只读开头判断生成文件，行数流式统计，大文件也不整个读入内存
+        let (head, line_count) = match count_lines(&rp) {
+            Ok(r) => r,
+            Err(e) => return format!("Error: {}", e),
+        };
+        let binary = crate::search_fallback::is_binary(&rp);
+        let content = String::from_utf8_lossy(&head);
 
-        let lines: Vec<&str> = content.lines().collect();
-        let s = start_line.unwrap_or(1).saturating_sub(1);
-        let e = end_line.unwrap_or(lines.len()).min(lines.len());
-
-        let numbered: Vec<String> = lines[s..e]
-            .iter()
-            .enumerate()
-            .map(|(i, line)| format!("{}:{}", s + i + 1, line))
-            .collect();
+        let mtime = meta.modified().ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| format_unix_time(d.as_secs()))
+            .unwrap_or_else(|| "unknown".into());
 
-        Self::truncate(&numbered.join("\n"))
+        let lines = [
+            file.to_string(),
+            format!("size: {} bytes", meta.len()),
+            format!("mtime: {}", mtime),
+            format!("lines: {}", if binary { "-".to_string() } else { line_count.to_string() }),
+            format!("language: {}", lang::detect_language(&rp).unwrap_or("unknown")),
+            format!("binary: {}", if binary { "yes" } else { "no" }),
+            format!("generated: {}", if binary { "no" } else { lang::generated_reason(&rp, &content).unwrap_or("no") }),
+        ];
+        lines.join("\n")
     }
 
     /// 目录树
-    pub fn tree(&self, path: &str, levels: Option<usize>) -> String {
-        let rp = self.real_path(path);
+    pub fn tree(&self, path: &str, levels: Option<usize>, sort: SortKey, max_entries: Option<usize>) -> String {
+        let rp = match self.access("tree", path) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
         if !rp.is_dir() {
             return format!("Error: dir not found: {}", path);
         }
 
+        let opts = TreeOptions { max_depth: levels.unwrap_or(3), sort, max_entries };
         let mut lines = vec![path.to_string()];
-        self.tree_walk(&rp, "", levels.unwrap_or(3), 0, &mut lines);
-        Self::truncate(&self.remap(&lines.join("\n")))
+        self.tree_walk(&rp, "", &opts, 0, &mut lines, &mut WalkGuard::new(&self.root));
+        self.truncate(&self.remap(&lines.join("\n")))
     }
 
-    fn tree_walk(&self, dir: &Path, prefix: &str, max_depth: usize, depth: usize, lines: &mut Vec<String>) {
-        if depth >= max_depth { return; }
+    fn tree_walk(&self, dir: &Path, prefix: &str, opts: &TreeOptions, depth: usize, lines: &mut Vec<String>, guard: &mut WalkGuard) {
+        if depth >= opts.max_depth { return; }
         if lines.len() > 500 { return; } // 安全限制
 
-        let mut entries: Vec<_> = match std::fs::read_dir(dir) {
-            Ok(rd) => rd.filter_map(|e| e.ok()).collect(),
-            Err(_) => return,
-        };
-        entries.sort_by_key(|e| e.file_name());
+        let mut entries = guard.list_dir(dir, &[]);
+        sort_entries(&mut entries, opts.sort);
 
-        let count = entries.len();
-        for (i, entry) in entries.iter().enumerate() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            if name.starts_with('.') { continue; }
+        let total = entries.len();
+        let shown = opts.max_entries.map_or(total, |m| m.min(total));
+        let omitted = total - shown;
+        for (i, path) in entries.iter().take(shown).enumerate() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
 
-            let is_last = i == count - 1;
+            let is_last = i == shown - 1 && omitted == 0;
             let connector = if is_last { "└── " } else { "├── " };
-            lines.push(format!("{}{}{}", prefix, connector, name));
+            let is_sub = self.submodules.is_submodule(path);
+            lines.push(format!("{}{}{}{}", prefix, connector, name, if is_sub { " [submodule]" } else { "" }));
 
-            if entry.path().is_dir() {
+            if path.is_dir() && (!is_sub || self.submodules.include) && guard.enter(path) {
                 let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
-                self.tree_walk(&entry.path(), &new_prefix, max_depth, depth + 1, lines);
+                self.tree_walk(path, &new_prefix, opts, depth + 1, lines, guard);
             }
         }
+        if omitted > 0 {
+            lines.push(format!("{}└── ... ({} more entries omitted)", prefix, omitted));
+        }
     }
 
     /// 列出目录
-    pub fn ls(&self, path: &str, long_format: bool, all: bool) -> String {
-        let rp = self.real_path(path);
-        let entries = match std::fs::read_dir(&rp) {
-            Ok(rd) => {
-                let mut v: Vec<String> = rd
-                    .filter_map(|e| e.ok())
-                    .map(|e| e.file_name().to_string_lossy().to_string())
-                    .filter(|n| all || !n.starts_with('.'))
-                    .collect();
-                v.sort();
-                v
-            }
+    pub fn ls(&self, path: &str, long_format: bool, all: bool, sort: SortKey, max_entries: Option<usize>) -> String {
+        let rp = match self.access("ls", path) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let mut entries: Vec<PathBuf> = match std::fs::read_dir(&rp) {
+            Ok(rd) => rd.filter_map(|e| e.ok())
+                .filter(|e| all || !e.file_name().to_string_lossy().starts_with('.'))
+                .map(|e| e.path())
+                .filter(|p| !crate::deny_paths::get().matches(p))
+                .collect(),
             Err(_) => return format!("Error: dir not found: {}", path),
         };
+        sort_entries(&mut entries, sort);
+
+        let total = entries.len();
+        let shown = max_entries.map_or(total, |m| m.min(total));
+        let omitted_marker = (shown < total).then(|| format!("... ({} more entries omitted)", total - shown));
+        let names: Vec<String> = entries.iter()
+            .take(shown)
+            .map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string())
+            .collect();
 
         if !long_format {
-            return Self::truncate(&entries.join("\n"));
+            let mut lines = names;
+            lines.extend(omitted_marker);
+            return self.truncate(&lines.join("\n"));
         }
 
-        let mut lines = vec![format!("total {}", entries.len())];
-        for name in &entries {
+        // 类型 + 权限、大小、修改时间、名称（目录带 `/`）；符号链接按链接本身显示
+        let mut lines = vec![format!("total {}", total)];
+        for name in &names {
             let fp = rp.join(name);
-            if let Ok(meta) = std::fs::metadata(&fp) {
-                let t = if meta.is_dir() { "d" } else { "-" };
-                lines.push(format!("{}rwxr-xr-x {:>8} {}", t, meta.len(), name));
+            if let Ok(meta) = std::fs::symlink_metadata(&fp) {
+                let t = if meta.is_symlink() { 'l' } else if meta.is_dir() { 'd' } else { '-' };
+                let mtime = meta.modified().ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| format_unix_time(d.as_secs()))
+                    .unwrap_or_else(|| "unknown".into());
+                let slash = if meta.is_dir() { "/" } else { "" };
+                lines.push(format!("{}{} {:>8} {} {}{}", t, mode_string(&meta), meta.len(), mtime, name, slash));
             }
         }
-        Self::truncate(&self.remap(&lines.join("\n")))
+        lines.extend(omitted_marker);
+        self.truncate(&self.remap(&lines.join("\n")))
     }
 
     /// glob 匹配
     pub fn glob(&self, pattern: &str, path: &str, type_filter: Option<&str>) -> String {
-        let rp = self.real_path(path);
+        let rp = match self.access("glob", path) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let glob = match PathGlob::new(pattern) {
+            Ok(g) => g,
+            Err(e) => return format!("Error: invalid glob pattern {}: {}", pattern, e),
+        };
         let mut matches = Vec::new();
-        self.glob_walk(&rp, pattern, type_filter.unwrap_or("all"), &mut matches, 0);
+        self.glob_walk(&rp, &rp, &glob, type_filter.unwrap_or("all"), &mut matches, 0, &mut WalkGuard::new(&self.root));
 
         if matches.is_empty() {
             return "(no matches)".into();
@@ -235,21 +1422,17 @@ impl ToolExecutor {
         out.join("\n")
     }
 
-    fn glob_walk(&self, dir: &Path, pattern: &str, type_filter: &str, matches: &mut Vec<PathBuf>, depth: usize) {
+    /// 按相对 base 的路径匹配；只进入模式还可能匹配到其下条目的目录
+    #[allow(clippy::too_many_arguments)]
+    fn glob_walk(&self, base: &Path, dir: &Path, glob: &PathGlob, type_filter: &str, matches: &mut Vec<PathBuf>, depth: usize, guard: &mut WalkGuard) {
         if matches.len() >= 100 || depth > 10 { return; }
 
-        let entries = match std::fs::read_dir(dir) {
-            Ok(rd) => rd,
-            Err(_) => return,
-        };
-
-        for entry in entries.filter_map(|e| e.ok()) {
+        for fp in guard.list_dir(dir, &[]) {
             if matches.len() >= 100 { return; }
-            let name = entry.file_name().to_string_lossy().to_string();
-            let fp = entry.path();
+            let Ok(rel) = fp.strip_prefix(base) else { continue };
+            let is_dir = fp.is_dir();
 
-            if simple_glob_match(&name, pattern) {
-                let is_dir = fp.is_dir();
+            if glob.is_match(rel) {
                 let ok = match type_filter {
                     "file" => !is_dir,
                     "directory" => is_dir,
@@ -258,17 +1441,162 @@ impl ToolExecutor {
                 if ok { matches.push(fp.clone()); }
             }
 
-            if fp.is_dir() && !name.starts_with('.') && pattern.contains("**") {
-                self.glob_walk(&fp, pattern, type_filter, matches, depth + 1);
+            let opaque = !self.submodules.include && self.submodules.is_submodule(&fp);
+            if is_dir && !opaque && glob.may_contain(rel) && guard.enter(&fp) {
+                self.glob_walk(base, &fp, glob, type_filter, matches, depth + 1, guard);
             }
         }
     }
 
-    /// 执行单个命令
-    pub async fn exec_command(&mut self, cmd: &serde_json::Value) -> String {
-        let cmd_type = cmd.get("type").and_then(|t| t.as_str()).unwrap_or("");
+    /// 语言/行数统计（tokei 风格）
+    pub fn stats(&self, path: &str) -> String {
+        let rp = match self.access("stats", path) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        if !rp.is_dir() {
+            return format!("Error: dir not found: {}", path);
+        }
 
-        match cmd_type {
+        let summary = language_stats(&rp);
+        if summary.languages.is_empty() {
+            return "(no files)".into();
+        }
+
+        let mut lines = vec![
+            format!("{} ({} files, {} lines)", path, summary.total_files, summary.total_lines),
+            format!("{:<14} {:>8} {:>10}", "Language", "Files", "Lines"),
+        ];
+        for s in &summary.languages {
+            lines.push(format!("{:<14} {:>8} {:>10}", s.language, s.files, s.lines));
+        }
+        if summary.partial {
+            lines.push(format!("(partial: stopped after {} files)", STATS_MAX_FILES));
+        }
+        self.truncate(&lines.join("\n"))
+    }
+
+    /// 启发式查找应用入口：按文件名找 Dockerfile/Procfile/清单文件，rg 找 main 函数、服务启动和
+    /// CLI 定义，再用 outline 扩展为所在函数的完整范围。第二项为跳过内容搜索时的说明
+    pub async fn entrypoints(&self, path: &str) -> (Vec<Entrypoint>, Option<String>) {
+        let rp = match self.locate("entrypoints", path).await {
+            Ok((p, _)) => p,
+            Err(e) => return (Vec::new(), Some(e.trim_start_matches("Error: ").to_string())),
+        };
+        // 遍历、读取文件和解析都在阻塞线程上执行
+        let this = self.clone();
+        let found = tokio::task::spawn_blocking(move || this.entry_files(&rp)).await.unwrap_or_default();
+
+        let pattern = ENTRY_PATTERNS.iter().map(|(_, p)| *p).collect::<Vec<_>>().join("|");
+        let include: Vec<String> = ENTRY_SOURCE_GLOBS.iter().map(|g| g.to_string()).collect();
+        let hits = self.rg(&pattern, path, Some(&include), None, &RgOptions::default()).await;
+        if let Some(err) = hits.strip_prefix("Error: ") {
+            return (found, Some(format!("content search skipped: {}", err)));
+        }
+        let this = self.clone();
+        let mut found = tokio::task::spawn_blocking(move || this.add_source_entries(&hits, found)).await.unwrap_or_default();
+        found.truncate(ENTRY_MAX);
+        (found, None)
+    }
+
+    /// 按文件名识别的入口（Dockerfile、Procfile、清单文件等）
+    fn entry_files(&self, rp: &Path) -> Vec<Entrypoint> {
+        let mut found = Vec::new();
+        walk_files(rp, |fp, meta| {
+            let name = fp.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            if meta.len() > ENTRY_FILE_MAX_BYTES || !ENTRY_FILES.iter().any(|p| simple_glob_match(&name, p)) {
+                return true;
+            }
+            let Ok(content) = std::fs::read_to_string(fp) else { return true };
+            found.extend(entry_file_lines(&name, &content).into_iter().map(|(start, end, kind, detail)| Entrypoint {
+                path: self.remap(&fp.to_string_lossy()),
+                start,
+                end,
+                kind,
+                detail,
+            }));
+            found.len() < ENTRY_MAX
+        });
+        found
+    }
+
+    /// rg 命中的 main 函数、服务启动和 CLI 定义，扩展为所在代码块
+    fn add_source_entries(&self, hits: &str, mut found: Vec<Entrypoint>) -> Vec<Entrypoint> {
+        let classifiers: Vec<(&'static str, regex_lite::Regex)> = ENTRY_PATTERNS
+            .iter()
+            .filter_map(|(kind, p)| regex_lite::Regex::new(p).ok().map(|re| (*kind, re)))
+            .collect();
+        for hit in hits.lines() {
+            let mut parts = hit.splitn(3, ':');
+            let (Some(file), Some(line), Some(text)) = (parts.next(), parts.next(), parts.next()) else { continue };
+            let Ok(line) = line.parse::<usize>() else { continue };
+            let Some((kind, _)) = classifiers.iter().find(|(_, re)| re.is_match(text)) else { continue };
+            let (start, end) = self.entry_range(file, line, kind);
+            if found.iter().any(|e: &Entrypoint| e.path == file && e.start <= line && line <= e.end) {
+                continue;
+            }
+            found.push(Entrypoint { path: file.to_string(), start, end, kind, detail: entry_detail(text) });
+        }
+        found
+    }
+
+    /// main 函数取完整函数体，`if __name__ == "__main__"` 取其缩进块，其余取所在函数（过长时只取该行）
+    fn entry_range(&self, file: &str, line: usize, kind: &str) -> (usize, usize) {
+        let Ok(rp) = self.real_path(file) else { return (line, line) };
+        let Ok(source) = std::fs::read_to_string(&rp) else { return (line, line) };
+        let lines: Vec<&str> = source.lines().collect();
+        if lines.get(line - 1).is_some_and(|l| l.contains("__main__")) {
+            let end = (line..lines.len())
+                .take_while(|&i| lines[i].trim().is_empty() || lines[i].starts_with([' ', '\t']))
+                .last()
+                .map(|i| i + 1)
+                .unwrap_or(line);
+            return (line, end);
+        }
+        // 属性/装饰器行取它修饰的定义
+        let decorated = lines.get(line - 1).is_some_and(|l| l.trim_start().starts_with(['#', '@']));
+        let target = if decorated { line + 1 } else { line };
+        match outline::enclosing_block(&rp, &source, target) {
+            Some(b) if kind == "main" || b.end_line - b.start_line < ENTRY_MAX_BLOCK_LINES => (b.start_line.min(line), b.end_line),
+            _ => (line, line),
+        }
+    }
+
+    /// 单文件输出：文件内容中的根目录路径同样替换为 /codebase（与 rg 结果一致），再按 max_file_bytes 截断
+    fn cap_file_output(&self, text: String) -> String {
+        let text = self.remap(&text);
+        match self.max_file_bytes {
+            Some(max) => truncate_bytes(&text, max),
+            None => text,
+        }
+    }
+
+    /// 执行单个命令；超过 timeouts.command 时放弃并返回超时错误
+    pub async fn exec_command(&self, cmd: &serde_json::Value) -> String {
+        // 记录读取过的文件；越界或被禁止的路径不记录
+        if matches!(cmd.get("type").and_then(|t| t.as_str()), Some("readfile") | Some("head") | Some("tail") | Some("readmatch") | Some("enclosing")) {
+            if let Some(f) = cmd.get("file").and_then(|f| f.as_str()).filter(|f| self.real_path(f).is_ok()) {
+                self.shared.collected.lock().unwrap().files.push(f.to_string());
+            }
+        }
+        let Some(limit) = self.timeouts.command else {
+            return self.run_command(cmd).await;
+        };
+        // 超时后取消：rg 子进程随 future 一起终止，内置搜索检查取消标记后退出
+        let mut this = self.clone();
+        this.cancel = self.cancel.child_token();
+        let out = tokio::time::timeout(limit, this.run_command(cmd)).await;
+        match out {
+            Ok(text) => text,
+            Err(_) => {
+                this.cancel.cancel();
+                format!("Error: timed out after {}s", limit.as_secs_f32())
+            }
+        }
+    }
+
+    async fn run_command(&self, cmd: &serde_json::Value) -> String {
+        match cmd.get("type").and_then(|t| t.as_str()).unwrap_or("") {
             "rg" => {
                 let pattern = cmd.get("pattern").and_then(|p| p.as_str()).unwrap_or("");
                 let path = cmd.get("path").and_then(|p| p.as_str()).unwrap_or("/codebase");
@@ -279,24 +1607,148 @@ impl ToolExecutor {
                     .and_then(|v| v.as_array())
                     .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect());
 
-                self.rg(pattern, path, include.as_deref(), exclude.as_deref()).await
+                self.rg(pattern, path, include.as_deref(), exclude.as_deref(), &RgOptions::from_json(cmd)).await
+            }
+            "rg_files" => {
+                let pattern = cmd.get("pattern").and_then(|p| p.as_str()).unwrap_or("");
+                let files: Vec<String> = cmd.get("files")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                    .unwrap_or_default();
+                let include: Option<Vec<String>> = cmd.get("include")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect());
+                let exclude: Option<Vec<String>> = cmd.get("exclude")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect());
+
+                self.rg_files(pattern, &files, include.as_deref(), exclude.as_deref(), &RgOptions::from_json(cmd)).await
+            }
+            "count" => {
+                let pattern = cmd.get("pattern").and_then(|p| p.as_str()).unwrap_or("");
+                let path = cmd.get("path").and_then(|p| p.as_str()).unwrap_or("/codebase");
+                let include: Option<Vec<String>> = cmd.get("include")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect());
+                let exclude: Option<Vec<String>> = cmd.get("exclude")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect());
+
+                self.count(pattern, path, include.as_deref(), exclude.as_deref(), &RgOptions::from_json(cmd)).await
+            }
+            "files" => {
+                let path = cmd.get("path").and_then(|p| p.as_str()).unwrap_or("/codebase");
+                let glob = cmd.get("glob").and_then(|v| v.as_str());
+                let max = cmd.get("max_count").and_then(|v| v.as_u64()).map(|v| v as usize);
+                self.files(path, glob, max).await
+            }
+            "git" => {
+                let str_arg = |key: &str| cmd.get(key).and_then(|v| v.as_str()).filter(|v| !v.is_empty());
+                let num = |key: &str| cmd.get(key).and_then(|v| v.as_u64()).map(|v| v as usize);
+                let lines = num("start_line").map(|s| (s, num("end_line").unwrap_or(s)));
+                self.git(str_arg("subcommand").unwrap_or(""), str_arg("ref"), str_arg("file"), lines, num("max_count")).await
+            }
+            // 其余命令是同步文件操作，放到阻塞线程上执行：慢速文件系统不会卡住运行时，超时也才能生效
+            _ => {
+                let (this, cmd) = (self.clone(), cmd.clone());
+                tokio::task::spawn_blocking(move || this.run_cached_command(&cmd))
+                    .await
+                    .unwrap_or_else(|e| format!("Error: {}", e))
+            }
+        }
+    }
+
+    /// run_sync_command，可缓存的命令先查缓存
+    fn run_cached_command(&self, cmd: &serde_json::Value) -> String {
+        let cache_key = self.cache_key(cmd);
+        if let Some((key, stamp)) = &cache_key {
+            if let Some(output) = self.shared.cache.lock().unwrap().get(key, *stamp) {
+                // 命中缓存时不经过命令本身，审计记录在这里补上
+                if self.audit.is_some() {
+                    let cmd_type = cmd.get("type").and_then(|t| t.as_str()).unwrap_or("");
+                    let _ = self.access(cmd_type, cache_target(cmd));
+                }
+                return output;
             }
+        }
+        let output = self.run_sync_command(cmd);
+        if let Some((key, stamp)) = cache_key.filter(|_| !output.starts_with("Error:")) {
+            self.shared.cache.lock().unwrap().put(key, stamp, output.clone());
+        }
+        output
+    }
+
+    /// 可缓存命令的缓存键（命令参数）和目标文件/目录的当前状态
+    fn cache_key(&self, cmd: &serde_json::Value) -> Option<(String, Stamp)> {
+        let cmd_type = cmd.get("type").and_then(|t| t.as_str())?;
+        if !result_cache::CACHEABLE.contains(&cmd_type) {
+            return None;
+        }
+        let stamp = Stamp::of(&self.real_path(cache_target(cmd)).ok()?)?;
+        Some((cmd.to_string(), stamp))
+    }
+
+    fn run_sync_command(&self, cmd: &serde_json::Value) -> String {
+        let cmd_type = cmd.get("type").and_then(|t| t.as_str()).unwrap_or("");
+
+        match cmd_type {
             "readfile" => {
                 let file = cmd.get("file").and_then(|f| f.as_str()).unwrap_or("");
-                let start = cmd.get("start_line").and_then(|v| v.as_u64()).map(|v| v as usize);
-                let end = cmd.get("end_line").and_then(|v| v.as_u64()).map(|v| v as usize);
-                self.readfile(file, start, end)
+                let line = |v: &serde_json::Value, key: &str| v.get(key).and_then(|n| n.as_u64()).map(|n| n as usize);
+                let (start, end) = (line(cmd, "start_line"), line(cmd, "end_line"));
+                let around_symbol = cmd.get("around_symbol").and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+                let mut ranges: Vec<(Option<usize>, Option<usize>)> = cmd
+                    .get("ranges")
+                    .and_then(|r| r.as_array())
+                    .map(|rs| rs.iter().map(|r| (line(r, "start_line"), line(r, "end_line"))).collect())
+                    .unwrap_or_default();
+                // 单个 start_line/end_line 与 ranges 一起读取；什么都没给时读整个文件
+                if start.is_some() || end.is_some() || (ranges.is_empty() && around_symbol.is_none()) {
+                    ranges.push((start, end));
+                }
+                self.cap_file_output(self.readfile_ranges(file, &ranges, around_symbol))
+            }
+            "head" | "tail" => {
+                let file = cmd.get("file").and_then(|f| f.as_str()).unwrap_or("");
+                let n = cmd.get("lines").and_then(|v| v.as_u64()).map(|v| v as usize);
+                self.cap_file_output(self.head_tail(file, n, cmd_type == "tail"))
+            }
+            "readmatch" => {
+                let file = cmd.get("file").and_then(|f| f.as_str()).unwrap_or("");
+                let pattern = cmd.get("pattern").and_then(|p| p.as_str()).unwrap_or("");
+                let context = cmd.get("context").and_then(|v| v.as_u64()).map(|v| v as usize);
+                self.cap_file_output(self.readmatch(file, pattern, context))
+            }
+            "enclosing" => {
+                let file = cmd.get("file").and_then(|f| f.as_str()).unwrap_or("");
+                let Some(line) = cmd.get("line").and_then(|v| v.as_u64()) else {
+                    return "Error: missing required argument: line".into();
+                };
+                self.cap_file_output(self.enclosing(file, line as usize))
+            }
+            "imports" => {
+                let file = cmd.get("file").and_then(|f| f.as_str()).unwrap_or("");
+                self.imports(file)
+            }
+            "archive" => {
+                let file = cmd.get("file").and_then(|f| f.as_str()).unwrap_or("");
+                let pattern = cmd.get("pattern").and_then(|p| p.as_str());
+                self.archive(file, pattern)
             }
             "tree" => {
                 let path = cmd.get("path").and_then(|p| p.as_str()).unwrap_or("/codebase");
                 let levels = cmd.get("levels").and_then(|v| v.as_u64()).map(|v| v as usize);
-                self.tree(path, levels)
+                let sort = SortKey::parse(cmd.get("sort").and_then(|v| v.as_str()));
+                let max_entries = cmd.get("max_entries").and_then(|v| v.as_u64()).map(|v| v as usize);
+                self.tree(path, levels, sort, max_entries)
             }
             "ls" => {
                 let path = cmd.get("path").and_then(|p| p.as_str()).unwrap_or("/codebase");
                 let long = cmd.get("long_format").and_then(|v| v.as_bool()).unwrap_or(false);
                 let all = cmd.get("all").and_then(|v| v.as_bool()).unwrap_or(false);
-                self.ls(path, long, all)
+                let sort = SortKey::parse(cmd.get("sort").and_then(|v| v.as_str()));
+                let max_entries = cmd.get("max_entries").and_then(|v| v.as_u64()).map(|v| v as usize);
+                self.ls(path, long, all, sort, max_entries)
             }
             "glob" => {
                 let pattern = cmd.get("pattern").and_then(|p| p.as_str()).unwrap_or("*");
@@ -304,12 +1756,20 @@ impl ToolExecutor {
                 let tf = cmd.get("type_filter").and_then(|v| v.as_str());
                 self.glob(pattern, path, tf)
             }
+            "stat" => {
+                let file = cmd.get("file").and_then(|f| f.as_str()).unwrap_or("");
+                self.stat(file)
+            }
+            "stats" => {
+                let path = cmd.get("path").and_then(|p| p.as_str()).unwrap_or("/codebase");
+                self.stats(path)
+            }
             _ => format!("Error: unknown command type '{}'", cmd_type),
         }
     }
 
     /// 并行执行所有 commandN
-    pub async fn exec_tool_call(&mut self, args: &serde_json::Value) -> String {
+    pub async fn exec_tool_call(&self, args: &serde_json::Value) -> String {
         let obj = match args.as_object() {
             Some(o) => o,
             None => return "(invalid args)".into(),
@@ -318,40 +1778,75 @@ impl ToolExecutor {
         let mut keys: Vec<&String> = obj.keys().filter(|k| k.starts_with("command")).collect();
         keys.sort();
 
-        // 收集命令，然后并行执行
-        let mut tasks = Vec::new();
-        for key in &keys {
-            if let Some(cmd) = obj.get(*key) {
-                let cmd_clone = cmd.clone();
-                let root = self.root.clone();
-
-                // 收集 rg patterns
-                if cmd.get("type").and_then(|t| t.as_str()) == Some("rg") {
-                    if let Some(p) = cmd.get("pattern").and_then(|p| p.as_str()) {
-                        self.collected_rg_patterns.push(p.to_string());
+        // 并行执行，同时最多 parallelism 个；便宜的命令先拿到名额，全库扫描排在后面。
+        // 各任务共用同一份共享状态，模式、文件和匹配在 exec_command 中直接记录
+        let turn_cancel = self.cancel.child_token();
+        let commands: Vec<serde_json::Value> = keys.iter().map(|k| obj[k.as_str()].clone()).collect();
+        let mut order: Vec<usize> = (0..commands.len()).collect();
+        order.sort_by_key(|&i| command_cost(&commands[i]));
+        let (mut senders, receivers): (Vec<_>, Vec<_>) = commands.iter().map(|_| {
+            let (tx, rx) = tokio::sync::oneshot::channel::<String>();
+            (Some(tx), rx)
+        }).unzip();
+        let permits = Arc::new(tokio::sync::Semaphore::new(self.parallelism.max(1)));
+        let mut executor = self.clone();
+        executor.cancel = turn_cancel.clone();
+        let dispatcher = tokio::spawn(async move {
+            for i in order {
+                let permit = tokio::select! {
+                    p = permits.clone().acquire_owned() => p,
+                    _ = executor.cancel.cancelled() => return,
+                };
+                let Ok(permit) = permit else { return };
+                let (executor, cmd, tx) = (executor.clone(), commands[i].clone(), senders[i].take());
+                tokio::spawn(async move {
+                    let output = executor.exec_command(&cmd).await;
+                    drop(permit);
+                    if let Some(tx) = tx {
+                        let _ = tx.send(output);
                     }
-                }
+                });
+            }
+        });
 
-                // 收集 readfile 文件路径
-                if cmd.get("type").and_then(|t| t.as_str()) == Some("readfile") {
-                    if let Some(f) = cmd.get("file").and_then(|f| f.as_str()) {
-                        self.collected_files.push(f.to_string());
-                    }
+        // 整轮超时：已完成的命令照常返回，其余命令（包括还在排队的）标记为超时
+        let deadline = self.timeouts.turn.map(|t| (tokio::time::Instant::now() + t, t));
+        let mut outputs = Vec::new();
+        let mut timed_out = false;
+        for rx in receivers {
+            let received = match deadline {
+                Some((at, _)) => tokio::time::timeout_at(at, rx).await.ok(),
+                None => Some(rx.await),
+            };
+            outputs.push(match received {
+                // 密钥在分配输出额度前替换，发往模型的内容中不出现
+                Some(Ok(output)) => Ok(crate::redact::redact(output)),
+                Some(Err(_)) => Err("command task failed".to_string()),
+                None => {
+                    timed_out = true;
+                    let secs = deadline.map(|(_, t)| t.as_secs_f32()).unwrap_or_default();
+                    Ok(format!("Error: timed out (turn budget of {}s used up)", secs))
                 }
-
-                let key_clone = (*key).clone();
-                tasks.push(tokio::spawn(async move {
-                    let mut executor = ToolExecutor::new(&root.to_string_lossy());
-                    let output = executor.exec_command(&cmd_clone).await;
-                    format!("<{}_result>\n{}\n</{}_result>", key_clone, output, key_clone)
-                }));
-            }
+            });
+        }
+        // 停止仍在后台运行的命令和排队中的分派
+        if timed_out {
+            turn_cancel.cancel();
+            dispatcher.abort();
         }
 
+        let sizes: Vec<usize> = outputs.iter().map(|o| o.as_ref().map_or(0, String::len)).collect();
+        let shares = self.limits.turn_max_bytes.map(|budget| fair_shares(&sizes, budget));
         let mut results = Vec::new();
-        for task in tasks {
-            match task.await {
-                Ok(r) => results.push(r),
+        for (i, (key, output)) in keys.iter().zip(outputs).enumerate() {
+            match output {
+                Ok(text) => {
+                    let text = match &shares {
+                        Some(shares) => truncate_to_share(&text, shares[i]),
+                        None => text,
+                    };
+                    results.push(format!("<{}_result>\n{}\n</{}_result>", key, text, key));
+                }
                 Err(e) => results.push(format!("<error>{}</error>", e)),
             }
         }
@@ -360,6 +1855,219 @@ impl ToolExecutor {
     }
 }
 
+/// 读取类命令的文件内容（非 UTF-8 时转码）；错误转为命令结果
+fn read_text(rp: &Path, file: &str) -> Result<crate::text_encoding::Decoded, String> {
+    use crate::text_encoding::ReadError;
+    crate::text_encoding::read(rp).map_err(|e| match e {
+        ReadError::NotFound => format!("Error: file not found: {}", file),
+        ReadError::Binary => format!("Error: binary file: {}", file),
+        ReadError::Io(e) => format!("Error: cannot read {}: {}", file, e),
+    })
+}
+
+/// 结果首行加上编码说明（有的话）
+fn with_note(note: Option<String>, text: String) -> String {
+    match note {
+        Some(note) => format!("{}\n{}", note, text),
+        None => text,
+    }
+}
+
+/// 命令的大致开销，决定 exec_tool_call 中的执行顺序（小的先执行）
+fn command_cost(cmd: &serde_json::Value) -> u8 {
+    match cmd.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+        "ls" | "stat" => 0,
+        "tree" | "readfile" | "head" | "tail" | "readmatch" | "enclosing" | "imports" | "archive" => 1,
+        "glob" | "files" | "stats" | "git" | "rg_files" => 2,
+        // rg / count：可能扫描整个仓库
+        _ => 3,
+    }
+}
+
+/// 把 budget 分给各项（命令输出的字节数、rg 结果各文件的行数）：小于平均份额的全部保留，剩余额度在较大的项间均分
+pub fn fair_shares(sizes: &[usize], budget: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| sizes[i]);
+    let mut shares = vec![0; sizes.len()];
+    let mut left = budget;
+    for (n, &i) in order.iter().enumerate() {
+        let share = sizes[i].min(left / (sizes.len() - n));
+        shares[i] = share;
+        left -= share;
+    }
+    shares
+}
+
+/// 取前 max 个字符，不拆开字符簇：组合附加符号、变体选择符、肤色修饰符和 ZWJ 连接的字符跟随前一个字符
+fn truncate_chars(line: &str, max: usize) -> &str {
+    let Some((mut cut, _)) = line.char_indices().nth(max) else { return line };
+    let joins = |c: Option<char>| c.is_some_and(|c| matches!(c as u32,
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F
+            | 0xFE00..=0xFE0F | 0xE0100..=0xE01EF | 0x1F3FB..=0x1F3FF | 0x200D));
+    while cut > 0 && (joins(line[cut..].chars().next()) || line[..cut].ends_with('\u{200D}')) {
+        cut = line[..cut].char_indices().next_back().map_or(0, |(i, _)| i);
+    }
+    &line[..cut]
+}
+
+/// 按字节上限截断，尽量停在行尾，并注明截断
+pub fn truncate_bytes(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    // 给截断说明留出空间，保证总长不超过 max
+    let mut cut = max.saturating_sub(48);
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    if let Some(nl) = text[..cut].rfind('\n') {
+        cut = nl;
+    }
+    format!("{}\n... (truncated to {} of {} bytes)", &text[..cut], cut, text.len())
+}
+
+/// 按一轮输出额度截断命令结果：停在行尾；截掉的部分含 rg 结果时按文件汇总匹配数，模型仍能知道其余命中在哪里
+fn truncate_to_share(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    // 给汇总说明留出空间，保证总长不超过 max
+    let mut cut = max.saturating_sub(96);
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    if let Some(nl) = text[..cut].rfind('\n') {
+        cut = nl;
+    }
+
+    // 匹配行 path:N:...、每个文件的 "(+N more matches in path)"、只列数量的 "path (N matches)"，以及 render 的汇总行
+    static PATTERNS: std::sync::OnceLock<[regex_lite::Regex; 4]> = std::sync::OnceLock::new();
+    let [line, more, counted, rest] = PATTERNS.get_or_init(|| {
+        [
+            regex_lite::Regex::new(r"^(/codebase.*?):\d+:").unwrap(),
+            regex_lite::Regex::new(r"^\(\+(\d+) more match(?:es)? in (/codebase.*)\)$").unwrap(),
+            regex_lite::Regex::new(r"^(/codebase.*) \((\d+) match(?:es)?\)$").unwrap(),
+            regex_lite::Regex::new(r"^\(\+(\d+) more match(?:es)? in (\d+) more files?\)$").unwrap(),
+        ]
+    });
+    let mut files: Vec<&str> = Vec::new();
+    let (mut omitted, mut other_files) = (0, 0);
+    for l in text[cut..].lines() {
+        let (path, n) = if let Some(c) = line.captures(l) {
+            (c.get(1).unwrap().as_str(), 1)
+        } else if let Some(c) = more.captures(l) {
+            (c.get(2).unwrap().as_str(), c[1].parse().unwrap_or(0))
+        } else if let Some(c) = counted.captures(l) {
+            (c.get(1).unwrap().as_str(), c[2].parse().unwrap_or(0))
+        } else {
+            if let Some(c) = rest.captures(l) {
+                omitted += c[1].parse::<usize>().unwrap_or(0);
+                other_files += c[2].parse::<usize>().unwrap_or(0);
+            }
+            continue;
+        };
+        omitted += n;
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    if omitted == 0 {
+        return truncate_bytes(text, max);
+    }
+    format!(
+        "{}\n... (+{} in {}; cut to fit the turn output budget)",
+        &text[..cut],
+        crate::rg_results::plural(omitted, "more match"),
+        crate::rg_results::plural(files.len() + other_files, "file")
+    )
+}
+
+/// 名称是否匹配任一排除模式（repo map 的 exclude 参数）
+pub fn excluded(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|p| simple_glob_match(name, p))
+}
+
+/// glob 命令的模式：相对搜索路径匹配，`*` 不跨目录，`**` 匹配任意层目录
+struct PathGlob {
+    full: globset::GlobMatcher,
+    /// 按 `/` 拆开的各段；None 为 `**`
+    segments: Vec<Option<globset::GlobMatcher>>,
+}
+
+impl PathGlob {
+    fn new(pattern: &str) -> Result<Self, globset::Error> {
+        let pattern = pattern.trim_start_matches("./").trim_start_matches('/');
+        let compile = |p: &str| globset::GlobBuilder::new(p).literal_separator(true).build().map(|g| g.compile_matcher());
+        let segments = pattern
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| if s == "**" { Ok(None) } else { compile(s).map(Some) })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { full: compile(pattern)?, segments })
+    }
+
+    fn is_match(&self, rel: &Path) -> bool {
+        self.full.is_match(rel)
+    }
+
+    /// rel 目录下是否可能有匹配的条目：目录的每一层都要匹配对应的段，遇到 `**` 后不再限制
+    fn may_contain(&self, rel: &Path) -> bool {
+        let components: Vec<_> = rel.components().collect();
+        for (i, c) in components.iter().enumerate() {
+            match self.segments.get(i) {
+                None => return false,
+                Some(None) => return true,
+                Some(Some(seg)) if !seg.is_match(c.as_os_str()) => return false,
+                Some(Some(_)) => {}
+            }
+        }
+        components.len() < self.segments.len()
+    }
+}
+
+/// 可缓存命令的目标文件或目录
+fn cache_target(cmd: &serde_json::Value) -> &str {
+    cmd.get("file").or_else(|| cmd.get("path")).and_then(|v| v.as_str()).unwrap_or("/codebase")
+}
+
+/// ls -l 的权限位
+#[cfg(unix)]
+fn mode_string(meta: &std::fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = meta.permissions().mode();
+    "rwxrwxrwx".chars().enumerate().map(|(i, c)| if mode & (0o400 >> i) != 0 { c } else { '-' }).collect()
+}
+
+/// ls -l 的权限位；非 unix 平台只能区分只读
+#[cfg(not(unix))]
+fn mode_string(meta: &std::fs::Metadata) -> String {
+    if meta.permissions().readonly() { "r--r--r--" } else { "rw-rw-rw-" }.to_string()
+}
+
+/// stat 读取的文件开头字节数（判断生成文件）
+const STAT_HEAD_BYTES: usize = 64 * 1024;
+
+/// 文件开头 STAT_HEAD_BYTES 字节和总行数（与 str::lines 相同：末尾没有换行的最后一行也算一行）
+fn count_lines(path: &Path) -> std::io::Result<(Vec<u8>, usize)> {
+    use std::io::Read;
+    let mut file = std::fs::File::open(path)?;
+    let mut head = Vec::new();
+    let (mut lines, mut last) = (0, b'\n');
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if head.len() < STAT_HEAD_BYTES {
+            head.extend_from_slice(&buf[..n.min(STAT_HEAD_BYTES - head.len())]);
+        }
+        lines += buf[..n].iter().filter(|b| **b == b'\n').count();
+        last = buf[n - 1];
+    }
+    Ok((head, lines + usize::from(last != b'\n')))
+}
+
 /// 简单 glob 匹配
 fn simple_glob_match(name: &str, pattern: &str) -> bool {
     // 处理常见 glob 模式
@@ -373,21 +2081,315 @@ fn simple_glob_match(name: &str, pattern: &str) -> bool {
     name == pattern
 }
 
-/// 查找 rg 二进制路径
-fn find_rg_binary() -> String {
-    // 优先使用系统 rg
-    if let Ok(output) = Command::new("which").arg("rg").output() {
-        if output.status.success() {
-            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !path.is_empty() { return path; }
+/// 按名称升序，或按修改时间/大小降序
+fn sort_entries(entries: &mut [PathBuf], sort: SortKey) {
+    match sort {
+        SortKey::Name => entries.sort_by(|a, b| a.file_name().cmp(&b.file_name())),
+        SortKey::Mtime => entries.sort_by_key(|p| {
+            std::cmp::Reverse(std::fs::symlink_metadata(p).and_then(|m| m.modified()).ok())
+        }),
+        SortKey::Size => entries.sort_by_key(|p| {
+            std::cmp::Reverse(std::fs::symlink_metadata(p).map(|m| m.len()).unwrap_or(0))
+        }),
+    }
+}
+
+/// 入口点候选
+pub struct Entrypoint {
+    /// /codebase/... 虚拟路径
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+    /// main / server / cli / container / manifest
+    pub kind: &'static str,
+    pub detail: String,
+}
+
+/// 入口点最多返回的条数
+const ENTRY_MAX: usize = 100;
+/// 超过此大小的候选文件不读取
+const ENTRY_FILE_MAX_BYTES: u64 = 256 * 1024;
+/// 说明文字的最大字符数
+const ENTRY_DETAIL_CHARS: usize = 100;
+/// server/cli 命中所在块超过此行数时只给出命中行
+const ENTRY_MAX_BLOCK_LINES: usize = 80;
+/// 按文件名识别的入口文件
+const ENTRY_FILES: &[&str] = &[
+    "Dockerfile", "Dockerfile.*", "*.dockerfile", "Containerfile", "Procfile",
+    "package.json", "Cargo.toml", "pyproject.toml", "setup.py",
+];
+/// 内容搜索的源文件范围
+const ENTRY_SOURCE_GLOBS: &[&str] = &[
+    "*.rs", "*.go", "*.py", "*.js", "*.mjs", "*.cjs", "*.ts", "*.java", "*.kt", "*.cs", "*.c", "*.cc", "*.cpp", "*.rb",
+];
+/// (类别, 入口代码模式)；rg 与 regex_lite 都支持的语法
+const ENTRY_PATTERNS: &[(&str, &str)] = &[
+    ("main", r"^\s*(pub\s+)?(async\s+)?fn\s+main\s*\("),
+    ("main", r"^func\s+main\s*\(\s*\)"),
+    ("main", r#"^if\s+__name__\s*==\s*['"]__main__['"]"#),
+    ("main", r"static\s+(async\s+)?(void|int|Task)\s+[Mm]ain\s*\("),
+    ("main", r"^\s*int\s+main\s*\("),
+    ("main", r"^fun\s+main\s*\("),
+    ("server", r"\b(app|server|router)\.listen\s*\("),
+    ("server", r"\bcreateServer\s*\("),
+    ("server", r"\b(uvicorn|app|socketio)\.run\s*\("),
+    ("server", r"\bhttp\.ListenAndServe(TLS)?\s*\("),
+    ("server", r"\b(axum::serve|HttpServer::new|Server::bind|warp::serve)\b"),
+    ("server", r"\bSpringApplication\.run\s*\("),
+    ("cli", r"#\[derive\([^)]*\bParser\b"),
+    ("cli", r"\bargparse\.ArgumentParser\s*\("),
+    ("cli", r"@(click|app)\.(command|group)\b"),
+    ("cli", r"\btyper\.Typer\s*\("),
+    ("cli", r"&cobra\.Command\s*\{"),
+    ("cli", r"\bprogram\s*\.\s*parse\s*\("),
+];
+
+/// 入口文件中相关的行：Dockerfile 的 ENTRYPOINT/CMD、Procfile 进程、清单文件的 main/bin/scripts
+fn entry_file_lines(name: &str, content: &str) -> Vec<(usize, usize, &'static str, String)> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut out = Vec::new();
+    let mut push = |i: usize, kind: &'static str| {
+        // 以 '\' 续行的指令包含后续行
+        let mut end = i;
+        while lines[end].trim_end().ends_with('\\') && end + 1 < lines.len() {
+            end += 1;
+        }
+        out.push((i + 1, end + 1, kind, entry_detail(lines[i])));
+    };
+    let docker = name.starts_with("Dockerfile") || name.ends_with(".dockerfile") || name == "Containerfile";
+    let mut section = "";
+    for (i, line) in lines.iter().enumerate() {
+        let t = line.trim();
+        if t.starts_with('[') {
+            section = t;
+        }
+        let hit = if docker {
+            let upper = t.to_ascii_uppercase();
+            upper.starts_with("ENTRYPOINT ") || upper.starts_with("CMD ")
+        } else {
+            match name {
+                "Procfile" => t.split_once(':').is_some_and(|(k, _)| !k.is_empty() && !k.contains(' ')),
+                "package.json" => ["\"main\"", "\"bin\"", "\"start\""].iter().any(|k| t.starts_with(k)),
+                "Cargo.toml" => t == "[[bin]]",
+                "pyproject.toml" => matches!(section, "[project.scripts]" | "[tool.poetry.scripts]") && t.contains('='),
+                "setup.py" => t.contains("console_scripts"),
+                _ => false,
+            }
+        };
+        if hit {
+            push(i, if docker { "container" } else { "manifest" });
+        }
+    }
+    out
+}
+
+/// 命中行的说明文字
+fn entry_detail(line: &str) -> String {
+    let t = line.trim();
+    match t.char_indices().nth(ENTRY_DETAIL_CHARS) {
+        Some((i, _)) => format!("{}...", &t[..i]),
+        None => t.to_string(),
+    }
+}
+
+/// 单个语言的统计
+pub struct LangStat {
+    pub language: String,
+    pub files: usize,
+    pub lines: usize,
+}
+
+/// 目录统计结果
+pub struct StatsSummary {
+    pub languages: Vec<LangStat>,
+    pub total_files: usize,
+    pub total_lines: usize,
+    /// 测试文件数（按 lang::is_test_path）
+    pub test_files: usize,
+    /// 深度不超过 STATS_DIR_DEPTH 的子目录汇总，路径相对统计根目录
+    pub dirs: Vec<DirStat>,
+    pub partial: bool,
+}
+
+/// 单个目录（含子目录）的统计
+pub struct DirStat {
+    pub path: String,
+    pub depth: usize,
+    pub files: usize,
+    pub lines: usize,
+}
+
+/// 遍历目录，按语言汇总文件数与行数（按行数降序），并按子目录汇总
+pub fn language_stats(dir: &Path) -> StatsSummary {
+    let mut by_lang: std::collections::HashMap<String, (usize, usize)> = std::collections::HashMap::new();
+    let mut by_dir: std::collections::HashMap<String, (usize, usize)> = std::collections::HashMap::new();
+    let mut total_files = 0;
+    let mut test_files = 0;
+
+    walk_files(dir, |fp, meta| {
+        if total_files >= STATS_MAX_FILES {
+            return false;
+        }
+        total_files += 1;
+
+        let lang = lang::detect_language(fp).unwrap_or("Other").to_string();
+        let lines = if meta.len() <= STATS_MAX_FILE_BYTES {
+            std::fs::read(fp).map(|d| bytecount_lines(&d)).unwrap_or(0)
+        } else {
+            0
+        };
+        let e = by_lang.entry(lang).or_insert((0, 0));
+        e.0 += 1;
+        e.1 += lines;
+
+        let rel = fp.strip_prefix(dir).unwrap_or(fp).to_string_lossy().replace('\\', "/");
+        if lang::is_test_path(&rel) {
+            test_files += 1;
         }
+        let parts: Vec<&str> = rel.split('/').collect();
+        for depth in 1..parts.len().min(STATS_DIR_DEPTH + 1) {
+            let e = by_dir.entry(parts[..depth].join("/")).or_insert((0, 0));
+            e.0 += 1;
+            e.1 += lines;
+        }
+        true
+    });
+
+    let mut languages: Vec<LangStat> = by_lang.into_iter()
+        .map(|(language, (files, lines))| LangStat { language, files, lines })
+        .collect();
+    languages.sort_by(|a, b| b.lines.cmp(&a.lines).then(b.files.cmp(&a.files)));
+    let total_lines = languages.iter().map(|s| s.lines).sum();
+
+    let mut dirs: Vec<DirStat> = by_dir.into_iter()
+        .map(|(path, (files, lines))| DirStat { depth: path.split('/').count(), path, files, lines })
+        .collect();
+    dirs.sort_by(|a, b| b.lines.cmp(&a.lines).then(b.files.cmp(&a.files)).then(a.path.cmp(&b.path)));
+
+    StatsSummary { languages, total_files, total_lines, test_files, dirs, partial: total_files >= STATS_MAX_FILES }
+}
+
+fn bytecount_lines(data: &[u8]) -> usize {
+    let n = data.iter().filter(|&&b| b == b'\n').count();
+    if data.last().is_some_and(|&b| b != b'\n') { n + 1 } else { n }
+}
+
+/// Unix 秒 → "YYYY-MM-DD HH:MM:SS UTC"
+pub fn format_unix_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // civil_from_days (Howard Hinnant)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", y, m, d, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// 查找 rg 二进制路径
+pub fn find_rg_binary() -> String {
+    crate::ripgrep::caps().path.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 临时项目 + 指向它的符号链接；返回 (规范路径, 链接路径)
+    #[cfg(unix)]
+    fn linked_project(name: &str) -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("windsurf-mcp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let project = base.join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        let link = base.join("link");
+        std::os::unix::fs::symlink(&project, &link).unwrap();
+        let project = project.canonicalize().unwrap();
+        let text = format!("fn needle() {{}}\n// built in {}/src and {}/src\n", project.display(), link.display());
+        std::fs::write(project.join("src").join("lib.rs"), text).unwrap();
+        (project, link)
     }
-    // Windows
-    if let Ok(output) = Command::new("where").arg("rg").output() {
-        if output.status.success() {
-            let path = String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("rg").trim().to_string();
-            if !path.is_empty() { return path; }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinked_root_shows_only_codebase_paths() {
+        let (project, link) = linked_project("symlink");
+        let exec = ToolExecutor::new(&link.to_string_lossy());
+        let commands = [
+            serde_json::json!({ "type": "rg", "pattern": "needle|built", "path": "/codebase" }),
+            serde_json::json!({ "type": "readfile", "file": "/codebase/src/lib.rs" }),
+            serde_json::json!({ "type": "tree", "path": "/codebase" }),
+        ];
+        for cmd in &commands {
+            let out = exec.exec_command(cmd).await;
+            assert!(out.contains("/codebase"), "{}: {}", cmd, out);
+            assert!(!out.contains(&*link.to_string_lossy()), "{}: link path leaked: {}", cmd, out);
+            assert!(!out.contains(&*project.to_string_lossy()), "{}: canonical path leaked: {}", cmd, out);
         }
+        let _ = std::fs::remove_dir_all(project.parent().unwrap());
+    }
+
+    #[test]
+    fn tail_keeps_the_last_lines_under_the_line_limit() {
+        let root = std::env::temp_dir().join(format!("windsurf-mcp-tail-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let text: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(root.join("a.txt"), text).unwrap();
+        let mut exec = ToolExecutor::new(&root.to_string_lossy());
+        exec.limits.max_lines = 10;
+
+        let out = exec.head_tail("a.txt", Some(50), true);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.first(), Some(&"... (lines 92-100 of 100)"));
+        assert_eq!(lines.last(), Some(&"100:line 100"));
+        assert_eq!(lines.len(), 10);
+
+        let out = exec.head_tail("a.txt", Some(50), false);
+        assert_eq!(out.lines().last(), Some("... (lines 1-9 of 100)"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn enclosing_requires_line() {
+        let exec = ToolExecutor::new(&std::env::temp_dir().to_string_lossy());
+        let out = exec.exec_command(&serde_json::json!({ "type": "enclosing", "file": "a.rs" })).await;
+        assert_eq!(out, "Error: missing required argument: line");
+    }
+
+    #[tokio::test]
+    async fn tree_sees_changes_in_subdirectories() {
+        let root = std::env::temp_dir().join(format!("windsurf-mcp-tree-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src/deep")).unwrap();
+        std::fs::write(root.join("src/deep/a.rs"), "").unwrap();
+        let exec = ToolExecutor::new(&root.to_string_lossy());
+        let tree = serde_json::json!({ "type": "tree", "path": "/codebase" });
+
+        assert!(!exec.exec_command(&tree).await.contains("b.rs"));
+        std::fs::write(root.join("src/deep/b.rs"), "").unwrap();
+        assert!(exec.exec_command(&tree).await.contains("b.rs"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn scoped_files_match_globs_on_the_relative_path() {
+        let root = std::env::temp_dir().join(format!("windsurf-mcp-scoped-files-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let mut exec = ToolExecutor::new(&root.to_string_lossy());
+        exec.set_scope(["src/a.rs", "src/deep/b.rs", "lib/c.rs", "src/d.txt"].into_iter());
+
+        let out = exec.files("/codebase", Some("src/**/*.rs"), None).await;
+        assert_eq!(out, "/codebase/src/a.rs\n/codebase/src/deep/b.rs");
+        let out = exec.files("/codebase", Some("*.rs"), Some(1)).await;
+        assert_eq!(out, "/codebase/src/a.rs\n... (1 of 3 files shown)");
+        let _ = std::fs::remove_dir_all(&root);
     }
-    "rg".into()
 }
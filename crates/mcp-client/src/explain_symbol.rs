@@ -0,0 +1,178 @@
+//! explain_symbol 工具：解释一个符号的作用
+//!
+//! 用 tree-sitter 找到符号定义（同 symbol_search），读取定义及其上方的注释，
+//! 交给模型做一轮解释，返回解释和定义位置。不跑多轮检索，比 fast_context_search 便宜得多；
+//! 模型调用在 main.rs 中完成，这里负责定位、提示内容和结果格式。
+
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use crate::i18n::Locale;
+use crate::outline::{self, Block};
+use crate::windsurf::ChatMessage;
+
+/// 候选定义的数量上限
+const MAX_CANDIDATES: usize = 40;
+/// 结果中列出的其他同名定义数
+const MAX_OTHERS: usize = 5;
+/// 交给模型的定义最多行数
+const MAX_DEFINITION_LINES: usize = 200;
+/// 定义上方最多带上的注释/属性行数
+const MAX_LEADING_LINES: usize = 20;
+
+/// 选中的定义
+pub struct Target {
+    pub name: String,
+    pub path: PathBuf,
+    pub block: Block,
+    /// 带行号的定义文本（含上方注释）
+    pub source: String,
+    /// 其他同名定义
+    pub others: Vec<(PathBuf, Block)>,
+}
+
+impl Target {
+    pub fn location(&self) -> String {
+        format!("{}:{}-{}", self.path.display(), self.block.start_line, self.block.end_line)
+    }
+}
+
+/// 工具定义；`provider` 为 fast_context_search 的 provider 参数
+pub fn definition(l: Locale, provider: Value) -> Value {
+    json!({
+        "name": "explain_symbol",
+        "description": l.pick(
+            "Explain what a function, method, class or type does: finds its definition with tree-sitter, reads it and asks the model for a short explanation in a single turn. Much cheaper than a full fast_context_search when you already know the name.",
+            "解释一个函数、方法、类或类型的作用：用 tree-sitter 找到定义，读取后让模型一轮给出简短解释。已知名称时比完整的 fast_context_search 便宜得多。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": l.pick("Symbol name", "符号名") },
+                "file": {
+                    "type": "string",
+                    "description": l.pick("File containing the definition, when the name is ambiguous (absolute, relative to project_path, or /codebase/...)", "名称有歧义时指定定义所在的文件（绝对路径、相对 project_path 的路径或 /codebase/...）")
+                },
+                "focus": { "type": "string", "description": l.pick("What to focus the explanation on, e.g. \"error handling\"", "解释的侧重点，例如“错误处理”") },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                },
+                "provider": provider
+            },
+            "required": ["name"]
+        }
+    })
+}
+
+/// 同名定义排序：大小写完全相同的在前，impl 块排在类型本身之后，其次按路径
+fn rank(found: &mut [(PathBuf, Block)], name: &str) {
+    found.sort_by(|(pa, a), (pb, b)| {
+        let key = |blk: &Block| (blk.name.as_deref() != Some(name), blk.kind == "impl_item");
+        key(a).cmp(&key(b)).then_with(|| pa.cmp(pb)).then(a.start_line.cmp(&b.start_line))
+    });
+}
+
+/// 定义及其上方紧邻的注释/属性行，带行号
+fn definition_text(source: &str, block: &Block) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut first = block.start_line.saturating_sub(1).min(lines.len());
+    while first > 0 && block.start_line - first <= MAX_LEADING_LINES {
+        let t = lines[first - 1].trim_start();
+        if !["//", "#", "/*", "*", "@", "--"].iter().any(|p| t.starts_with(p)) {
+            break;
+        }
+        first -= 1;
+    }
+    let end = block.end_line.min(lines.len()).min(block.start_line + MAX_DEFINITION_LINES - 1);
+    let mut out: Vec<String> = (first..end).map(|i| format!("{:>5}  {}", i + 1, lines[i])).collect();
+    if end < block.end_line {
+        out.push(format!("... ({} more lines)", block.end_line - end));
+    }
+    out.join("\n")
+}
+
+/// 查找要解释的定义
+pub async fn locate(args: &Value) -> Result<Target, String> {
+    let root = PathBuf::from(crate::direct_tools::project_root(args)?);
+    let name = args.get("name").and_then(|n| n.as_str()).map(str::trim).filter(|n| !n.is_empty())
+        .ok_or("missing required argument: name")?
+        .to_string();
+    let file = match args.get("file").and_then(|f| f.as_str()).map(str::trim).filter(|f| !f.is_empty()) {
+        Some(f) => {
+            let virtual_path = crate::direct_tools::virtual_path(&root, f)?;
+            let path = root.join(virtual_path.trim_start_matches("/codebase/"));
+            if !path.is_file() {
+                return Err(format!("file not found: {}", f));
+            }
+            if !outline::is_supported(&path) {
+                return Err(format!("unsupported language: {} (Rust, Python, Go, JS/TS)", f));
+            }
+            Some(path)
+        }
+        None => None,
+    };
+
+    let needle = name.to_lowercase();
+    let search_root = root.clone();
+    let mut found = tokio::task::spawn_blocking(move || {
+        let matches = |candidate: &str| candidate.to_lowercase() == needle;
+        match file {
+            Some(path) => {
+                let source = std::fs::read_to_string(&path).unwrap_or_default();
+                outline::symbols(&path, &source)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|b| b.name.as_deref().is_some_and(matches))
+                    .map(|b| (path.clone(), b))
+                    .collect()
+            }
+            None => outline::find_symbols(&search_root, MAX_CANDIDATES, matches),
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    rank(&mut found, &name);
+
+    let mut found = found.into_iter();
+    let (path, block) = found.next().ok_or_else(|| format!("No definitions of {} found", name))?;
+    let source = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Target {
+        name,
+        source: definition_text(&source, &block),
+        path,
+        block,
+        others: found.collect(),
+    })
+}
+
+/// 一轮解释的对话
+pub fn messages(target: &Target, focus: Option<&str>) -> Vec<ChatMessage> {
+    let user = crate::prompt::explain_request(&target.name, &target.location(), &target.source, focus);
+    vec![
+        ChatMessage { role: 5, content: crate::prompt::EXPLAIN_SYSTEM_PROMPT.to_string(), tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None },
+        ChatMessage { role: 1, content: user, tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None },
+    ]
+}
+
+/// 解释 + 定义位置 + 其他同名定义
+pub fn render(target: &Target, explanation: &str) -> String {
+    let mut out = vec![
+        format!("{} ({}) defined at {}", target.name, target.block.kind, target.location()),
+        String::new(),
+        explanation.trim().to_string(),
+    ];
+    if !target.others.is_empty() {
+        out.push(String::new());
+        out.push(format!("Other definitions of {}:", target.name));
+        for (path, block) in target.others.iter().take(MAX_OTHERS) {
+            out.push(format!("  {}:{}-{} {}", path.display(), block.start_line, block.end_line, block.kind));
+        }
+        if target.others.len() > MAX_OTHERS {
+            out.push(format!("  (+{} more; use symbol_search)", target.others.len() - MAX_OTHERS));
+        }
+    }
+    out.join("\n")
+}
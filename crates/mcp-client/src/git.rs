@@ -0,0 +1,106 @@
+//! 只读 git 查询
+//!
+//! 工具和搜索需要的 git 信息都经 `run` 获取：只允许不修改仓库的子命令，参数不接受以 '-' 开头的 ref。
+//! blame（porcelain 格式）和 `log -L` 的输出在这里解析为结构化数据。
+
+use std::path::Path;
+
+/// 允许执行的子命令
+const READ_ONLY: &[&str] = &["blame", "log", "show", "diff", "rev-parse", "ls-files", "status"];
+/// 工作区中尚未提交的行在 blame 中的 commit
+pub const UNCOMMITTED: &str = "0000000000000000000000000000000000000000";
+/// 提交说明最多保留的行数
+const MAX_MESSAGE_LINES: usize = 12;
+
+/// 在 root 下运行只读 git 子命令
+pub async fn run(root: &Path, args: &[&str]) -> Result<String, String> {
+    let sub = args.first().copied().unwrap_or("");
+    if !READ_ONLY.contains(&sub) {
+        return Err(format!("git {} is not allowed (read-only: {})", sub, READ_ONLY.join(", ")));
+    }
+    crate::remote::git(root, args).await.map_err(|e| e.to_string())
+}
+
+/// 拒绝会被 git 当作选项的 ref
+pub fn check_ref(r: &str) -> Result<&str, String> {
+    if r.starts_with('-') {
+        return Err(format!("invalid git ref: {}", r));
+    }
+    Ok(r)
+}
+
+/// 一次提交
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub sha: String,
+    pub author: String,
+    pub email: String,
+    /// YYYY-MM-DD
+    pub date: String,
+    pub message: String,
+}
+
+impl Commit {
+    pub fn short_sha(&self) -> &str {
+        &self.sha[..self.sha.len().min(8)]
+    }
+}
+
+/// git blame：起止行（含）内每行最后修改它的 commit，返回 (commit, 行号)
+pub async fn blame(root: &Path, rel: &str, start: usize, end: usize) -> Result<Vec<(String, usize)>, String> {
+    let range = format!("{},{}", start, end);
+    let out = run(root, &["blame", "--porcelain", "-L", &range, "--", rel]).await?;
+    let mut lines = Vec::new();
+    for line in out.lines() {
+        // 头部行："<sha> <原行号> <现行号>[ <组内行数>]"
+        let mut parts = line.split(' ');
+        let (Some(sha), Some(_), Some(now)) = (parts.next(), parts.next(), parts.next()) else { continue };
+        if sha.len() != 40 || !sha.bytes().all(|b| b.is_ascii_hexdigit()) {
+            continue;
+        }
+        if let Ok(n) = now.parse() {
+            lines.push((sha.to_string(), n));
+        }
+    }
+    Ok(lines)
+}
+
+/// git log -L：修改过这些行的提交，最近的在前
+pub async fn line_history(root: &Path, rel: &str, start: usize, end: usize, max: usize) -> Result<Vec<Commit>, String> {
+    let range = format!("-L{},{}:{}", start, end, rel);
+    let max = format!("-n{}", max);
+    let out = run(root, &["log", "-s", "--date=short", "--format=%H%x1f%an%x1f%ae%x1f%ad%x1f%B%x1e", &max, &range]).await?;
+    Ok(parse_log(&out))
+}
+
+/// 指定提交的信息（顺序同输入）
+pub async fn show(root: &Path, shas: &[&str]) -> Result<Vec<Commit>, String> {
+    if shas.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = vec!["show", "-s", "--date=short", "--format=%H%x1f%an%x1f%ae%x1f%ad%x1f%B%x1e"];
+    args.extend(shas.iter().copied());
+    Ok(parse_log(&run(root, &args).await?))
+}
+
+/// 解析 `%H %an %ae %ad %B`（字段以 0x1f 分隔，提交以 0x1e 结尾）
+fn parse_log(out: &str) -> Vec<Commit> {
+    out.split('\u{1e}')
+        .filter_map(|record| {
+            let mut fields = record.trim().splitn(5, '\u{1f}');
+            let sha = fields.next()?.trim().to_string();
+            if sha.is_empty() {
+                return None;
+            }
+            let (author, email, date) = (fields.next()?, fields.next()?, fields.next()?);
+            let message: Vec<&str> = fields.next().unwrap_or("").trim().lines().take(MAX_MESSAGE_LINES).collect();
+            Some(Commit {
+                sha,
+                author: author.to_string(),
+                email: email.to_string(),
+                date: date.to_string(),
+                message: message.join("\n").trim_end().to_string(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,142 @@
+//! git_blame_context 工具：一段代码的修改历史
+//!
+//! 对文件的一个行范围给出两部分：git blame 得到的当前每行归属（哪个提交、谁、哪些行），
+//! 以及 `git log -L` 得到的修改过这些行的最近提交及其说明，帮助理解代码为何写成现在这样。
+//! 只读取仓库，不修改任何内容。
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::git::{self, Commit};
+use crate::i18n::Locale;
+
+const DEFAULT_MAX_COMMITS: usize = 5;
+const MAX_COMMITS: usize = 20;
+/// blame 部分最多列出的提交数
+const MAX_OWNERS: usize = 20;
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "git_blame_context",
+        "description": l.pick(
+            "Show who last changed a range of lines and why: git blame ownership of each line plus the recent commits (author, date, full message) that touched the range. Read-only; needs the project to be a git work tree.",
+            "查看一段代码最后由谁修改、为何修改：git blame 的逐行归属，以及修改过该范围的最近提交（作者、日期、完整说明）。只读，项目需在 git 工作区内。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "file": {
+                    "type": "string",
+                    "description": l.pick("File: absolute, relative to project_path, or /codebase/...", "文件：绝对路径、相对 project_path 的路径或 /codebase/...")
+                },
+                "start_line": {
+                    "type": "integer",
+                    "description": l.pick("First line (1-based, default 1)", "起始行（从 1 开始，默认 1）"),
+                    "minimum": 1
+                },
+                "end_line": {
+                    "type": "integer",
+                    "description": l.pick("Last line, inclusive (default end of file)", "结束行（含，默认到文件末尾）"),
+                    "minimum": 1
+                },
+                "max_commits": {
+                    "type": "integer",
+                    "description": l.pick("Max commits of history to return (1-20, default 5)", "最多返回的历史提交数（1-20，默认 5）"),
+                    "default": DEFAULT_MAX_COMMITS,
+                    "minimum": 1,
+                    "maximum": MAX_COMMITS
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            },
+            "required": ["file"]
+        }
+    })
+}
+
+/// 行号列表 → "3-4, 6"
+fn line_spans(lines: &[usize]) -> String {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for &n in lines {
+        match spans.last_mut() {
+            Some((_, end)) if *end + 1 == n => *end = n,
+            _ => spans.push((n, n)),
+        }
+    }
+    spans
+        .iter()
+        .map(|(s, e)| if s == e { s.to_string() } else { format!("{}-{}", s, e) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn render_commit(out: &mut Vec<String>, c: &Commit) {
+    out.push(format!("{} {} {} <{}>", c.short_sha(), c.date, c.author, c.email));
+    for line in c.message.lines() {
+        out.push(format!("    {}", line).trim_end().to_string());
+    }
+}
+
+/// 执行 git_blame_context
+pub async fn call(args: &Value) -> Result<String, String> {
+    let root = crate::direct_tools::project_root(args)?;
+    let root = Path::new(&root);
+    let file = args.get("file").and_then(|f| f.as_str()).map(str::trim).filter(|f| !f.is_empty())
+        .ok_or("missing required argument: file")?;
+    let virtual_path = crate::direct_tools::virtual_path(root, file)?;
+    let rel = virtual_path.trim_start_matches("/codebase").trim_start_matches('/').to_string();
+    let path = root.join(&rel);
+    if !path.is_file() {
+        return Err(format!("file not found: {}", file));
+    }
+    let data = std::fs::read(&path).map_err(|e| e.to_string())?;
+    let total = (data.iter().filter(|&&b| b == b'\n').count() + usize::from(data.last().is_some_and(|&b| b != b'\n'))).max(1);
+    let start = args.get("start_line").and_then(|v| v.as_u64()).map(|v| v as usize).unwrap_or(1).max(1);
+    let end = args.get("end_line").and_then(|v| v.as_u64()).map(|v| v as usize).unwrap_or(total).min(total);
+    if start > end {
+        return Err(format!("start_line {} is past end_line {} (file has {} lines)", start, end, total));
+    }
+    let max = args.get("max_commits").and_then(|m| m.as_u64()).map(|m| m as usize)
+        .unwrap_or(DEFAULT_MAX_COMMITS)
+        .clamp(1, MAX_COMMITS);
+
+    let blame = git::blame(root, &rel, start, end).await?;
+    // 按首次出现的顺序汇总每个提交拥有的行
+    let mut owners: Vec<(String, Vec<usize>)> = Vec::new();
+    for (sha, line) in blame {
+        match owners.iter_mut().find(|(s, _)| *s == sha) {
+            Some((_, lines)) => lines.push(line),
+            None => owners.push((sha, vec![line])),
+        }
+    }
+    let committed: Vec<&str> = owners.iter().map(|(s, _)| s.as_str()).filter(|s| *s != git::UNCOMMITTED).collect();
+    let owner_info = git::show(root, &committed[..committed.len().min(MAX_OWNERS)]).await?;
+    let history = if committed.is_empty() { Vec::new() } else { git::line_history(root, &rel, start, end, max).await? };
+
+    let mut out = vec![format!("{}:{}-{}", path.display(), start, end), String::new(), "Last changed by:".to_string()];
+    for (sha, lines) in &owners {
+        let spans = line_spans(lines);
+        if sha == git::UNCOMMITTED {
+            out.push(format!("  (uncommitted): lines {}", spans));
+        } else if let Some(c) = owner_info.iter().find(|c| c.sha == *sha) {
+            let subject = c.message.lines().next().unwrap_or("");
+            out.push(format!("  {} {} {}: lines {} — {}", c.short_sha(), c.date, c.author, spans, subject));
+        }
+    }
+    if committed.len() > MAX_OWNERS {
+        out.push(format!("  (+{} more commits)", committed.len() - MAX_OWNERS));
+    }
+    if !history.is_empty() {
+        out.push(String::new());
+        out.push(format!("History ({} commits touching these lines, newest first):", history.len()));
+        for c in &history {
+            render_commit(&mut out, c);
+        }
+    }
+    Ok(out.join("\n"))
+}
@@ -0,0 +1,86 @@
+//! grep_project 工具：直接调用 ripgrep
+//!
+//! 已知关键词时不必走 AI 搜索：用 `ToolExecutor::rg` 搜索项目，支持 include/exclude glob 和上下文行。
+//! 输出路径为 /codebase/...，可直接交给 read_ranges。
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+use tokio_util::sync::CancellationToken;
+
+use crate::executor::{self, RgOptions, ToolExecutor};
+use crate::i18n::Locale;
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    let globs = |en: &'static str, zh: &'static str| json!({ "type": "array", "items": { "type": "string" }, "description": l.pick(en, zh) });
+    json!({
+        "name": "grep_project",
+        "description": l.pick(
+            "Search the project with ripgrep for a known keyword or regex, without the AI loop. Returns path:line:text matches (max 50 per file, long output truncated); paths are /codebase/... and work with read_ranges.",
+            "已知关键词或正则时直接用 ripgrep 搜索项目，不经过 AI。返回 路径:行号:内容（每个文件最多 50 处，过长输出会截断）；路径为 /codebase/...，可直接用于 read_ranges。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "pattern": { "type": "string", "description": l.pick("Regex pattern (invalid regexes are searched literally)", "正则表达式（无效的正则按字面量搜索）") },
+                "path": {
+                    "type": "string",
+                    "description": l.pick("File or directory to search, relative to project_path (default: whole project)", "搜索的文件或目录，相对 project_path（默认整个项目）"),
+                    "default": ""
+                },
+                "include": globs("Globs of files to include, e.g. [\"*.rs\"]", "包含的文件 glob，如 [\"*.rs\"]"),
+                "exclude": globs("Globs of files to exclude", "排除的文件 glob"),
+                "context_lines": {
+                    "type": "integer",
+                    "description": l.pick("Lines of context around each match (0-10, default 0)", "每处匹配前后的上下文行数（0-10，默认 0）"),
+                    "default": 0,
+                    "minimum": 0,
+                    "maximum": executor::MAX_CONTEXT_LINES
+                },
+                "case_insensitive": { "type": "boolean", "description": l.pick("Ignore case", "忽略大小写"), "default": false },
+                "fixed_strings": { "type": "boolean", "description": l.pick("Treat the pattern as a literal string", "把 pattern 当作字面量"), "default": false },
+                "word_boundary": { "type": "boolean", "description": l.pick("Only match whole words", "只匹配整个单词"), "default": false },
+                "multiline": { "type": "boolean", "description": l.pick("Allow matches to span lines", "允许跨行匹配"), "default": false },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            },
+            "required": ["pattern"]
+        }
+    })
+}
+
+fn globs(args: &Value, key: &str) -> Option<Vec<String>> {
+    args.get(key)
+        .and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|g| g.as_str()).filter(|g| !g.is_empty()).map(str::to_string).collect())
+}
+
+/// 执行 grep_project
+pub async fn call(args: &Value, cancel: &CancellationToken) -> Result<String, String> {
+    let root = crate::direct_tools::project_root(args)?;
+    let pattern = args.get("pattern").and_then(|p| p.as_str()).filter(|p| !p.is_empty())
+        .ok_or_else(|| "missing required argument: pattern".to_string())?;
+    let path = match args.get("path").and_then(|p| p.as_str()).filter(|p| !p.is_empty()) {
+        Some(p) => crate::direct_tools::virtual_path(Path::new(&root), p)?,
+        None => "/codebase".to_string(),
+    };
+    // 其余选项与 rg 命令同名
+    let opts = RgOptions {
+        context: args.get("context_lines").and_then(|c| c.as_u64()).map(|c| c as usize),
+        ..RgOptions::from_json(args)
+    };
+
+    let mut executor = ToolExecutor::new(&root);
+    executor.cancel = cancel.clone();
+    let output = executor
+        .rg(pattern, &path, globs(args, "include").as_deref(), globs(args, "exclude").as_deref(), &opts)
+        .await;
+    match output.strip_prefix("Error: ") {
+        Some(e) => Err(e.to_string()),
+        None => Ok(output),
+    }
+}
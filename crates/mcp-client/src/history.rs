@@ -0,0 +1,231 @@
+//! 搜索历史
+//!
+//! 每次 AI 搜索（fast_context_search、codebase_qa、diff_context_search、fast_context_refine）结束后，
+//! 把查询、耗时、结果路径和结果文本追加到本地 JSONL 文件，只保留最近 `max_entries` 条。
+//! search_history 工具和 `history://searches` 资源读取这些记录，可以直接取回之前的结果而不必重新搜索。
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::config::HistoryConfig;
+use crate::i18n::Locale;
+
+pub const SCHEME: &str = "history://";
+pub const INDEX_URI: &str = "history://searches";
+pub const DEFAULT_MAX_ENTRIES: usize = 200;
+/// 每条记录保存的结果文本上限
+const MAX_TEXT_BYTES: usize = 64 * 1024;
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+
+/// 同一进程内串行读写历史文件
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 一次搜索的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+    /// Unix 秒
+    pub time: u64,
+    pub tool: String,
+    pub query: String,
+    pub project: String,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub partial: bool,
+    /// 结果文件：(路径, 行范围)
+    #[serde(default)]
+    pub files: Vec<(String, Vec<(u64, u64)>)>,
+    /// 返回给客户端的结果文本
+    pub text: String,
+}
+
+/// 历史文件路径；关闭或无法确定目录时为 None
+pub fn path(cfg: &HistoryConfig) -> Option<PathBuf> {
+    if cfg.max_entries == Some(0) {
+        return None;
+    }
+    if let Some(p) = &cfg.path {
+        return Some(p.clone());
+    }
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("state")))?;
+    Some(base.join("windsurf-mcp").join("history.jsonl"))
+}
+
+/// 新记录的 id
+pub fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..8].to_string()
+}
+
+/// 读取全部记录（旧的在前），无法解析的行跳过
+pub fn load(cfg: &HistoryConfig) -> Vec<Entry> {
+    let Some(path) = path(cfg) else { return Vec::new() };
+    let _guard = FILE_LOCK.lock().unwrap();
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect()
+}
+
+/// 追加一条记录，超过上限时只保留最近的
+pub fn record(cfg: &HistoryConfig, mut entry: Entry) {
+    let Some(path) = path(cfg) else { return };
+    if entry.text.len() > MAX_TEXT_BYTES {
+        entry.text = crate::executor::truncate_bytes(&entry.text, MAX_TEXT_BYTES);
+    }
+    let max = cfg.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+    let Ok(line) = serde_json::to_string(&entry) else { return };
+
+    let _guard = FILE_LOCK.lock().unwrap();
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let existing = std::fs::read_to_string(&path).unwrap_or_default();
+    let count = existing.lines().count();
+    let result = if count + 1 > max {
+        // 超出上限：重写文件，去掉最旧的
+        let mut lines: Vec<&str> = existing.lines().skip(count + 1 - max).collect();
+        lines.push(&line);
+        std::fs::write(&path, format!("{}\n", lines.join("\n")))
+    } else {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| writeln!(f, "{}", line))
+    };
+    if let Err(e) = result {
+        log_warn!("cannot write search history {}: {}", path.display(), e);
+    }
+}
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "search_history",
+        "description": l.pick(
+            "List recent AI searches (query, tool, duration, result files), or return a past result by id without running the search again. Searches are kept across sessions in a local history file.",
+            "列出最近的 AI 搜索（查询、工具、耗时、结果文件），或按 id 取回之前的结果而不必重新搜索。搜索记录跨会话保存在本地历史文件中。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": {
+                    "type": "string",
+                    "description": l.pick("Return the full stored result of this search", "返回这次搜索保存的完整结果")
+                },
+                "query": {
+                    "type": "string",
+                    "description": l.pick("Only searches whose query contains this text (case-insensitive)", "只列出查询包含该文本的搜索（不区分大小写）")
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Only searches of this project", "只列出该项目的搜索")
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": l.pick("Max searches to list (1-50, default 10)", "最多列出的搜索数（1-50，默认 10）"),
+                    "default": DEFAULT_LIMIT,
+                    "minimum": 1,
+                    "maximum": MAX_LIMIT
+                }
+            }
+        }
+    })
+}
+
+fn summary_line(e: &Entry) -> String {
+    format!(
+        "[{}] {} {} ({} ms{}) {:?} in {} -> {} files",
+        e.id,
+        crate::executor::format_unix_time(e.time),
+        e.tool,
+        e.duration_ms,
+        if e.partial { ", partial" } else { "" },
+        e.query,
+        e.project,
+        e.files.len()
+    )
+}
+
+fn render_list(entries: &[&Entry]) -> String {
+    let mut out = Vec::new();
+    for e in entries {
+        out.push(summary_line(e));
+        for (path, ranges) in &e.files {
+            let ranges: Vec<String> = ranges.iter().map(|(s, e)| format!("L{}-{}", s, e)).collect();
+            if ranges.is_empty() {
+                out.push(format!("  {}", path));
+            } else {
+                out.push(format!("  {} ({})", path, ranges.join(", ")));
+            }
+        }
+    }
+    out.join("\n")
+}
+
+fn render_entry(e: &Entry) -> String {
+    format!("{}\n\n{}", summary_line(e), e.text)
+}
+
+/// 执行 search_history
+pub fn call(args: &Value, cfg: &HistoryConfig) -> Result<String, String> {
+    if path(cfg).is_none() {
+        return Err("search history is disabled (history.max_entries = 0, or no HOME to store it in)".into());
+    }
+    let entries = load(cfg);
+    if let Some(id) = args.get("id").and_then(|v| v.as_str()).map(str::trim).filter(|v| !v.is_empty()) {
+        let entry = entries.iter().find(|e| e.id == id).ok_or_else(|| format!("no search with id {} in history", id))?;
+        return Ok(render_entry(entry));
+    }
+    let query = args.get("query").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+    let project = args.get("project_path").and_then(|v| v.as_str()).unwrap_or("").trim_end_matches('/');
+    let limit = args.get("limit").and_then(|v| v.as_u64()).map(|v| v as usize).unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let matched: Vec<&Entry> = entries
+        .iter()
+        .rev()
+        .filter(|e| query.is_empty() || e.query.to_lowercase().contains(&query))
+        .filter(|e| project.is_empty() || e.project == project)
+        .take(limit)
+        .collect();
+    if matched.is_empty() {
+        return Ok("No searches in history".into());
+    }
+    Ok(render_list(&matched))
+}
+
+/// resources/list 中的历史条目；没有记录时不列出
+pub fn resources(cfg: &HistoryConfig) -> Vec<Value> {
+    let count = load(cfg).len();
+    if count == 0 {
+        return Vec::new();
+    }
+    vec![json!({
+        "uri": INDEX_URI,
+        "name": "Search history",
+        "description": format!("{} recent searches; read history://searches/<id> for a stored result", count),
+        "mimeType": "text/plain"
+    })]
+}
+
+/// resources/read：history://searches 为列表，history://searches/<id> 为单次结果
+pub fn read(uri: &str, cfg: &HistoryConfig) -> Result<Value, String> {
+    let not_found = || format!("Resource not found: {}", uri);
+    let entries = load(cfg);
+    let text = if uri == INDEX_URI {
+        let recent: Vec<&Entry> = entries.iter().rev().take(MAX_LIMIT).collect();
+        if recent.is_empty() { "No searches in history".to_string() } else { render_list(&recent) }
+    } else {
+        let id = uri.strip_prefix(INDEX_URI).and_then(|r| r.strip_prefix('/')).ok_or_else(not_found)?;
+        render_entry(entries.iter().find(|e| e.id == id).ok_or_else(not_found)?)
+    };
+    Ok(json!({ "contents": [{ "uri": uri, "mimeType": "text/plain", "text": text }] }))
+}
@@ -0,0 +1,38 @@
+//! 工具描述本地化
+//!
+//! 语言来源：配置 language > 环境变量 WINDSURF_MCP_LANG > 默认 en。支持 en、zh-CN。
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    ZhCn,
+}
+
+impl Locale {
+    /// 解析语言标签（en、en-US、zh、zh-CN、zh_CN.UTF-8 …）
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.trim().to_lowercase().replace('_', "-");
+        let primary = tag.split(['-', '.']).next().unwrap_or("");
+        match primary {
+            "en" => Some(Locale::En),
+            "zh" => Some(Locale::ZhCn),
+            _ => None,
+        }
+    }
+
+    /// 按配置和环境变量确定语言
+    pub fn resolve(configured: Option<&str>) -> Self {
+        configured
+            .and_then(Self::parse)
+            .or_else(|| std::env::var("WINDSURF_MCP_LANG").ok().as_deref().and_then(Self::parse))
+            .unwrap_or(Locale::En)
+    }
+
+    /// 选择对应语言的文本
+    pub fn pick(self, en: &'static str, zh: &'static str) -> &'static str {
+        match self {
+            Locale::En => en,
+            Locale::ZhCn => zh,
+        }
+    }
+}
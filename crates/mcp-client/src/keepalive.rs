@@ -0,0 +1,69 @@
+//! 空闲保活
+//!
+//! 定期向客户端发送 MCP ping；连续多次收不到任何消息则判定对端已断开，
+//! 由主循环关闭会话并清理后台任务。
+//! 默认只在网络传输（sse / streamable-http / ws / --listen）上开启：stdio 的对端是启动本进程的客户端，
+//! 断开时 stdin 随之关闭，不需要 ping。
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::{mpsc, Notify};
+
+pub const PING_ID_PREFIX: &str = "keepalive-";
+const DEFAULT_INTERVAL_SECS: u64 = 30;
+const MAX_MISSED: u32 = 3;
+
+/// 默认开启保活的传输（Session::transport 的取值）
+const NETWORK_TRANSPORTS: &[&str] = &["sse", "streamable-http", "websocket", "tcp", "unix"];
+
+/// ping 间隔：配置 keepalive_secs > 环境变量 KEEPALIVE_SECS > 默认（网络传输 30 秒，其余关闭），0 = 关闭
+pub fn interval(configured: Option<u64>, transport: &str) -> Option<Duration> {
+    let default = if NETWORK_TRANSPORTS.contains(&transport) { DEFAULT_INTERVAL_SECS } else { 0 };
+    let secs = configured
+        .or_else(|| std::env::var("KEEPALIVE_SECS").ok().and_then(|v| v.trim().parse().ok()))
+        .unwrap_or(default);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// 对端存活状态
+#[derive(Clone, Default)]
+pub struct Liveness {
+    missed: Arc<AtomicU32>,
+    dead: Arc<Notify>,
+}
+
+impl Liveness {
+    /// 收到客户端任意消息
+    pub fn seen(&self) {
+        self.missed.store(0, Ordering::Relaxed);
+    }
+
+    /// 等待对端被判定断开
+    pub async fn dead(&self) {
+        self.dead.notified().await
+    }
+}
+
+/// 周期发送 ping，超过 MAX_MISSED 次无消息则通知 `dead`
+pub fn spawn(live: Liveness, every: Duration, out: mpsc::UnboundedSender<String>) -> tokio::task::JoinHandle<()> {
+    let seq = AtomicU64::new(0);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if live.missed.load(Ordering::Relaxed) >= MAX_MISSED {
+                log_warn!("no reply to {} keepalive pings, closing session", MAX_MISSED);
+                live.dead.notify_one();
+                return;
+            }
+            let id = format!("{}{}", PING_ID_PREFIX, seq.fetch_add(1, Ordering::Relaxed) + 1);
+            let ping = json!({ "jsonrpc": "2.0", "id": id, "method": "ping" });
+            if out.send(ping.to_string()).is_err() { return; }
+            live.missed.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
@@ -0,0 +1,243 @@
+//! 语言识别 + 生成/第三方文件检测 + 测试文件识别
+//!
+//! 按扩展名/文件名猜测语言，按内容特征、.gitattributes (linguist-generated/linguist-vendored)
+//! 和目录名判断是否为生成、压缩或第三方文件，按路径判断是否为测试文件。
+
+use std::path::Path;
+
+/// 按扩展名/文件名猜测语言
+pub fn detect_language(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    match name.as_str() {
+        "dockerfile" => return Some("Dockerfile"),
+        "makefile" | "gnumakefile" => return Some("Makefile"),
+        "cmakelists.txt" => return Some("CMake"),
+        _ => {}
+    }
+    let ext = name.rsplit_once('.')?.1;
+    let lang = match ext {
+        "rs" => "Rust",
+        "go" => "Go",
+        "py" | "pyi" => "Python",
+        "js" | "mjs" | "cjs" | "jsx" => "JavaScript",
+        "ts" | "mts" | "cts" | "tsx" => "TypeScript",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "scala" => "Scala",
+        "swift" => "Swift",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "lua" => "Lua",
+        "dart" => "Dart",
+        "ex" | "exs" => "Elixir",
+        "erl" | "hrl" => "Erlang",
+        "hs" => "Haskell",
+        "ml" | "mli" => "OCaml",
+        "zig" => "Zig",
+        "vue" => "Vue",
+        "svelte" => "Svelte",
+        "sh" | "bash" | "zsh" => "Shell",
+        "ps1" => "PowerShell",
+        "sql" => "SQL",
+        "proto" => "Protobuf",
+        "html" | "htm" => "HTML",
+        "css" | "scss" | "sass" | "less" => "CSS",
+        "json" => "JSON",
+        "yaml" | "yml" => "YAML",
+        "toml" => "TOML",
+        "xml" => "XML",
+        "md" | "markdown" => "Markdown",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+/// 判断是否为生成/压缩文件，返回原因
+pub fn generated_reason(path: &Path, content: &str) -> Option<&'static str> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.contains(".min.") {
+        return Some("minified");
+    }
+    if name.ends_with(".pb.go") || name.ends_with("_pb2.py") || name.ends_with(".g.dart") || name.ends_with(".generated.ts") {
+        return Some("generated");
+    }
+
+    let markers = ["@generated", "do not edit", "code generated by", "auto-generated", "autogenerated"];
+    for line in content.lines().take(10) {
+        let lower = line.to_lowercase();
+        if markers.iter().any(|m| lower.contains(m)) {
+            return Some("generated");
+        }
+    }
+
+    // 行很少但极长 → 压缩文件
+    let (count, total) = content.lines().take(200).fold((0usize, 0usize), |(c, t), l| (c + 1, t + l.len()));
+    if count > 0 && total / count > 500 {
+        return Some("minified");
+    }
+    None
+}
+
+/// 视为第三方代码的目录名
+const VENDOR_DIRS: &[&str] = &["vendor", "vendors", "third_party", "third-party", "thirdparty", "node_modules", "bower_components", "Pods"];
+
+/// 按路径判断是否为第三方代码
+pub fn is_vendored_path(path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    let mut parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    parts.pop();
+    parts.iter().any(|d| VENDOR_DIRS.contains(d))
+}
+
+/// .gitattributes 模式 → 正则（不含 / 的模式匹配任意层级的文件名）
+fn gitattr_regex(pattern: &str) -> Option<regex_lite::Regex> {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    let mut re = String::from(if anchored { "^" } else { "(^|/)" });
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex_lite::escape(&c.to_string())),
+        }
+    }
+    // 目录模式同时匹配其下所有文件
+    re.push_str("(/.*)?$");
+    regex_lite::Regex::new(&re).ok()
+}
+
+/// 根目录 .gitattributes 中的 linguist-generated / linguist-vendored 规则
+#[derive(Default)]
+pub struct LinguistAttrs {
+    /// (模式, 属性名, 是否设置)，按文件顺序
+    rules: Vec<(regex_lite::Regex, &'static str, bool)>,
+}
+
+impl LinguistAttrs {
+    pub fn load(root: &Path) -> Self {
+        let Ok(text) = std::fs::read_to_string(root.join(".gitattributes")) else { return Self::default() };
+        let mut rules = Vec::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let mut fields = line.split_whitespace();
+            let Some(re) = fields.next().and_then(gitattr_regex) else { continue };
+            for attr in fields {
+                let (name, set) = match attr.strip_prefix('-').or_else(|| attr.strip_prefix('!')) {
+                    Some(n) => (n, false),
+                    None => match attr.split_once('=') {
+                        Some((n, v)) => (n, v != "false"),
+                        None => (attr, true),
+                    },
+                };
+                let name = match name {
+                    "linguist-generated" => "generated",
+                    "linguist-vendored" => "vendored",
+                    _ => continue,
+                };
+                rules.push((re.clone(), name, set));
+            }
+        }
+        Self { rules }
+    }
+
+    /// 最后一条匹配规则生效；None = 未指定
+    fn get(&self, rel: &str, attr: &str) -> Option<bool> {
+        self.rules.iter().rev().find(|(re, name, _)| *name == attr && re.is_match(rel)).map(|(_, _, set)| *set)
+    }
+}
+
+/// 生成/第三方/压缩文件判断（.gitattributes > 目录名 > 文件内容），返回标注
+pub fn provenance(root: &Path, rel: &str, attrs: &LinguistAttrs) -> Option<&'static str> {
+    let rel = rel.replace('\\', "/");
+    let generated = attrs.get(&rel, "generated");
+    let vendored = attrs.get(&rel, "vendored");
+    if generated == Some(true) {
+        return Some("generated");
+    }
+    if vendored == Some(true) || (vendored.is_none() && is_vendored_path(&rel)) {
+        return Some("vendored");
+    }
+    if generated == Some(false) {
+        return None;
+    }
+    let path = root.join(&rel);
+    let mut head = Vec::new();
+    let file = std::fs::File::open(&path).ok()?;
+    std::io::Read::read_to_end(&mut std::io::Read::take(file, 64 * 1024), &mut head).ok()?;
+    generated_reason(&path, &String::from_utf8_lossy(&head))
+}
+
+/// 测试目录/文件的 rg glob（include_tests=false 时排除）
+pub const TEST_GLOBS: &[&str] = &[
+    "**/test/**", "**/tests/**", "**/__tests__/**", "**/spec/**", "**/testdata/**",
+    "**/*_test.*", "**/test_*.py", "**/*.test.*", "**/*.spec.*", "**/*Test.java", "**/*Tests.cs",
+];
+
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "specs", "testdata", "testing"];
+
+/// 按路径判断是否为测试文件（目录名或文件命名约定）
+pub fn is_test_path(path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    let mut parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    let Some(name) = parts.pop() else { return false };
+    if parts.iter().any(|d| TEST_DIRS.contains(&d.to_lowercase().as_str())) {
+        return true;
+    }
+    let stem = name.split('.').next().unwrap_or(name);
+    let lower = name.to_lowercase();
+    lower.starts_with("test_")
+        || stem.ends_with("_test")
+        || stem.ends_with("_spec")
+        || lower.contains(".test.")
+        || lower.contains(".spec.")
+        || ((stem.ends_with("Test") || stem.ends_with("Tests")) && stem.len() > 5)
+}
+
+/// 查询是否明确针对测试
+pub fn mentions_tests(query: &str) -> bool {
+    let lower = query.to_lowercase();
+    if lower.contains("测试") || lower.contains("单测") {
+        return true;
+    }
+    lower
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|w| w.starts_with("test") || matches!(w, "spec" | "specs" | "fixture" | "fixtures" | "mock" | "mocks" | "e2e"))
+}
+
+/// 各语言 import/include 语句的正则（tree-sitter 不支持时使用）
+pub fn import_pattern(language: &str) -> Option<&'static str> {
+    let pat = match language {
+        "C" | "C++" => r"^\s*#\s*(include|import)\b",
+        "Java" | "Kotlin" | "Scala" | "Swift" | "Dart" | "Haskell" => r"^\s*import\b",
+        "C#" => r"^\s*(global\s+)?using\s+[\w.]+",
+        "Ruby" => r"^\s*(require|require_relative|load)\b",
+        "PHP" => r"^\s*(use|require|require_once|include|include_once)\b",
+        "Lua" => r#"\brequire\s*[("']"#,
+        "Elixir" => r"^\s*(import|alias|use|require)\b",
+        "Erlang" => r"^\s*-(include|include_lib|import)\b",
+        "OCaml" => r"^\s*open\b",
+        "Zig" => r"@import\(",
+        "Shell" => r"^\s*(source|\.)\s+\S",
+        "Protobuf" => r"^\s*import\b",
+        "CSS" => r"^\s*@(import|use)\b",
+        "Vue" | "Svelte" => r"^\s*import\b",
+        "Rust" => r"^\s*(pub\s+)?(use|extern\s+crate|mod)\b",
+        "Python" => r"^\s*(import|from\s+\S+\s+import)\b",
+        "Go" => r"^\s*import\b",
+        "JavaScript" | "TypeScript" => r#"^\s*import\b|\brequire\(['"]"#,
+        _ => return None,
+    };
+    Some(pat)
+}
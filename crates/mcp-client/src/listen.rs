@@ -0,0 +1,108 @@
+//! 本地套接字监听模式
+//!
+//! `--listen unix:/path/to.sock` 或 `--listen tcp:127.0.0.1:9000`：一个常驻进程服务多个本地客户端，
+//! 每个连接是一个独立会话，分帧方式与 stdio 相同（按首条消息自动识别 LSP / 按行）。
+//! TCP 只允许监听回环地址，需要远程访问请使用带令牌的 ws 传输。
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+pub enum Listen {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Listen {
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        match spec.split_once(':') {
+            Some(("unix", path)) if !path.is_empty() => Ok(Listen::Unix(PathBuf::from(path))),
+            Some(("tcp", addr)) if !addr.is_empty() => Ok(Listen::Tcp(addr.to_string())),
+            _ => anyhow::bail!("invalid --listen {} (expected unix:/path/to.sock or tcp:host:port)", spec),
+        }
+    }
+}
+
+/// 监听并为每个连接启动会话，Ctrl-C 退出
+pub async fn run(listen: Listen) -> anyhow::Result<()> {
+    match listen {
+        Listen::Tcp(addr) => run_tcp(&addr).await,
+        Listen::Unix(path) => run_unix(path).await,
+    }
+}
+
+async fn run_tcp(addr: &str) -> anyhow::Result<()> {
+    let addr: SocketAddr = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("cannot resolve {}", addr))?;
+    if !addr.ip().is_loopback() {
+        anyhow::bail!("refusing to listen on {}: tcp listen mode is loopback-only (use --transport ws with --auth-token for remote clients)", addr);
+    }
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log_info!("listening on tcp:{}", listener.local_addr()?);
+    crate::ripgrep::spawn_probe();
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log_warn!("accept error: {}", e);
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        tokio::spawn(async move {
+            let label = format!("connection {}", peer);
+            log_info!("{} opened", label);
+            let (input, output) = stream.into_split();
+            crate::serve_stream(input, output, &label, "tcp").await;
+            log_info!("{} closed", label);
+        });
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn run_unix(path: PathBuf) -> anyhow::Result<()> {
+    // 上次异常退出留下的套接字文件：无人监听则删除，仍在使用则拒绝
+    if path.exists() {
+        if tokio::net::UnixStream::connect(&path).await.is_ok() {
+            anyhow::bail!("{} is already served by another process", path.display());
+        }
+        std::fs::remove_file(&path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(&path)?;
+    log_info!("listening on unix:{}", path.display());
+    crate::ripgrep::spawn_probe();
+
+    let mut seq: u64 = 0;
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log_warn!("accept error: {}", e);
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        seq += 1;
+        tokio::spawn(async move {
+            let label = format!("connection #{}", seq);
+            log_info!("{} opened", label);
+            let (input, output) = stream.into_split();
+            crate::serve_stream(input, output, &label, "unix").await;
+            log_info!("{} closed", label);
+        });
+    }
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn run_unix(path: PathBuf) -> anyhow::Result<()> {
+    anyhow::bail!("unix sockets are not supported on this platform ({}); use tcp:127.0.0.1:PORT", path.display())
+}
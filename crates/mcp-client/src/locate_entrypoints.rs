@@ -0,0 +1,108 @@
+//! locate_entrypoints 工具：启发式查找应用入口
+//!
+//! 由执行器组合 glob、rg 和 outline 完成（见 `ToolExecutor::entrypoints`）：main 函数、服务启动代码、
+//! CLI 定义、Dockerfile 的 ENTRYPOINT/CMD 以及 package.json/Cargo.toml/pyproject.toml 中声明的入口，
+//! 返回路径和行范围。rg 不可用时只给出按文件名找到的结果。
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::executor::ToolExecutor;
+use crate::i18n::Locale;
+
+const KINDS: &[&str] = &["main", "server", "cli", "container", "manifest"];
+const DEFAULT_MAX_RESULTS: usize = 30;
+const MAX_RESULTS: usize = 100;
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "locate_entrypoints",
+        "description": l.pick(
+            "Find where an application starts: main functions, server bootstrap code, CLI definitions, Dockerfile ENTRYPOINT/CMD and entrypoints declared in package.json, Cargo.toml or pyproject.toml. Heuristic, without an AI search; returns paths with line ranges.",
+            "查找应用从哪里启动：main 函数、服务启动代码、CLI 定义、Dockerfile 的 ENTRYPOINT/CMD，以及 package.json、Cargo.toml、pyproject.toml 中声明的入口。启发式查找，不经 AI 搜索，返回路径和行范围。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": l.pick("Directory to search, relative to project_path (default: whole project)", "要搜索的目录，相对 project_path（默认整个项目）"),
+                    "default": ""
+                },
+                "kinds": {
+                    "type": "array",
+                    "items": { "type": "string", "enum": KINDS },
+                    "description": l.pick("Only these kinds of entrypoints (default all)", "只返回这些类别的入口（默认全部）")
+                },
+                "max_results": {
+                    "type": "integer",
+                    "description": l.pick("Max entrypoints to return (1-100, default 30)", "最多返回的入口数（1-100，默认 30）"),
+                    "default": DEFAULT_MAX_RESULTS,
+                    "minimum": 1,
+                    "maximum": MAX_RESULTS
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            }
+        }
+    })
+}
+
+/// 执行 locate_entrypoints
+pub async fn call(args: &Value, cancel: &tokio_util::sync::CancellationToken) -> Result<String, String> {
+    let root = crate::direct_tools::project_root(args)?;
+    let path = args.get("path").and_then(|p| p.as_str()).map(str::trim).unwrap_or("");
+    let virtual_path = crate::direct_tools::virtual_path(Path::new(&root), path)?;
+    let virtual_path = virtual_path.trim_end_matches('/');
+    let kinds: Vec<&str> = args
+        .get("kinds")
+        .and_then(|k| k.as_array())
+        .map(|a| a.iter().filter_map(|k| k.as_str()).collect())
+        .unwrap_or_default();
+    if let Some(bad) = kinds.iter().find(|k| !KINDS.contains(k)) {
+        return Err(format!("unknown kind: {} (expected {})", bad, KINDS.join(", ")));
+    }
+    let max = args.get("max_results").and_then(|m| m.as_u64()).map(|m| m as usize)
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .clamp(1, MAX_RESULTS);
+
+    let display = |p: &str| {
+        let rel = p.trim_start_matches("/codebase").trim_start_matches('/');
+        if rel.is_empty() { Path::new(&root).to_path_buf() } else { Path::new(&root).join(rel) }
+    };
+    if !display(virtual_path).is_dir() {
+        return Err(format!("directory not found: {}", path));
+    }
+
+    let mut exec = ToolExecutor::new(&root);
+    exec.cancel = cancel.clone();
+    let (mut found, note) = exec.entrypoints(virtual_path).await;
+    found.retain(|e| kinds.is_empty() || kinds.contains(&e.kind));
+    found.sort_by(|a, b| {
+        let rank = |k: &str| KINDS.iter().position(|x| *x == k).unwrap_or(KINDS.len());
+        rank(a.kind).cmp(&rank(b.kind)).then_with(|| a.path.cmp(&b.path)).then(a.start.cmp(&b.start))
+    });
+    let total = found.len();
+    found.truncate(max);
+
+    let mut out = if found.is_empty() {
+        vec![format!("No entrypoints found in {}", display(virtual_path).display())]
+    } else {
+        vec![format!("Entrypoints in {} ({}):", display(virtual_path).display(), total)]
+    };
+    for e in &found {
+        out.push(format!("[{}] {}:{}-{} {}", e.kind, display(&e.path).display(), e.start, e.end, e.detail));
+    }
+    if total > found.len() {
+        out.push(format!("(+{} more, raise max_results)", total - found.len()));
+    }
+    if let Some(note) = note {
+        out.push(format!("({})", note));
+    }
+    Ok(out.join("\n"))
+}
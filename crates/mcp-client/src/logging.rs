@@ -0,0 +1,319 @@
+//! 日志输出
+//!
+//! 目标：stderr（默认）、file、syslog、journald、eventlog (Windows 事件日志)。
+//! 来源：环境变量 WINDSURF_MCP_LOG（`syslog`、`journald`、`file:/path/to.log` …）> 配置 log。
+//! 启动时确定一次，配置热重载不影响日志目标；目标不可用时退回 stderr。
+
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    /// syslog severity
+    fn severity(self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug => 7,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    #[default]
+    Stderr,
+    File,
+    Syslog,
+    Journald,
+    Eventlog,
+}
+
+impl LogTarget {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "stderr" => Some(LogTarget::Stderr),
+            "file" => Some(LogTarget::File),
+            "syslog" => Some(LogTarget::Syslog),
+            "journald" => Some(LogTarget::Journald),
+            "eventlog" => Some(LogTarget::Eventlog),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogTarget::Stderr => "stderr",
+            LogTarget::File => "file",
+            LogTarget::Syslog => "syslog",
+            LogTarget::Journald => "journald",
+            LogTarget::Eventlog => "eventlog",
+        }
+    }
+}
+
+/// 配置文件中的 log 段
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub target: Option<LogTarget>,
+    /// target = file 时的日志文件
+    pub path: Option<String>,
+    /// syslog/journald/eventlog 的标识，默认 windsurf-mcp
+    pub ident: Option<String>,
+    /// 最低输出级别，默认 debug（全部输出）
+    pub level: Option<Level>,
+}
+
+impl LogConfig {
+    /// 叠加环境变量 WINDSURF_MCP_LOG
+    pub fn effective(&self) -> Result<LogConfig, String> {
+        let mut cfg = self.clone();
+        let Some(spec) = std::env::var("WINDSURF_MCP_LOG").ok().filter(|v| !v.is_empty()) else { return Ok(cfg) };
+        let (name, path) = match spec.split_once(':') {
+            Some((n, p)) => (n, Some(p.to_string())),
+            None => (spec.as_str(), None),
+        };
+        cfg.target = Some(LogTarget::parse(name).ok_or_else(|| format!("WINDSURF_MCP_LOG: unknown target {}", name))?);
+        if path.is_some() {
+            cfg.path = path;
+        }
+        Ok(cfg)
+    }
+}
+
+const DEFAULT_IDENT: &str = "windsurf-mcp";
+
+enum Sink {
+    Stderr,
+    File(std::fs::File),
+    #[cfg(unix)]
+    Syslog(std::os::unix::net::UnixDatagram),
+    #[cfg(unix)]
+    Journald(std::os::unix::net::UnixDatagram),
+    #[cfg(windows)]
+    EventLog(eventlog::Source),
+}
+
+struct Logger {
+    sink: Sink,
+    ident: String,
+    level: Level,
+}
+
+static LOGGER: OnceLock<Mutex<Logger>> = OnceLock::new();
+
+fn open(cfg: &LogConfig) -> Result<Sink, String> {
+    match cfg.target.unwrap_or_default() {
+        LogTarget::Stderr => Ok(Sink::Stderr),
+        LogTarget::File => {
+            let path = cfg.path.as_deref().ok_or("log.target = file requires log.path")?;
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(Sink::File)
+                .map_err(|e| format!("{}: {}", path, e))
+        }
+        #[cfg(unix)]
+        LogTarget::Syslog => {
+            let sock = std::os::unix::net::UnixDatagram::unbound().map_err(|e| e.to_string())?;
+            ["/dev/log", "/var/run/syslog", "/var/run/log"]
+                .iter()
+                .find(|p| sock.connect(p).is_ok())
+                .ok_or("no syslog socket (/dev/log)")?;
+            Ok(Sink::Syslog(sock))
+        }
+        #[cfg(unix)]
+        LogTarget::Journald => {
+            let sock = std::os::unix::net::UnixDatagram::unbound().map_err(|e| e.to_string())?;
+            sock.connect("/run/systemd/journal/socket").map_err(|e| format!("journald: {}", e))?;
+            Ok(Sink::Journald(sock))
+        }
+        #[cfg(windows)]
+        LogTarget::Eventlog => eventlog::Source::register(cfg.ident.as_deref().unwrap_or(DEFAULT_IDENT)).map(Sink::EventLog),
+        #[allow(unreachable_patterns)]
+        other => Err(format!("log target {} is not supported on this platform", other.as_str())),
+    }
+}
+
+/// 按配置初始化日志目标，失败时退回 stderr 并提示
+pub fn init(cfg: &LogConfig) {
+    let (cfg, err) = match cfg.effective() {
+        Ok(c) => (c, None),
+        Err(e) => (cfg.clone(), Some(e)),
+    };
+    let (sink, err) = match open(&cfg) {
+        Ok(s) => (s, err),
+        Err(e) => (Sink::Stderr, Some(e)),
+    };
+    let logger = Logger {
+        sink,
+        ident: cfg.ident.clone().unwrap_or_else(|| DEFAULT_IDENT.into()),
+        level: cfg.level.unwrap_or(Level::Debug),
+    };
+    if LOGGER.set(Mutex::new(logger)).is_err() {
+        return;
+    }
+    if let Some(e) = err {
+        write(Level::Warn, &format!("log target unavailable ({}), logging to stderr", e));
+    }
+}
+
+/// UTC 时间 YYYY-MM-DDTHH:MM:SSZ
+fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // civil_from_days (Howard Hinnant)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// journald 原生协议：含换行的值使用二进制长度编码
+#[cfg(unix)]
+fn journal_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+fn write_stderr(msg: &str) {
+    eprintln!("[mcp-client] {}", msg);
+}
+
+/// 写一条日志；未初始化时写 stderr
+pub fn write(level: Level, msg: &str) {
+    let Some(logger) = LOGGER.get() else { return write_stderr(msg) };
+    let Ok(mut logger) = logger.lock() else { return write_stderr(msg) };
+    if level > logger.level {
+        return;
+    }
+    let Logger { sink, ident, .. } = &mut *logger;
+    let delivered = match sink {
+        Sink::Stderr => {
+            write_stderr(msg);
+            true
+        }
+        Sink::File(f) => writeln!(f, "{} {:5} {}", timestamp(), level.as_str(), msg).is_ok(),
+        #[cfg(unix)]
+        Sink::Syslog(sock) => {
+            // facility user (1)
+            let line = format!("<{}>{}[{}]: {}", 8 + level.severity(), ident, std::process::id(), msg);
+            sock.send(line.as_bytes()).is_ok()
+        }
+        #[cfg(unix)]
+        Sink::Journald(sock) => {
+            let mut buf = Vec::new();
+            journal_field(&mut buf, "MESSAGE", msg);
+            journal_field(&mut buf, "PRIORITY", &level.severity().to_string());
+            journal_field(&mut buf, "SYSLOG_IDENTIFIER", ident);
+            sock.send(&buf).is_ok()
+        }
+        #[cfg(windows)]
+        Sink::EventLog(source) => source.report(level, msg),
+    };
+    if !delivered {
+        write_stderr(msg);
+    }
+}
+
+#[cfg(windows)]
+mod eventlog {
+    use windows_sys::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+        EVENTLOG_WARNING_TYPE,
+    };
+
+    use super::Level;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    pub struct Source(windows_sys::Win32::Foundation::HANDLE);
+
+    // 事件源句柄可跨线程使用，访问由外层 Mutex 串行化
+    unsafe impl Send for Source {}
+
+    impl Source {
+        pub fn register(name: &str) -> Result<Self, String> {
+            let name = wide(name);
+            let handle = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+            if handle.is_null() {
+                return Err(format!("RegisterEventSourceW failed: {}", std::io::Error::last_os_error()));
+            }
+            Ok(Self(handle))
+        }
+
+        pub fn report(&self, level: Level, msg: &str) -> bool {
+            let kind = match level {
+                Level::Error => EVENTLOG_ERROR_TYPE,
+                Level::Warn => EVENTLOG_WARNING_TYPE,
+                Level::Info | Level::Debug => EVENTLOG_INFORMATION_TYPE,
+            };
+            let text = wide(msg);
+            let strings = [text.as_ptr()];
+            unsafe {
+                ReportEventW(self.0, kind, 0, 1, std::ptr::null_mut(), 1, 0, strings.as_ptr(), std::ptr::null()) != 0
+            }
+        }
+    }
+
+    impl Drop for Source {
+        fn drop(&mut self) {
+            unsafe { DeregisterEventSource(self.0) };
+        }
+    }
+}
+
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::logging::write($crate::logging::Level::Error, &format!($($arg)*)) };
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::logging::write($crate::logging::Level::Warn, &format!($($arg)*)) };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::logging::write($crate::logging::Level::Info, &format!($($arg)*)) };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::logging::write($crate::logging::Level::Debug, &format!($($arg)*)) };
+}
@@ -0,0 +1,161 @@
+//! LSP 服务器模式 (`--lsp`)
+//!
+//! 给不支持 MCP 的编辑器 (vim/emacs LSP 客户端) 使用：
+//! workspace/symbol 基于 tree-sitter 符号搜索，
+//! workspace/executeCommand `windsurf.fastContextSearch` 执行语义搜索。
+
+use std::path::PathBuf;
+
+use reqwest::Url;
+use serde_json::{json, Value};
+use tokio::io::BufReader;
+use tokio_util::sync::CancellationToken;
+
+use crate::session::Session;
+use crate::{config, outline, TransportMode};
+
+pub const SEARCH_COMMAND: &str = "windsurf.fastContextSearch";
+const MAX_SYMBOLS: usize = 200;
+
+/// tree-sitter 节点类型 → LSP SymbolKind
+fn symbol_kind(kind: &str) -> u32 {
+    match kind {
+        "mod_item" => 2,
+        "class_definition" | "class_declaration" | "abstract_class_declaration" | "impl_item" => 5,
+        "method_declaration" | "method_definition" => 6,
+        "enum_item" | "enum_declaration" => 10,
+        "trait_item" | "interface_declaration" => 11,
+        "struct_item" | "union_item" => 23,
+        "type_declaration" | "type_alias_declaration" => 26,
+        _ => 12,
+    }
+}
+
+/// initialize 参数中的工作区根目录：workspaceFolders > rootUri > rootPath > cwd
+fn workspace_root(params: &Value) -> PathBuf {
+    let from_uri = |v: Option<&Value>| {
+        v.and_then(|u| u.as_str())
+            .and_then(|u| Url::parse(u).ok())
+            .and_then(|u| u.to_file_path().ok())
+    };
+    from_uri(params.pointer("/workspaceFolders/0/uri"))
+        .or_else(|| from_uri(params.get("rootUri")))
+        .or_else(|| params.get("rootPath").and_then(|p| p.as_str()).map(PathBuf::from))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+}
+
+async fn workspace_symbol(root: PathBuf, query: String) -> Value {
+    let found = tokio::task::spawn_blocking(move || outline::workspace_symbols(&root, &query, MAX_SYMBOLS))
+        .await
+        .unwrap_or_default();
+    let symbols: Vec<Value> = found
+        .into_iter()
+        .filter_map(|(path, block)| {
+            let uri = Url::from_file_path(&path).ok()?;
+            Some(json!({
+                "name": block.name?,
+                "kind": symbol_kind(&block.kind),
+                "location": {
+                    "uri": uri.as_str(),
+                    "range": {
+                        "start": { "line": block.start_line - 1, "character": 0 },
+                        "end": { "line": block.end_line - 1, "character": 0 }
+                    }
+                }
+            }))
+        })
+        .collect();
+    json!(symbols)
+}
+
+/// executeCommand 参数：字符串查询或与 fast_context_search 相同的参数对象
+async fn execute_search(
+    params: &Value,
+    root: &std::path::Path,
+    config: &config::Config,
+    client: &reqwest::Client,
+    session: &Session,
+) -> Result<Value, String> {
+    let command = params.get("command").and_then(|c| c.as_str()).unwrap_or("");
+    if command != SEARCH_COMMAND {
+        return Err(format!("Unknown command: {}", command));
+    }
+    let mut args = match params.pointer("/arguments/0") {
+        Some(Value::String(q)) => json!({ "query": q }),
+        Some(Value::Object(o)) => Value::Object(o.clone()),
+        _ => return Err("Missing argument: query".into()),
+    };
+    let project_path = args.get("project_path").and_then(|p| p.as_str()).filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| root.to_string_lossy().to_string());
+    args["project_path"] = json!(project_path);
+
+    let (relay_url, access_token) = crate::relay_settings();
+    let search = crate::search_params(&args, config, &project_path).await?;
+    crate::do_search(client, &relay_url, &access_token, &search, session, &CancellationToken::new())
+        .await
+        .map(|out| json!(out.text))
+        .map_err(|e| e.to_string())
+}
+
+/// LSP 主循环（Content-Length 分帧）
+pub async fn run() -> anyhow::Result<()> {
+    let mut reader = BufReader::new(tokio::io::stdin());
+    let mut stdout = tokio::io::stdout();
+    let config = config::load();
+    crate::ripgrep::spawn_probe();
+    let client = reqwest::Client::builder().build()?;
+    let session = Session::new("lsp-server");
+    let mut root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+
+    loop {
+        let message = match crate::read_lsp_message(&mut reader, None, config.max_message_bytes()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(e) => {
+                log_error!("lsp read error: {}", e);
+                continue;
+            }
+        };
+        let request: Value = match serde_json::from_str(&message) {
+            Ok(v) => v,
+            Err(e) => {
+                log_warn!("lsp JSON parse error: {}", e);
+                continue;
+            }
+        };
+        let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = request.get("params").cloned().unwrap_or(json!({}));
+        if method == "exit" {
+            break;
+        }
+        let Some(id) = request.get("id").cloned() else { continue };
+
+        let result: Result<Value, (i64, String)> = match method {
+            "initialize" => {
+                root = workspace_root(&params);
+                Ok(json!({
+                    "capabilities": {
+                        "workspaceSymbolProvider": true,
+                        "executeCommandProvider": { "commands": [SEARCH_COMMAND] }
+                    },
+                    "serverInfo": { "name": "windsurf-relay-lsp", "version": env!("CARGO_PKG_VERSION") }
+                }))
+            }
+            "shutdown" => Ok(Value::Null),
+            "workspace/symbol" => {
+                let query = params.get("query").and_then(|q| q.as_str()).unwrap_or("").to_string();
+                Ok(workspace_symbol(root.clone(), query).await)
+            }
+            "workspace/executeCommand" => execute_search(&params, &root, &config, &client, &session).await.map_err(|e| (-32602, e)),
+            _ => Err((-32601, format!("Method not found: {}", method))),
+        };
+
+        let response = match result {
+            Ok(r) => json!({ "jsonrpc": "2.0", "id": id, "result": r }),
+            Err((code, msg)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": msg } }),
+        };
+        crate::write_message(&mut stdout, TransportMode::Lsp, &response.to_string()).await?;
+    }
+    Ok(())
+}
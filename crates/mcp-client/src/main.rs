@@ -1,19 +1,133 @@
+#[macro_use]
+mod logging;
 mod protocol;
 mod windsurf;
 mod prompt;
 mod executor;
+mod lang;
+mod outline;
+mod archive;
+mod config;
+mod provider;
+mod quota;
+mod remote;
+mod i18n;
+mod keepalive;
+mod lsp;
+mod resources;
+mod pages;
+mod config_cmd;
+mod ripgrep;
+mod search_fallback;
+mod rg_results;
+mod result_cache;
+mod rg_install;
+mod repo_sample;
+mod path_remap;
+mod direct_tools;
+mod sse;
+mod streamable;
+mod ws;
+mod listen;
+mod roots;
+mod mcp_version;
+mod tool_registry;
+mod completion;
+mod session;
+mod read_ranges;
+mod repo_overview;
+mod grep_project;
+mod symbol_search;
+mod diff_scope;
+mod related_tests;
+mod dependency_trace;
+mod codebase_stats;
+mod git;
+mod git_blame_context;
+mod locate_entrypoints;
+mod conversation;
+mod history;
+mod audit;
+mod deny_paths;
+mod redact;
+mod text_encoding;
+mod verify_ranges;
+mod explain_symbol;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
 use serde_json::{json, Value};
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio_util::sync::CancellationToken;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Catch panics so the process doesn't silently die
     std::panic::set_hook(Box::new(|info| {
-        eprintln!("[mcp-client] PANIC: {}", info);
+        log_error!("PANIC: {}", info);
     }));
-    run_mcp_server().await
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(i) = args.iter().position(|a| a == "--config") {
+        match args.get(i + 1) {
+            Some(p) => config::set_cli_path(PathBuf::from(p)),
+            None => anyhow::bail!("--config requires a path"),
+        }
+    }
+    let subcommand = (0..args.len())
+        .find(|&i| matches!(args[i].as_str(), "config" | "doctor") && (i == 0 || args[i - 1] != "--config"));
+    if let Some(i) = subcommand {
+        std::process::exit(match args[i].as_str() {
+            "doctor" => config_cmd::doctor(args.iter().any(|a| a == "--install-rg")).await,
+            _ => config_cmd::run(args.get(i + 1).map(|s| s.as_str())),
+        });
+    }
+    let startup_config = config::load();
+    logging::init(&startup_config.log);
+    executor::set_no_ignore(args.iter().any(|a| a == "--no-ignore") || startup_config.no_ignore == Some(true));
+    redact::set_enabled(!args.iter().any(|a| a == "--no-redact") && startup_config.redact_secrets != Some(false));
+    // Fail closed: a denylist that does not compile must not silently allow everything
+    deny_paths::init(&startup_config.deny_paths).map_err(|e| anyhow::anyhow!(e))?;
+    if args.iter().any(|a| a == "--lsp") {
+        return lsp::run().await;
+    }
+    let flag = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).cloned();
+    if let Some(spec) = flag("--listen") {
+        return listen::run(listen::Listen::parse(&spec)?).await;
+    }
+    let transport = flag("--transport").or_else(|| std::env::var("WINDSURF_MCP_TRANSPORT").ok().filter(|t| !t.is_empty()));
+    match transport.as_deref() {
+        None | Some("stdio") => run_mcp_server().await,
+        Some(t @ ("sse" | "http" | "streamable-http" | "ws" | "websocket")) => {
+            let host = flag("--host").unwrap_or_else(|| "127.0.0.1".into());
+            let port = match flag("--port") {
+                Some(p) => p.parse::<u16>().map_err(|_| anyhow::anyhow!("invalid --port: {}", p))?,
+                None => sse::DEFAULT_PORT,
+            };
+            let addr = tokio::net::lookup_host((host.as_str(), port))
+                .await?
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("cannot resolve --host {}", host))?;
+            let allow_origin = flag("--allow-origin");
+            let token = flag("--auth-token").or_else(|| std::env::var("WINDSURF_MCP_AUTH_TOKEN").ok().filter(|t| !t.is_empty()));
+            match t {
+                "sse" => sse::run(addr, token, allow_origin).await,
+                "ws" | "websocket" => ws::run(addr, token, allow_origin).await,
+                _ => streamable::run(addr, token, allow_origin).await,
+            }
+        }
+        Some(other) => anyhow::bail!("unknown transport {} (expected stdio, sse, http or ws)", other),
+    }
+}
+
+/// Relay URL and access token from the environment
+fn relay_settings() -> (String, String) {
+    let relay_url = std::env::var("RELAY_URL")
+        .unwrap_or_else(|_| "http://localhost:3000".into());
+    let access_token = std::env::var("ACCESS_TOKEN")
+        .or_else(|_| std::env::var("WINDSURF_API_KEY"))
+        .unwrap_or_default();
+    (relay_url, access_token)
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -29,8 +143,13 @@ fn is_header_line(line: &str) -> bool {
     }
 }
 
-/// Read LSP-framed message (Content-Length header + body)
-async fn read_lsp_message(reader: &mut BufReader<tokio::io::Stdin>, first_line: Option<&str>) -> anyhow::Result<Option<String>> {
+/// Longest accepted LSP header line; the rest of a longer line is discarded unread
+const MAX_HEADER_LINE: u64 = 8 * 1024;
+
+/// Read LSP-framed message (Content-Length header + body).
+/// Content-Length counts bytes of the UTF-8 body, not characters; bodies over `max_bytes`
+/// are skipped without being buffered, and so are header lines over `MAX_HEADER_LINE`.
+async fn read_lsp_message<R: AsyncBufRead + Unpin>(reader: &mut R, first_line: Option<&str>, max_bytes: usize) -> anyhow::Result<Option<String>> {
     let mut content_length: Option<usize> = None;
     let mut seen_header = false;
 
@@ -47,9 +166,16 @@ async fn read_lsp_message(reader: &mut BufReader<tokio::io::Stdin>, first_line:
     }
 
     loop {
-        let mut line = String::new();
-        let bytes = reader.read_line(&mut line).await?;
+        let mut raw = Vec::new();
+        let bytes = (&mut *reader).take(MAX_HEADER_LINE).read_until(b'\n', &mut raw).await?;
         if bytes == 0 { return Ok(None); }
+        if !raw.ends_with(b"\n") && bytes as u64 == MAX_HEADER_LINE {
+            log_warn!("LSP header line longer than {} bytes ignored", MAX_HEADER_LINE);
+            skip_line(reader).await?;
+            seen_header = true;
+            continue;
+        }
+        let line = String::from_utf8_lossy(&raw);
         let trimmed = line.trim_end_matches(&['\r', '\n'][..]);
         if trimmed.is_empty() {
             if seen_header { break; }
@@ -66,13 +192,35 @@ async fn read_lsp_message(reader: &mut BufReader<tokio::io::Stdin>, first_line:
     }
 
     let length = content_length.ok_or_else(|| anyhow::anyhow!("Missing Content-Length"))?;
+    if length > max_bytes {
+        tokio::io::copy(&mut (&mut *reader).take(length as u64), &mut tokio::io::sink()).await?;
+        anyhow::bail!("Content-Length {} exceeds max_message_bytes {}; message discarded", length, max_bytes);
+    }
     let mut buf = vec![0u8; length];
     reader.read_exact(&mut buf).await?;
     Ok(Some(String::from_utf8(buf)?))
 }
 
+/// Consume input up to and including the next newline without buffering it
+async fn skip_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<()> {
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() { return Ok(()); }
+        match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                reader.consume(i + 1);
+                return Ok(());
+            }
+            None => {
+                let n = buf.len();
+                reader.consume(n);
+            }
+        }
+    }
+}
+
 /// Read a single line JSON message (Line mode)
-async fn read_line_message(reader: &mut BufReader<tokio::io::Stdin>) -> anyhow::Result<Option<String>> {
+async fn read_line_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> anyhow::Result<Option<String>> {
     loop {
         let mut line = String::new();
         let bytes = reader.read_line(&mut line).await?;
@@ -84,10 +232,10 @@ async fn read_line_message(reader: &mut BufReader<tokio::io::Stdin>) -> anyhow::
 }
 
 /// Auto-detect transport mode and read message
-async fn read_message(reader: &mut BufReader<tokio::io::Stdin>, mode: &mut Option<TransportMode>) -> anyhow::Result<Option<String>> {
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R, mode: &mut Option<TransportMode>, max_bytes: usize) -> anyhow::Result<Option<String>> {
     match mode {
         Some(TransportMode::Line) => read_line_message(reader).await,
-        Some(TransportMode::Lsp) => read_lsp_message(reader, None).await,
+        Some(TransportMode::Lsp) => read_lsp_message(reader, None, max_bytes).await,
         None => {
             // Auto-detect: read first non-empty line
             loop {
@@ -100,7 +248,7 @@ async fn read_message(reader: &mut BufReader<tokio::io::Stdin>, mode: &mut Optio
                 if is_header_line(trimmed) {
                     // LSP mode
                     *mode = Some(TransportMode::Lsp);
-                    return read_lsp_message(reader, Some(trimmed)).await;
+                    return read_lsp_message(reader, Some(trimmed), max_bytes).await;
                 } else {
                     // Line mode — this line IS the JSON message
                     *mode = Some(TransportMode::Line);
@@ -111,12 +259,14 @@ async fn read_message(reader: &mut BufReader<tokio::io::Stdin>, mode: &mut Optio
     }
 }
 
-async fn write_message(stdout: &mut tokio::io::Stdout, mode: TransportMode, payload: &str) -> anyhow::Result<()> {
+async fn write_message<W: AsyncWrite + Unpin>(stdout: &mut W, mode: TransportMode, payload: &str) -> anyhow::Result<()> {
     match mode {
         TransportMode::Lsp => {
-            let header = format!("Content-Length: {}\r\n\r\n", payload.len());
+            // Length of the encoded body in bytes (CJK/emoji take 3-4 bytes per char)
+            let body = payload.as_bytes();
+            let header = format!("Content-Length: {}\r\n\r\n", body.len());
             stdout.write_all(header.as_bytes()).await?;
-            stdout.write_all(payload.as_bytes()).await?;
+            stdout.write_all(body).await?;
         }
         TransportMode::Line => {
             stdout.write_all(payload.as_bytes()).await?;
@@ -128,31 +278,211 @@ async fn write_message(stdout: &mut tokio::io::Stdout, mode: TransportMode, payl
 }
 
 async fn run_mcp_server() -> anyhow::Result<()> {
-    let stdin = tokio::io::stdin();
-    let mut stdout = tokio::io::stdout();
-    let mut reader = BufReader::new(stdin);
-    let mut transport_mode: Option<TransportMode> = None;
+    ripgrep::spawn_probe();
+    serve_stream(stdin_stream(), tokio::io::stdout(), "stdin", "stdio").await;
+    Ok(())
+}
 
-    let relay_url = std::env::var("RELAY_URL")
-        .unwrap_or_else(|_| "http://localhost:3000".into());
-    let access_token = std::env::var("ACCESS_TOKEN")
-        .or_else(|_| std::env::var("WINDSURF_API_KEY"))
-        .unwrap_or_default();
-    let client = reqwest::Client::builder()
-        .build()?;
+/// stdin read on a plain thread: tokio's stdin reads on the blocking pool, which keeps the
+/// runtime from shutting down (after a signal or `exit`) while the client holds the pipe open
+fn stdin_stream() -> tokio::io::DuplexStream {
+    const CHUNK: usize = 64 * 1024;
+    let (mut tx, rx) = tokio::io::duplex(CHUNK);
+    let handle = tokio::runtime::Handle::current();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = vec![0u8; CHUNK];
+        loop {
+            let n = match std::io::Read::read(&mut stdin, &mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log_error!("stdin read error: {}", e);
+                    break;
+                }
+            };
+            if handle.block_on(tx.write_all(&buf[..n])).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Serve one session over a byte stream (stdin/stdout or a socket connection),
+/// auto-detecting LSP or line framing from the first message
+async fn serve_stream<R, W>(input: R, output: W, label: &str, transport: &'static str)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    // All output writes go through one task so notifications can interleave with responses
+    let (out_tx, mut out_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let out_mode: Arc<OnceLock<TransportMode>> = Arc::new(OnceLock::new());
+    let writer_mode = out_mode.clone();
+    let writer = tokio::spawn(async move {
+        let mut output = output;
+        while let Some(payload) = out_rx.recv().await {
+            let mode = writer_mode.get().copied().unwrap_or(TransportMode::Line);
+            if let Err(e) = write_message(&mut output, mode, &payload).await {
+                log_error!("write error: {}, but continuing...", e);
+            }
+        }
+    });
+
+    // Input is read on its own task; the session loop only sees whole messages
+    let (in_tx, in_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let label = label.to_string();
+    let max_bytes = config::load().max_message_bytes();
+    let session = session::Session::new(transport);
+    let reader_session = session.clone();
+    let reader = tokio::spawn(async move {
+        let mut reader = BufReader::new(input);
+        let mut transport_mode: Option<TransportMode> = None;
+        loop {
+            match read_message(&mut reader, &mut transport_mode, max_bytes).await {
+                Ok(Some(msg)) => {
+                    if let Some(mode) = transport_mode {
+                        out_mode.get_or_init(|| mode);
+                        if transport == "stdio" {
+                            reader_session.set_framing(match mode {
+                                TransportMode::Lsp => "stdio-lsp",
+                                TransportMode::Line => "stdio-line",
+                            });
+                        }
+                    }
+                    if in_tx.send(msg).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {
+                    log_info!("{} EOF, exiting", label);
+                    return;
+                }
+                Err(e) => {
+                    log_error!("read error: {}", e);
+                    // A broken connection keeps failing; only malformed frames are skipped
+                    if e.downcast_ref::<std::io::Error>().is_some_and(|io| io.kind() != std::io::ErrorKind::InvalidData) {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    serve_session(in_rx, out_tx, session).await;
+    reader.abort();
+    let _ = writer.await;
+}
+
+/// One MCP session: reads whole JSON-RPC messages from `inbound` and queues
+/// responses/notifications on `out_tx` until the inbound side closes.
+/// Everything a client negotiates or accumulates lives here or in `session`, so any
+/// number of sessions can share one process.
+async fn serve_session(
+    mut inbound: tokio::sync::mpsc::UnboundedReceiver<String>,
+    out_tx: tokio::sync::mpsc::UnboundedSender<String>,
+    session: Arc<session::Session>,
+) {
+    log_debug!("session {} started ({})", session.id, session.transport());
+    let config = config::SharedConfig::new(config::load());
+    let reloader = config::spawn_reloader(config.clone(), out_tx.clone());
+
+    let subscriptions = resources::Subscriptions::default();
+    let watcher = resources::spawn_watcher(subscriptions.clone(), out_tx.clone());
+    let cwd_root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).to_string_lossy().to_string();
+
+    let (relay_url, access_token) = relay_settings();
+    let client = reqwest::Client::new();
+
+    // Elicitation: tools/call requests parked until the client answers our elicitation/create
+    let mut client_elicitation = false;
+    let mut elicit_seq: u64 = 0;
+    let mut pending_elicitations: HashMap<String, Value> = HashMap::new();
+
+    // Roots: the client's workspace folders, used when tools/call has no project_path
+    let mut client_roots = false;
+    let mut roots_seq: u64 = 0;
+    let mut roots: Vec<String> = Vec::new();
+    // Projects and queries used in this session, offered by completion/complete
+    let mut recent = completion::Recent::default();
+
+    let mut protocol_version = mcp_version::DEFAULT;
+
+    // Keepalive: started after initialize, ends the session when the client stops answering
+    let liveness = keepalive::Liveness::default();
+    let mut pinger: Option<tokio::task::JoinHandle<()>> = None;
+
+    // tools/call runs on its own task so a long search doesn't block ping or other calls;
+    // in-flight calls are keyed by request id so notifications/cancelled can stop them
+    let mut calls = tokio::task::JoinSet::new();
+    let mut in_flight: HashMap<String, CancellationToken> = HashMap::new();
+
+    // Shutdown: a termination signal, `exit` or a `shutdown` request cancels in-flight calls, which
+    // then answer with an error. Per-call tokens are children of this one. The `shutdown` response
+    // waits (at most SHUTDOWN_GRACE) until those calls have answered; ping and exit are still read.
+    let shutdown = CancellationToken::new();
+    let mut shutting_down = false;
+    let mut abort_in_flight = false;
+    let mut pending_shutdown: Option<(Value, tokio::time::Instant)> = None;
+    let signal = shutdown_signal();
+    tokio::pin!(signal);
 
     loop {
-        let message = match read_message(&mut reader, &mut transport_mode).await {
-            Ok(Some(msg)) => msg,
-            Ok(None) => {
-                eprintln!("[mcp-client] stdin EOF, exiting");
-                break;
+        let message = tokio::select! {
+            msg = inbound.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            Some(done) = calls.join_next() => {
+                match done {
+                    Ok(key) => {
+                        in_flight.remove(&key);
+                    }
+                    Err(e) if e.is_cancelled() => {}
+                    Err(e) => log_error!("tools/call task failed: {}", e),
+                }
+                if calls.is_empty() {
+                    if let Some((id, _)) = pending_shutdown.take() {
+                        send_response(&out_tx, &json!({ "jsonrpc": "2.0", "id": id, "result": {} }));
+                    }
+                }
+                continue;
             }
-            Err(e) => {
-                eprintln!("[mcp-client] read error: {}", e);
+            _ = async {
+                match &pending_shutdown {
+                    Some((_, deadline)) => tokio::time::sleep_until(*deadline).await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                log_warn!("in-flight calls did not finish within {}s, aborting", SHUTDOWN_GRACE.as_secs());
+                calls.abort_all();
+                // Aborted calls never answer themselves
+                for key in in_flight.drain().map(|(key, _)| key) {
+                    let id: Value = serde_json::from_str(&key).unwrap_or(Value::Null);
+                    send_response(&out_tx, &json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": { "content": [{ "type": "text", "text": "Error: server is shutting down" }], "isError": true }
+                    }));
+                }
+                if let Some((id, _)) = pending_shutdown.take() {
+                    send_response(&out_tx, &json!({ "jsonrpc": "2.0", "id": id, "result": {} }));
+                }
                 continue;
             }
+            _ = liveness.dead() => {
+                calls.abort_all();
+                break;
+            }
+            _ = &mut signal => {
+                log_info!("termination signal received, shutting down");
+                abort_in_flight = true;
+                break;
+            }
         };
+        liveness.seen();
 
         if message.is_empty() {
             continue;
@@ -161,24 +491,157 @@ async fn run_mcp_server() -> anyhow::Result<()> {
         let request: Value = match serde_json::from_str(&message) {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("[mcp-client] JSON parse error: {}", e);
+                log_warn!("JSON parse error: {}", e);
+                send_response(&out_tx, &rpc_error(recover_id(&message), -32700, &format!("Parse error: {}", e)));
                 continue;
             }
         };
+        if let Err(resp) = check_envelope(&request) {
+            log_warn!("invalid request: {}", resp["error"]["message"]);
+            send_response(&out_tx, &resp);
+            continue;
+        }
 
-        let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
+        let mut request = request;
+        let mut method = request.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
         let id = request.get("id").cloned();
 
         // Notifications (no id) — don't respond
         if id.is_none() {
+            match method.as_str() {
+                "exit" | "notifications/exit" => {
+                    log_info!("exit received, shutting down");
+                    abort_in_flight = true;
+                    break;
+                }
+                "notifications/cancelled" => cancel_request(&request, &in_flight),
+                "notifications/initialized" | "notifications/roots/list_changed" if client_roots => {
+                    roots_seq += 1;
+                    let _ = out_tx.send(roots::request(roots_seq).to_string());
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        // Responses to our own requests — roots/list, or resume the parked tools/call
+        if method.is_empty() && id.as_ref().and_then(|i| i.as_str()).is_some_and(|i| i.starts_with(roots::REQUEST_ID_PREFIX)) {
+            roots = roots::parse(&request);
+            log_info!("client roots: {}", if roots.is_empty() { "(none)".to_string() } else { roots.join(", ") });
+            continue;
+        }
+        if method.is_empty() {
+            let Some(parked) = id.as_ref().and_then(|i| i.as_str()).and_then(|i| pending_elicitations.remove(i)) else {
+                continue;
+            };
+            match apply_elicited_project_path(parked, &request) {
+                Ok(resumed) => {
+                    request = resumed;
+                    method = "tools/call".to_string();
+                }
+                Err(resp) => {
+                    if let Ok(resp_json) = serde_json::to_string(&resp) {
+                        let _ = out_tx.send(resp_json);
+                    }
+                    continue;
+                }
+            }
+        }
+        let id = request.get("id").cloned();
+
+        if method == "initialize" {
+            client_elicitation = request.pointer("/params/capabilities/elicitation").is_some();
+            client_roots = request.pointer("/params/capabilities/roots").is_some();
+            protocol_version = mcp_version::negotiate(request.pointer("/params/protocolVersion").and_then(|v| v.as_str()));
+            if pinger.is_none() {
+                if let Some(every) = keepalive::interval(config.get().keepalive_secs, session.transport()) {
+                    pinger = Some(keepalive::spawn(liveness.clone(), every, out_tx.clone()));
+                }
+            }
+        }
+
+        if method == "tools/call" {
+            if let Some(root) = roots.first() {
+                roots::apply_default(&mut request, root);
+            }
+        }
+        let default_root = roots.first().unwrap_or(&cwd_root);
+
+        if shutting_down && !matches!(method.as_str(), "ping" | "shutdown") {
+            send_response(&out_tx, &json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32600, "message": "server is shutting down" }
+            }));
             continue;
         }
 
         let response = match method.as_str() {
-            "initialize" => handle_initialize(&request),
-            "tools/list" => handle_tools_list(&request),
+            "initialize" => handle_initialize(&request, protocol_version),
+            "tools/list" => handle_tools_list(&request, &config.get(), protocol_version),
+            "tools/call" if needs_project_path(&request, &cwd_root) => {
+                if client_elicitation {
+                    elicit_seq += 1;
+                    let elicit_id = format!("elicit-{}", elicit_seq);
+                    let _ = out_tx.send(elicit_project_path(&elicit_id, &cwd_root).to_string());
+                    pending_elicitations.insert(elicit_id, request);
+                    continue;
+                }
+                missing_project_path(&request, &cwd_root)
+            }
             "tools/call" => {
-                handle_tools_call(&request, &client, &relay_url, &access_token).await
+                recent.record(&request);
+                let config = config.get();
+                let (client, relay_url, access_token, out_tx) = (client.clone(), relay_url.clone(), access_token.clone(), out_tx.clone());
+                let key = id.as_ref().map(|i| i.to_string()).unwrap_or_default();
+                let cancel = shutdown.child_token();
+                let shutdown = shutdown.clone();
+                let session = session.clone();
+                in_flight.insert(key.clone(), cancel.clone());
+                calls.spawn(async move {
+                    let discovered = session.discovered.generation();
+                    let audit = config.audit_log.clone().map(|path| audit::Context {
+                        path,
+                        request_id: id.as_ref().map(|i| i.as_str().map_or_else(|| i.to_string(), str::to_string)).unwrap_or_default(),
+                        tool: request.pointer("/params/name").and_then(|n| n.as_str()).unwrap_or("").to_string(),
+                    });
+                    let call = handle_tools_call(&request, &config, &client, &relay_url, &access_token, &session, &cancel);
+                    let mut response = audit::scope(audit, call).await;
+                    mcp_version::adapt_result(&mut response, protocol_version);
+                    if shutdown.is_cancelled() {
+                        response = json!({
+                            "jsonrpc": "2.0",
+                            "id": request.get("id"),
+                            "result": { "content": [{ "type": "text", "text": "Error: server is shutting down" }], "isError": true }
+                        });
+                    }
+                    // A request cancelled by the client gets no response
+                    if !cancel.is_cancelled() || shutdown.is_cancelled() {
+                        send_response(&out_tx, &response);
+                    }
+                    if session.discovered.generation() != discovered {
+                        send_response(&out_tx, &resources::list_changed());
+                    }
+                    key
+                });
+                log_debug!("dispatched tools/call, loop continues");
+                continue;
+            }
+            "completion/complete" => completion::complete(&request, &roots, &recent),
+            "resources/list" => handle_resources_list(&request, default_root, &session.discovered, &config.get()),
+            "resources/read" => handle_resources_read(&request, &session.discovered, &config.get()),
+            "resources/subscribe" | "resources/unsubscribe" => {
+                handle_resources_subscribe(&request, &subscriptions)
+            }
+            "shutdown" => {
+                // Abort in-flight calls; answer once they have answered (see the join_next arm)
+                shutting_down = true;
+                shutdown.cancel();
+                if !calls.is_empty() && pending_shutdown.is_none() {
+                    pending_shutdown = Some((id.clone().unwrap_or(json!(null)), tokio::time::Instant::now() + SHUTDOWN_GRACE));
+                    continue;
+                }
+                json!({ "jsonrpc": "2.0", "id": id, "result": {} })
             }
             "ping" => json!({ "jsonrpc": "2.0", "id": id, "result": {} }),
             _ => json!({
@@ -188,32 +651,124 @@ async fn run_mcp_server() -> anyhow::Result<()> {
             }),
         };
 
-        // Write response — if this fails, log but don't exit
-        match serde_json::to_string(&response) {
-            Ok(resp_json) => {
-                let mode = transport_mode.unwrap_or(TransportMode::Line);
-                if let Err(e) = write_message(&mut stdout, mode, &resp_json).await {
-                    eprintln!("[mcp-client] write error: {}, but continuing...", e);
-                }
-            }
-            Err(e) => {
-                eprintln!("[mcp-client] serialize error: {}", e);
-            }
+        send_response(&out_tx, &response);
+        log_debug!("responded to method={}, loop continues", method);
+    }
+
+    // Input may close right after the last request; let in-flight calls answer first.
+    // On shutdown they are cancelled and get a grace period to answer and report to the relay.
+    if abort_in_flight {
+        shutdown.cancel();
+        let drain = async { while calls.join_next().await.is_some() {} };
+        if tokio::time::timeout(SHUTDOWN_GRACE, drain).await.is_err() {
+            log_warn!("in-flight calls did not finish within {}s, aborting", SHUTDOWN_GRACE.as_secs());
+            calls.abort_all();
         }
-        eprintln!("[mcp-client] responded to method={}, loop continues", method);
     }
+    while calls.join_next().await.is_some() {}
 
-    Ok(())
+    // Stop producers, then let the writer drain queued responses
+    watcher.abort();
+    let _ = watcher.await;
+    reloader.abort();
+    let _ = reloader.await;
+    if let Some(pinger) = pinger {
+        pinger.abort();
+        let _ = pinger.await;
+    }
 }
 
-fn handle_initialize(msg: &Value) -> Value {
+/// Time cancelled calls get to answer on shutdown; covers report_log's 5s timeout
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(6);
+
+/// Ctrl-C, or SIGTERM on Unix
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    if let Ok(mut term) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+        return;
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// notifications/cancelled: stop the in-flight tools/call with that request id
+fn cancel_request(msg: &Value, in_flight: &HashMap<String, CancellationToken>) {
+    let Some(request_id) = msg.pointer("/params/requestId") else { return };
+    let reason = msg.pointer("/params/reason").and_then(|r| r.as_str()).unwrap_or("no reason given");
+    match in_flight.get(&request_id.to_string()) {
+        Some(token) => {
+            log_info!("request {} cancelled by client: {}", request_id, reason);
+            token.cancel();
+        }
+        None => log_debug!("cancellation for unknown or finished request {}", request_id),
+    }
+}
+
+/// JSON-RPC error response
+fn rpc_error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Best-effort id from a message that failed to parse, so the client isn't left waiting on it
+fn recover_id(raw: &str) -> Value {
+    let re = regex_lite::Regex::new(r#""id"\s*:\s*(-?\d+|"(?:[^"\\]|\\.)*")"#).unwrap();
+    re.captures(raw).and_then(|c| serde_json::from_str(&c[1]).ok()).unwrap_or(Value::Null)
+}
+
+/// Structural JSON-RPC 2.0 checks; Err carries the -32600 response
+fn check_envelope(msg: &Value) -> Result<(), Value> {
+    let invalid = |id: Value, why: &str| rpc_error(id, -32600, &format!("Invalid Request: {}", why));
+    let Some(obj) = msg.as_object() else {
+        let why = if msg.is_array() { "batches are not supported" } else { "expected a JSON object" };
+        return Err(invalid(Value::Null, why));
+    };
+    let id = match obj.get("id") {
+        None => Value::Null,
+        Some(id @ (Value::String(_) | Value::Number(_) | Value::Null)) => id.clone(),
+        Some(_) => return Err(invalid(Value::Null, "id must be a string or number")),
+    };
+    if obj.get("jsonrpc").and_then(|v| v.as_str()) != Some("2.0") {
+        return Err(invalid(id, "jsonrpc must be \"2.0\""));
+    }
+    if obj.get("params").is_some_and(|p| !(p.is_object() || p.is_array())) {
+        return Err(invalid(id, "params must be an object or array"));
+    }
+    match obj.get("method") {
+        Some(Value::String(m)) if !m.is_empty() => Ok(()),
+        Some(_) => Err(invalid(id, "method must be a non-empty string")),
+        // Response to one of our own requests
+        None if obj.contains_key("id") && (obj.contains_key("result") || obj.contains_key("error")) => Ok(()),
+        None => Err(invalid(id, "missing method")),
+    }
+}
+
+/// Queue a response — the writer task logs write errors but doesn't exit
+fn send_response(out_tx: &tokio::sync::mpsc::UnboundedSender<String>, response: &Value) {
+    match serde_json::to_string(response) {
+        Ok(resp_json) => {
+            let _ = out_tx.send(resp_json);
+        }
+        Err(e) => {
+            log_error!("serialize error: {}", e);
+        }
+    }
+}
+
+fn handle_initialize(msg: &Value, protocol_version: &str) -> Value {
     let id = msg.get("id").cloned().unwrap_or(json!(null));
     json!({
         "jsonrpc": "2.0",
         "id": id,
         "result": {
-            "protocolVersion": "2024-11-05",
-            "capabilities": { "tools": {} },
+            "protocolVersion": protocol_version,
+            "capabilities": {
+                "tools": { "listChanged": true },
+                "resources": { "subscribe": true, "listChanged": true },
+                "completions": {}
+            },
             "serverInfo": {
                 "name": "windsurf-relay-mcp",
                 "version": "0.1.0"
@@ -222,44 +777,407 @@ fn handle_initialize(msg: &Value) -> Value {
     })
 }
 
-fn handle_tools_list(msg: &Value) -> Value {
+fn handle_tools_list(msg: &Value, config: &config::Config, protocol_version: &str) -> Value {
     let id = msg.get("id").cloned().unwrap_or(json!(null));
+    let mut tools = tool_definitions(config);
+    mcp_version::adapt_tools(&mut tools, protocol_version);
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": { "tools": tools }
+    })
+}
 
-    let tools = vec![json!({
+/// Shape of fast_context_search structuredContent
+fn search_output_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "files": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string" },
+                        "ranges": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": { "start": { "type": "integer" }, "end": { "type": "integer" } },
+                                "required": ["start", "end"]
+                            }
+                        },
+                        "keywords": { "type": "array", "items": { "type": "string" } },
+                        "matches": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": { "line": { "type": "integer" }, "column": { "type": "integer" }, "text": { "type": "string" } },
+                                "required": ["line", "column", "text"]
+                            }
+                        }
+                    },
+                    "required": ["path", "ranges", "keywords"]
+                }
+            },
+            "partial": { "type": "boolean" },
+            "session_id": { "type": "string" }
+        },
+        "required": ["files"]
+    })
+}
+
+/// Every tool this server implements (tools/list shows those enabled by config)
+const TOOL_NAMES: &[&str] = &["fast_context_search", "multi_repo_search", "read_ranges", "repo_overview", "grep_project", "symbol_search", "codebase_qa", "diff_context_search", "fast_context_refine", "related_tests", "dependency_trace", "codebase_stats", "git_blame_context", "locate_entrypoints", "search_history", "verify_ranges", "explain_symbol", "relay_status", "fetch_result_page"];
+
+/// Tool definitions exposed under the current config
+fn tool_definitions(config: &config::Config) -> Vec<Value> {
+    let l = i18n::Locale::resolve(config.language.as_deref());
+    let providers = provider::names(&config.providers);
+    let default_provider = config.default_provider.clone().unwrap_or_else(|| provider::WINDSURF.into());
+    // A broken defaults.profile falls back to built-in defaults here; tools/call reports the error
+    let limits = config.limits(None).or_else(|_| config::Config::default().limits(None)).expect("built-in defaults");
+    let n = |template: &str, value: u32| template.replace("{}", &value.to_string());
+    let mut search_tool = json!({
         "name": "fast_context_search",
-        "description": "AI-driven semantic code search. Searches a codebase with natural language and returns relevant file paths with line ranges, plus suggested grep keywords.",
+        "description": l.pick(
+            "AI-driven semantic code search. Searches a codebase with natural language and returns relevant file paths with line ranges, plus suggested grep keywords.",
+            "AI 驱动的语义代码搜索。用自然语言搜索代码库，返回相关文件路径及行号范围，并附带建议的 grep 关键词。"
+        ),
         "inputSchema": {
             "type": "object",
             "properties": {
-                "query": { "type": "string", "description": "Natural language search query" },
-                "project_path": { "type": "string", "description": "Absolute path to project root. Empty = cwd.", "default": "" },
-                "tree_depth": { "type": "integer", "description": "Directory tree depth (1-6, default 3)", "default": 3, "minimum": 1, "maximum": 6 },
-                "max_turns": { "type": "integer", "description": "Search rounds (1-5, default 5)", "default": 5, "minimum": 1, "maximum": 5 },
-                "max_results": { "type": "integer", "description": "Max files to return (1-30, default 10)", "default": 10, "minimum": 1, "maximum": 30 }
+                "query": { "type": "string", "description": l.pick("Natural language search query", "自然语言搜索问题") },
+                "project_path": { "type": "string", "description": l.pick("Absolute path to project root, or a git URL (optionally url#ref) to shallow-clone and search. Empty = first client root, else cwd.", "项目根目录的绝对路径，或 git URL（可写 url#ref，浅克隆后搜索），留空 = 客户端第一个根目录，否则为当前工作目录"), "default": "" },
+                "tree_depth": { "type": "integer", "description": n(l.pick("Directory tree depth (1-6, default {})", "目录树深度（1-6，默认 {}）"), limits.tree_depth), "default": limits.tree_depth, "minimum": 1, "maximum": 6 },
+                "max_turns": { "type": "integer", "description": n(l.pick("Search rounds (1-5, default {})", "搜索轮数（1-5，默认 {}）"), limits.max_turns), "default": limits.max_turns, "minimum": 1, "maximum": 5 },
+                "max_results": { "type": "integer", "description": n(l.pick("Max files to return (1-30, default {})", "最多返回的文件数（1-30，默认 {}）"), limits.max_results), "default": limits.max_results, "minimum": 1, "maximum": 30 },
+                "map_format": { "type": "string", "description": l.pick("Repo map given to the model: tree = directory tree, files = flat gitignore-aware file list with size buckets (better for wide, shallow repos)", "提供给模型的 repo map：tree = 目录树，files = 遵守 gitignore 的扁平文件列表并附大小分级（适合宽而浅的仓库）"), "enum": ["tree", "files"], "default": limits.map_format.as_str() },
+                "drop_generated": { "type": "boolean", "description": l.pick("Drop generated, minified and vendored files from the result instead of annotating them (default false)", "从结果中去掉生成、压缩和第三方文件，而不只是标注（默认 false）"), "default": false },
+                "include_tests": { "type": "boolean", "description": l.pick("Include test files; false excludes test directories/files from the search and the result (default true)", "是否包含测试文件；false 时搜索和结果中都排除测试目录/文件（默认 true）"), "default": true },
+                "include_submodules": { "type": "boolean", "description": l.pick("Descend into initialized git submodules (default false)", "是否进入已初始化的 git 子模块（默认 false）"), "default": false },
+                "max_result_bytes": { "type": "integer", "description": l.pick("Upper bound on the total result size in bytes (min 256)", "结果总大小上限（字节，最小 256）"), "minimum": 256 },
+                "max_file_bytes": { "type": "integer", "description": l.pick("Upper bound on each file snippet read during the search, in bytes (min 256)", "搜索过程中每个文件片段的大小上限（字节，最小 256）"), "minimum": 256 },
+                "verbosity": { "type": "string", "description": l.pick("Result detail: minimal = file list only, normal = plus grep keywords and config footer, debug = plus search stats", "结果详细程度：minimal = 仅文件列表，normal = 附带 grep 关键词和配置信息，debug = 再附带搜索统计"), "enum": ["minimal", "normal", "debug"], "default": "normal" },
+                "provider": { "type": "string", "description": l.pick("Search backend to route this query to", "本次查询使用的搜索后端"), "enum": providers, "default": default_provider }
             },
             "required": ["query"]
         }
-    })];
+    });
+    if !config.profiles.is_empty() {
+        let names: Vec<&String> = config.profiles.keys().collect();
+        let mut profile = json!({
+            "type": "string",
+            "description": l.pick("Named preset of tree_depth/max_turns/max_results; explicit arguments still win", "tree_depth/max_turns/max_results 的命名预设，显式参数优先"),
+            "enum": names
+        });
+        if let Some(d) = &config.defaults.profile {
+            profile["default"] = json!(d);
+        }
+        search_tool["inputSchema"]["properties"]["profile"] = profile;
+    }
+    search_tool["outputSchema"] = search_output_schema();
+
+    let mut tools = vec![search_tool.clone()];
+
+    // multi_repo_search: same arguments, with repo_set in place of project_path
+    if !config.repo_sets.is_empty() {
+        let mut schema = search_tool["inputSchema"].clone();
+        if let Some(props) = schema["properties"].as_object_mut() {
+            props.remove("project_path");
+            let sets: Vec<&String> = config.repo_sets.keys().collect();
+            props.insert("repo_set".into(), json!({
+                "type": "string",
+                "description": l.pick("Configured repository set to search (optional when only one is configured)", "要搜索的仓库集合（只配置了一个时可省略）"),
+                "enum": sets
+            }));
+            props.insert("parallel".into(), json!({
+                "type": "integer",
+                "description": l.pick("Repositories searched concurrently (1-8)", "同时搜索的仓库数（1-8）"),
+                "default": config.multi_repo_parallelism.unwrap_or(MULTI_REPO_DEFAULT_PARALLEL),
+                "minimum": 1,
+                "maximum": MULTI_REPO_MAX_PARALLEL
+            }));
+        }
+        tools.push(json!({
+            "name": "multi_repo_search",
+            "description": l.pick(
+                "Run fast_context_search across every repository of a configured set and merge the results; each path is prefixed with its repository name.",
+                "在配置的仓库集合中逐个执行 fast_context_search 并合并结果，每个路径前标注所属仓库名。"
+            ),
+            "inputSchema": schema
+        }));
+    }
+
+    tools.push(read_ranges::definition(l));
+    tools.push(repo_overview::definition(l, &limits));
+    tools.push(grep_project::definition(l));
+    tools.push(symbol_search::definition(l));
+
+    // codebase_qa: same search arguments, with question in place of query
+    let mut qa_schema = search_tool["inputSchema"].clone();
+    if let Some(props) = qa_schema["properties"].as_object_mut() {
+        props.remove("query");
+        props.remove("max_results");
+        props.remove("drop_generated");
+        props.insert("question".into(), json!({
+            "type": "string",
+            "description": l.pick("Natural-language question about the code, e.g. \"how is the auth token refreshed?\"", "关于代码的自然语言问题，例如“auth token 是如何刷新的？”")
+        }));
+    }
+    qa_schema["required"] = json!(["question"]);
+    let mut qa_output = search_output_schema();
+    qa_output["properties"]["answer"] = json!({ "type": "string" });
+    tools.push(json!({
+        "name": "codebase_qa",
+        "description": l.pick(
+            "Answer a natural-language question about the codebase. Runs the same AI search as fast_context_search, then returns a prose answer citing the files and line ranges it is based on.",
+            "回答关于代码库的自然语言问题。执行与 fast_context_search 相同的 AI 搜索，返回引用了相关文件和行号范围的文字回答。"
+        ),
+        "inputSchema": qa_schema,
+        "outputSchema": qa_output
+    }));
+
+    // diff_context_search: same arguments, query optional, plus the diff to scope to
+    let mut diff_schema = search_tool["inputSchema"].clone();
+    if let Some(props) = diff_schema["properties"].as_object_mut() {
+        props.insert("query".into(), json!({
+            "type": "string",
+            "description": l.pick("What to look for around the change (default: context needed to review it)", "围绕改动要找的内容（默认：审查改动所需的上下文）")
+        }));
+        props.insert("project_path".into(), json!({
+            "type": "string",
+            "description": l.pick("Absolute path to the project root inside a git work tree. Empty = first client root, else cwd.", "git 工作区内的项目根目录绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+            "default": ""
+        }));
+        props.insert("base".into(), json!({
+            "type": "string",
+            "description": l.pick("Git ref to diff against, e.g. main or HEAD~3 (default HEAD)", "对比的 git ref，如 main 或 HEAD~3（默认 HEAD）"),
+            "default": "HEAD"
+        }));
+        props.insert("staged".into(), json!({
+            "type": "boolean",
+            "description": l.pick("Only staged changes (git diff --cached)", "只看已暂存的改动（git diff --cached）"),
+            "default": false
+        }));
+    }
+    diff_schema["required"] = json!([]);
+    tools.push(json!({
+        "name": "diff_context_search",
+        "description": l.pick(
+            "fast_context_search scoped to a git diff: collects the changed files (git diff --name-only against a ref, or the staged changes) and restricts the search, repo map and results to them plus the files that directly import them. Useful for gathering code-review context.",
+            "限定在 git diff 范围内的 fast_context_search：收集改动文件（对比某个 ref 或暂存区的 git diff --name-only），搜索、repo map 和结果都只包含这些文件及直接 import 它们的文件，适合收集代码审查上下文。"
+        ),
+        "inputSchema": diff_schema,
+        "outputSchema": search_output_schema()
+    }));
+
+    let mut refine_output = search_output_schema();
+    refine_output["properties"]["answer"] = json!({ "type": "string" });
+    tools.push(json!({
+        "name": "fast_context_refine",
+        "description": l.pick(
+            "Continue an earlier fast_context_search, codebase_qa or diff_context_search with a follow-up request. Resumes the same conversation, so the files and results already collected are reused instead of searching from scratch.",
+            "用追加的要求继续之前的 fast_context_search、codebase_qa 或 diff_context_search。沿用同一段对话，已收集的文件和结果直接复用，不必从头搜索。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string", "description": l.pick("session_id returned by the earlier search", "之前搜索返回的 session_id") },
+                "query": { "type": "string", "description": l.pick("Follow-up request, e.g. \"also include the callers\" or \"only the server side\"", "追加的要求，例如“把调用方也找出来”或“只看服务端”") },
+                "max_turns": { "type": "integer", "description": l.pick("Additional search rounds (1-5, default: same as the earlier search)", "追加的搜索轮数（1-5，默认与之前的搜索相同）"), "minimum": 1, "maximum": 5 },
+                "verbosity": { "type": "string", "description": l.pick("Result detail (default: same as the earlier search)", "结果详细程度（默认与之前的搜索相同）"), "enum": ["minimal", "normal", "debug"] }
+            },
+            "required": ["session_id", "query"]
+        },
+        "outputSchema": refine_output
+    }));
+
+    tools.push(related_tests::definition(l));
+    tools.push(dependency_trace::definition(l));
+    tools.push(codebase_stats::definition(l));
+    tools.push(git_blame_context::definition(l));
+    tools.push(locate_entrypoints::definition(l));
+    tools.push(history::definition(l));
+    tools.push(verify_ranges::definition(l));
+    tools.push(explain_symbol::definition(l, search_tool["inputSchema"]["properties"]["provider"].clone()));
+
+    tools.push(json!({
+        "name": "relay_status",
+        "description": l.pick(
+            "Check the relay connection: reachability, credentials and remaining quota.",
+            "检查 relay 连接状态：是否可达、凭据是否有效以及剩余额度。"
+        ),
+        "inputSchema": { "type": "object", "properties": {} }
+    }));
+
+    if config.direct_tools == Some(true) {
+        tools.extend(direct_tools::definitions(l));
+    }
+
+    if config.page_bytes().is_some() {
+        tools.push(json!({
+            "name": "fetch_result_page",
+            "description": l.pick(
+                "Fetch the next page of a large result that was split; use the token and page number given at the end of the previous page.",
+                "获取被分页的大结果的后续页；token 和页码见上一页末尾的提示。"
+            ),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "token": { "type": "string", "description": l.pick("Continuation token from the previous page", "上一页给出的续页 token") },
+                    "page": { "type": "integer", "description": l.pick("Page number (starting at 2)", "页码（从 2 开始）"), "minimum": 2 }
+                },
+                "required": ["token", "page"]
+            }
+        }));
+    }
+
+    tool_registry::annotate(&mut tools, l);
+    tools
+        .into_iter()
+        .filter(|t| t.get("name").and_then(|n| n.as_str()).is_some_and(|n| config.tool_enabled(n)))
+        .collect()
+}
+
+fn handle_resources_list(msg: &Value, project_root: &str, discovered: &resources::Discovered, config: &config::Config) -> Value {
+    let id = msg.get("id").cloned().unwrap_or(json!(null));
+    let mut result = resources::list(project_root, discovered);
+    if let Some(list) = result.get_mut("resources").and_then(|r| r.as_array_mut()) {
+        list.extend(history::resources(&config.history));
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn handle_resources_read(msg: &Value, discovered: &resources::Discovered, config: &config::Config) -> Value {
+    let id = msg.get("id").cloned().unwrap_or(json!(null));
+    let uri = msg.pointer("/params/uri").and_then(|u| u.as_str()).unwrap_or("");
+    let read = if uri.starts_with(history::SCHEME) { history::read(uri, &config.history) } else { resources::read(uri, discovered) };
+    match read {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32002, "message": e }
+        }),
+    }
+}
+
+fn handle_resources_subscribe(msg: &Value, subs: &resources::Subscriptions) -> Value {
+    let id = msg.get("id").cloned().unwrap_or(json!(null));
+    let uri = msg.pointer("/params/uri").and_then(|u| u.as_str()).unwrap_or("");
+    if msg.get("method").and_then(|m| m.as_str()) == Some("resources/unsubscribe") {
+        subs.unsubscribe(uri);
+        return json!({ "jsonrpc": "2.0", "id": id, "result": {} });
+    }
+    match subs.subscribe(uri) {
+        Ok(()) => json!({ "jsonrpc": "2.0", "id": id, "result": {} }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32002, "message": e }
+        }),
+    }
+}
+
+/// Files/dirs whose presence marks a directory as a code root
+const PROJECT_MARKERS: &[&str] = &[
+    ".git", ".hg", ".svn", "Cargo.toml", "package.json", "go.mod", "pyproject.toml",
+    "setup.py", "requirements.txt", "pom.xml", "build.gradle", "build.gradle.kts",
+    "CMakeLists.txt", "Makefile", "composer.json", "Gemfile", "mix.exs", "deno.json",
+];
+
+fn looks_like_project_root(dir: &str) -> bool {
+    let dir = std::path::Path::new(dir);
+    PROJECT_MARKERS.iter().any(|m| dir.join(m).exists())
+}
+
+/// AI search tools without project_path while cwd isn't a code root
+fn needs_project_path(msg: &Value, cwd: &str) -> bool {
+    let tool_name = msg.pointer("/params/name").and_then(|n| n.as_str()).unwrap_or("");
+    let project_path = msg.pointer("/params/arguments/project_path").and_then(|p| p.as_str()).unwrap_or("");
+    matches!(tool_name, "fast_context_search" | "codebase_qa" | "diff_context_search") && project_path.is_empty() && !looks_like_project_root(cwd)
+}
+
+fn elicit_project_path(elicit_id: &str, cwd: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": elicit_id,
+        "method": "elicitation/create",
+        "params": {
+            "message": format!("The server's working directory ({}) does not look like a project root. Which project should be searched?", cwd),
+            "requestedSchema": {
+                "type": "object",
+                "properties": {
+                    "project_path": {
+                        "type": "string",
+                        "title": "Project path",
+                        "description": "Absolute path to the project root"
+                    }
+                },
+                "required": ["project_path"]
+            }
+        }
+    })
+}
 
+fn missing_project_path(msg: &Value, cwd: &str) -> Value {
+    let id = msg.get("id").cloned().unwrap_or(json!(null));
+    let text = format!(
+        "Error: project_path is required: working directory {} does not look like a project root (no VCS or manifest found)",
+        cwd
+    );
     json!({
         "jsonrpc": "2.0",
         "id": id,
-        "result": { "tools": tools }
+        "result": { "content": [{ "type": "text", "text": text }], "isError": true }
     })
 }
 
+/// Patch the parked tools/call with the elicited path, or build the error response for it
+fn apply_elicited_project_path(mut parked: Value, reply: &Value) -> Result<Value, Value> {
+    let id = parked.get("id").cloned().unwrap_or(json!(null));
+    let action = reply.pointer("/result/action").and_then(|a| a.as_str()).unwrap_or("");
+    let path = reply.pointer("/result/content/project_path").and_then(|p| p.as_str()).unwrap_or("").trim();
+
+    let error = |text: String| json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": { "content": [{ "type": "text", "text": text }], "isError": true }
+    });
+
+    if action != "accept" || path.is_empty() {
+        let reason = if reply.get("error").is_some() { "elicitation failed" } else { "no project path provided" };
+        return Err(error(format!("Error: project_path is required ({})", reason)));
+    }
+    if !std::path::Path::new(path).is_dir() {
+        return Err(error(format!("Error: project_path is not a directory: {}", path)));
+    }
+
+    if let Some(args) = parked.pointer_mut("/params/arguments").and_then(|a| a.as_object_mut()) {
+        args.insert("project_path".into(), json!(path));
+    } else if let Some(params) = parked.get_mut("params").and_then(|p| p.as_object_mut()) {
+        params.insert("arguments".into(), json!({ "project_path": path }));
+    }
+    Ok(parked)
+}
+
 async fn handle_tools_call(
     msg: &Value,
+    config: &config::Config,
     client: &reqwest::Client,
     relay_url: &str,
     access_token: &str,
+    session: &Arc<session::Session>,
+    cancel: &CancellationToken,
 ) -> Value {
     let id = msg.get("id").cloned().unwrap_or(json!(null));
     let params = msg.get("params").cloned().unwrap_or(json!({}));
     let tool_name = params.get("name").and_then(|n| n.as_str()).unwrap_or("");
     let args = params.get("arguments").cloned().unwrap_or(json!({}));
 
-    if tool_name != "fast_context_search" {
+    let direct = direct_tools::NAMES.contains(&tool_name);
+    if !(TOOL_NAMES.contains(&tool_name) || (direct && config.direct_tools == Some(true))) || !config.tool_enabled(tool_name) {
         return json!({
             "jsonrpc": "2.0",
             "id": id,
@@ -267,24 +1185,96 @@ async fn handle_tools_call(
         });
     }
 
-    let query = args.get("query").and_then(|q| q.as_str()).unwrap_or("");
-    let project_path = args.get("project_path").and_then(|p| p.as_str()).unwrap_or("");
-    let tree_depth = args.get("tree_depth").and_then(|v| v.as_u64()).unwrap_or(3) as u32;
-    let max_turns = args.get("max_turns").and_then(|v| v.as_u64()).unwrap_or(5) as u32;
-    let max_results = args.get("max_results").and_then(|v| v.as_u64()).unwrap_or(10) as u32;
+    if tool_name == "fetch_result_page" {
+        let token = args.get("token").and_then(|t| t.as_str()).unwrap_or("");
+        let page = args.get("page").and_then(|p| p.as_u64()).unwrap_or(0) as usize;
+        return match session.pages.fetch(token, page) {
+            Ok((text, footer)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": { "content": [{ "type": "text", "text": text }, { "type": "text", "text": footer }] }
+            }),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": { "content": [{ "type": "text", "text": format!("Error: {}", e) }], "isError": true }
+            }),
+        };
+    }
 
-    let project_root = if project_path.is_empty() {
-        std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).to_string_lossy().to_string()
+    // (text, structuredContent)
+    let outcome = if tool_name == "multi_repo_search" {
+        multi_repo_search(&args, config, client, relay_url, access_token, session, cancel).await.map(|text| (text, None))
+    } else if tool_name == "read_ranges" {
+        read_ranges::call(&args).await.map(|text| (text, None))
+    } else if tool_name == "repo_overview" {
+        repo_overview::call(&args, config).map(|text| (text, None))
+    } else if tool_name == "grep_project" {
+        grep_project::call(&args, cancel).await.map(|text| (text, None))
+    } else if tool_name == "symbol_search" {
+        symbol_search::call(&args).await.map(|text| (text, None))
+    } else if tool_name == "fast_context_refine" {
+        refine_search(&args, config, client, relay_url, access_token, session, cancel).await
+    } else if tool_name == "related_tests" {
+        related_tests::call(&args).await.map(|text| (text, None))
+    } else if tool_name == "dependency_trace" {
+        dependency_trace::call(&args).await.map(|text| (text, None))
+    } else if tool_name == "codebase_stats" {
+        codebase_stats::call(&args).await.map(|text| (text, None))
+    } else if tool_name == "git_blame_context" {
+        git_blame_context::call(&args).await.map(|text| (text, None))
+    } else if tool_name == "locate_entrypoints" {
+        locate_entrypoints::call(&args, cancel).await.map(|text| (text, None))
+    } else if tool_name == "search_history" {
+        history::call(&args, &config.history).map(|text| (text, None))
+    } else if tool_name == "verify_ranges" {
+        verify_ranges::call(&args).map(|text| (text, None))
+    } else if tool_name == "explain_symbol" {
+        explain_symbol(&args, config, client, relay_url, access_token, session, cancel).await.map(|text| (text, None))
+    } else if tool_name == "relay_status" {
+        Ok((relay_status(client, relay_url, access_token, session, config).await, None))
+    } else if direct {
+        direct_tools::call(tool_name, &args, config, cancel).await.map(|text| (text, None))
     } else {
-        project_path.to_string()
+        let project_path = args.get("project_path").and_then(|p| p.as_str()).unwrap_or("");
+        let search = match search_params(&args, config, project_path).await {
+            Ok(search) => tool_search_params(tool_name, &args, search).await,
+            Err(e) => Err(e),
+        };
+        match search {
+            Ok(search) => {
+                let started = std::time::Instant::now();
+                do_search(client, relay_url, access_token, &search, session, cancel)
+                    .await
+                    .map(|out| {
+                        record_history(config, tool_name, &search, started, &out);
+                        let (text, structured) = keep_conversation(out, tool_name, &args, search.verbosity, session, None);
+                        (text, Some(structured))
+                    })
+                    .map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e),
+        }
     };
 
-    match do_search(client, relay_url, access_token, query, &project_root, tree_depth, max_turns, max_results).await {
-        Ok(text) => json!({
-            "jsonrpc": "2.0",
-            "id": id,
-            "result": { "content": [{ "type": "text", "text": text }] }
-        }),
+    match outcome {
+        Ok((text, structured)) => {
+            let (text, more) = match config.page_bytes() {
+                Some(max) => session.pages.paginate(text, max),
+                None => (text, None),
+            };
+            let mut content = vec![json!({ "type": "text", "text": text })];
+            content.extend(more.map(|hint| json!({ "type": "text", "text": hint })));
+            let mut result = json!({ "content": content });
+            if let Some(structured) = structured {
+                result["structuredContent"] = structured;
+            }
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result
+            })
+        }
         Err(e) => json!({
             "jsonrpc": "2.0",
             "id": id,
@@ -293,250 +1283,1385 @@ async fn handle_tools_call(
     }
 }
 
+/// Store the search conversation for fast_context_refine; returns text and structuredContent carrying its session_id
+fn keep_conversation(mut out: SearchOutput, tool: &str, args: &Value, verbosity: Verbosity, session: &session::Session, id: Option<&str>) -> (String, Value) {
+    let mut structured = out.structured();
+    if let Some(transcript) = out.transcript.take() {
+        let conversation = conversation::Conversation { tool: tool.to_string(), args: args.clone(), transcript };
+        let id = session.conversations.save(id, conversation);
+        if verbosity != Verbosity::Minimal {
+            out.text.push_str(&format!("\n[session] id={} (pass to fast_context_refine to continue this search)", id));
+        }
+        structured["session_id"] = json!(id);
+    }
+    (out.text, structured)
+}
+
+/// Append a finished search to the persistent history read by search_history
+fn record_history(config: &config::Config, tool: &str, search: &SearchParams, started: std::time::Instant, out: &SearchOutput) {
+    let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let entry = history::Entry {
+        id: history::new_id(),
+        time,
+        tool: tool.to_string(),
+        query: search.query.clone(),
+        project: search.project_root.clone(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        partial: out.partial,
+        files: out.files.iter().map(|f| (f.path.clone(), f.ranges.clone())).collect(),
+        text: out.text.clone(),
+    };
+    history::record(&config.history, entry);
+}
+
+/// fast_context_refine: rerun the stored search's setup and continue its conversation with a follow-up request
+async fn refine_search(
+    args: &Value,
+    config: &config::Config,
+    client: &reqwest::Client,
+    relay_url: &str,
+    access_token: &str,
+    session: &Arc<session::Session>,
+    cancel: &CancellationToken,
+) -> Result<(String, Option<Value>), String> {
+    let id = args.get("session_id").and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+    let query = args.get("query").and_then(|v| v.as_str()).map(str::trim).unwrap_or("");
+    if id.is_empty() || query.is_empty() {
+        return Err("session_id and query are required".into());
+    }
+    let prev = session.conversations.get(id).ok_or_else(|| {
+        format!("unknown session_id: {} (only the last {} searches of this session are kept)", id, conversation::MAX_STORED)
+    })?;
+
+    let project_path = prev.args.get("project_path").and_then(|p| p.as_str()).unwrap_or("");
+    let search = search_params(&prev.args, config, project_path).await?;
+    let mut search = tool_search_params(&prev.tool, &prev.args, search).await?;
+    search.query = query.to_string();
+    if let Some(turns) = args.get("max_turns").and_then(|v| v.as_u64()) {
+        search.max_turns = turns.clamp(1, 5) as u32;
+    }
+    if let Some(v) = args.get("verbosity").and_then(|v| v.as_str()) {
+        search.verbosity = Verbosity::parse(v).ok_or_else(|| format!("invalid verbosity: {} (expected minimal, normal or debug)", v))?;
+    }
+    search.resume = Some(prev.transcript);
+
+    let started = std::time::Instant::now();
+    let out = do_search(client, relay_url, access_token, &search, session, cancel).await.map_err(|e| e.to_string())?;
+    record_history(config, "fast_context_refine", &search, started, &out);
+    let (text, structured) = keep_conversation(out, &prev.tool, &prev.args, search.verbosity, session, Some(id));
+    Ok((text, Some(structured)))
+}
+
+/// explain_symbol: locate the definition, then a single model turn without repo map or commands
+async fn explain_symbol(
+    args: &Value,
+    config: &config::Config,
+    client: &reqwest::Client,
+    relay_url: &str,
+    access_token: &str,
+    session: &Arc<session::Session>,
+    cancel: &CancellationToken,
+) -> Result<String, String> {
+    let target = explain_symbol::locate(args).await?;
+    let root = direct_tools::project_root(args)?;
+    let mut search_args = args.clone();
+    search_args["query"] = json!(format!("explain {}", target.name));
+    let search = search_params(&search_args, config, &root).await?;
+
+    let start = std::time::Instant::now();
+    let backend = connect_backend(client, relay_url, access_token, &search, session, cancel, start).await.map_err(|e| e.to_string())?;
+    let focus = args.get("focus").and_then(|f| f.as_str()).map(str::trim).filter(|f| !f.is_empty());
+    let messages = explain_symbol::messages(&target, focus);
+    let tool_defs = prompt::get_explain_tool_definitions();
+    let turn = tokio::select! {
+        r = backend_turn(client, &backend, &search, &messages, &tool_defs) => r,
+        _ = cancel.cancelled() => return Err("cancelled".into()),
+    };
+    let elapsed = || start.elapsed().as_millis() as i64;
+    let explanation = match turn {
+        Ok((_, Some((name, call)))) if name == "answer" => call.get("answer").and_then(|a| a.as_str()).unwrap_or("").to_string(),
+        Ok((thinking, _)) if !thinking.starts_with("[Error]") => thinking,
+        Ok((thinking, _)) | Err(thinking) => {
+            report_log(client, relay_url, access_token, session, &search, "error", &thinking, elapsed()).await;
+            return Err(thinking);
+        }
+    };
+    report_log(client, relay_url, access_token, session, &search, "success", "", elapsed()).await;
+    if explanation.trim().is_empty() {
+        return Err("the model returned an empty explanation".into());
+    }
+    Ok(explain_symbol::render(&target, &explanation))
+}
+
+/// Query used by diff_context_search when none is given
+const DIFF_DEFAULT_QUERY: &str = "Gather the context needed to review these changes";
+
+/// Tool-specific tweaks on top of the shared search arguments
+async fn tool_search_params(tool_name: &str, args: &Value, mut search: SearchParams) -> Result<SearchParams, String> {
+    match tool_name {
+        "codebase_qa" => {
+            search.query = args.get("question").and_then(|q| q.as_str()).unwrap_or("").to_string();
+            search.qa = true;
+        }
+        "diff_context_search" => {
+            if search.remote.is_some() {
+                return Err("diff_context_search needs a local project_path".into());
+            }
+            let base = args.get("base").and_then(|b| b.as_str());
+            let staged = args.get("staged").and_then(|s| s.as_bool()).unwrap_or(false);
+            let scope = diff_scope::collect(std::path::Path::new(&search.project_root), base, staged).await?;
+            if search.query.trim().is_empty() {
+                search.query = DIFF_DEFAULT_QUERY.into();
+            }
+            search.scope = Some(scope);
+        }
+        _ => {}
+    }
+    Ok(search)
+}
+
+/// Validate search arguments and resolve the project root (cloning git URLs)
+async fn search_params(args: &Value, config: &config::Config, project_path: &str) -> Result<SearchParams, String> {
+    let query = args.get("query").and_then(|q| q.as_str()).unwrap_or("");
+    let profile = args.get("profile").and_then(|v| v.as_str());
+    let limits = config.limits(profile)?;
+    let tree_depth = args.get("tree_depth").and_then(|v| v.as_u64()).map(|v| v as u32).unwrap_or(limits.tree_depth);
+    let max_turns = args.get("max_turns").and_then(|v| v.as_u64()).map(|v| v.clamp(1, 5) as u32).unwrap_or(limits.max_turns);
+    let max_results = args.get("max_results").and_then(|v| v.as_u64()).map(|v| v as u32).unwrap_or(limits.max_results);
+
+    let map_format = match args.get("map_format").and_then(|v| v.as_str()) {
+        Some(f) => config::MapFormat::parse(f).ok_or_else(|| format!("invalid map_format: {} (expected tree or files)", f))?,
+        None => limits.map_format,
+    };
+
+    let include_submodules = args.get("include_submodules").and_then(|v| v.as_bool()).unwrap_or(false);
+    let include_tests = args.get("include_tests").and_then(|v| v.as_bool()).unwrap_or(true);
+    let drop_generated = args.get("drop_generated").and_then(|v| v.as_bool()).unwrap_or(false);
+    let requested_provider = args.get("provider").and_then(|v| v.as_str()).unwrap_or("");
+    let verbosity_arg = args.get("verbosity").and_then(|v| v.as_str()).unwrap_or("");
+    let verbosity = Verbosity::parse(verbosity_arg)
+        .ok_or_else(|| format!("invalid verbosity: {} (expected minimal, normal or debug)", verbosity_arg))?;
+
+    let provider = provider::resolve(&config.providers, config.default_provider.as_deref(), requested_provider)?;
+
+    let mut remote_label = None;
+    let project_root = if project_path.is_empty() {
+        std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).to_string_lossy().to_string()
+    } else if let Some(spec) = remote::parse(project_path) {
+        let co = remote::checkout(&spec)
+            .await
+            .map_err(|e| format!("cannot fetch {}: {}", spec.label(), e))?;
+        remote_label = Some(format!("{} ({})", spec.label(), co.commit));
+        co.dir.to_string_lossy().to_string()
+    } else if std::path::Path::new(project_path).is_dir() {
+        project_path.to_string()
+    } else {
+        return Err(format!("project_path is not a directory: {}", project_path));
+    };
+
+    Ok(SearchParams {
+        query: query.to_string(),
+        qa: false,
+        scope: None,
+        resume: None,
+        project_root,
+        tree_depth,
+        max_turns,
+        max_results,
+        map_format,
+        include_submodules,
+        include_tests,
+        drop_generated,
+        sample_threshold: config.sample_threshold(),
+        provider,
+        verbosity,
+        max_result_bytes: args.get("max_result_bytes").and_then(|v| v.as_u64()).map(|v| (v as usize).max(MIN_BYTE_BUDGET)),
+        remote: remote_label,
+        max_file_bytes: args.get("max_file_bytes").and_then(|v| v.as_u64()).map(|v| (v as usize).max(MIN_BYTE_BUDGET)),
+        repo_label: None,
+        call_options: config.transport.call_options(),
+        send_client_info: config.send_client_info(),
+        quota_policy: config.quota.clone(),
+        timeouts: config.timeouts.resolve(),
+        output_limits: config.output.resolve(),
+        command_parallelism: config.command_parallelism.unwrap_or(executor::DEFAULT_COMMAND_PARALLELISM).clamp(1, executor::MAX_COMMAND_PARALLELISM),
+        search_max_filesize: match config.search_max_filesize {
+            Some(0) => None,
+            n => Some(n.unwrap_or(executor::DEFAULT_SEARCH_MAX_FILESIZE)),
+        },
+        prompt_template: match config.prompt_template() {
+            Some(path) => Some(std::fs::read_to_string(&path).map_err(|e| format!("cannot read prompt template {}: {}", path.display(), e))?),
+            None => None,
+        },
+    })
+}
+
+/// Run one query across a configured repo set, merging per-repo results
+async fn multi_repo_search(
+    args: &Value,
+    config: &config::Config,
+    client: &reqwest::Client,
+    relay_url: &str,
+    access_token: &str,
+    session: &Arc<session::Session>,
+    cancel: &CancellationToken,
+) -> Result<String, String> {
+    let set_name = match args.get("repo_set").and_then(|v| v.as_str()).filter(|s| !s.is_empty()) {
+        Some(s) => s.to_string(),
+        None if config.repo_sets.len() == 1 => config.repo_sets.keys().next().cloned().unwrap_or_default(),
+        None => return Err("repo_set is required".into()),
+    };
+    let repos = config.repo_sets.get(&set_name).ok_or_else(|| {
+        let names: Vec<&str> = config.repo_sets.keys().map(|k| k.as_str()).collect();
+        format!("unknown repo_set: {} (available: {})", set_name, if names.is_empty() { "none".into() } else { names.join(", ") })
+    })?;
+    if repos.is_empty() {
+        return Err(format!("repo_set {} is empty", set_name));
+    }
+    let parallel = args.get("parallel").and_then(|v| v.as_u64()).map(|v| v as usize)
+        .unwrap_or(config.multi_repo_parallelism.unwrap_or(MULTI_REPO_DEFAULT_PARALLEL))
+        .clamp(1, MULTI_REPO_MAX_PARALLEL);
+
+    // Repos are resolved (including remote clone/fetch) inside their task so `parallel` bounds that too;
+    // repos that fail to resolve (missing dir, clone error) are reported inline
+    let mut results: Vec<Option<String>> = vec![None; repos.len()];
+    let (args, config) = (Arc::new(args.clone()), Arc::new(config.clone()));
+    let mut running = tokio::task::JoinSet::new();
+    let mut pending = repos.iter().map(|(name, path)| (name.clone(), path.clone())).enumerate();
+    loop {
+        while running.len() < parallel {
+            let Some((i, (name, path))) = pending.next() else { break };
+            if cancel.is_cancelled() {
+                results[i] = Some(format!("[{}] Error: cancelled", name));
+                continue;
+            }
+            let (client, relay_url, access_token, session, cancel) =
+                (client.clone(), relay_url.to_string(), access_token.to_string(), session.clone(), cancel.clone());
+            let (args, config) = (args.clone(), config.clone());
+            running.spawn(async move {
+                let resolved = tokio::select! {
+                    r = search_params(&args, &config, &path) => r,
+                    _ = cancel.cancelled() => Err("cancelled".into()),
+                };
+                let out = match resolved {
+                    Ok(mut search) => {
+                        search.repo_label = Some(name.clone());
+                        match do_search(&client, &relay_url, &access_token, &search, &session, &cancel).await {
+                            Ok(out) => out.text,
+                            Err(e) => format!("[{}] Error: {}", name, e),
+                        }
+                    }
+                    Err(e) => format!("[{}] Error: {}", name, e),
+                };
+                (i, out)
+            });
+        }
+        match running.join_next().await {
+            Some(Ok((i, out))) => results[i] = Some(out),
+            Some(Err(e)) => log_error!("multi_repo_search task failed: {}", e),
+            None => break,
+        }
+    }
+
+    let mut parts = vec![format!("Searched {} repositories in set '{}'.", repos.len(), set_name)];
+    for ((name, _), out) in repos.iter().zip(results) {
+        parts.push(String::new());
+        parts.push(format!("=== {} ===", name));
+        parts.push(out.unwrap_or_else(|| format!("[{}] Error: search task failed", name)));
+    }
+    Ok(parts.join("\n"))
+}
+
+/// Client build/platform metadata sent to the relay for triage
+fn client_info(session: &session::Session) -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "transport": session.transport(),
+    })
+}
+
+/// POST /api/windsurf/credentials, returning the body and any quota it exposes
+async fn fetch_credentials(
+    client: &reqwest::Client,
+    relay_url: &str,
+    access_token: &str,
+    session: &session::Session,
+    send_client_info: bool,
+) -> anyhow::Result<(Value, Option<quota::Quota>)> {
+    let mut req = client
+        .post(format!("{}/api/windsurf/credentials", relay_url))
+        .bearer_auth(access_token);
+    if send_client_info {
+        req = req.json(&json!({ "client": client_info(session) }));
+    }
+    let resp = req.send().await?;
+    let headers = resp.headers().clone();
+    let creds: Value = resp.json().await?;
+    let quota = quota::parse(&headers, &creds);
+    Ok((creds, quota))
+}
+
+/// relay_status tool: relay reachability, credentials and last known quota
+async fn relay_status(client: &reqwest::Client, relay_url: &str, access_token: &str, session: &session::Session, config: &config::Config) -> String {
+    let mut lines = vec![
+        format!("relay: {}", relay_url),
+        format!("access token: {}", if access_token.is_empty() { "not set" } else { "set" }),
+    ];
+    let start = std::time::Instant::now();
+    match fetch_credentials(client, relay_url, access_token, session, config.send_client_info()).await {
+        Ok((creds, quota)) => {
+            lines.push(format!("reachable: yes ({} ms)", start.elapsed().as_millis()));
+            match creds.get("error") {
+                Some(err) => lines.push(format!("credentials: error: {}", err.as_str().unwrap_or("Authentication failed"))),
+                None => lines.push("credentials: ok".into()),
+            }
+            if let Some(q) = quota {
+                session.quota.record(q);
+            }
+        }
+        Err(e) => lines.push(format!("reachable: no ({})", e)),
+    }
+    match session.quota.last() {
+        Some(q) => {
+            let age = q.seen_at.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+            lines.push(format!("quota: {} remaining (as of {}s ago)", q.display(), age));
+        }
+        None => lines.push("quota: not reported by relay".into()),
+    }
+    if let Some(min) = config.quota.min_remaining {
+        let action = match config.quota.action {
+            quota::LowQuotaAction::Refuse => "refuse",
+            quota::LowQuotaAction::Throttle => "throttle",
+        };
+        lines.push(format!("quota threshold: {} ({})", min, action));
+    }
+    lines.join("\n")
+}
+
 /// Report search log to relay server (fire-and-forget)
+// Mirrors do_search's relay context; every call site passes the same first five
+#[allow(clippy::too_many_arguments)]
 async fn report_log(
     client: &reqwest::Client,
     relay_url: &str,
     access_token: &str,
-    query: &str,
+    session: &session::Session,
+    params: &SearchParams,
     status: &str,
     error_msg: &str,
     duration_ms: i64,
 ) {
+    // Queries routed to other providers never leave the machine
+    if params.provider.is_some() {
+        return;
+    }
+    let mut payload = json!({
+        "query": params.query,
+        "status": status,
+        "error_msg": error_msg,
+        "duration_ms": duration_ms,
+        "provider": provider::WINDSURF,
+    });
+    if params.send_client_info {
+        payload["client"] = client_info(session);
+    }
     let _ = client
-        .post(&format!("{}/api/windsurf/log", relay_url))
+        .post(format!("{}/api/windsurf/log", relay_url))
         .bearer_auth(access_token)
-        .json(&json!({
-            "query": query,
-            "status": status,
-            "error_msg": error_msg,
-            "duration_ms": duration_ms,
-            "provider": "windsurf",
-        }))
+        .json(&payload)
         .timeout(std::time::Duration::from_secs(5))
         .send()
         .await;
 }
 
-async fn do_search(
-    client: &reqwest::Client,
-    relay_url: &str,
-    access_token: &str,
-    query: &str,
-    project_root: &str,
+/// multi_repo_search concurrency: default and upper bound
+const MULTI_REPO_DEFAULT_PARALLEL: usize = 2;
+const MULTI_REPO_MAX_PARALLEL: usize = 8;
+
+/// Floor for byte budget arguments, so a result always has room for its first lines
+const MIN_BYTE_BUDGET: usize = 256;
+
+/// Arguments of a single fast_context_search call
+struct SearchParams {
+    query: String,
+    /// codebase_qa: answer the query in prose with citations instead of listing files
+    qa: bool,
+    /// diff_context_search: changed files and their dependents; the map, rg and results stay inside
+    scope: Option<diff_scope::Scope>,
+    /// fast_context_refine: earlier conversation to continue instead of starting over
+    resume: Option<conversation::Transcript>,
+    project_root: String,
     tree_depth: u32,
     max_turns: u32,
     max_results: u32,
-) -> anyhow::Result<String> {
-    let max_commands: u32 = 8;
-    let start = std::time::Instant::now();
+    map_format: config::MapFormat,
+    include_submodules: bool,
+    /// false = exclude test files from commands and results
+    include_tests: bool,
+    /// Drop generated/vendored files from results instead of tagging them
+    drop_generated: bool,
+    /// File count above which the repo map is sampled; None = never
+    sample_threshold: Option<usize>,
+    /// None = windsurf via relay
+    provider: Option<(String, provider::ProviderConfig)>,
+    verbosity: Verbosity,
+    /// Cap on the whole result text
+    max_result_bytes: Option<usize>,
+    /// Cap on each file read by the executor
+    max_file_bytes: Option<usize>,
+    /// "url@ref (commit)" when searching a cached clone; paths are then shown repo-relative
+    remote: Option<String>,
+    /// Repo name prefixed to every path in multi_repo_search results
+    repo_label: Option<String>,
+    /// Windsurf stream/unary selection
+    call_options: windsurf::CallOptions,
+    /// Attach client_info() to relay requests
+    send_client_info: bool,
+    /// What to do when the relay reports low quota
+    quota_policy: quota::QuotaPolicy,
+    /// Per-command and per-turn executor time limits
+    timeouts: executor::Timeouts,
+    /// Executor output truncation limits
+    output_limits: executor::OutputLimits,
+    /// Commands run at the same time within one turn
+    command_parallelism: usize,
+    /// Files larger than this are skipped by rg and count; None = no limit
+    search_max_filesize: Option<u64>,
+    /// Contents of the external system prompt template, re-read for every search
+    prompt_template: Option<String>,
+}
 
-    let creds: Value = client
-        .post(&format!("{}/api/windsurf/credentials", relay_url))
-        .bearer_auth(access_token)
-        .send()
-        .await?
-        .json()
-        .await?;
-
-    if let Some(err) = creds.get("error") {
-        let msg = err.as_str().unwrap_or("Authentication failed");
-        report_log(client, relay_url, access_token, query, "error", msg, start.elapsed().as_millis() as i64).await;
-        anyhow::bail!("{}", msg);
-    }
-
-    let api_key = creds["api_key"].as_str().ok_or_else(|| anyhow::anyhow!("No api_key"))?;
-    let jwt = creds["jwt"].as_str().ok_or_else(|| anyhow::anyhow!("No jwt"))?;
-    let ws_cfg = windsurf::WindsurfConfig {
-        api_base: creds["windsurf_config"]["api_base"].as_str().unwrap_or("").into(),
-        auth_base: creds["windsurf_config"]["auth_base"].as_str().unwrap_or("").into(),
-        app_version: creds["windsurf_config"]["app_version"].as_str().unwrap_or("").into(),
-        ls_version: creds["windsurf_config"]["ls_version"].as_str().unwrap_or("").into(),
-        model: creds["windsurf_config"]["model"].as_str().unwrap_or("").into(),
-        timeout_ms: creds["windsurf_config"]["timeout_ms"].as_u64().unwrap_or(30000),
+impl SearchParams {
+    /// How a /codebase-relative path is shown in results
+    fn display_path(&self, rel: &str) -> String {
+        let path = if self.remote.is_some() {
+            rel.to_string()
+        } else {
+            PathBuf::from(&self.project_root).join(rel).to_string_lossy().to_string()
+        };
+        match &self.repo_label {
+            Some(repo) => format!("[{}] {}", repo, path),
+            None => path,
+        }
+    }
+}
+
+/// Model backend for one search
+enum Backend {
+    Windsurf { cfg: windsurf::WindsurfConfig, api_key: String, jwt: String },
+    OpenAi(provider::ProviderConfig),
+}
+
+/// Backend for a search: the configured provider, or windsurf with credentials (and quota check) from the relay
+async fn connect_backend(
+    client: &reqwest::Client,
+    relay_url: &str,
+    access_token: &str,
+    params: &SearchParams,
+    session: &session::Session,
+    cancel: &CancellationToken,
+    start: std::time::Instant,
+) -> anyhow::Result<Backend> {
+    let backend = match &params.provider {
+        Some((_, cfg)) => Backend::OpenAi(cfg.clone()),
+        None => {
+            let (creds, quota) = fetch_credentials(client, relay_url, access_token, session, params.send_client_info).await?;
+
+            if let Some(err) = creds.get("error") {
+                let msg = err.as_str().unwrap_or("Authentication failed");
+                report_log(client, relay_url, access_token, session, params, "error", msg, start.elapsed().as_millis() as i64).await;
+                anyhow::bail!("{}", msg);
+            }
+
+            // Refuse or delay before the first turn rather than running out mid-search
+            if let Some(q) = quota {
+                let verdict = quota::check(&params.quota_policy, &q);
+                session.quota.record(q);
+                match verdict {
+                    Ok(Some(delay)) => {
+                        log_warn!("quota low, throttling {}ms", delay.as_millis());
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {}
+                            _ = cancel.cancelled() => anyhow::bail!("cancelled"),
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(msg) => {
+                        report_log(client, relay_url, access_token, session, params, "error", &msg, start.elapsed().as_millis() as i64).await;
+                        anyhow::bail!("{}", msg);
+                    }
+                }
+            }
+
+            let api_key = creds["api_key"].as_str().ok_or_else(|| anyhow::anyhow!("No api_key"))?;
+            let jwt = creds["jwt"].as_str().ok_or_else(|| anyhow::anyhow!("No jwt"))?;
+            let ws_cfg = windsurf::WindsurfConfig {
+                api_base: creds["windsurf_config"]["api_base"].as_str().unwrap_or("").into(),
+                auth_base: creds["windsurf_config"]["auth_base"].as_str().unwrap_or("").into(),
+                app_version: creds["windsurf_config"]["app_version"].as_str().unwrap_or("").into(),
+                ls_version: creds["windsurf_config"]["ls_version"].as_str().unwrap_or("").into(),
+                model: creds["windsurf_config"]["model"].as_str().unwrap_or("").into(),
+                timeout_ms: creds["windsurf_config"]["timeout_ms"].as_u64().unwrap_or(30000),
+            };
+            Backend::Windsurf { cfg: ws_cfg, api_key: api_key.to_string(), jwt: jwt.to_string() }
+        }
     };
+    Ok(backend)
+}
 
-    let repo_map = generate_repo_map(project_root, tree_depth);
-    let system_prompt = prompt::build_system_prompt(max_turns, max_commands, max_results);
-    let user_content = format!(
-        "Problem Statement: {}\n\nRepo Map (tree -L {} /codebase):\n```text\n{}\n```",
-        query, tree_depth, repo_map
-    );
-    let tool_defs = prompt::get_tool_definitions(max_commands);
+/// One model turn: (thinking text, tool call)
+async fn backend_turn(
+    client: &reqwest::Client,
+    backend: &Backend,
+    params: &SearchParams,
+    messages: &[windsurf::ChatMessage],
+    tool_defs: &str,
+) -> Result<(String, Option<(String, Value)>), String> {
+    match backend {
+        Backend::Windsurf { cfg, api_key, jwt } => {
+            let proto = windsurf::build_request(cfg, api_key, jwt, messages, tool_defs);
+            windsurf::call(client, cfg, &proto, &params.call_options)
+                .await
+                .map_err(|e| format!("Windsurf API error: {}", e))
+        }
+        Backend::OpenAi(cfg) => {
+            let provider_name = params.provider.as_ref().map(|(name, _)| name.as_str()).unwrap_or(provider::WINDSURF);
+            provider::chat_turn(client, cfg, messages, tool_defs)
+                .await
+                .map_err(|e| format!("{} API error: {}", provider_name, e))
+        }
+    }
+}
 
-    let mut messages = vec![
-        windsurf::ChatMessage { role: 5, content: system_prompt, tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None },
-        windsurf::ChatMessage { role: 1, content: user_content, tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None },
-    ];
+async fn do_search(
+    client: &reqwest::Client,
+    relay_url: &str,
+    access_token: &str,
+    params: &SearchParams,
+    session: &session::Session,
+    cancel: &CancellationToken,
+) -> anyhow::Result<SearchOutput> {
+    let query = params.query.as_str();
+    let project_root = params.project_root.as_str();
+    let (tree_depth, max_turns, max_results) = (params.tree_depth, params.max_turns, params.max_results);
+    let max_commands: u32 = 8;
+    let start = std::time::Instant::now();
+    let provider_name = params.provider.as_ref().map(|(name, _)| name.as_str()).unwrap_or(provider::WINDSURF);
 
-    let mut exec = executor::ToolExecutor::new(project_root);
-    let total_api_calls = max_turns + 1;
+    let backend = connect_backend(client, relay_url, access_token, params, session, cancel, start).await?;
 
-    for turn in 0..total_api_calls {
-        let proto = windsurf::build_request(&ws_cfg, api_key, jwt, &messages, &tool_defs);
-        let resp_data = match windsurf::streaming_request(client, &ws_cfg, &proto).await {
-            Ok(data) => data,
-            Err(e) => {
-                let msg = format!("Windsurf API error: {}", e);
-                report_log(client, relay_url, access_token, query, "error", &msg, start.elapsed().as_millis() as i64).await;
-                anyhow::bail!("{}", msg);
+    let tool_defs = if params.qa { prompt::get_qa_tool_definitions(max_commands) } else { prompt::get_tool_definitions(max_commands) };
+    let mut messages = match &params.resume {
+        // The earlier system prompt, repo map and command results stay in place; only the follow-up is new
+        Some(prev) => {
+            let mut messages = prev.messages.clone();
+            messages.push(windsurf::ChatMessage {
+                role: 1, content: prompt::refine_request(query),
+                tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None,
+            });
+            messages
+        }
+        None => {
+            let (map_title, repo_map) = match &params.scope {
+                Some(scope) => (scope.title(), scope.map()),
+                None => build_repo_map(project_root, tree_depth, params.map_format, params.include_submodules, params.sample_threshold, &[]),
+            };
+            let mut system_prompt = match &params.prompt_template {
+                _ if params.qa => prompt::build_qa_system_prompt(max_turns, max_commands),
+                Some(template) => prompt::render_template(template, max_turns, max_commands, max_results),
+                None => prompt::build_system_prompt(max_turns, max_commands, max_results),
+            };
+            if let Some(guidance) = prompt::test_guidance(params.include_tests, lang::mentions_tests(query)) {
+                system_prompt.push_str(&guidance);
             }
-        };
+            if let Some(scope) = &params.scope {
+                system_prompt.push_str(&prompt::diff_guidance(&scope.label));
+            }
+            let user_content = format!(
+                "{}: {}\n\nRepo Map ({}):\n```text\n{}\n```",
+                if params.qa { "Question" } else { "Problem Statement" },
+                query, map_title, repo_map
+            );
+            vec![
+                windsurf::ChatMessage { role: 5, content: system_prompt, tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None },
+                windsurf::ChatMessage { role: 1, content: user_content, tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None },
+            ]
+        }
+    };
 
-        let (thinking, tool_info) = windsurf::parse_response(&resp_data);
+    let mut exec = executor::ToolExecutor::new(project_root);
+    if let Some(prev) = &params.resume {
+        exec.restore(executor::Collected { rg_patterns: prev.rg_patterns.clone(), files: prev.files.clone(), matches: prev.matches.clone() });
+    }
+    exec.submodules.include = params.include_submodules;
+    exec.max_file_bytes = params.max_file_bytes;
+    exec.exclude_tests = !params.include_tests;
+    exec.timeouts = params.timeouts;
+    exec.limits = params.output_limits;
+    exec.parallelism = params.command_parallelism;
+    exec.search_max_filesize = params.search_max_filesize;
+    exec.cancel = cancel.clone();
+    if let Some(scope) = &params.scope {
+        exec.set_scope(scope.files());
+    }
+    let total_api_calls = max_turns + 1;
+    let mut commands_run = 0usize;
 
-        match tool_info {
-            None => {
-                if thinking.starts_with("[Error]") {
-                    report_log(client, relay_url, access_token, query, "error", &thinking, start.elapsed().as_millis() as i64).await;
-                    anyhow::bail!("{}", thinking);
+    let mut output: SearchOutput = 'search: {
+        for turn in 0..total_api_calls {
+            let turn_call = backend_turn(client, &backend, params, &messages, &tool_defs);
+            // Dropping the in-flight call closes the streaming connection
+            let turn_result = tokio::select! {
+                r = turn_call => r,
+                _ = cancel.cancelled() => {
+                    report_log(client, relay_url, access_token, session, params, "cancelled", "cancelled by client", start.elapsed().as_millis() as i64).await;
+                    anyhow::bail!("cancelled");
                 }
-                report_log(client, relay_url, access_token, query, "success", "", start.elapsed().as_millis() as i64).await;
-                return Ok(format!("No relevant files found.\n\nRaw: {}", thinking));
-            }
-            Some((name, args)) => {
-                if name == "answer" {
-                    let answer_xml = args.get("answer").and_then(|v| v.as_str()).unwrap_or("");
-                    let result = format_answer(answer_xml, project_root, &exec.collected_rg_patterns, tree_depth, max_turns);
-                    report_log(client, relay_url, access_token, query, "success", "", start.elapsed().as_millis() as i64).await;
-                    return Ok(result);
+            };
+            let (thinking, tool_info) = match turn_result {
+                Ok(turn) => turn,
+                Err(msg) => {
+                    report_log(client, relay_url, access_token, session, params, "error", &msg, start.elapsed().as_millis() as i64).await;
+                    anyhow::bail!("{}", msg);
                 }
-                if name == "restricted_exec" {
-                    let call_id = uuid::Uuid::new_v4().to_string();
-                    let args_json = serde_json::to_string(&args)?;
-                    let results = exec.exec_tool_call(&args).await;
+            };
 
+            match tool_info {
+                None => {
+                    if thinking.starts_with("[Error]") {
+                        report_log(client, relay_url, access_token, session, params, "error", &thinking, start.elapsed().as_millis() as i64).await;
+                        anyhow::bail!("{}", thinking);
+                    }
+                    report_log(client, relay_url, access_token, session, params, "success", "", start.elapsed().as_millis() as i64).await;
                     messages.push(windsurf::ChatMessage {
-                        role: 2, content: thinking,
-                        tool_call_id: Some(call_id.clone()),
-                        tool_name: Some("restricted_exec".into()),
-                        tool_args_json: Some(args_json),
-                        ref_call_id: None,
-                    });
-                    messages.push(windsurf::ChatMessage {
-                        role: 4, content: results,
-                        tool_call_id: None, tool_name: None, tool_args_json: None,
-                        ref_call_id: Some(call_id),
+                        role: 2, content: thinking.clone(),
+                        tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None,
                     });
+                    // Some models answer a question in plain text instead of calling the answer tool
+                    if params.qa && !thinking.trim().is_empty() {
+                        let stats = SearchStats { provider: provider_name.to_string(), turns: turn + 1, commands: commands_run, elapsed_ms: start.elapsed().as_millis() };
+                        let collected = exec.collected();
+                        break 'search format_qa_answer(&thinking, params, session, &collected.rg_patterns, &collected.matches, &stats);
+                    }
+                    if params.verbosity == Verbosity::Minimal {
+                        break 'search "No relevant files found.".into();
+                    }
+                    break 'search format!("No relevant files found.\n\nRaw: {}", thinking).into();
+                }
+                Some((name, args)) => {
+                    if name == "answer" {
+                        let answer_xml = args.get("answer").and_then(|v| v.as_str()).unwrap_or("");
+                        let stats = SearchStats { provider: provider_name.to_string(), turns: turn + 1, commands: commands_run, elapsed_ms: start.elapsed().as_millis() };
+                        let collected = exec.collected();
+                        let result = if params.qa {
+                            format_qa_answer(answer_xml, params, session, &collected.rg_patterns, &collected.matches, &stats)
+                        } else {
+                            format_answer(answer_xml, params, session, &collected.rg_patterns, &collected.matches, &stats)
+                        };
+                        report_log(client, relay_url, access_token, session, params, "success", "", start.elapsed().as_millis() as i64).await;
+                        // Keep the answer in the conversation so fast_context_refine can build on it
+                        let call_id = uuid::Uuid::new_v4().to_string();
+                        messages.push(windsurf::ChatMessage {
+                            role: 2, content: thinking,
+                            tool_call_id: Some(call_id.clone()),
+                            tool_name: Some("answer".into()),
+                            tool_args_json: Some(serde_json::to_string(&args)?),
+                            ref_call_id: None,
+                        });
+                        messages.push(windsurf::ChatMessage {
+                            role: 4, content: prompt::ANSWER_RECEIVED.into(),
+                            tool_call_id: None, tool_name: None, tool_args_json: None,
+                            ref_call_id: Some(call_id),
+                        });
+                        break 'search result;
+                    }
+                    if name == "restricted_exec" {
+                        let call_id = uuid::Uuid::new_v4().to_string();
+                        let args_json = serde_json::to_string(&args)?;
+                        let results = exec.exec_tool_call(&args).await;
+                        if cancel.is_cancelled() {
+                            report_log(client, relay_url, access_token, session, params, "cancelled", "cancelled by client", start.elapsed().as_millis() as i64).await;
+                            anyhow::bail!("cancelled");
+                        }
+                        commands_run += args.as_object().map(|o| o.keys().filter(|k| k.starts_with("command")).count()).unwrap_or(0);
 
-                    if turn >= max_turns - 1 {
                         messages.push(windsurf::ChatMessage {
-                            role: 1, content: prompt::FINAL_FORCE_ANSWER.into(),
-                            tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None,
+                            role: 2, content: thinking,
+                            tool_call_id: Some(call_id.clone()),
+                            tool_name: Some("restricted_exec".into()),
+                            tool_args_json: Some(args_json),
+                            ref_call_id: None,
+                        });
+                        messages.push(windsurf::ChatMessage {
+                            role: 4, content: results,
+                            tool_call_id: None, tool_name: None, tool_args_json: None,
+                            ref_call_id: Some(call_id),
                         });
+
+                        if turn >= max_turns - 1 {
+                            messages.push(windsurf::ChatMessage {
+                                role: 1, content: prompt::FINAL_FORCE_ANSWER.into(),
+                                tool_call_id: None, tool_name: None, tool_args_json: None, ref_call_id: None,
+                            });
+                        }
                     }
                 }
             }
         }
-    }
 
-    report_log(client, relay_url, access_token, query, "timeout", "max turns", start.elapsed().as_millis() as i64).await;
+        report_log(client, relay_url, access_token, session, params, "timeout", "max turns", start.elapsed().as_millis() as i64).await;
 
-    // Fallback: build answer from files the AI read during search
-    if !exec.collected_files.is_empty() {
-        let mut seen = std::collections::HashSet::new();
-        let mut parts = Vec::new();
-        let files: Vec<&String> = exec.collected_files.iter()
-            .filter(|f| seen.insert(f.to_string()))
-            .collect();
-        let n = files.len();
-        parts.push(format!("Found {} files (max turns reached, partial result).", n));
-        parts.push(String::new());
-        for (i, f) in files.iter().enumerate() {
-            let rel = f.replace("/codebase/", "");
-            let full = PathBuf::from(project_root).join(&rel);
-            parts.push(format!("  [{}/{}] {}", i + 1, n, full.to_string_lossy()));
-        }
-        let unique_rg: Vec<&String> = exec.collected_rg_patterns.iter()
-            .collect::<std::collections::HashSet<_>>()
-            .into_iter()
-            .filter(|p| p.len() >= 3)
-            .collect();
-        if !unique_rg.is_empty() {
+        // Fallback: build answer from files the AI read during search
+        let collected = exec.collected();
+        if collected.files.iter().any(|f| params.include_tests || !lang::is_test_path(f)) {
+            let mut seen = std::collections::HashSet::new();
+            let mut parts = Vec::new();
+            let files: Vec<&String> = collected.files.iter()
+                .filter(|f| params.include_tests || !lang::is_test_path(f))
+                .filter(|f| seen.insert(f.to_string()))
+                .collect();
+            let root = std::path::Path::new(project_root);
+            let attrs = lang::LinguistAttrs::load(root);
+            let mut fixes = Vec::new();
+            let files: Vec<(String, Option<&str>)> = verified_files(root, &files, &mut fixes)
+                .into_iter()
+                .map(|rel| {
+                    let tag = lang::provenance(root, &rel, &attrs);
+                    (rel, tag)
+                })
+                .filter(|(rel, tag)| !(params.drop_generated && tag.is_some()) && params.scope.as_ref().is_none_or(|s| s.contains(rel)))
+                .collect();
+            let mut found = Vec::new();
+            for (rel, _) in &files {
+                session.discovered.remember(root, &root.join(rel));
+                found.push(FoundFile::new(params, rel, Vec::new(), &collected.rg_patterns, &collected.matches));
+            }
+            let n = files.len();
+            if let Some(label) = &params.remote {
+                parts.push(format!("Repository: {}", label));
+            }
+            if params.qa {
+                parts.push(format!("No answer within {} turns; the model examined {} files (partial result).", max_turns, n));
+            } else {
+                parts.push(format!("Found {} files (max turns reached, partial result).", n));
+            }
             parts.push(String::new());
-            let kw: Vec<&str> = unique_rg.iter().map(|s| s.as_str()).collect();
-            parts.push(format!("grep keywords: {}", kw.join(", ")));
+            for (i, (rel, tag)) in files.iter().enumerate() {
+                parts.push(format!("  [{}/{}] {}{}", i + 1, n, params.display_path(rel), provenance_suffix(*tag)));
+            }
+            push_fixes(&mut parts, params, &fixes);
+            let stats = SearchStats { provider: provider_name.to_string(), turns: total_api_calls, commands: commands_run, elapsed_ms: start.elapsed().as_millis() };
+            result_footer(&mut parts, params, session, &collected.rg_patterns, &stats, " (timeout fallback)");
+            break 'search SearchOutput { text: apply_result_budget(parts.join("\n"), params), files: found, partial: true, answer: None, transcript: None };
         }
-        parts.push(String::new());
-        parts.push(format!("[config] tree_depth={}, max_turns={} (timeout fallback)", tree_depth, max_turns));
-        return Ok(parts.join("\n"));
-    }
 
-    Ok("Max turns reached without answer".into())
+        "Max turns reached without answer".into()
+    };
+
+    let collected = exec.collected();
+    output.transcript = Some(conversation::Transcript {
+        messages,
+        rg_patterns: collected.rg_patterns,
+        matches: collected.matches,
+        files: collected.files,
+    });
+    Ok(output)
+}
+
+/// Repo map given to the model (and returned by repo_overview): (title, map).
+/// Repos over the sample threshold get a sampled map instead of a truncated one.
+fn build_repo_map(
+    project_root: &str,
+    depth: u32,
+    format: config::MapFormat,
+    include_submodules: bool,
+    sample_threshold: Option<usize>,
+    exclude: &[String],
+) -> (String, String) {
+    let sampled = sample_threshold.and_then(|t| repo_sample::sampled_map(project_root, depth, include_submodules, t, exclude));
+    match (sampled, format) {
+        (Some((total, map)), _) => (format!("sampled, {} files, depth {}", total, depth), map),
+        (None, config::MapFormat::Tree) => (
+            format!("tree -L {} /codebase", depth),
+            generate_repo_map(project_root, depth, include_submodules, exclude),
+        ),
+        (None, config::MapFormat::Files) => (
+            format!("rg --files --max-depth {} /codebase; size: {}", depth, SIZE_BUCKET_LEGEND),
+            generate_file_list_map(project_root, depth, include_submodules, exclude),
+        ),
+    }
 }
 
-fn generate_repo_map(project_root: &str, target_depth: u32) -> String {
+fn generate_repo_map(project_root: &str, target_depth: u32, include_submodules: bool, exclude: &[String]) -> String {
     let root = PathBuf::from(project_root);
+    let subs = executor::Submodules::discover(&root, include_submodules);
     let mut lines = vec!["/codebase".to_string()];
-    tree_walk_for_map(&root, "", target_depth as usize, 0, &mut lines);
+    tree_walk_for_map(&root, "", target_depth as usize, 0, &mut lines, &subs, exclude, &mut executor::WalkGuard::new(&root));
     let result = lines.join("\n");
     if result.len() > 250 * 1024 && target_depth > 1 {
-        return generate_repo_map(project_root, target_depth - 1);
+        return generate_repo_map(project_root, target_depth - 1, include_submodules, exclude);
     }
     result
 }
 
-fn tree_walk_for_map(dir: &std::path::Path, prefix: &str, max_depth: usize, depth: usize, lines: &mut Vec<String>) {
-    if depth >= max_depth || lines.len() > 2000 { return; }
-    let mut entries: Vec<_> = match std::fs::read_dir(dir) {
-        Ok(rd) => rd.filter_map(|e| e.ok()).collect(),
-        Err(_) => return,
-    };
-    entries.sort_by_key(|e| e.file_name());
-    let skip = ["node_modules", ".git", "dist", "build", "target", ".venv", "__pycache__", "vendor", ".cache"];
-    let filtered: Vec<_> = entries.into_iter()
-        .filter(|e| {
-            let name = e.file_name().to_string_lossy().to_string();
-            !name.starts_with('.') && !skip.contains(&name.as_str())
+const SIZE_BUCKET_LEGEND: &str = "S <1KB, M <10KB, L <100KB, XL >=100KB";
+const MAP_MAX_LINES: usize = 2000;
+
+fn size_bucket(bytes: u64) -> &'static str {
+    match bytes {
+        0..=1023 => "S",
+        1024..=10_239 => "M",
+        10_240..=102_399 => "L",
+        _ => "XL",
+    }
+}
+
+/// Flat repo map: one `/codebase/<path> <size bucket>` line per file, gitignore-aware via rg (or the ignore walker)
+fn generate_file_list_map(project_root: &str, target_depth: u32, include_submodules: bool, exclude: &[String]) -> String {
+    let root = PathBuf::from(project_root);
+    let subs = executor::Submodules::discover(&root, include_submodules);
+    // The map goes to the remote model: denied paths must not appear in it
+    let deny = deny_paths::get();
+    let listed = ripgrep::caps()
+        .unusable()
+        .is_none()
+        .then(|| {
+            std::process::Command::new(executor::find_rg_binary())
+                .args(["--files", "--sort", "path", "--max-depth", &target_depth.to_string()])
+                .args(executor::no_ignore().then_some("--no-ignore"))
+                .args(deny.rg_globs().into_iter().flat_map(|g| ["--glob".to_string(), g]))
+                .current_dir(&root)
+                .output()
+                .ok()
         })
-        .collect();
-    let count = filtered.len();
-    for (i, entry) in filtered.iter().enumerate() {
-        let name = entry.file_name().to_string_lossy().to_string();
+        .flatten()
+        .filter(|o| o.status.success() || o.status.code() == Some(1));
+    let mut files: Vec<PathBuf> = match listed {
+        Some(out) => String::from_utf8_lossy(&out.stdout).lines().filter(|l| !l.is_empty()).map(PathBuf::from).collect(),
+        // No rg: the tree map's walk, same ignore rules
+        None => {
+            let mut files = Vec::new();
+            file_walk_for_map(&root, &root, target_depth as usize, 0, &mut files, exclude, &mut executor::WalkGuard::new(&root));
+            files
+        }
+    };
+    files.retain(|rel| !deny.denies(&root, &root.join(rel)));
+
+    let mut lines = Vec::new();
+    let mut shown = 0;
+    for rel in &files {
+        let abs = root.join(rel);
+        if !subs.include && subs.contains(&abs) {
+            continue;
+        }
+        if rel.components().any(|c| executor::excluded(&c.as_os_str().to_string_lossy(), exclude)) {
+            continue;
+        }
+        if shown == MAP_MAX_LINES {
+            lines.push(format!("... ({} more files)", files.len() - shown));
+            break;
+        }
+        let size = std::fs::metadata(&abs).map(|m| m.len()).unwrap_or(0);
+        lines.push(format!("/codebase/{} {}", rel.to_string_lossy().replace('\\', "/"), size_bucket(size)));
+        shown += 1;
+    }
+    if lines.is_empty() {
+        return "(no files)".into();
+    }
+    lines.join("\n")
+}
+
+fn file_walk_for_map(
+    root: &std::path::Path,
+    dir: &std::path::Path,
+    max_depth: usize,
+    depth: usize,
+    files: &mut Vec<PathBuf>,
+    exclude: &[String],
+    guard: &mut executor::WalkGuard,
+) {
+    if depth >= max_depth || files.len() > MAP_MAX_LINES {
+        return;
+    }
+    for path in guard.list_dir(dir, exclude) {
+        if path.is_dir() {
+            if guard.enter(&path) {
+                file_walk_for_map(root, &path, max_depth, depth + 1, files, exclude, guard);
+            }
+        } else if let Ok(rel) = path.strip_prefix(root) {
+            files.push(rel.to_path_buf());
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn tree_walk_for_map(
+    dir: &std::path::Path,
+    prefix: &str,
+    max_depth: usize,
+    depth: usize,
+    lines: &mut Vec<String>,
+    subs: &executor::Submodules,
+    exclude: &[String],
+    guard: &mut executor::WalkGuard,
+) {
+    if depth >= max_depth || lines.len() > 2000 { return; }
+    let entries = guard.list_dir(dir, exclude);
+    let count = entries.len();
+    for (i, path) in entries.iter().enumerate() {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
         let is_last = i == count - 1;
         let connector = if is_last { "└── " } else { "├── " };
-        lines.push(format!("{}{}{}", prefix, connector, name));
-        if entry.path().is_dir() {
+        let is_sub = subs.is_submodule(path);
+        lines.push(format!("{}{}{}{}", prefix, connector, name, if is_sub { " [submodule]" } else { "" }));
+        if path.is_dir() && (!is_sub || subs.include) && guard.enter(path) {
             let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
-            tree_walk_for_map(&entry.path(), &new_prefix, max_depth, depth + 1, lines);
+            tree_walk_for_map(path, &new_prefix, max_depth, depth + 1, lines, subs, exclude, guard);
+        }
+    }
+}
+
+/// How much surrounding detail a result carries
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Verbosity {
+    /// File list only
+    Minimal,
+    /// Plus grep keywords and the [config] footer
+    Normal,
+    /// Plus search stats
+    Debug,
+}
+
+impl Verbosity {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "minimal" => Some(Verbosity::Minimal),
+            "" | "normal" => Some(Verbosity::Normal),
+            "debug" => Some(Verbosity::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// Largest file scanned for structured-result keywords
+const KEYWORD_SCAN_BYTES: u64 = 1024 * 1024;
+
+/// One result file, as reported in structuredContent
+struct FoundFile {
+    path: String,
+    ranges: Vec<(u64, u64)>,
+    /// grep patterns from the search that match inside the reported ranges
+    keywords: Vec<String>,
+    /// rg matches the model saw inside the reported ranges (whole file when there are none)
+    matches: Vec<executor::RgMatch>,
+}
+
+impl FoundFile {
+    fn new(params: &SearchParams, rel: &str, ranges: Vec<(u64, u64)>, rg_patterns: &[String], rg_matches: &[executor::RgMatch]) -> Self {
+        let real = PathBuf::from(&params.project_root).join(rel);
+        let path = if params.remote.is_some() { rel.to_string() } else { real.to_string_lossy().to_string() };
+        let keywords = Self::matching_keywords(&real, &ranges, rg_patterns);
+        let virtual_path = format!("/codebase/{}", rel);
+        let mut matches: Vec<executor::RgMatch> = rg_matches
+            .iter()
+            .filter(|m| m.path == virtual_path && (ranges.is_empty() || ranges.iter().any(|(s, e)| (*s..=*e).contains(&(m.line as u64)))))
+            .cloned()
+            .collect();
+        matches.sort_by_key(|m| m.line);
+        Self { path, ranges, keywords, matches }
+    }
+
+    fn matching_keywords(file: &std::path::Path, ranges: &[(u64, u64)], rg_patterns: &[String]) -> Vec<String> {
+        if rg_patterns.is_empty() || std::fs::metadata(file).map(|m| m.len() > KEYWORD_SCAN_BYTES).unwrap_or(true) {
+            return Vec::new();
+        }
+        let Ok(content) = std::fs::read_to_string(file) else { return Vec::new() };
+        let text: String = if ranges.is_empty() {
+            content
+        } else {
+            content
+                .lines()
+                .enumerate()
+                .filter(|(i, _)| ranges.iter().any(|(s, e)| (*s..=*e).contains(&(*i as u64 + 1))))
+                .map(|(_, l)| l)
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut keywords: Vec<String> = Vec::new();
+        for p in rg_patterns {
+            if keywords.contains(p) {
+                continue;
+            }
+            if regex_lite::Regex::new(p).is_ok_and(|re| re.is_match(&text)) {
+                keywords.push(p.clone());
+            }
+        }
+        keywords
+    }
+}
+
+/// do_search result: formatted text plus the files behind it
+struct SearchOutput {
+    text: String,
+    files: Vec<FoundFile>,
+    /// max turns reached; files are those the model read, without ranges
+    partial: bool,
+    /// codebase_qa answer text (citations already rewritten)
+    answer: Option<String>,
+    /// Conversation at the end of the search, kept for fast_context_refine
+    transcript: Option<conversation::Transcript>,
+}
+
+impl From<&str> for SearchOutput {
+    fn from(text: &str) -> Self {
+        Self { text: text.to_string(), files: Vec::new(), partial: false, answer: None, transcript: None }
+    }
+}
+
+impl From<String> for SearchOutput {
+    fn from(text: String) -> Self {
+        Self { text, files: Vec::new(), partial: false, answer: None, transcript: None }
+    }
+}
+
+impl SearchOutput {
+    /// structuredContent for tools/call
+    fn structured(&self) -> Value {
+        let files: Vec<Value> = self
+            .files
+            .iter()
+            .map(|f| {
+                json!({
+                    "path": f.path,
+                    "ranges": f.ranges.iter().map(|(s, e)| json!({ "start": s, "end": e })).collect::<Vec<_>>(),
+                    "keywords": f.keywords,
+                    "matches": f.matches.iter().map(|m| json!({ "line": m.line, "column": m.column, "text": m.text })).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut structured = json!({ "files": files, "partial": self.partial });
+        if let Some(answer) = &self.answer {
+            structured["answer"] = json!(answer);
         }
+        structured
     }
 }
 
-fn format_answer(xml: &str, project_root: &str, rg_patterns: &[String], tree_depth: u32, max_turns: u32) -> String {
+/// Counters reported at debug verbosity
+struct SearchStats {
+    provider: String,
+    turns: u32,
+    commands: usize,
+    elapsed_ms: u128,
+}
+
+/// grep keywords, [config] footer and stats, depending on verbosity
+fn result_footer(parts: &mut Vec<String>, params: &SearchParams, session: &session::Session, rg_patterns: &[String], stats: &SearchStats, note: &str) {
+    if params.verbosity == Verbosity::Minimal {
+        return;
+    }
+    let unique: Vec<&String> = rg_patterns.iter()
+        .collect::<std::collections::HashSet<_>>()
+        .into_iter()
+        .filter(|p| p.len() >= 3)
+        .collect();
+    if !unique.is_empty() {
+        parts.push(String::new());
+        let kw: Vec<&str> = unique.iter().map(|s| s.as_str()).collect();
+        parts.push(format!("grep keywords: {}", kw.join(", ")));
+    }
+    parts.push(String::new());
+    parts.push(format!("[config] tree_depth={}, max_turns={}{}", params.tree_depth, params.max_turns, note));
+    if params.verbosity == Verbosity::Debug {
+        parts.push(format!(
+            "[stats] provider={}, turns={}, commands={}, elapsed_ms={}",
+            stats.provider, stats.turns, stats.commands, stats.elapsed_ms
+        ));
+    }
+    if params.provider.is_none() {
+        if let Some(q) = session.quota.last() {
+            parts.push(format!("[quota] remaining={}", q.display()));
+        }
+    }
+}
+
+fn apply_result_budget(text: String, params: &SearchParams) -> String {
+    match params.max_result_bytes {
+        Some(max) => executor::truncate_bytes(&text, max),
+        None => text,
+    }
+}
+
+/// " [generated]" / " [vendored]" / " [minified]" after a result line
+fn provenance_suffix(tag: Option<&str>) -> String {
+    tag.map(|t| format!(" [{}]", t)).unwrap_or_default()
+}
+
+/// Check a reported file against the project: None drops it (missing), ranges past EOF are clamped.
+/// What was changed is appended to `fixes`.
+fn verified_spans(root: &std::path::Path, rel: &str, spans: &[(u64, u64)], fixes: &mut Vec<String>) -> Option<Vec<(u64, u64)>> {
+    match verify_ranges::check(root, rel, spans, false) {
+        Ok(checked) => {
+            fixes.extend(checked.notes.iter().map(|n| format!("{}: {}", rel, n)));
+            Some(checked.ranges)
+        }
+        Err(e) => {
+            log_debug!("dropping result {}: {}", rel, e);
+            fixes.push(format!("{}: dropped ({})", rel, e));
+            None
+        }
+    }
+}
+
+/// Files the model asked to read (raw `file` arguments), as project-relative paths that
+/// exist inside the root; anything else is dropped and noted in `fixes`
+fn verified_files(root: &std::path::Path, requested: &[&String], fixes: &mut Vec<String>) -> Vec<String> {
+    requested
+        .iter()
+        .map(|f| f.strip_prefix("/codebase/").unwrap_or(f).to_string())
+        .filter(|rel| verified_spans(root, rel, &[], fixes).is_some())
+        .collect()
+}
+
+/// "[verify]" lines listing results fixed by verified_spans
+fn push_fixes(parts: &mut Vec<String>, params: &SearchParams, fixes: &[String]) {
+    if params.verbosity == Verbosity::Minimal || fixes.is_empty() {
+        return;
+    }
+    parts.push(String::new());
+    parts.extend(fixes.iter().map(|f| format!("[verify] {}", f)));
+}
+
+fn format_answer(xml: &str, params: &SearchParams, session: &session::Session, rg_patterns: &[String], rg_matches: &[executor::RgMatch], stats: &SearchStats) -> SearchOutput {
     let file_re = regex_lite::Regex::new(r#"<file\s+path="([^"]+)">([\s\S]*?)</file>"#).unwrap();
     let range_re = regex_lite::Regex::new(r"<range>(\d+)-(\d+)</range>").unwrap();
+    let root = std::path::Path::new(&params.project_root);
+    let attrs = lang::LinguistAttrs::load(root);
     let mut files = Vec::new();
+    let mut found = Vec::new();
+    let mut fixes = Vec::new();
     for cap in file_re.captures_iter(xml) {
         let rel = cap[1].replace("/codebase/", "");
-        let full_path = PathBuf::from(project_root).join(&rel);
-        let ranges: Vec<String> = range_re.captures_iter(&cap[2])
-            .map(|rc| format!("L{}-{}", &rc[1], &rc[2]))
+        if !params.include_tests && lang::is_test_path(&rel) {
+            continue;
+        }
+        if params.scope.as_ref().is_some_and(|s| !s.contains(&rel)) {
+            continue;
+        }
+        let tag = lang::provenance(root, &rel, &attrs);
+        if params.drop_generated && tag.is_some() {
+            continue;
+        }
+        let spans: Vec<(u64, u64)> = range_re.captures_iter(&cap[2])
+            .filter_map(|rc| Some((rc[1].parse().ok()?, rc[2].parse().ok()?)))
             .collect();
-        files.push((full_path.to_string_lossy().to_string(), ranges.join(", ")));
+        let Some(spans) = verified_spans(root, &rel, &spans, &mut fixes) else { continue };
+        let ranges: Vec<String> = spans.iter().map(|(s, e)| format!("L{}-{}", s, e)).collect();
+        session.discovered.remember(root, &root.join(&rel));
+        found.push(FoundFile::new(params, &rel, spans, rg_patterns, rg_matches));
+        files.push((params.display_path(&rel), ranges.join(", "), tag));
     }
     let mut parts = Vec::new();
+    if let Some(label) = &params.remote {
+        parts.push(format!("Repository: {}", label));
+    }
     let n = files.len();
     if n > 0 {
         parts.push(format!("Found {} relevant files.", n));
         parts.push(String::new());
-        for (i, (path, ranges)) in files.iter().enumerate() {
-            parts.push(format!("  [{}/{}] {} ({})", i + 1, n, path, ranges));
+        for (i, (path, ranges, tag)) in files.iter().enumerate() {
+            parts.push(format!("  [{}/{}] {} ({}){}", i + 1, n, path, ranges, provenance_suffix(*tag)));
         }
     } else {
         parts.push("No relevant files found.".into());
     }
-    let unique: Vec<&String> = rg_patterns.iter()
-        .collect::<std::collections::HashSet<_>>()
+    push_fixes(&mut parts, params, &fixes);
+    result_footer(&mut parts, params, session, rg_patterns, stats, "");
+    SearchOutput { text: apply_result_budget(parts.join("\n"), params), files: found, partial: false, answer: None, transcript: None }
+}
+
+/// codebase_qa answer: /codebase citations shown as real paths, cited ranges listed as sources
+fn format_qa_answer(answer: &str, params: &SearchParams, session: &session::Session, rg_patterns: &[String], rg_matches: &[executor::RgMatch], stats: &SearchStats) -> SearchOutput {
+    let cite_re = regex_lite::Regex::new(r#"/codebase/([^\s:`'"()\[\]<>,;]+)(?::(\d+)(?:-(\d+))?)?"#).unwrap();
+    let body = answer.trim();
+    let body = body.strip_prefix("<ANSWER>").unwrap_or(body);
+    let body = body.strip_suffix("</ANSWER>").unwrap_or(body).trim();
+
+    // (rel, ranges) in order of first citation
+    let mut cited: Vec<(String, Vec<(u64, u64)>)> = Vec::new();
+    let text = cite_re.replace_all(body, |c: &regex_lite::Captures| {
+        // A sentence-ending period is not part of the path
+        let rel = c[1].trim_end_matches('.');
+        let trailing = &c[1][rel.len()..];
+        let span = c.get(2).and_then(|s| {
+            let start: u64 = s.as_str().parse().ok()?;
+            let end = c.get(3).and_then(|e| e.as_str().parse().ok()).unwrap_or(start);
+            Some((start, end.max(start)))
+        });
+        let idx = match cited.iter().position(|(r, _)| r == rel) {
+            Some(i) => i,
+            None => {
+                cited.push((rel.to_string(), Vec::new()));
+                cited.len() - 1
+            }
+        };
+        let lines = match span {
+            Some((s, e)) => {
+                if !cited[idx].1.contains(&(s, e)) {
+                    cited[idx].1.push((s, e));
+                }
+                if s == e { format!(":{}", s) } else { format!(":{}-{}", s, e) }
+            }
+            None => String::new(),
+        };
+        format!("{}{}{}", params.display_path(rel), lines, trailing)
+    });
+    let text = text.into_owned();
+
+    let root = std::path::Path::new(&params.project_root);
+    let attrs = lang::LinguistAttrs::load(root);
+    let mut parts = Vec::new();
+    if let Some(label) = &params.remote {
+        parts.push(format!("Repository: {}", label));
+    }
+    parts.push(if text.is_empty() { "No answer found.".to_string() } else { text.clone() });
+    let mut found = Vec::new();
+    let mut fixes = Vec::new();
+    let cited: Vec<(String, Vec<(u64, u64)>)> = cited
         .into_iter()
-        .filter(|p| p.len() >= 3)
+        .filter_map(|(rel, spans)| verified_spans(root, &rel, &spans, &mut fixes).map(|spans| (rel, spans)))
         .collect();
-    if !unique.is_empty() {
+    if !cited.is_empty() {
         parts.push(String::new());
-        let kw: Vec<&str> = unique.iter().map(|s| s.as_str()).collect();
-        parts.push(format!("grep keywords: {}", kw.join(", ")));
+        parts.push("Sources:".into());
+        let n = cited.len();
+        for (i, (rel, spans)) in cited.into_iter().enumerate() {
+            let ranges: Vec<String> = spans.iter().map(|(s, e)| format!("L{}-{}", s, e)).collect();
+            let tag = lang::provenance(root, &rel, &attrs);
+            let ranges = if ranges.is_empty() { String::new() } else { format!(" ({})", ranges.join(", ")) };
+            parts.push(format!("  [{}/{}] {}{}{}", i + 1, n, params.display_path(&rel), ranges, provenance_suffix(tag)));
+            session.discovered.remember(root, &root.join(&rel));
+            found.push(FoundFile::new(params, &rel, spans, rg_patterns, rg_matches));
+        }
+    }
+    push_fixes(&mut parts, params, &fixes);
+    result_footer(&mut parts, params, session, rg_patterns, stats, "");
+    let answer = (!text.is_empty()).then_some(text);
+    SearchOutput { text: apply_result_budget(parts.join("\n"), params), files: found, partial: false, answer, transcript: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout_fallback_keeps_only_files_inside_the_project() {
+        let base = std::env::temp_dir().join(format!("windsurf-mcp-fallback-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let root = base.join("project");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(base.join("outside")).unwrap();
+        std::fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(base.join("outside/secret.txt"), "s\n").unwrap();
+        std::os::unix::fs::symlink(base.join("outside"), root.join("out")).unwrap();
+
+        let outside = base.join("outside/secret.txt").to_string_lossy().to_string();
+        let requested: Vec<String> = vec![
+            "/codebase/src/a.rs".into(),
+            outside,
+            "/etc/passwd".into(),
+            "../outside/secret.txt".into(),
+            "out/secret.txt".into(),
+            "src/missing.rs".into(),
+        ];
+        let requested: Vec<&String> = requested.iter().collect();
+        let mut fixes = Vec::new();
+        assert_eq!(verified_files(&root, &requested, &mut fixes), ["src/a.rs"]);
+        assert_eq!(fixes.len(), 5);
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn file_list_map_omits_denied_paths() {
+        let root = std::env::temp_dir().join(format!("windsurf-mcp-filemap-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join(deny_paths::TEST_DENIED)).unwrap();
+        std::fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(root.join(deny_paths::TEST_DENIED).join("key.pem"), "k\n").unwrap();
+        std::fs::write(root.join("src").join(deny_paths::TEST_DENIED), "k\n").unwrap();
+
+        let map = generate_file_list_map(&root.to_string_lossy(), 3, false, &[]);
+        assert!(map.contains("/codebase/src/a.rs"), "{}", map);
+        assert!(!map.contains(deny_paths::TEST_DENIED), "{}", map);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn max_turns_is_clamped_to_the_advertised_range() {
+        let config = config::Config::default();
+        let dir = std::env::temp_dir().to_string_lossy().to_string();
+        for (given, expected) in [(0, 1), (3, 3), (u64::MAX, 5)] {
+            let args = serde_json::json!({ "query": "q", "max_turns": given });
+            assert_eq!(search_params(&args, &config, &dir).await.unwrap().max_turns, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn multi_repo_search_stops_resolving_repos_once_cancelled() {
+        let mut config = config::Config::default();
+        let set = [("a", "/nonexistent/a"), ("b", "https://example.invalid/b.git")];
+        config.repo_sets.insert("s".into(), set.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect());
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let out = multi_repo_search(&json!({ "query": "q", "parallel": 1 }), &config, &reqwest::Client::new(), "", "", &session::Session::new("test"), &cancel)
+            .await
+            .unwrap();
+        assert!(out.contains("[a] Error: cancelled"), "{}", out);
+        assert!(out.contains("[b] Error: cancelled"), "{}", out);
+    }
+
+    #[tokio::test]
+    async fn lsp_frames_round_trip_multibyte_bodies() {
+        let payload = r#"{"jsonrpc":"2.0","method":"搜索","params":{"q":"查找函数 🦀🚀"}}"#;
+        let mut wire = Vec::new();
+        write_message(&mut wire, TransportMode::Lsp, payload).await.unwrap();
+        assert!(wire.starts_with(format!("Content-Length: {}\r\n", payload.len()).as_bytes()));
+        write_message(&mut wire, TransportMode::Lsp, "{}").await.unwrap();
+
+        let mut reader = BufReader::new(wire.as_slice());
+        let mut mode = None;
+        assert_eq!(read_message(&mut reader, &mut mode, 1024).await.unwrap().as_deref(), Some(payload));
+        assert_eq!(mode, Some(TransportMode::Lsp));
+        assert_eq!(read_message(&mut reader, &mut mode, 1024).await.unwrap().as_deref(), Some("{}"));
+        assert_eq!(read_message(&mut reader, &mut mode, 1024).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn oversize_lsp_body_is_skipped_and_the_next_message_parses() {
+        let big = "x".repeat(64);
+        let wire = format!("Content-Length: {}\r\n\r\n{}Content-Length: 2\r\n\r\n{{}}", big.len(), big);
+        let mut reader = BufReader::new(wire.as_bytes());
+        let err = read_lsp_message(&mut reader, None, 16).await.unwrap_err();
+        assert!(err.to_string().contains("exceeds max_message_bytes"), "{}", err);
+        assert_eq!(read_lsp_message(&mut reader, None, 16).await.unwrap().as_deref(), Some("{}"));
+    }
+
+    #[tokio::test]
+    async fn lsp_message_without_content_length_is_rejected() {
+        let mut reader = BufReader::new(&b"Content-Type: application/json\r\n\r\n{}"[..]);
+        let err = read_lsp_message(&mut reader, None, 1024).await.unwrap_err();
+        assert_eq!(err.to_string(), "Missing Content-Length");
+    }
+
+    #[tokio::test]
+    async fn overlong_lsp_header_line_is_ignored() {
+        let wire = format!("X-Junk: {}\r\nContent-Length: 2\r\n\r\n{{}}", "y".repeat(MAX_HEADER_LINE as usize * 4));
+        let mut reader = BufReader::new(wire.as_bytes());
+        assert_eq!(read_lsp_message(&mut reader, None, 1024).await.unwrap().as_deref(), Some("{}"));
     }
-    parts.push(String::new());
-    parts.push(format!("[config] tree_depth={}, max_turns={}", tree_depth, max_turns));
-    parts.join("\n")
 }
@@ -0,0 +1,56 @@
+//! MCP 协议版本协商
+//!
+//! initialize 时客户端请求的版本受支持则原样返回，否则返回最新支持的版本。
+//! 旧版本不认识的字段（工具 annotations、title、outputSchema、structuredContent）按协商结果去掉。
+
+use serde_json::Value;
+
+/// 支持的版本，新的在前；日期格式可直接按字符串比较
+pub const SUPPORTED: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+/// 未完成 initialize 时按最早的版本处理
+pub const DEFAULT: &str = "2024-11-05";
+
+/// 协商版本
+pub fn negotiate(requested: Option<&str>) -> &'static str {
+    match requested.and_then(|r| SUPPORTED.iter().find(|v| **v == r)) {
+        Some(v) => v,
+        None => {
+            if let Some(r) = requested {
+                log_info!("client requested unsupported protocol version {}, offering {}", r, SUPPORTED[0]);
+            }
+            SUPPORTED[0]
+        }
+    }
+}
+
+/// 工具 annotations（2025-03-26 起）
+pub fn tool_annotations(version: &str) -> bool {
+    version >= "2025-03-26"
+}
+
+/// 工具 title、outputSchema 与结果中的 structuredContent（2025-06-18 起）
+pub fn structured_content(version: &str) -> bool {
+    version >= "2025-06-18"
+}
+
+/// 去掉该版本不支持的工具定义字段
+pub fn adapt_tools(tools: &mut [Value], version: &str) {
+    for tool in tools.iter_mut().filter_map(|t| t.as_object_mut()) {
+        if !tool_annotations(version) {
+            tool.remove("annotations");
+        }
+        if !structured_content(version) {
+            tool.remove("title");
+            tool.remove("outputSchema");
+        }
+    }
+}
+
+/// 去掉该版本不支持的工具结果字段
+pub fn adapt_result(response: &mut Value, version: &str) {
+    if !structured_content(version) {
+        if let Some(result) = response.get_mut("result").and_then(|r| r.as_object_mut()) {
+            result.remove("structuredContent");
+        }
+    }
+}
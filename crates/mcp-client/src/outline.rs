@@ -0,0 +1,194 @@
+//! 代码结构解析 (tree-sitter)
+//!
+//! 定位包含某一行的完整函数/类/impl 块、提取符号，支持 Rust、Python、Go、JS/TS。
+
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser, Point};
+
+/// 语义块（行号 1-indexed，闭区间）
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub kind: String,
+    pub name: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+fn language_for(path: &Path) -> Option<Language> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    let lang = match ext.as_str() {
+        "rs" => tree_sitter_rust::LANGUAGE.into(),
+        "py" | "pyi" => tree_sitter_python::LANGUAGE.into(),
+        "go" => tree_sitter_go::LANGUAGE.into(),
+        "js" | "mjs" | "cjs" | "jsx" => tree_sitter_javascript::LANGUAGE.into(),
+        "ts" | "mts" | "cts" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+        "tsx" => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        _ => return None,
+    };
+    Some(lang)
+}
+
+/// 是否支持解析该文件
+pub fn is_supported(path: &Path) -> bool {
+    language_for(path).is_some()
+}
+
+/// 视为"完整语义块"的节点类型
+fn is_block_kind(kind: &str) -> bool {
+    matches!(
+        kind,
+        // Rust
+        "function_item" | "impl_item" | "struct_item" | "enum_item" | "trait_item"
+        | "mod_item" | "union_item" | "macro_definition"
+        // Python
+        | "function_definition" | "class_definition" | "decorated_definition"
+        // Go
+        | "function_declaration" | "method_declaration" | "type_declaration"
+        // JS/TS
+        | "class_declaration" | "abstract_class_declaration" | "method_definition"
+        | "generator_function_declaration" | "interface_declaration"
+        | "type_alias_declaration" | "enum_declaration"
+    )
+}
+
+fn block_name(node: Node, source: &[u8]) -> Option<String> {
+    let named = node.child_by_field_name("name")
+        .or_else(|| node.child_by_field_name("type"))
+        .or_else(|| node.child_by_field_name("definition").and_then(|d| d.child_by_field_name("name")))
+        .or_else(|| {
+            // Go type_declaration → type_spec.name
+            let mut cursor = node.walk();
+            let spec = node.named_children(&mut cursor).find(|c| c.kind() == "type_spec");
+            spec.and_then(|s| s.child_by_field_name("name"))
+        })?;
+    named.utf8_text(source).ok().map(|s| s.to_string())
+}
+
+fn to_block(node: Node, source: &[u8]) -> Block {
+    Block {
+        kind: node.kind().to_string(),
+        name: block_name(node, source),
+        start_line: node.start_position().row + 1,
+        end_line: node.end_position().row + 1,
+    }
+}
+
+fn parse(path: &Path, source: &str) -> Option<tree_sitter::Tree> {
+    let lang = language_for(path)?;
+    let mut parser = Parser::new();
+    parser.set_language(&lang).ok()?;
+    parser.parse(source, None)
+}
+
+/// 查找包含第 `line` 行（1-indexed）的最内层语义块
+pub fn enclosing_block(path: &Path, source: &str, line: usize) -> Option<Block> {
+    let tree = parse(path, source)?;
+    let row = line.checked_sub(1)?;
+    let text = source.lines().nth(row)?;
+    let col = text.len() - text.trim_start().len();
+    let point = Point::new(row, col);
+
+    let mut node = tree.root_node().descendant_for_point_range(point, point)?;
+    loop {
+        if is_block_kind(node.kind()) {
+            // Python 装饰器：包含装饰器在内的整个定义
+            if let Some(parent) = node.parent() {
+                if parent.kind() == "decorated_definition" {
+                    return Some(to_block(parent, source.as_bytes()));
+                }
+            }
+            return Some(to_block(node, source.as_bytes()));
+        }
+        node = node.parent()?;
+    }
+}
+
+fn is_import_kind(kind: &str) -> bool {
+    matches!(
+        kind,
+        // Rust
+        "use_declaration" | "extern_crate_declaration"
+        // Python / JS/TS
+        | "import_statement" | "import_from_statement" | "future_import_statement"
+        // Go
+        | "import_declaration"
+    )
+}
+
+fn collect_imports(node: Node, source: &[u8], out: &mut Vec<(usize, String)>) {
+    if is_import_kind(node.kind()) {
+        if let Ok(text) = node.utf8_text(source) {
+            let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            out.push((node.start_position().row + 1, flat));
+        }
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_imports(child, source, out);
+    }
+}
+
+/// 提取 import/use 语句（行号 1-indexed），不支持的语言返回 None
+pub fn imports(path: &Path, source: &str) -> Option<Vec<(usize, String)>> {
+    let tree = parse(path, source)?;
+    let mut out = Vec::new();
+    collect_imports(tree.root_node(), source.as_bytes(), &mut out);
+    Some(out)
+}
+
+fn collect_symbols(node: Node, source: &[u8], out: &mut Vec<Block>) {
+    // decorated_definition 只是包装，符号取内部定义
+    if is_block_kind(node.kind()) && node.kind() != "decorated_definition" {
+        let mut block = to_block(node, source);
+        // 范围包含装饰器
+        if let Some(parent) = node.parent().filter(|p| p.kind() == "decorated_definition") {
+            block.start_line = parent.start_position().row + 1;
+        }
+        if block.name.is_some() {
+            out.push(block);
+        }
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_symbols(child, source, out);
+    }
+}
+
+/// 提取文件内所有具名语义块，不支持的语言返回 None
+pub fn symbols(path: &Path, source: &str) -> Option<Vec<Block>> {
+    let tree = parse(path, source)?;
+    let mut out = Vec::new();
+    collect_symbols(tree.root_node(), source.as_bytes(), &mut out);
+    Some(out)
+}
+
+/// 单文件大小上限，超过的文件（通常是生成代码）不解析
+const SYMBOL_MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// 在目录下查找名称包含 `query`（不区分大小写）的符号，最多 `max` 个
+pub fn workspace_symbols(root: &Path, query: &str, max: usize) -> Vec<(std::path::PathBuf, Block)> {
+    let needle = query.to_lowercase();
+    find_symbols(root, max, |name| name.to_lowercase().contains(&needle))
+}
+
+/// 遍历目录，收集名称满足 `matches` 的符号，最多 `max` 个
+pub fn find_symbols(root: &Path, max: usize, matches: impl Fn(&str) -> bool) -> Vec<(std::path::PathBuf, Block)> {
+    let mut out = Vec::new();
+    crate::executor::walk_files(root, |path, meta| {
+        if !is_supported(path) || meta.len() > SYMBOL_MAX_FILE_BYTES {
+            return true;
+        }
+        let Ok(source) = std::fs::read_to_string(path) else { return true };
+        for block in symbols(path, &source).unwrap_or_default() {
+            if block.name.as_deref().is_some_and(&matches) {
+                out.push((path.to_path_buf(), block));
+                if out.len() >= max {
+                    return false;
+                }
+            }
+        }
+        true
+    });
+    out
+}
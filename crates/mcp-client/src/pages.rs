@@ -0,0 +1,90 @@
+//! 大结果分页
+//!
+//! 结果超过 `result_page_bytes`（默认 128 KiB）时只返回第一页，并附带续页 token；
+//! 其余页保存在会话内存中，由 fetch_result_page 工具按页取回。每个会话只保留最近 MAX_STORED 个结果。
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+pub const DEFAULT_PAGE_BYTES: usize = 128 * 1024;
+/// 过小的分页只会制造大量往返
+pub const MIN_PAGE_BYTES: usize = 4096;
+const MAX_STORED: usize = 16;
+
+/// (token, 第 2 页起的各页)
+#[derive(Default)]
+pub struct Store(Mutex<VecDeque<(String, Vec<String>)>>);
+
+/// 按行切分，每页不超过 `max` 字节；超长单行在字符边界处截断
+fn split(text: &str, max: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut cur = String::new();
+    for line in text.split_inclusive('\n') {
+        let mut rest = line;
+        while !rest.is_empty() {
+            if cur.len() + rest.len() <= max {
+                cur.push_str(rest);
+                break;
+            }
+            if !cur.is_empty() {
+                pages.push(std::mem::take(&mut cur));
+                continue;
+            }
+            let mut cut = max;
+            while !rest.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            pages.push(rest[..cut].to_string());
+            rest = &rest[cut..];
+        }
+    }
+    if !cur.is_empty() {
+        pages.push(cur);
+    }
+    pages
+}
+
+fn hint(token: &str, next: usize, total: usize) -> String {
+    format!(
+        "[page {}/{}] Result continues: call fetch_result_page with token=\"{}\" and page={}",
+        next - 1,
+        total,
+        token,
+        next
+    )
+}
+
+impl Store {
+    /// 结果超过 `max` 时保存剩余页，返回 (第一页, 续页提示)
+    pub fn paginate(&self, text: String, max: usize) -> (String, Option<String>) {
+        if text.len() <= max {
+            return (text, None);
+        }
+        let mut pages = split(&text, max);
+        let total = pages.len();
+        let first = pages.remove(0);
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let mut store = self.0.lock().unwrap();
+        if store.len() >= MAX_STORED {
+            store.pop_front();
+        }
+        store.push_back((token.clone(), pages));
+        (first, Some(hint(&token, 2, total)))
+    }
+
+    /// 取第 `page` 页（1-based，第 1 页已随工具结果返回），返回 (页内容, 页码/续页提示)
+    pub fn fetch(&self, token: &str, page: usize) -> Result<(String, String), String> {
+        let store = self.0.lock().unwrap();
+        let (_, rest) = store
+            .iter()
+            .find(|(t, _)| t == token)
+            .ok_or_else(|| format!("unknown or expired result token: {}", token))?;
+        let total = rest.len() + 1;
+        if page < 2 || page > total {
+            return Err(format!("page {} out of range (2-{})", page, total));
+        }
+        let text = rest[page - 2].clone();
+        let footer = if page < total { hint(token, page + 1, total) } else { format!("[page {}/{}]", page, total) };
+        Ok((text, footer))
+    }
+}
@@ -0,0 +1,214 @@
+//! 真实路径 → /codebase 虚拟路径
+//!
+//! 命令输出中的项目根目录（及其别名）替换为 /codebase。按路径匹配而不是简单的字符串替换：根目录须作为完整的
+//! 路径组件出现（/repo 不会替换 /repo2 的前缀）。Windows 路径（盘符、UNC、`\\?\` 长路径）不区分大小写，
+//! `\` 与 `/` 视为相同，替换后路径剩余部分的 `\` 改为 `/`。
+
+use std::path::{Path, PathBuf};
+
+/// Windows 不允许出现在文件名中的字符，以及通常跟在路径后面的标点：路径到此结束（空白另行判断）
+const PATH_END: &[char] = &['<', '>', ':', '"', '|', '?', '*', ',', ')'];
+
+#[derive(Debug, Clone)]
+pub struct PathRemapper {
+    /// 根目录的各种写法，最长优先
+    roots: Vec<Root>,
+}
+
+#[derive(Debug, Clone)]
+struct Root {
+    chars: Vec<char>,
+    /// Windows 路径：不区分大小写，两种分隔符等价
+    windows: bool,
+}
+
+impl Root {
+    fn new(path: &str) -> Self {
+        Self { chars: path.chars().collect(), windows: is_windows_path(path) }
+    }
+
+    /// text 以该根目录开头时返回匹配的字符数
+    fn match_at(&self, text: &[char]) -> Option<usize> {
+        if text.len() < self.chars.len() {
+            return None;
+        }
+        let same = |a: char, b: char| {
+            a == b || (self.windows && ((is_sep(a) && is_sep(b)) || a.to_lowercase().eq(b.to_lowercase())))
+        };
+        self.chars.iter().zip(text).all(|(a, b)| same(*a, *b)).then_some(self.chars.len())
+    }
+}
+
+impl PathRemapper {
+    /// 根目录的所有绝对路径写法：规范化路径、调用方传入的路径、指向同一目录的 $PWD / 当前目录（符号链接别名），
+    /// 以及 `\\?\` 长路径对应的普通写法
+    pub fn new(project_root: &str, canonical: &Path) -> Self {
+        let mut candidates = Vec::new();
+        let given = PathBuf::from(project_root);
+        if given.is_absolute() {
+            candidates.push(given);
+        }
+        if let Ok(pwd) = std::env::var("PWD") {
+            candidates.push(PathBuf::from(pwd));
+        }
+        if let Ok(cwd) = std::env::current_dir() {
+            candidates.push(cwd);
+        }
+
+        let mut aliases: Vec<String> = vec![canonical.to_string_lossy().to_string()];
+        for c in candidates {
+            if !c.is_absolute() { continue; }
+            // 候选路径解析后须为根目录或其祖先，别名 = 候选路径 + 剩余相对部分
+            let Ok(resolved) = c.canonicalize() else { continue };
+            let Ok(rel) = canonical.strip_prefix(&resolved) else { continue };
+            let alias = if rel.as_os_str().is_empty() { c } else { c.join(rel) };
+            aliases.push(alias.to_string_lossy().to_string());
+        }
+        Self::from_aliases(aliases)
+    }
+
+    fn from_aliases(aliases: Vec<String>) -> Self {
+        let mut all: Vec<String> = Vec::new();
+        for a in aliases {
+            let a = a.trim_end_matches(['/', '\\']).to_string();
+            let plain = strip_verbatim(&a);
+            for s in [Some(a), plain] {
+                if let Some(s) = s.filter(|s| !s.is_empty() && !all.contains(s)) {
+                    all.push(s);
+                }
+            }
+        }
+        // 最长优先，避免较短别名先匹配较长别名的前缀
+        all.sort_by_key(|a| std::cmp::Reverse(a.chars().count()));
+        Self { roots: all.iter().map(|a| Root::new(a)).collect() }
+    }
+
+    /// 替换 text 中所有位于根目录下的路径
+    pub fn remap(&self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let at_start = i == 0 || !is_name_char(chars[i - 1]);
+            let hit = at_start
+                .then(|| {
+                    self.roots.iter().find_map(|r| {
+                        let len = r.match_at(&chars[i..])?;
+                        let next = chars.get(i + len);
+                        next.is_none_or(|c| is_sep(*c) || !is_name_char(*c)).then_some((r, len))
+                    })
+                })
+                .flatten();
+            let Some((root, len)) = hit else {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            };
+            out.push_str("/codebase");
+            i += len;
+            if root.windows {
+                while let Some(c) = chars.get(i).filter(|c| !c.is_whitespace() && !PATH_END.contains(c)) {
+                    out.push(if *c == '\\' { '/' } else { *c });
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+}
+
+fn is_sep(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// 可以出现在文件名中间的字符：前后紧邻这些字符时不是完整的路径组件
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// 盘符路径（C:\、C:/）或 UNC / 长路径（\\server、\\?\）
+fn is_windows_path(path: &str) -> bool {
+    let b = path.as_bytes();
+    path.starts_with("\\\\") || (b.len() >= 2 && b[0].is_ascii_alphabetic() && b[1] == b':')
+}
+
+/// `\\?\C:\x` → `C:\x`，`\\?\UNC\server\share` → `\\server\share`
+fn strip_verbatim(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\?\")?;
+    match rest.get(..4) {
+        Some(unc) if unc.eq_ignore_ascii_case(r"UNC\") => Some(format!(r"\\{}", &rest[4..])),
+        _ => Some(rest.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remapper(roots: &[&str]) -> PathRemapper {
+        PathRemapper::from_aliases(roots.iter().map(|r| r.to_string()).collect())
+    }
+
+    #[test]
+    fn unix_root_is_a_whole_component() {
+        let r = remapper(&["/home/u/repo"]);
+        assert_eq!(r.remap("/home/u/repo/src/a.rs:3: x"), "/codebase/src/a.rs:3: x");
+        assert_eq!(r.remap("cd /home/u/repo"), "cd /codebase");
+        assert_eq!(r.remap("/home/u/repo2/a.rs"), "/home/u/repo2/a.rs");
+        assert_eq!(r.remap("/x/home/u/repo/a.rs"), "/x/home/u/repo/a.rs");
+    }
+
+    #[test]
+    fn drive_letter_is_case_insensitive() {
+        let r = remapper(&[r"c:\Repo"]);
+        assert_eq!(r.remap(r"C:\repo\src\main.rs"), "/codebase/src/main.rs");
+        assert_eq!(r.remap(r"c:\REPO"), "/codebase");
+    }
+
+    #[test]
+    fn mixed_separators() {
+        let r = remapper(&[r"C:\work\repo"]);
+        assert_eq!(r.remap("C:/work/repo/src\\lib.rs"), "/codebase/src/lib.rs");
+        assert_eq!(r.remap(r"C:\work/repo\a/b.rs"), "/codebase/a/b.rs");
+    }
+
+    #[test]
+    fn sibling_prefix_does_not_map() {
+        let r = remapper(&[r"C:\repo"]);
+        assert_eq!(r.remap(r"C:\repo2\a.rs"), r"C:\repo2\a.rs");
+        assert_eq!(r.remap(r"C:\repo.bak\a.rs"), r"C:\repo.bak\a.rs");
+        assert_eq!(r.remap(r"C:\repo\a.rs"), "/codebase/a.rs");
+    }
+
+    #[test]
+    fn unc_root() {
+        let r = remapper(&[r"\\server\share\repo"]);
+        assert_eq!(r.remap(r"\\SERVER\share\repo\x\y.rs"), "/codebase/x/y.rs");
+        assert_eq!(r.remap(r"\\server\share\repo2\y.rs"), r"\\server\share\repo2\y.rs");
+    }
+
+    #[test]
+    fn verbatim_roots_also_match_plain_form() {
+        let r = remapper(&[r"\\?\C:\repo"]);
+        assert_eq!(r.remap(r"\\?\C:\repo\a.rs"), "/codebase/a.rs");
+        assert_eq!(r.remap(r"C:\repo\a.rs"), "/codebase/a.rs");
+
+        let r = remapper(&[r"\\?\UNC\server\share"]);
+        assert_eq!(r.remap(r"\\server\share\a.rs"), "/codebase/a.rs");
+    }
+
+    #[test]
+    fn windows_path_stops_at_whitespace_and_punctuation() {
+        let r = remapper(&[r"C:\repo"]);
+        assert_eq!(r.remap(r"C:\repo\a.rs escapes \n as \\n"), r"/codebase/a.rs escapes \n as \\n");
+        assert_eq!(r.remap(r"C:\repo\a.rs is like D:\other\b.rs"), r"/codebase/a.rs is like D:\other\b.rs");
+        assert_eq!(r.remap(r"(C:\repo\a.rs), then \n"), r"(/codebase/a.rs), then \n");
+        assert_eq!(r.remap("C:\\repo\\a.rs\tx\\y"), "/codebase/a.rs\tx\\y");
+    }
+
+    #[test]
+    fn longest_alias_wins() {
+        let r = remapper(&["/repo", "/repo/sub"]);
+        assert_eq!(r.remap("/repo/sub/a.rs"), "/codebase/a.rs");
+    }
+}
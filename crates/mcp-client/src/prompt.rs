@@ -4,6 +4,40 @@
 
 use serde_json::json;
 
+use crate::archive;
+
+/// 测试文件相关的附加提示：排除测试，或查询针对测试时优先测试目录
+pub fn test_guidance(include_tests: bool, query_about_tests: bool) -> Option<String> {
+    if !include_tests {
+        return Some(format!(
+            "\n\n# TEST FILES\nThe engineer does NOT want test files. Exclude test directories and \
+test files from every search (for rg, add these to exclude: {}) and never return them in \
+your answer; focus on implementation code.",
+            crate::lang::TEST_GLOBS.join(", ")
+        ));
+    }
+    if query_about_tests {
+        return Some(
+            "\n\n# TEST FILES\nThe query is about tests. Start by searching test directories \
+(tests/, test/, __tests__/, spec/) and test files (*_test.*, test_*.py, *.test.*, *.spec.*), \
+then include the implementation code they exercise."
+                .into(),
+        );
+    }
+    None
+}
+
+/// diff_context_search 的附加提示：搜索范围是改动文件及其直接依赖
+pub fn diff_guidance(label: &str) -> String {
+    format!(
+        "\n\n# CHANGED FILES\nThe engineer is reviewing a change (git diff --name-only {}). The repo map \
+lists only the changed files and the files that directly import them, and rg/files only search \
+those files. Return the parts of them needed to review the change: the modified code and the \
+definitions and callers it interacts with.",
+        label
+    )
+}
+
 /// 完整系统提示模板
 pub fn build_system_prompt(max_turns: u32, max_commands: u32, max_results: u32) -> String {
     format!(r#"You are an expert software engineer, responsible for providing context \
@@ -41,14 +75,61 @@ directory, not `.
 - Allowed sub-commands (schema-enforced):
   - rg: Search for patterns in files using ripgrep
     - Required: pattern (string), path (string)
-    - Optional: include (array of globs), exclude (array of globs)
-  - readfile: Read contents of a file with optional line range
+    - Optional: include (array of globs), exclude (array of globs), \
+context / before / after (int, lines around each match, max 10), \
+case_insensitive, fixed_strings, word_boundary, multiline (bool)
+  - rg_files: Search only the listed files (e.g. the output of a previous \
+glob / files / rg); use it instead of re-scanning a directory in later turns
+    - Required: pattern (string), files (array of paths, up to 500)
+    - Optional: include, exclude, context / before / after, case_insensitive, \
+fixed_strings, word_boundary, multiline (same as rg)
+  - count: Count matching lines per file without showing them; use it to \
+gauge how widespread a pattern is before running rg
+    - Required: pattern (string), path (string)
+    - Optional: include, exclude, case_insensitive, fixed_strings, \
+word_boundary, multiline (same as rg)
+  - readfile: Read contents of a file with optional line range(s); read \
+several ranges or a whole function in one command instead of spending turns
+    - Required: file (string)
+    - Optional: start_line (int), end_line (int) — 1-indexed, inclusive; \
+ranges (array of objects with start_line and end_line, up to 20); around_symbol \
+(string) — also read the complete definition of this function/class/type \
+(Rust, Python, Go, JS/TS)
+  - head / tail: Read the first / last lines of a file (with its total \
+line count); cheaper than readfile for peeking at big files
+    - Required: file (string)
+    - Optional: lines (int, default 20)
+  - readmatch: Read only the lines of a file matching a pattern, with \
+surrounding context; ideal right after an rg hit
+    - Required: file (string), pattern (string)
+    - Optional: context (int, default 3)
+  - enclosing: Read the complete function/class/impl block containing a \
+line, with its exact range (Rust, Python, Go, JS/TS)
+    - Required: file (string), line (int)
+  - imports: List the import/include/use statements of a file, to trace \
+dependencies without reading the file header
     - Required: file (string)
-    - Optional: start_line (int), end_line (int) — 1-indexed, inclusive
   - tree: Display directory structure as a tree
     - Required: path (string)
-    - Optional: levels (int)
-
+    - Optional: levels (int), sort ("name" | "mtime" | "size"), \
+max_entries (int, per directory)
+  - files: List files under a directory, honoring .gitignore
+    - Required: path (string)
+    - Optional: glob (string), max_count (int)
+  - stat: Show size, mtime, line count, language, binary and \
+generated/minified flags of a file; use it to decide whether a file is worth reading
+    - Required: file (string)
+  - stats: Summarize languages, file counts and line counts under a \
+directory; use it to characterize unfamiliar directories quickly
+    - Required: path (string)
+  - git: Read-only version-control history, to find where and why code \
+changed
+    - Required: subcommand ("log" = log --oneline, "diff" = diff --stat, \
+"show", "blame" = blame -L)
+    - Optional: ref (string, e.g. "HEAD~5", "main..HEAD"), file (string), \
+max_count (int, log only, default 20), start_line / end_line (int, \
+required for blame)
+{archive_doc}
 # THINKING RULES
 - Think step-by-step. Plan, reason, and reflect before each tool call.
 - Use tool calls liberally and purposefully to ground every conclusion \
@@ -85,7 +166,7 @@ must change.
 # TOOL USE GUIDELINES
 - You must use a SINGLE restricted_exec call in your answer, that lets \
 you execute at most {max_commands} commands in a single turn. Each command must be \
-an object with a `type` field of `rg`, `readfile`, or `tree` and the appropriate fields for that type.
+an object with a `type` field of `rg`, `rg_files`, `count`, `readfile`, `head`, `tail`, `readmatch`, `enclosing`, `imports`, `tree`, `files`, `stat`, `stats`, or `git` and the appropriate fields for that type.
 - Example restricted_exec usage:
 [TOOL_CALLS]restricted_exec[ARGS]{{{{
   "command1": {{{{
@@ -112,7 +193,9 @@ an object with a `type` field of `rg`, `readfile`, or `tree` and the appropriate
 tools, so issuing multiple commands at once is necessary and encouraged \
 to speed up your research.
 - Each command result may be truncated to 50 lines; prefer multiple \
-targeted reads/searches to build complete context.
+targeted reads/searches to build complete context. Truncated rg results \
+list the remaining files with their match counts ("path (N matches)", \
+"(+N more matches in M more files)"); narrow the search to those files.
 - DO NOT EVER USE MORE THAN {max_commands} commands in a single turn, or you will \
 be penalized.
 
@@ -154,14 +237,125 @@ relevant files first. If fewer files are relevant, return fewer."#,
         max_commands = max_commands,
         max_turns = max_turns,
         max_results = max_results,
+        archive_doc = if archive::enabled() { ARCHIVE_DOC } else { "" },
     )
 }
 
+/// 外部模板（配置 prompt_template / 环境变量 PROMPT_TEMPLATE_PATH）替换内置的系统提示。
+/// 只替换 {max_turns}、{max_commands}、{max_results}，模板中其余的花括号原样保留，不需要转义
+pub fn render_template(template: &str, max_turns: u32, max_commands: u32, max_results: u32) -> String {
+    template
+        .replace("{max_turns}", &max_turns.to_string())
+        .replace("{max_commands}", &max_commands.to_string())
+        .replace("{max_results}", &max_results.to_string())
+}
+
+const ARCHIVE_DOC: &str = "  - archive: List entries of a zip/jar/wheel archive, or grep its text \
+entries when a pattern is given
+    - Required: file (string)
+    - Optional: pattern (string)
+";
+
+/// codebase_qa 的系统提示：沿用检索环境与规则，任务说明和答案格式换成带引用的问答
+pub fn build_qa_system_prompt(max_turns: u32, max_commands: u32) -> String {
+    let base = build_system_prompt(max_turns, max_commands, 0);
+    let start = base.find("# ENVIRONMENT").unwrap_or(0);
+    let end = base.find("# ANSWER FORMAT").unwrap_or(base.len());
+    format!("{}{}{}", QA_INTRO, &base[start..end], QA_ANSWER_FORMAT)
+}
+
+const QA_INTRO: &str = "You are an expert software engineer answering another engineer's \
+question about the current codebase. Investigate the code with the tools below until you \
+can answer from what the code actually does, then give a concise, accurate answer that \
+cites the files and line ranges supporting each claim.
+
+";
+
+const QA_ANSWER_FORMAT: &str = "# ANSWER FORMAT
+- Call the answer tool with your answer written in Markdown, wrapped in <ANSWER></ANSWER>.
+- Ground every claim in code you have read. Cite the supporting code inline as \
+/codebase/<path>:<start>-<end> (1-indexed, inclusive), preferring whole functions over \
+single lines.
+- Answer the question directly first, then explain the flow step by step if needed. Do not \
+paste large code blocks; the citations let the engineer open the code.
+
+Output example inside the \"answer\" tool argument:
+<ANSWER>
+Access tokens are refreshed lazily: `Client::send` checks the expiry before every request \
+(/codebase/src/client.rs:88-120) and calls `refresh_token` \
+(/codebase/src/auth/refresh.rs:12-47), which exchanges the refresh token and stores the new pair.
+</ANSWER>
+
+# NO ANSWER POLICY
+If after thorough searching the codebase does not contain what the question asks about, say \
+so plainly in the answer and cite what you checked. Never guess or describe code you have not read.";
+
 pub const FINAL_FORCE_ANSWER: &str =
     "You have no turns left. Now you MUST provide your final ANSWER, even if it's not complete.";
 
+/// 保存对话时 answer 调用的工具结果
+pub const ANSWER_RECEIVED: &str = "Answer received.";
+
+/// fast_context_refine 追加到原对话的用户消息
+pub fn refine_request(request: &str) -> String {
+    format!(
+        "Follow-up request: {}\n\nRefine your previous answer for this request. Reuse what you have already \
+read and run more commands only where needed, then call answer again with the complete updated answer \
+in the same format.",
+        request
+    )
+}
+
+/// explain_symbol 的系统提示：只根据给出的定义作答，一轮完成
+pub const EXPLAIN_SYSTEM_PROMPT: &str = "You are an expert software engineer explaining a piece of code \
+to another engineer who is about to work with it. You are given one definition (function, type, class...) \
+from their codebase. Explain in a short paragraph what it does and why it exists, then list its inputs and \
+outputs, side effects, and any notable edge cases or error handling. Base every statement on the code shown; \
+when behaviour depends on code that is not shown, say so instead of guessing. Keep it under 200 words, use \
+Markdown, do not repeat the code. Call the answer tool with the explanation.";
+
+/// explain_symbol 的用户消息
+pub fn explain_request(name: &str, location: &str, definition: &str, focus: Option<&str>) -> String {
+    let focus = focus.map(|f| format!("\nFocus on: {}\n", f)).unwrap_or_default();
+    format!("Explain `{}`, defined at {}:{}\n```\n{}\n```", name, location, focus, definition)
+}
+
+/// explain_symbol 的工具定义：只有 answer
+pub fn get_explain_tool_definitions() -> String {
+    json!([
+        {
+            "type": "function",
+            "function": {
+                "name": "answer",
+                "description": "The explanation of the symbol.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "answer": { "type": "string", "description": "The explanation in Markdown." }
+                    },
+                    "required": ["answer"]
+                }
+            }
+        }
+    ])
+    .to_string()
+}
+
 /// 完整工具定义 JSON
 pub fn get_tool_definitions(max_commands: u32) -> String {
+    tool_definitions(max_commands, "Final answer with relevant files and line ranges.", "The final answer in XML format.")
+}
+
+/// codebase_qa 的工具定义：answer 参数为带引用的文字回答
+pub fn get_qa_tool_definitions(max_commands: u32) -> String {
+    tool_definitions(
+        max_commands,
+        "Final answer to the question, citing files and line ranges.",
+        "The answer in Markdown wrapped in <ANSWER></ANSWER>, citing code as /codebase/<path>:<start>-<end>.",
+    )
+}
+
+fn tool_definitions(max_commands: u32, answer_description: &str, answer_argument: &str) -> String {
     let mut props = serde_json::Map::new();
     for i in 1..=max_commands {
         props.insert(format!("command{}", i), build_command_schema(i));
@@ -172,7 +366,7 @@ pub fn get_tool_definitions(max_commands: u32) -> String {
             "type": "function",
             "function": {
                 "name": "restricted_exec",
-                "description": "Execute restricted commands (rg, readfile, tree, ls, glob) in parallel.",
+                "description": "Execute restricted commands (rg, rg_files, count, readfile, head, tail, readmatch, enclosing, imports, tree, ls, glob, files, stat, stats, git) in parallel.",
                 "parameters": {
                     "type": "object",
                     "properties": props,
@@ -184,13 +378,13 @@ pub fn get_tool_definitions(max_commands: u32) -> String {
             "type": "function",
             "function": {
                 "name": "answer",
-                "description": "Final answer with relevant files and line ranges.",
+                "description": answer_description,
                 "parameters": {
                     "type": "object",
                     "properties": {
                         "answer": {
                             "type": "string",
-                            "description": "The final answer in XML format."
+                            "description": answer_argument
                         }
                     },
                     "required": ["answer"]
@@ -203,9 +397,9 @@ pub fn get_tool_definitions(max_commands: u32) -> String {
 }
 
 fn build_command_schema(n: u32) -> serde_json::Value {
-    json!({
+    let mut schema = json!({
         "type": "object",
-        "description": format!("Command {} to execute. Must be one of: rg, readfile, tree, ls, glob.", n),
+        "description": format!("Command {} to execute. Must be one of: rg, rg_files, count, readfile, head, tail, readmatch, enclosing, imports, tree, ls, glob, files, stat, stats, git.", n),
         "oneOf": [
             {
                 "properties": {
@@ -213,7 +407,45 @@ fn build_command_schema(n: u32) -> serde_json::Value {
                     "pattern": { "type": "string", "description": "The regex pattern to search for." },
                     "path": { "type": "string", "description": "The path to search in." },
                     "include": { "type": "array", "items": { "type": "string" }, "description": "File patterns to include." },
-                    "exclude": { "type": "array", "items": { "type": "string" }, "description": "File patterns to exclude." }
+                    "exclude": { "type": "array", "items": { "type": "string" }, "description": "File patterns to exclude." },
+                    "context": { "type": "integer", "description": "Lines of context before and after each match (max 10)." },
+                    "before": { "type": "integer", "description": "Lines of context before each match; overrides context." },
+                    "after": { "type": "integer", "description": "Lines of context after each match; overrides context." },
+                    "case_insensitive": { "type": "boolean", "description": "Ignore case." },
+                    "fixed_strings": { "type": "boolean", "description": "Treat the pattern as a literal string, not a regex." },
+                    "word_boundary": { "type": "boolean", "description": "Only match whole words." },
+                    "multiline": { "type": "boolean", "description": "Allow matches to span lines (use \\n in the pattern)." }
+                },
+                "required": ["type", "pattern", "path"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "rg_files", "description": "Search for patterns only in the listed files." },
+                    "pattern": { "type": "string", "description": "The regex pattern to search for." },
+                    "files": { "type": "array", "items": { "type": "string" }, "description": "Files (or directories) to search, e.g. from a previous glob (up to 500)." },
+                    "include": { "type": "array", "items": { "type": "string" }, "description": "File patterns to include." },
+                    "exclude": { "type": "array", "items": { "type": "string" }, "description": "File patterns to exclude." },
+                    "context": { "type": "integer", "description": "Lines of context before and after each match (max 10)." },
+                    "before": { "type": "integer", "description": "Lines of context before each match; overrides context." },
+                    "after": { "type": "integer", "description": "Lines of context after each match; overrides context." },
+                    "case_insensitive": { "type": "boolean", "description": "Ignore case." },
+                    "fixed_strings": { "type": "boolean", "description": "Treat the pattern as a literal string, not a regex." },
+                    "word_boundary": { "type": "boolean", "description": "Only match whole words." },
+                    "multiline": { "type": "boolean", "description": "Allow matches to span lines (use \\n in the pattern)." }
+                },
+                "required": ["type", "pattern", "files"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "count", "description": "Count matching lines per file without showing them." },
+                    "pattern": { "type": "string", "description": "The regex pattern to count." },
+                    "path": { "type": "string", "description": "The path to search in." },
+                    "include": { "type": "array", "items": { "type": "string" }, "description": "File patterns to include." },
+                    "exclude": { "type": "array", "items": { "type": "string" }, "description": "File patterns to exclude." },
+                    "case_insensitive": { "type": "boolean", "description": "Ignore case." },
+                    "fixed_strings": { "type": "boolean", "description": "Treat the pattern as a literal string, not a regex." },
+                    "word_boundary": { "type": "boolean", "description": "Only match whole words." },
+                    "multiline": { "type": "boolean", "description": "Allow matches to span lines (use \\n in the pattern)." }
                 },
                 "required": ["type", "pattern", "path"]
             },
@@ -222,7 +454,59 @@ fn build_command_schema(n: u32) -> serde_json::Value {
                     "type": { "type": "string", "const": "readfile", "description": "Read contents of a file with optional line range." },
                     "file": { "type": "string", "description": "Path to the file to read." },
                     "start_line": { "type": "integer", "description": "Starting line number (1-indexed)." },
-                    "end_line": { "type": "integer", "description": "Ending line number (1-indexed)." }
+                    "end_line": { "type": "integer", "description": "Ending line number (1-indexed)." },
+                    "ranges": {
+                        "type": "array",
+                        "description": "Several line ranges to read at once (up to 20).",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "start_line": { "type": "integer", "description": "Starting line number (1-indexed)." },
+                                "end_line": { "type": "integer", "description": "Ending line number (1-indexed)." }
+                            }
+                        }
+                    },
+                    "around_symbol": { "type": "string", "description": "Also read the complete definition of this function/class/type." }
+                },
+                "required": ["type", "file"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "head", "description": "Read the first lines of a file." },
+                    "file": { "type": "string", "description": "Path to the file to read." },
+                    "lines": { "type": "integer", "description": "Number of lines (default 20)." }
+                },
+                "required": ["type", "file"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "tail", "description": "Read the last lines of a file." },
+                    "file": { "type": "string", "description": "Path to the file to read." },
+                    "lines": { "type": "integer", "description": "Number of lines (default 20)." }
+                },
+                "required": ["type", "file"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "readmatch", "description": "Read only lines of a file matching a pattern, with context." },
+                    "file": { "type": "string", "description": "Path to the file to read." },
+                    "pattern": { "type": "string", "description": "The regex pattern to match." },
+                    "context": { "type": "integer", "description": "Lines of context around each match (default 3)." }
+                },
+                "required": ["type", "file", "pattern"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "enclosing", "description": "Read the complete function/class/impl block containing a line." },
+                    "file": { "type": "string", "description": "Path to the file." },
+                    "line": { "type": "integer", "description": "Line number inside the block (1-indexed)." }
+                },
+                "required": ["type", "file", "line"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "imports", "description": "List the import/include/use statements of a file." },
+                    "file": { "type": "string", "description": "Path to the file." }
                 },
                 "required": ["type", "file"]
             },
@@ -230,7 +514,9 @@ fn build_command_schema(n: u32) -> serde_json::Value {
                 "properties": {
                     "type": { "type": "string", "const": "tree", "description": "Display directory structure as a tree." },
                     "path": { "type": "string", "description": "Path to the directory." },
-                    "levels": { "type": "integer", "description": "Number of directory levels." }
+                    "levels": { "type": "integer", "description": "Number of directory levels." },
+                    "sort": { "type": "string", "enum": ["name", "mtime", "size"], "description": "Entry order (default name)." },
+                    "max_entries": { "type": "integer", "description": "Maximum entries shown per directory." }
                 },
                 "required": ["type", "path"]
             },
@@ -238,20 +524,71 @@ fn build_command_schema(n: u32) -> serde_json::Value {
                 "properties": {
                     "type": { "type": "string", "const": "ls", "description": "List files in a directory." },
                     "path": { "type": "string", "description": "Path to the directory." },
-                    "long_format": { "type": "boolean" },
-                    "all": { "type": "boolean" }
+                    "long_format": { "type": "boolean", "description": "Show type and permissions, size, modification time, and a trailing / on directories." },
+                    "all": { "type": "boolean", "description": "Include hidden entries." },
+                    "sort": { "type": "string", "enum": ["name", "mtime", "size"], "description": "Entry order: name (default), mtime (newest first) or size (largest first)." },
+                    "max_entries": { "type": "integer", "description": "Maximum entries to list." }
                 },
                 "required": ["type", "path"]
             },
             {
                 "properties": {
                     "type": { "type": "string", "const": "glob", "description": "Find files matching a glob pattern." },
-                    "pattern": { "type": "string" },
+                    "pattern": { "type": "string", "description": "Glob relative to path, e.g. \"src/*.rs\" or \"**/*.rs\"; * stays within one directory, ** spans any number." },
                     "path": { "type": "string" },
                     "type_filter": { "type": "string", "enum": ["file", "directory", "all"] }
                 },
                 "required": ["type", "pattern", "path"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "files", "description": "List files under a directory, honoring .gitignore." },
+                    "path": { "type": "string", "description": "Path to the directory." },
+                    "glob": { "type": "string", "description": "Optional glob filter, e.g. \"*.rs\"." },
+                    "max_count": { "type": "integer", "description": "Maximum number of files to list (default 40)." }
+                },
+                "required": ["type", "path"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "stat", "description": "Show file metadata: size, mtime, line count, language, binary, generated/minified." },
+                    "file": { "type": "string", "description": "Path to the file." }
+                },
+                "required": ["type", "file"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "stats", "description": "Summarize languages, file counts and line counts under a directory." },
+                    "path": { "type": "string", "description": "Path to the directory." }
+                },
+                "required": ["type", "path"]
+            },
+            {
+                "properties": {
+                    "type": { "type": "string", "const": "git", "description": "Read-only git history: log --oneline, diff --stat, show, blame -L." },
+                    "subcommand": { "type": "string", "enum": ["log", "diff", "show", "blame"] },
+                    "ref": { "type": "string", "description": "Commit, ref or range, e.g. \"HEAD~5\" or \"main..HEAD\"." },
+                    "file": { "type": "string", "description": "Limit to this file (required for blame)." },
+                    "max_count": { "type": "integer", "description": "Commits to list for log (default 20, max 100)." },
+                    "start_line": { "type": "integer", "description": "First line for blame (1-indexed)." },
+                    "end_line": { "type": "integer", "description": "Last line for blame (1-indexed)." }
+                },
+                "required": ["type", "subcommand"]
             }
         ]
-    })
+    });
+
+    if archive::enabled() {
+        if let Some(variants) = schema["oneOf"].as_array_mut() {
+            variants.push(json!({
+                "properties": {
+                    "type": { "type": "string", "const": "archive", "description": "List or grep the contents of a zip/jar/wheel archive." },
+                    "file": { "type": "string", "description": "Path to the archive." },
+                    "pattern": { "type": "string", "description": "Regex to search inside text entries. Omit to list entries." }
+                },
+                "required": ["type", "file"]
+            }));
+        }
+    }
+    schema
 }
@@ -65,6 +65,13 @@ impl ProtobufEncoder {
         self
     }
 
+    /// 原样追加保留的字段（如 RawMessage::split 得到的未知字段）
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn write_raw(&mut self, fields: &RawMessage) -> &mut Self {
+        self.buf.extend_from_slice(&fields.encode());
+        self
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         &self.buf
     }
@@ -131,6 +138,187 @@ pub fn decode_varint(data: &[u8], mut offset: usize) -> (u64, usize) {
     (value, offset)
 }
 
+/// 字段值（按 wire type）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireValue {
+    Varint(u64),
+    Fixed64([u8; 8]),
+    Bytes(Vec<u8>),
+    /// 已废弃的 group 起止标记，仅原样保留
+    StartGroup,
+    EndGroup,
+    Fixed32([u8; 4]),
+}
+
+/// 单个字段，`raw` 为线上原始字节（tag + 值），重新编码时原样输出
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawField {
+    pub number: u32,
+    pub value: WireValue,
+    raw: Vec<u8>,
+}
+
+impl RawField {
+    /// 新建字段（规范编码）
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn new(number: u32, value: WireValue) -> Self {
+        let mut buf = BytesMut::new();
+        let wire = match &value {
+            WireValue::Varint(_) => 0,
+            WireValue::Fixed64(_) => 1,
+            WireValue::Bytes(_) => 2,
+            WireValue::StartGroup => 3,
+            WireValue::EndGroup => 4,
+            WireValue::Fixed32(_) => 5,
+        };
+        ProtobufEncoder::write_varint_raw(&mut buf, ((number as u64) << 3) | wire);
+        match &value {
+            WireValue::Varint(v) => ProtobufEncoder::write_varint_raw(&mut buf, *v),
+            WireValue::Fixed64(b) => buf.extend_from_slice(b),
+            WireValue::Bytes(b) => {
+                ProtobufEncoder::write_varint_raw(&mut buf, b.len() as u64);
+                buf.extend_from_slice(b);
+            }
+            WireValue::StartGroup | WireValue::EndGroup => {}
+            WireValue::Fixed32(b) => buf.extend_from_slice(b),
+        }
+        Self { number, value, raw: buf.to_vec() }
+    }
+
+    /// 线上原始字节
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            WireValue::Bytes(b) => std::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// 保留全部字段（含未知字段）的消息，未修改时可逐字节还原
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawMessage {
+    pub fields: Vec<RawField>,
+}
+
+/// 严格读取 varint：数据截断或超过 10 字节时返回 None
+fn read_varint(data: &[u8], offset: usize) -> Option<(u64, usize)> {
+    let mut value: u64 = 0;
+    for (n, &b) in data.get(offset..)?.iter().take(10).enumerate() {
+        value |= ((b & 0x7f) as u64) << (7 * n);
+        if b & 0x80 == 0 {
+            return Some((value, offset + n + 1));
+        }
+    }
+    None
+}
+
+impl RawMessage {
+    /// 解析消息；任何截断或非法 wire type 都返回带偏移的错误
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let start = i;
+            let (tag, next) = read_varint(data, i).ok_or_else(|| format!("truncated tag at byte {}", start))?;
+            i = next;
+            let number = u32::try_from(tag >> 3).map_err(|_| format!("field number out of range at byte {}", start))?;
+            if number == 0 {
+                return Err(format!("field number 0 at byte {}", start));
+            }
+            let take = |i: usize, n: usize| {
+                i.checked_add(n)
+                    .and_then(|end| data.get(i..end))
+                    .ok_or_else(|| format!("field {} at byte {} truncated", number, start))
+            };
+            let value = match tag & 0x7 {
+                0 => {
+                    let (v, next) = read_varint(data, i).ok_or_else(|| format!("field {} at byte {} truncated", number, start))?;
+                    i = next;
+                    WireValue::Varint(v)
+                }
+                1 => {
+                    let b = take(i, 8)?;
+                    i += 8;
+                    WireValue::Fixed64(b.try_into().unwrap())
+                }
+                2 => {
+                    let (len, next) = read_varint(data, i).ok_or_else(|| format!("field {} at byte {} truncated", number, start))?;
+                    let len = usize::try_from(len).map_err(|_| format!("field {} at byte {} too long", number, start))?;
+                    let b = take(next, len)?;
+                    i = next + b.len();
+                    WireValue::Bytes(b.to_vec())
+                }
+                3 => WireValue::StartGroup,
+                4 => WireValue::EndGroup,
+                5 => {
+                    let b = take(i, 4)?;
+                    i += 4;
+                    WireValue::Fixed32(b.try_into().unwrap())
+                }
+                w => return Err(format!("invalid wire type {} at byte {}", w, start)),
+            };
+            fields.push(RawField { number, value, raw: data[start..i].to_vec() });
+        }
+        Ok(Self { fields })
+    }
+
+    /// 重新编码：未修改的字段与原始字节完全一致
+    pub fn encode(&self) -> Vec<u8> {
+        self.fields.iter().flat_map(|f| f.raw.iter().copied()).collect()
+    }
+
+    /// 拆分为 (已知字段, 未知字段)，两者均保持原始顺序
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn split(&self, known: &[u32]) -> (RawMessage, RawMessage) {
+        let (k, u): (Vec<_>, Vec<_>) = self.fields.iter().cloned().partition(|f| known.contains(&f.number));
+        (RawMessage { fields: k }, RawMessage { fields: u })
+    }
+
+    /// 第一个编号为 `number` 的字段
+    pub fn get(&self, number: u32) -> Option<&RawField> {
+        self.fields.iter().find(|f| f.number == number)
+    }
+
+    /// 编号为 `number` 的所有字段（repeated）
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn get_all(&self, number: u32) -> impl Iterator<Item = &RawField> {
+        self.fields.iter().filter(move |f| f.number == number)
+    }
+
+    /// 将字段解析为嵌套消息
+    pub fn sub_message(&self, number: u32) -> Option<RawMessage> {
+        match &self.get(number)?.value {
+            WireValue::Bytes(b) => RawMessage::parse(b).ok(),
+            _ => None,
+        }
+    }
+
+    /// 替换第一个同编号字段（保持位置），不存在则追加；其余字段不变
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn set(&mut self, number: u32, value: WireValue) {
+        let field = RawField::new(number, value);
+        match self.fields.iter_mut().find(|f| f.number == number) {
+            Some(f) => *f = field,
+            None => self.fields.push(field),
+        }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn set_message(&mut self, number: u32, sub: &RawMessage) {
+        self.set(number, WireValue::Bytes(sub.encode()));
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn remove(&mut self, number: u32) {
+        self.fields.retain(|f| f.number != number);
+    }
+}
+
 /// Connect-RPC 帧编码（gzip 压缩）
 pub fn connect_frame_encode(proto_bytes: &[u8]) -> Vec<u8> {
     let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
@@ -145,36 +333,102 @@ pub fn connect_frame_encode(proto_bytes: &[u8]) -> Vec<u8> {
     frame
 }
 
-/// Connect-RPC 帧解码
-pub fn connect_frame_decode(data: &[u8]) -> Vec<Vec<u8>> {
+/// 单帧负载上限（压缩前/解压后均适用）
+pub const MAX_FRAME_BYTES: usize = 16 * 1024 * 1024;
+/// 单个响应的帧数上限
+pub const MAX_FRAMES: usize = 10_000;
+/// 单个响应解压后总大小上限（防压缩炸弹）
+pub const MAX_DECODED_BYTES: usize = 64 * 1024 * 1024;
+
+/// 帧解码错误，offset 为出错帧头在响应中的字节偏移
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// 帧头声明的长度超过剩余数据
+    Truncated { offset: usize, declared: usize, available: usize },
+    /// 帧头不足 5 字节的尾部数据
+    TrailingBytes { offset: usize, len: usize },
+    FrameTooLarge { offset: usize, declared: usize },
+    TooManyFrames,
+    Decompress { offset: usize, reason: String },
+    DecodedTooLarge { offset: usize },
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Truncated { offset, declared, available } => write!(
+                f, "frame at byte {} declares {} bytes but only {} remain", offset, declared, available
+            ),
+            FrameError::TrailingBytes { offset, len } => write!(
+                f, "{} trailing bytes at byte {} (incomplete frame header)", len, offset
+            ),
+            FrameError::FrameTooLarge { offset, declared } => write!(
+                f, "frame at byte {} declares {} bytes (limit {})", offset, declared, MAX_FRAME_BYTES
+            ),
+            FrameError::TooManyFrames => write!(f, "more than {} frames in response", MAX_FRAMES),
+            FrameError::Decompress { offset, reason } => write!(
+                f, "gzip frame at byte {} failed to decompress: {}", offset, reason
+            ),
+            FrameError::DecodedTooLarge { offset } => write!(
+                f, "decompressed output exceeds limit at frame byte {} (frame {} / total {})", offset, MAX_FRAME_BYTES, MAX_DECODED_BYTES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Connect-RPC 帧解码（限制帧大小、帧数和解压后大小）
+pub fn connect_frame_decode(data: &[u8]) -> Result<Vec<Vec<u8>>, FrameError> {
     let mut frames = Vec::new();
+    let mut decoded_total = 0usize;
     let mut i = 0;
 
-    while i + 5 <= data.len() {
+    while i < data.len() {
+        let offset = i;
+        if i + 5 > data.len() {
+            return Err(FrameError::TrailingBytes { offset, len: data.len() - i });
+        }
+        if frames.len() >= MAX_FRAMES {
+            return Err(FrameError::TooManyFrames);
+        }
         let flags = data[i];
         let length = u32::from_be_bytes([data[i + 1], data[i + 2], data[i + 3], data[i + 4]]) as usize;
         i += 5;
 
-        if i + length > data.len() { break; }
+        if length > MAX_FRAME_BYTES {
+            return Err(FrameError::FrameTooLarge { offset, declared: length });
+        }
+        if length > data.len() - i {
+            return Err(FrameError::Truncated { offset, declared: length, available: data.len() - i });
+        }
         let payload = &data[i..i + length];
         i += length;
 
-        let decoded = if flags == 1 || flags == 3 {
-            // gzip compressed
-            let mut decoder = GzDecoder::new(payload);
+        let decoded = if flags & 1 == 1 {
+            // gzip compressed：多读 1 字节用于判断是否超限
+            let budget = MAX_FRAME_BYTES.min(MAX_DECODED_BYTES - decoded_total);
             let mut buf = Vec::new();
-            match decoder.read_to_end(&mut buf) {
-                Ok(_) => buf,
-                Err(_) => payload.to_vec(),
+            GzDecoder::new(payload)
+                .take(budget as u64 + 1)
+                .read_to_end(&mut buf)
+                .map_err(|e| FrameError::Decompress { offset, reason: e.to_string() })?;
+            if buf.len() > budget {
+                return Err(FrameError::DecodedTooLarge { offset });
             }
+            buf
         } else {
             payload.to_vec()
         };
 
+        decoded_total += decoded.len();
+        if decoded_total > MAX_DECODED_BYTES {
+            return Err(FrameError::DecodedTooLarge { offset });
+        }
         frames.push(decoded);
     }
 
-    frames
+    Ok(frames)
 }
 
 /// gzip 压缩
@@ -183,3 +437,100 @@ pub fn gzip_compress(data: &[u8]) -> Vec<u8> {
     encoder.write_all(data).unwrap();
     encoder.finish().unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 字段 1 = 150（varint），字段 2 = "hi"，字段 9 = fixed32，字段 12 = fixed64（后两者视为未知）
+    fn sample() -> Vec<u8> {
+        let mut data = vec![0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i'];
+        data.extend([0x4d, 1, 2, 3, 4]);
+        data.extend([0x61, 1, 2, 3, 4, 5, 6, 7, 8]);
+        data
+    }
+
+    #[test]
+    fn unknown_fields_round_trip() {
+        let data = sample();
+        let msg = RawMessage::parse(&data).unwrap();
+        assert_eq!(msg.fields.len(), 4);
+        assert_eq!(msg.get(1).unwrap().value, WireValue::Varint(150));
+        assert_eq!(msg.get(2).unwrap().as_str(), Some("hi"));
+        assert_eq!(msg.get(9).unwrap().value, WireValue::Fixed32([1, 2, 3, 4]));
+        assert_eq!(msg.get(12).unwrap().raw(), &data[12..]);
+        assert_eq!(msg.encode(), data);
+    }
+
+    #[test]
+    fn groups_are_kept_verbatim() {
+        // 字段 3 的 group：start、内部字段 1 = 1、end
+        let data = [0x1b, 0x08, 0x01, 0x1c, 0x08, 0x05];
+        let msg = RawMessage::parse(&data).unwrap();
+        let values: Vec<&WireValue> = msg.fields.iter().map(|f| &f.value).collect();
+        assert_eq!(values, [&WireValue::StartGroup, &WireValue::Varint(1), &WireValue::EndGroup, &WireValue::Varint(5)]);
+        assert_eq!(msg.encode(), data);
+    }
+
+    #[test]
+    fn non_minimal_varints_are_preserved() {
+        // 150 用 3 字节编码，tag 也多带一个续位字节
+        let data = [0x88, 0x00, 0x96, 0x81, 0x00];
+        let msg = RawMessage::parse(&data).unwrap();
+        assert_eq!(msg.get(1).unwrap().value, WireValue::Varint(150));
+        assert_eq!(msg.encode(), data);
+        // 重新设置后改为规范编码
+        let mut msg = msg;
+        msg.set(1, WireValue::Varint(150));
+        assert_eq!(msg.encode(), [0x08, 0x96, 0x01]);
+    }
+
+    #[test]
+    fn split_keeps_order_and_rejoins() {
+        let data = sample();
+        let msg = RawMessage::parse(&data).unwrap();
+        let (known, unknown) = msg.split(&[2, 1]);
+        assert_eq!(known.fields.iter().map(|f| f.number).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(unknown.fields.iter().map(|f| f.number).collect::<Vec<_>>(), [9, 12]);
+
+        let mut enc = ProtobufEncoder::new();
+        enc.write_varint(1, 150).write_string(2, "hi").write_raw(&unknown);
+        assert_eq!(enc.as_bytes(), &data[..]);
+    }
+
+    #[test]
+    fn edits_touch_only_their_field() {
+        let data = sample();
+        let mut msg = RawMessage::parse(&data).unwrap();
+        let mut sub = RawMessage::default();
+        sub.fields.push(RawField::new(1, WireValue::Bytes(b"x".to_vec())));
+        msg.set_message(20, &sub);
+        msg.set(2, WireValue::Bytes(b"yo".to_vec()));
+        assert_eq!(msg.sub_message(20), Some(sub));
+        assert_eq!(msg.get(2).unwrap().as_str(), Some("yo"));
+        assert_eq!(msg.get_all(12).count(), 1);
+        msg.remove(20);
+        msg.remove(2);
+        let mut expected = data[..3].to_vec();
+        expected.extend(&data[7..]);
+        assert_eq!(msg.encode(), expected);
+    }
+
+    #[test]
+    fn truncated_and_malformed_input_is_an_error() {
+        assert!(RawMessage::parse(&[0x08]).unwrap_err().contains("truncated"));
+        assert!(RawMessage::parse(&[0x12, 0x05, b'a']).unwrap_err().contains("truncated"));
+        assert!(RawMessage::parse(&[0x4d, 1, 2]).unwrap_err().contains("truncated"));
+        assert!(RawMessage::parse(&[0x0e]).unwrap_err().contains("wire type"));
+        assert!(RawMessage::parse(&[0x00]).unwrap_err().contains("field number 0"));
+    }
+
+    #[test]
+    fn huge_length_does_not_overflow() {
+        // 长度 = u64::MAX，i + len 会溢出
+        let mut data = vec![0x12];
+        data.extend([0xff; 9]);
+        data.push(0x01);
+        assert!(RawMessage::parse(&data).unwrap_err().contains("truncated"));
+    }
+}
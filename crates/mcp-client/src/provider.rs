@@ -0,0 +1,125 @@
+//! 搜索后端 (provider)
+//!
+//! 内置 windsurf（经 relay 获取凭据）；配置 providers 可添加 OpenAI 兼容接口的本地/私有模型，
+//! 单次查询通过 `provider` 参数选择。
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::windsurf::ChatMessage;
+
+pub const WINDSURF: &str = "windsurf";
+
+/// OpenAI 兼容 (/chat/completions) 后端
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// 例如 http://localhost:11434/v1
+    pub base_url: String,
+    pub model: String,
+    /// 保存 API key 的环境变量名，本地模型可不填
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// 可用 provider 名称（windsurf 在前）
+pub fn names(providers: &BTreeMap<String, ProviderConfig>) -> Vec<String> {
+    std::iter::once(WINDSURF.to_string())
+        .chain(providers.keys().filter(|k| k.as_str() != WINDSURF).cloned())
+        .collect()
+}
+
+/// 校验请求的 provider：空 = 默认；返回 None 表示 windsurf
+pub fn resolve(
+    providers: &BTreeMap<String, ProviderConfig>,
+    default: Option<&str>,
+    requested: &str,
+) -> Result<Option<(String, ProviderConfig)>, String> {
+    let name = if requested.is_empty() { default.unwrap_or(WINDSURF) } else { requested };
+    if name == WINDSURF {
+        return Ok(None);
+    }
+    match providers.get(name) {
+        Some(p) => Ok(Some((name.to_string(), p.clone()))),
+        None => Err(format!("unknown provider: {} (available: {})", name, names(providers).join(", "))),
+    }
+}
+
+/// ChatMessage (windsurf 角色编号) → OpenAI messages
+fn to_openai_messages(messages: &[ChatMessage]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|m| match m.role {
+            5 => json!({ "role": "system", "content": m.content }),
+            2 => match (&m.tool_call_id, &m.tool_name) {
+                (Some(id), Some(name)) => json!({
+                    "role": "assistant",
+                    "content": m.content,
+                    "tool_calls": [{
+                        "id": id,
+                        "type": "function",
+                        "function": { "name": name, "arguments": m.tool_args_json.clone().unwrap_or_else(|| "{}".into()) }
+                    }]
+                }),
+                _ => json!({ "role": "assistant", "content": m.content }),
+            },
+            4 => json!({ "role": "tool", "tool_call_id": m.ref_call_id, "content": m.content }),
+            _ => json!({ "role": "user", "content": m.content }),
+        })
+        .collect()
+}
+
+/// 单轮对话，返回值与 windsurf::parse_response 相同：(思考文本, 工具调用)
+pub async fn chat_turn(
+    client: &reqwest::Client,
+    cfg: &ProviderConfig,
+    messages: &[ChatMessage],
+    tool_defs: &str,
+) -> Result<(String, Option<(String, Value)>)> {
+    let tools: Value = serde_json::from_str(tool_defs)?;
+    let url = format!("{}/chat/completions", cfg.base_url.trim_end_matches('/'));
+    let mut req = client
+        .post(&url)
+        .timeout(std::time::Duration::from_millis(cfg.timeout_ms.unwrap_or(120_000)))
+        .json(&json!({
+            "model": cfg.model,
+            "messages": to_openai_messages(messages),
+            "tools": tools,
+            "tool_choice": "auto",
+        }));
+    if let Some(key) = cfg.api_key_env.as_deref().and_then(|v| std::env::var(v).ok()) {
+        req = req.bearer_auth(key);
+    }
+
+    let resp = req.send().await?;
+    let status = resp.status();
+    let body: Value = resp.json().await?;
+    if let Some(err) = body.get("error") {
+        let msg = err.get("message").and_then(|m| m.as_str()).map(str::to_string).unwrap_or_else(|| err.to_string());
+        anyhow::bail!("HTTP {}: {}", status.as_u16(), msg);
+    }
+    if !status.is_success() {
+        anyhow::bail!("HTTP {}", status.as_u16());
+    }
+
+    let message = &body["choices"][0]["message"];
+    let thinking = message.get("content").and_then(|c| c.as_str()).unwrap_or("").to_string();
+    let tool_info = message
+        .get("tool_calls")
+        .and_then(|t| t.as_array())
+        .and_then(|calls| calls.first())
+        .and_then(|call| {
+            let name = call.pointer("/function/name")?.as_str()?.to_string();
+            let args = match call.pointer("/function/arguments")? {
+                Value::String(s) => serde_json::from_str(s).unwrap_or_else(|_| json!({})),
+                v => v.clone(),
+            };
+            Some((name, args))
+        });
+    Ok((thinking, tool_info))
+}
@@ -0,0 +1,102 @@
+//! 额度感知
+//!
+//! 从 relay 凭据响应中解析剩余额度（响应体 quota/remaining_credits 字段或 X-Quota-* 头），
+//! 每个会话记录最近一次的值，用于结果尾注、relay_status 以及低额度时的限流/拒绝。
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+pub struct Quota {
+    pub remaining: f64,
+    pub limit: Option<f64>,
+    pub seen_at: SystemTime,
+}
+
+impl Quota {
+    /// "120/500" 或 "120"
+    pub fn display(&self) -> String {
+        match self.limit {
+            Some(l) => format!("{}/{}", fmt_num(self.remaining), fmt_num(l)),
+            None => fmt_num(self.remaining),
+        }
+    }
+}
+
+fn fmt_num(v: f64) -> String {
+    if v.fract() == 0.0 { format!("{}", v as i64) } else { format!("{:.2}", v) }
+}
+
+/// 低额度时的处理
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LowQuotaAction {
+    /// 拒绝新搜索
+    #[default]
+    Refuse,
+    /// 延迟后继续
+    Throttle,
+}
+
+/// 配置：剩余额度低于 min_remaining 时执行 action
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaPolicy {
+    pub min_remaining: Option<f64>,
+    pub action: LowQuotaAction,
+    /// throttle 时每次搜索前等待的毫秒数，默认 5000
+    pub throttle_ms: Option<u64>,
+}
+
+fn as_f64(v: &Value) -> Option<f64> {
+    v.as_f64().or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+}
+
+/// 从凭据响应解析额度
+pub fn parse(headers: &reqwest::header::HeaderMap, body: &Value) -> Option<Quota> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).and_then(|s| s.trim().parse::<f64>().ok());
+    let (remaining, limit) = if let Some(q) = body.get("quota").filter(|q| q.is_object()) {
+        (q.get("remaining").and_then(as_f64), q.get("limit").and_then(as_f64))
+    } else {
+        (
+            body.get("remaining_credits").or_else(|| body.get("remaining_quota")).and_then(as_f64),
+            body.get("credit_limit").or_else(|| body.get("quota_limit")).and_then(as_f64),
+        )
+    };
+    let remaining = remaining.or_else(|| header("x-quota-remaining"))?;
+    let limit = limit.or_else(|| header("x-quota-limit"));
+    Some(Quota { remaining, limit, seen_at: SystemTime::now() })
+}
+
+/// 最近一次观察到的额度
+#[derive(Default)]
+pub struct Cache(Mutex<Option<Quota>>);
+
+impl Cache {
+    pub fn record(&self, q: Quota) {
+        *self.0.lock().unwrap() = Some(q);
+    }
+
+    pub fn last(&self) -> Option<Quota> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// 按策略检查额度：Err = 拒绝；Ok(Some(d)) = 需等待 d
+pub fn check(policy: &QuotaPolicy, q: &Quota) -> Result<Option<Duration>, String> {
+    let Some(min) = policy.min_remaining else { return Ok(None) };
+    if q.remaining >= min {
+        return Ok(None);
+    }
+    match policy.action {
+        LowQuotaAction::Refuse => Err(format!(
+            "quota too low: {} remaining (threshold {}); refusing new searches",
+            q.display(),
+            fmt_num(min)
+        )),
+        LowQuotaAction::Throttle => Ok(Some(Duration::from_millis(policy.throttle_ms.unwrap_or(5000)))),
+    }
+}
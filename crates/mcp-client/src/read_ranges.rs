@@ -0,0 +1,89 @@
+//! read_ranges 工具：读取搜索结果给出的行范围
+//!
+//! 接受 `[{path, start, end}]`，路径可以是 fast_context_search 返回的绝对路径、相对项目根目录的路径
+//! 或 /codebase/...；逐段用 `ToolExecutor::readfile` 输出带行号的内容，省去宿主再用自己的文件工具读一遍。
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::executor::ToolExecutor;
+use crate::i18n::Locale;
+
+/// 单次最多读取的范围数
+const MAX_RANGES: usize = 50;
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "read_ranges",
+        "description": l.pick(
+            "Read the line ranges returned by fast_context_search (or any [{path, start, end}] list) with line numbers, in one call.",
+            "一次读取 fast_context_search 返回的行范围（或任意 [{path, start, end}] 列表），带行号输出。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "ranges": {
+                    "type": "array",
+                    "description": l.pick("Ranges to read (at most {})", "要读取的范围（最多 {} 个）").replace("{}", &MAX_RANGES.to_string()),
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "path": { "type": "string", "description": l.pick("Absolute path, path relative to project_path, or /codebase/...", "绝对路径、相对 project_path 的路径或 /codebase/...") },
+                            "start": { "type": "integer", "description": l.pick("First line (1-based, default 1)", "起始行（从 1 开始，默认 1）"), "minimum": 1 },
+                            "end": { "type": "integer", "description": l.pick("Last line, inclusive (default end of file)", "结束行（含，默认到文件末尾）"), "minimum": 1 }
+                        },
+                        "required": ["path"]
+                    },
+                    "minItems": 1
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Project root the paths belong to. Empty = first client root, else cwd.", "路径所属的项目根目录，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            },
+            "required": ["ranges"]
+        }
+    })
+}
+
+/// 执行 read_ranges；单个范围的错误写在该段内，不影响其他范围。文件读取在阻塞线程上执行
+pub async fn call(args: &Value) -> Result<String, String> {
+    let args = args.clone();
+    tokio::task::spawn_blocking(move || read(&args)).await.map_err(|e| e.to_string())?
+}
+
+fn read(args: &Value) -> Result<String, String> {
+    let root = crate::direct_tools::project_root(args)?;
+    let ranges = args.get("ranges").and_then(|r| r.as_array()).filter(|r| !r.is_empty())
+        .ok_or_else(|| "missing required argument: ranges".to_string())?;
+    if ranges.len() > MAX_RANGES {
+        return Err(format!("too many ranges: {} (max {})", ranges.len(), MAX_RANGES));
+    }
+    let executor = ToolExecutor::new(&root);
+    let line = |r: &Value, key: &str| r.get(key).and_then(|v| v.as_u64()).map(|v| v.max(1) as usize);
+
+    let mut parts = Vec::new();
+    for r in ranges {
+        let path = r.get("path").and_then(|p| p.as_str()).unwrap_or("");
+        let (start, end) = (line(r, "start"), line(r, "end"));
+        let label = match (start, end) {
+            (None, None) => path.to_string(),
+            _ => format!("{}:{}-{}", path, start.unwrap_or(1), end.map(|e| e.to_string()).unwrap_or_default()),
+        };
+        let body = if path.is_empty() {
+            "Error: missing path".to_string()
+        } else if start.zip(end).is_some_and(|(s, e)| s > e) {
+            "Error: start is after end".to_string()
+        } else {
+            match crate::direct_tools::virtual_path(Path::new(&root), path) {
+                Ok(file) => executor.readfile(&file, start, end),
+                Err(e) => format!("Error: {}", e),
+            }
+        };
+        parts.push(format!("=== {} ===\n{}", label, body));
+    }
+    Ok(parts.join("\n\n"))
+}
@@ -0,0 +1,298 @@
+//! related_tests 工具：查找覆盖某个源文件或符号的测试
+//!
+//! 在测试文件（tests/、*_test.*、test_*.py、*.spec.* 等）中按三条线索打分：文件名包含模块名、
+//! import 引用了该模块、正文提到目标符号。返回测试文件路径及其中提到目标的测试函数行范围；
+//! Rust 源文件内的 `mod tests` 也算在内。不经过 AI，也不依赖 rg。
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::diff_scope;
+use crate::i18n::Locale;
+use crate::outline;
+
+const DEFAULT_MAX_RESULTS: usize = 10;
+const MAX_RESULTS: usize = 50;
+/// 超过此大小的测试文件不检查
+const TEST_SCAN_BYTES: u64 = 1024 * 1024;
+/// 只给文件时，最多用其中这么多个顶层符号名匹配
+const MAX_TARGET_NAMES: usize = 30;
+/// 没有测试函数命中时列出的提及行数上限
+const MAX_MENTION_LINES: usize = 5;
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "related_tests",
+        "description": l.pick(
+            "Find the tests that exercise a source file or symbol, combining test naming conventions, import tracing and a scan of test files. Returns test file paths with the ranges of the test functions that reference it.",
+            "查找覆盖某个源文件或符号的测试：结合测试命名约定、import 追踪和测试文件扫描，返回测试文件路径以及其中引用目标的测试函数行范围。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "file": {
+                    "type": "string",
+                    "description": l.pick("Source file under test: absolute, relative to project_path, or /codebase/...", "被测源文件：绝对路径、相对 project_path 的路径或 /codebase/..."),
+                },
+                "symbol": {
+                    "type": "string",
+                    "description": l.pick("Function, method or type under test", "被测的函数、方法或类型名"),
+                },
+                "max_results": {
+                    "type": "integer",
+                    "description": l.pick("Max test files to return (1-50, default 10)", "最多返回的测试文件数（1-50，默认 10）"),
+                    "default": DEFAULT_MAX_RESULTS,
+                    "minimum": 1,
+                    "maximum": MAX_RESULTS
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            }
+        }
+    })
+}
+
+/// 要找的目标：模块名（来自文件）与符号名
+struct Target {
+    /// 源文件（项目相对路径）
+    file: Option<String>,
+    module: Option<String>,
+    language: Option<&'static str>,
+    /// 正文中要找的名字：指定的符号，或文件里的顶层符号
+    names: Vec<String>,
+}
+
+/// 一个测试文件的命中情况
+struct Hit {
+    rel: String,
+    reasons: Vec<&'static str>,
+    /// (起止行, 测试函数名)
+    tests: Vec<(usize, usize, String)>,
+    /// 没有测试函数命中时的提及行
+    lines: Vec<usize>,
+}
+
+impl Hit {
+    fn score(&self) -> usize {
+        self.reasons.iter().map(|r| if *r == "mentions" { 1 } else { 2 }).sum::<usize>() + self.tests.len().min(3)
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// 整词出现
+fn contains_word(text: &str, word: &str) -> bool {
+    text.match_indices(word).any(|(i, _)| {
+        let before = text[..i].chars().next_back().is_none_or(|c| !is_word_char(c));
+        let after = text[i + word.len()..].chars().next().is_none_or(|c| !is_word_char(c));
+        before && after
+    })
+}
+
+/// 测试文件名（去掉 test/spec 字样后）是否就是模块名
+fn name_matches(rel: &str, module: &str) -> bool {
+    let stem = Path::new(rel).file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let stem = stem.split('.').next().unwrap_or("");
+    let trimmed = stem
+        .trim_start_matches("test_")
+        .trim_end_matches("_test")
+        .trim_end_matches("_tests")
+        .trim_end_matches("_spec");
+    trimmed.eq_ignore_ascii_case(module)
+        || stem.strip_prefix("Test").is_some_and(|s| s.eq_ignore_ascii_case(module))
+        || stem.strip_suffix("Test").is_some_and(|s| s.eq_ignore_ascii_case(module))
+        || stem.strip_suffix("Tests").is_some_and(|s| s.eq_ignore_ascii_case(module))
+}
+
+fn is_function_kind(kind: &str) -> bool {
+    matches!(kind, "function_item" | "function_definition" | "function_declaration" | "method_declaration" | "method_definition")
+}
+
+/// 函数块及其提及目标的情况；嵌套时只保留最内层
+fn matching_tests(path: &Path, source: &str, names: &[String], within: Option<(usize, usize)>) -> Vec<(usize, usize, String)> {
+    let lines: Vec<&str> = source.lines().collect();
+    let blocks: Vec<outline::Block> = outline::symbols(path, source)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|b| is_function_kind(&b.kind))
+        .filter(|b| within.is_none_or(|(s, e)| b.start_line >= s && b.end_line <= e))
+        .filter(|b| {
+            let body = lines.get(b.start_line.saturating_sub(1)..b.end_line.min(lines.len())).unwrap_or(&[]).join("\n");
+            names.iter().any(|n| contains_word(&body, n))
+        })
+        .collect();
+    blocks
+        .iter()
+        .filter(|b| !blocks.iter().any(|o| o.start_line >= b.start_line && o.end_line <= b.end_line && (o.start_line, o.end_line) != (b.start_line, b.end_line)))
+        .map(|b| {
+            // 范围包含 #[test] 之类的属性行
+            let mut start = b.start_line;
+            while start > 1 && lines.get(start - 2).is_some_and(|l| l.trim_start().starts_with("#[")) {
+                start -= 1;
+            }
+            (start, b.end_line, b.name.clone().unwrap_or_default())
+        })
+        .collect()
+}
+
+/// 检查一个测试文件
+fn check_test_file(path: &Path, rel: &str, target: &Target) -> Option<Hit> {
+    let language = crate::lang::detect_language(path)?;
+    if target.language.is_some_and(|l| !diff_scope::same_family(l, language)) {
+        return None;
+    }
+    let source = std::fs::read_to_string(path).ok()?;
+    let mut reasons = Vec::new();
+    if let Some(module) = &target.module {
+        if name_matches(rel, module) {
+            reasons.push("naming");
+        }
+        if diff_scope::import_words(path, language, &source).iter().any(|w| w == module) {
+            reasons.push("imports");
+        }
+    }
+    let mentioned = target.names.iter().any(|n| contains_word(&source, n));
+    if mentioned {
+        reasons.push("mentions");
+    }
+    if reasons.is_empty() {
+        return None;
+    }
+    let tests = matching_tests(path, &source, &target.names, None);
+    let lines = if tests.is_empty() && mentioned {
+        source
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| target.names.iter().any(|n| contains_word(l, n)))
+            .map(|(i, _)| i + 1)
+            .take(MAX_MENTION_LINES)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Some(Hit { rel: rel.to_string(), reasons, tests, lines })
+}
+
+/// Rust 源文件自带的 `mod tests`
+fn inline_tests(root: &Path, target: &Target) -> Option<Hit> {
+    let rel = target.file.as_ref()?;
+    let path = root.join(rel);
+    let source = std::fs::read_to_string(&path).ok()?;
+    let module = outline::symbols(&path, &source)?
+        .into_iter()
+        .find(|b| b.kind == "mod_item" && b.name.as_deref() == Some("tests"))?;
+    let tests = matching_tests(&path, &source, &target.names, Some((module.start_line, module.end_line)));
+    let tests = if tests.is_empty() {
+        vec![(module.start_line, module.end_line, "tests".to_string())]
+    } else {
+        tests
+    };
+    Some(Hit { rel: rel.clone(), reasons: vec!["inline tests"], tests, lines: Vec::new() })
+}
+
+fn find(root: &Path, target: &Target, max: usize) -> Vec<Hit> {
+    let mut hits: Vec<Hit> = inline_tests(root, target).into_iter().collect();
+    crate::executor::walk_files(root, |path, meta| {
+        let Ok(rel) = path.strip_prefix(root) else { return true };
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if meta.len() > TEST_SCAN_BYTES || !crate::lang::is_test_path(&rel) || target.file.as_deref() == Some(rel.as_str()) {
+            return true;
+        }
+        hits.extend(check_test_file(path, &rel, target));
+        true
+    });
+    hits.sort_by(|a, b| b.score().cmp(&a.score()).then_with(|| a.rel.cmp(&b.rel)));
+    hits.truncate(max);
+    hits
+}
+
+fn render(root: &Path, hits: &[Hit]) -> String {
+    let mut out = Vec::new();
+    for hit in hits {
+        out.push(format!("{} ({})", root.join(&hit.rel).display(), hit.reasons.join(", ")));
+        for (start, end, name) in &hit.tests {
+            out.push(format!("  {}-{} {}", start, end, name));
+        }
+        if !hit.lines.is_empty() {
+            let lines: Vec<String> = hit.lines.iter().map(|l| l.to_string()).collect();
+            out.push(format!("  mentioned on lines {}", lines.join(", ")));
+        }
+    }
+    out.join("\n")
+}
+
+/// 执行 related_tests
+pub async fn call(args: &Value) -> Result<String, String> {
+    let root = PathBuf::from(crate::direct_tools::project_root(args)?);
+    let file = args.get("file").and_then(|f| f.as_str()).map(str::trim).filter(|f| !f.is_empty());
+    let symbol = args.get("symbol").and_then(|s| s.as_str()).map(str::trim).filter(|s| !s.is_empty());
+    if file.is_none() && symbol.is_none() {
+        return Err("give a file, a symbol, or both".into());
+    }
+    let max = args.get("max_results").and_then(|m| m.as_u64()).map(|m| m as usize)
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .clamp(1, MAX_RESULTS);
+
+    let rel = match file {
+        Some(f) => {
+            let virtual_path = crate::direct_tools::virtual_path(&root, f)?;
+            let rel = virtual_path.trim_start_matches("/codebase").trim_start_matches('/').to_string();
+            if !root.join(&rel).is_file() {
+                return Err(format!("file not found: {}", f));
+            }
+            Some(rel)
+        }
+        None => None,
+    };
+    let label = match (&rel, symbol) {
+        (Some(r), Some(s)) => format!("{} in {}", s, r),
+        (Some(r), None) => r.clone(),
+        (None, Some(s)) => s.to_string(),
+        (None, None) => String::new(),
+    };
+
+    let search_root = root.clone();
+    let symbol = symbol.map(str::to_string);
+    let hits = tokio::task::spawn_blocking(move || {
+        let root = search_root;
+        let names = match (&symbol, &rel) {
+            (Some(s), _) => vec![s.clone()],
+            (None, Some(r)) => {
+                let path = root.join(r);
+                let source = std::fs::read_to_string(&path).unwrap_or_default();
+                let mut names: Vec<String> = outline::symbols(&path, &source)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|b| b.kind != "impl_item" && b.kind != "mod_item")
+                    .filter_map(|b| b.name)
+                    .filter(|n| n.len() >= 3)
+                    .collect();
+                names.dedup();
+                names.truncate(MAX_TARGET_NAMES);
+                names
+            }
+            (None, None) => Vec::new(),
+        };
+        let target = Target {
+            module: rel.as_deref().and_then(diff_scope::module_key),
+            language: rel.as_deref().and_then(|r| crate::lang::detect_language(Path::new(r))),
+            file: rel,
+            names,
+        };
+        find(&root, &target, max)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if hits.is_empty() {
+        return Ok(format!("No tests found for {}", label));
+    }
+    Ok(format!("Tests for {}:\n{}", label, render(&root, &hits)))
+}
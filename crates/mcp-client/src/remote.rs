@@ -0,0 +1,130 @@
+//! 远程仓库搜索
+//!
+//! project_path 为 git URL 时（可带 `#ref`），浅克隆到缓存目录后搜索，
+//! 结果以仓库相对路径 + ref 展示。缓存目录：$XDG_CACHE_HOME/windsurf-mcp/repos 或 ~/.cache/windsurf-mcp/repos。
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::process::Command;
+
+const GIT_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// 远程仓库 + 可选 ref
+#[derive(Debug, Clone)]
+pub struct RemoteSpec {
+    pub url: String,
+    pub git_ref: Option<String>,
+}
+
+/// 已检出的远程仓库
+#[derive(Debug, Clone)]
+pub struct Checkout {
+    pub dir: PathBuf,
+    pub commit: String,
+}
+
+/// 解析 `url[#ref]`，不是 git URL 时返回 None
+pub fn parse(project_path: &str) -> Option<RemoteSpec> {
+    let s = project_path.trim();
+    let is_url = ["https://", "http://", "ssh://", "git://", "file://", "git@"].iter().any(|p| s.starts_with(p));
+    if !is_url {
+        return None;
+    }
+    let (url, git_ref) = match s.split_once('#') {
+        Some((u, r)) if !r.is_empty() => (u, Some(r.to_string())),
+        Some((u, _)) => (u, None),
+        None => (s, None),
+    };
+    Some(RemoteSpec { url: url.to_string(), git_ref })
+}
+
+impl RemoteSpec {
+    /// 展示用标签：url@ref
+    pub fn label(&self) -> String {
+        match &self.git_ref {
+            Some(r) => format!("{}@{}", self.url, r),
+            None => self.url.clone(),
+        }
+    }
+
+    /// 缓存目录名：仓库名 + URL/ref 哈希
+    fn cache_dir(&self) -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+        let name: String = self.url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or("repo")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let mut h = DefaultHasher::new();
+        (&self.url, &self.git_ref).hash(&mut h);
+        Some(base.join("windsurf-mcp").join("repos").join(format!("{}-{:016x}", name, h.finish())))
+    }
+}
+
+/// 在 dir 下运行 git，返回去掉首尾空白的 stdout
+pub async fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let run = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .kill_on_drop(true)
+        .output();
+    let out = tokio::time::timeout(GIT_TIMEOUT, run)
+        .await
+        .map_err(|_| anyhow::anyhow!("git {} timed out", args.first().unwrap_or(&"")))??;
+    if !out.status.success() {
+        let err = String::from_utf8_lossy(&out.stderr);
+        anyhow::bail!("git {} failed: {}", args.first().unwrap_or(&""), err.trim());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// 允许克隆的 URL：https、ssh（含 scp 写法 git@host:path）、file。ext:: 等可执行命令的传输方式不接受
+const CLONE_SCHEMES: [&str; 4] = ["https://", "ssh://", "file://", "git@"];
+
+/// URL 和 ref 来自调用方，交给 git 之前检查：URL 只接受 CLONE_SCHEMES，ref 不能以 `-` 开头（会被当作选项，
+/// 如 --upload-pack）且须通过 `git check-ref-format --allow-onelevel`
+async fn validate(spec: &RemoteSpec) -> anyhow::Result<()> {
+    if !CLONE_SCHEMES.iter().any(|p| spec.url.starts_with(p)) || spec.url.chars().any(char::is_whitespace) {
+        anyhow::bail!("unsupported repository URL {} (expected https://, ssh://, git@host:path or file://)", spec.url);
+    }
+    if let Some(r) = &spec.git_ref {
+        crate::git::check_ref(r).map_err(|e| anyhow::anyhow!(e))?;
+        let valid = Command::new("git")
+            .args(["check-ref-format", "--allow-onelevel", r])
+            .kill_on_drop(true)
+            .status()
+            .await
+            .is_ok_and(|s| s.success());
+        if !valid {
+            anyhow::bail!("invalid git ref: {}", r);
+        }
+    }
+    Ok(())
+}
+
+/// 浅克隆（或更新已缓存的克隆）到指定 ref
+pub async fn checkout(spec: &RemoteSpec) -> anyhow::Result<Checkout> {
+    validate(spec).await?;
+    let dir = spec.cache_dir().ok_or_else(|| anyhow::anyhow!("no cache directory (HOME not set)"))?;
+    if !dir.join(".git").is_dir() {
+        std::fs::create_dir_all(&dir)?;
+        git(&dir, &["init", "-q"]).await?;
+        git(&dir, &["remote", "add", "origin", &spec.url]).await?;
+    }
+    let git_ref = spec.git_ref.as_deref().unwrap_or("HEAD");
+    git(&dir, &["fetch", "-q", "--depth", "1", "--end-of-options", "origin", git_ref]).await?;
+    git(&dir, &["checkout", "-q", "-f", "--detach", "FETCH_HEAD"]).await?;
+    let commit = git(&dir, &["rev-parse", "--short", "HEAD"]).await?;
+    Ok(Checkout { dir, commit })
+}
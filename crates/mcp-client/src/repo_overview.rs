@@ -0,0 +1,77 @@
+//! repo_overview 工具：直接返回 repo map
+//!
+//! 与 fast_context_search 交给模型的 repo map 相同（目录树或扁平文件列表，超大仓库为抽样结果），
+//! 不调用 AI，供 agent 先了解项目结构。
+
+use serde_json::{json, Value};
+
+use crate::config::{self, MapFormat, SearchLimits};
+use crate::i18n::Locale;
+
+/// 工具定义；默认值取自当前配置
+pub fn definition(l: Locale, limits: &SearchLimits) -> Value {
+    json!({
+        "name": "repo_overview",
+        "description": l.pick(
+            "Return the project's repo map (directory tree or flat file list) without running an AI search, to get oriented in a codebase.",
+            "不经 AI 搜索，直接返回项目的 repo map（目录树或扁平文件列表），用于了解代码库结构。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                },
+                "depth": {
+                    "type": "integer",
+                    "description": l.pick("Directory depth (1-6, default {})", "目录深度（1-6，默认 {}）").replace("{}", &limits.tree_depth.to_string()),
+                    "default": limits.tree_depth,
+                    "minimum": 1,
+                    "maximum": 6
+                },
+                "format": {
+                    "type": "string",
+                    "description": l.pick("tree = directory tree, files = flat gitignore-aware file list with size buckets", "tree = 目录树，files = 遵守 gitignore 的扁平文件列表并附大小分级"),
+                    "enum": ["tree", "files"],
+                    "default": limits.map_format.as_str()
+                },
+                "exclude": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": l.pick("File or directory names to leave out, e.g. [\"docs\", \"*.lock\"] (hidden entries and node_modules/target/... are always skipped)", "要省略的文件或目录名，如 [\"docs\", \"*.lock\"]（隐藏条目和 node_modules/target 等始终跳过）")
+                },
+                "include_submodules": {
+                    "type": "boolean",
+                    "description": l.pick("Descend into initialized git submodules (default false)", "是否进入已初始化的 git 子模块（默认 false）"),
+                    "default": false
+                }
+            }
+        }
+    })
+}
+
+/// 执行 repo_overview
+pub fn call(args: &Value, config: &config::Config) -> Result<String, String> {
+    let root = crate::direct_tools::project_root(args)?;
+    let limits = config.limits(None)?;
+    let depth = match args.get("depth").and_then(|d| d.as_u64()) {
+        Some(d) if (1..=6).contains(&d) => d as u32,
+        Some(d) => return Err(format!("depth must be between 1 and 6, got {}", d)),
+        None => limits.tree_depth,
+    };
+    let format = match args.get("format").and_then(|f| f.as_str()).filter(|f| !f.is_empty()) {
+        Some(f) => MapFormat::parse(f).ok_or_else(|| format!("invalid format: {} (expected tree or files)", f))?,
+        None => limits.map_format,
+    };
+    let exclude: Vec<String> = args
+        .get("exclude")
+        .and_then(|e| e.as_array())
+        .map(|a| a.iter().filter_map(|p| p.as_str()).filter(|p| !p.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default();
+    let include_submodules = args.get("include_submodules").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let (title, map) = crate::build_repo_map(&root, depth, format, include_submodules, config.sample_threshold(), &exclude);
+    Ok(format!("Repo Map ({}) of {}:\n{}", title, root, map))
+}
@@ -0,0 +1,170 @@
+//! 超大仓库的抽样 repo map
+//!
+//! 文件数超过 `map_sample_threshold`（默认 20000）时不再生成完整目录树（会被 2000 行上限截断、
+//! 隐藏大部分目录），改为：顶层目录完整列出，更深层每个目录只列出部分条目并附文件计数，
+//! 末尾列出最大的和最近修改的文件。标题中注明是抽样结果。
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::executor::{self, Submodules};
+
+pub const DEFAULT_THRESHOLD: usize = 20_000;
+/// 全量遍历的文件数上限，超出后只计数
+const MAX_INDEXED: usize = 500_000;
+/// 第二层起每个目录展示的子目录/文件数
+const SAMPLE_DIRS: usize = 6;
+const SAMPLE_FILES: usize = 4;
+/// 最大 / 最近修改文件各列出多少个
+const HIGHLIGHTS: usize = 15;
+
+#[derive(Default)]
+struct Dir {
+    dirs: BTreeMap<String, Dir>,
+    files: Vec<String>,
+    /// 含子目录的文件总数
+    total: usize,
+}
+
+struct FileInfo {
+    rel: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// 计数到 limit 为止（提前退出），用于判断是否需要抽样
+pub fn count_files(root: &Path, subs: &Submodules, limit: usize, exclude: &[String]) -> usize {
+    let mut n = 0;
+    for entry in executor::walk_builder(root, exclude, Some(subs)).build().flatten() {
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            n += 1;
+            if n > limit {
+                break;
+            }
+        }
+    }
+    n
+}
+
+/// 遍历 root（遵守忽略规则）建立目录树，`files` 收集前 MAX_INDEXED 个文件的大小和修改时间
+fn index(root: &Path, tree: &mut Dir, subs: &Submodules, exclude: &[String], files: &mut Vec<FileInfo>) {
+    for entry in executor::walk_builder(root, exclude, Some(subs)).build().flatten() {
+        let Ok(rel) = entry.path().strip_prefix(root) else { continue };
+        let Some(t) = entry.file_type().filter(|t| t.is_dir() || t.is_file()) else { continue };
+        let names: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+        let Some((name, parents)) = names.split_last() else { continue };
+        // 目录逐级创建；文件计入所有上级目录
+        let mut node = &mut *tree;
+        for p in parents {
+            if t.is_file() {
+                node.total += 1;
+            }
+            node = node.dirs.entry(p.clone()).or_default();
+        }
+        if t.is_dir() {
+            node.dirs.entry(name.clone()).or_default();
+            continue;
+        }
+        node.total += 1;
+        if files.len() < MAX_INDEXED {
+            let meta = entry.metadata().ok();
+            files.push(FileInfo {
+                rel: names.join("/"),
+                size: meta.as_ref().map(|m| m.len()).unwrap_or(0),
+                modified: meta.and_then(|m| m.modified().ok()).unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+            node.files.push(name.clone());
+        }
+    }
+}
+
+fn render(node: &Dir, prefix: &str, depth: usize, max_depth: usize, lines: &mut Vec<String>) {
+    if depth >= max_depth {
+        return;
+    }
+    // 顶层完整列出；更深层取文件最多的几个子目录 + 前几个文件
+    let (dirs, files): (Vec<(&String, &Dir)>, &[String]) = if depth == 0 {
+        (node.dirs.iter().collect(), &node.files)
+    } else {
+        let mut dirs: Vec<_> = node.dirs.iter().collect();
+        dirs.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+        dirs.truncate(SAMPLE_DIRS);
+        dirs.sort_by(|a, b| a.0.cmp(b.0));
+        (dirs, &node.files[..node.files.len().min(SAMPLE_FILES)])
+    };
+    let hidden_dirs = node.dirs.len() - dirs.len();
+    let hidden_files = node.files.len() - files.len();
+    let more = (hidden_dirs + hidden_files > 0).then(|| {
+        let hidden_total: usize = node.dirs.values().map(|d| d.total).sum::<usize>() - dirs.iter().map(|(_, d)| d.total).sum::<usize>();
+        let mut parts = Vec::new();
+        if hidden_dirs > 0 {
+            parts.push(format!("{} more dirs with {} files", hidden_dirs, hidden_total));
+        }
+        if hidden_files > 0 {
+            parts.push(format!("{} more files", hidden_files));
+        }
+        format!("... ({})", parts.join(", "))
+    });
+
+    let count = dirs.len() + files.len() + usize::from(more.is_some());
+    let mut i = 0;
+    let mut push = |text: String, lines: &mut Vec<String>| -> String {
+        i += 1;
+        let last = i == count;
+        lines.push(format!("{}{}{}", prefix, if last { "└── " } else { "├── " }, text));
+        format!("{}{}", prefix, if last { "    " } else { "│   " })
+    };
+    for (name, child) in dirs {
+        let child_prefix = push(format!("{}/ ({} files)", name, child.total), lines);
+        render(child, &child_prefix, depth + 1, max_depth, lines);
+    }
+    for name in files {
+        push(name.clone(), lines);
+    }
+    if let Some(more) = more {
+        push(more, lines);
+    }
+}
+
+pub fn human_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{}B", bytes),
+        1024..=1_048_575 => format!("{:.1}KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1}MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// 文件数超过 threshold 时返回 (文件总数, 抽样 map)，否则 None；exclude 中的名称 glob 不计入也不展示
+pub fn sampled_map(project_root: &str, max_depth: u32, include_submodules: bool, threshold: usize, exclude: &[String]) -> Option<(usize, String)> {
+    let root = PathBuf::from(project_root);
+    let subs = Submodules::discover(&root, include_submodules);
+    if count_files(&root, &subs, threshold, exclude) <= threshold {
+        return None;
+    }
+
+    let mut tree = Dir::default();
+    let mut files = Vec::new();
+    index(&root, &mut tree, &subs, exclude, &mut files);
+
+    let mut lines = vec![
+        format!(
+            "NOTE: this repository has {} files, so this map is a SAMPLE: top-level entries are complete, deeper directories list only their largest subdirectories and first few files. Directory counts are exact; use find/rg/tree to explore what is not shown.",
+            tree.total
+        ),
+        "/codebase".to_string(),
+    ];
+    render(&tree, "", 0, max_depth.max(1) as usize, &mut lines);
+
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.rel.cmp(&b.rel)));
+    lines.push(String::new());
+    lines.push("Largest files:".into());
+    lines.extend(files.iter().take(HIGHLIGHTS).map(|f| format!("  /codebase/{} ({})", f.rel, human_size(f.size))));
+
+    files.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.rel.cmp(&b.rel)));
+    lines.push(String::new());
+    lines.push("Recently modified files:".into());
+    lines.extend(files.iter().take(HIGHLIGHTS).map(|f| format!("  /codebase/{}", f.rel)));
+
+    Some((tree.total, lines.join("\n")))
+}
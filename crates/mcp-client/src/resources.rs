@@ -0,0 +1,287 @@
+//! MCP resources：仓库目录树 (repo map) 与搜索发现的文件
+//!
+//! 支持 resources/list、resources/read、resources/subscribe。
+//! 订阅后定期重新生成目录树，结构变化时发送 notifications/resources/updated。
+//! 搜索结果中的文件以 `file:///` URI 列出，只有这些文件可以通过 resources/read 读取。
+//! 发现列表属于会话（`session::Session::discovered`），一个客户端的搜索不会出现在另一个客户端的列表里。
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+pub const REPO_MAP_SCHEME: &str = "repomap://";
+const REPO_MAP_DEPTH: u32 = 3;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const FILE_SCHEME: &str = "file://";
+/// 只保留最近发现的文件
+const MAX_DISCOVERED: usize = 200;
+/// resources/read 单个文件上限
+const MAX_READ_BYTES: u64 = 2 * 1024 * 1024;
+
+/// 会话内搜索发现的文件（连同所属项目根目录）；generation 在列表每次变化时加一，用于判断是否需要发送 list_changed
+#[derive(Default)]
+pub struct Discovered(Mutex<(VecDeque<Found>, u64)>);
+
+/// 规范化后的项目根目录和文件路径
+#[derive(Clone, PartialEq)]
+struct Found {
+    root: PathBuf,
+    path: PathBuf,
+}
+
+/// 解析符号链接后仍在 root 内、且不被 deny_paths 禁止的文件
+fn admissible(root: &Path, path: &Path) -> Option<Found> {
+    let root = root.canonicalize().ok()?;
+    let path = path.canonicalize().ok().filter(|p| p.is_file() && p.starts_with(&root))?;
+    (!crate::deny_paths::get().denies(&root, &path)).then_some(Found { root, path })
+}
+
+impl Discovered {
+    /// 记录搜索结果中 root 下的文件；项目外或被禁止的路径忽略
+    pub fn remember(&self, root: &Path, path: &Path) {
+        let Some(found) = admissible(root, path) else { return };
+        let (list, generation) = &mut *self.0.lock().unwrap();
+        if let Some(i) = list.iter().position(|f| f.path == found.path) {
+            list.remove(i);
+        } else {
+            *generation += 1;
+            if list.len() >= MAX_DISCOVERED {
+                list.pop_front();
+            }
+        }
+        list.push_back(found);
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.0.lock().unwrap().1
+    }
+
+    /// 已记录的文件；读取前按当前文件系统和禁止列表重新检查
+    fn lookup(&self, path: &Path) -> Option<Found> {
+        let root = self.0.lock().unwrap().0.iter().find(|f| f.path == path)?.root.clone();
+        admissible(&root, path)
+    }
+
+    /// 最近发现的排在前面
+    fn recent_first(&self) -> Vec<PathBuf> {
+        self.0.lock().unwrap().0.iter().rev().map(|f| f.path.clone()).collect()
+    }
+}
+
+/// resources/list 变化通知
+pub fn list_changed() -> Value {
+    json!({ "jsonrpc": "2.0", "method": "notifications/resources/list_changed" })
+}
+
+/// 绝对路径 → file:// URI（非 URL 安全字符百分号编码）
+pub fn file_uri(path: &Path) -> String {
+    let mut uri = String::from(FILE_SCHEME);
+    let path = path.to_string_lossy().replace('\\', "/");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~:".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+/// file:// URI → 路径
+pub fn path_of(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix(FILE_SCHEME)?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut it = encoded.bytes();
+    while let Some(b) = it.next() {
+        if b == b'%' {
+            let hex = [it.next()?, it.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // Windows: file:///C:/x → C:/x
+    let path = match path.strip_prefix('/') {
+        Some(p) if p.as_bytes().get(1) == Some(&b':') => p.to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+fn read_discovered(uri: &str, discovered: &Discovered) -> Result<Value, String> {
+    let not_found = || format!("Resource not found: {}", uri);
+    let path = path_of(uri).ok_or_else(not_found)?;
+    let path = path.canonicalize().map_err(|_| not_found())?;
+    let path = discovered.lookup(&path).ok_or_else(not_found)?.path;
+    let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    if size > MAX_READ_BYTES {
+        return Err(format!("Resource too large: {} ({} bytes, max {})", uri, size, MAX_READ_BYTES));
+    }
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", uri, e))?;
+    Ok(json!({
+        "contents": [{ "uri": uri, "mimeType": "text/plain", "text": text }]
+    }))
+}
+
+/// 项目根目录 → repo map 资源 URI
+pub fn repo_map_uri(project_root: &str) -> String {
+    format!("{}{}", REPO_MAP_SCHEME, project_root)
+}
+
+fn root_of(uri: &str) -> Option<&str> {
+    uri.strip_prefix(REPO_MAP_SCHEME).filter(|r| std::path::Path::new(r).is_dir())
+}
+
+fn render(project_root: &str) -> String {
+    crate::generate_repo_map(project_root, REPO_MAP_DEPTH, false, &[])
+}
+
+fn fingerprint(map: &str) -> u64 {
+    let mut h = DefaultHasher::new();
+    map.hash(&mut h);
+    h.finish()
+}
+
+/// resources/list 结果
+pub fn list(project_root: &str, discovered: &Discovered) -> Value {
+    let mut resources = vec![json!({
+        "uri": repo_map_uri(project_root),
+        "name": "Repository map",
+        "description": format!("Directory tree of {} (depth {})", project_root, REPO_MAP_DEPTH),
+        "mimeType": "text/plain"
+    })];
+    resources.extend(discovered.recent_first().iter().map(|path| {
+        json!({
+            "uri": file_uri(path),
+            "name": path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            "description": format!("Found by search: {}", path.display()),
+            "mimeType": "text/plain"
+        })
+    }));
+    json!({ "resources": resources })
+}
+
+/// resources/read 结果
+pub fn read(uri: &str, discovered: &Discovered) -> Result<Value, String> {
+    if uri.starts_with(FILE_SCHEME) {
+        return read_discovered(uri, discovered);
+    }
+    let root = root_of(uri).ok_or_else(|| format!("Resource not found: {}", uri))?;
+    Ok(json!({
+        "contents": [{ "uri": uri, "mimeType": "text/plain", "text": render(root) }]
+    }))
+}
+
+/// 已订阅资源 URI → 最近一次目录树指纹
+#[derive(Clone, Default)]
+pub struct Subscriptions(Arc<Mutex<HashMap<String, u64>>>);
+
+impl Subscriptions {
+    pub fn subscribe(&self, uri: &str) -> Result<(), String> {
+        let root = root_of(uri).ok_or_else(|| format!("Resource not found: {}", uri))?;
+        let fp = fingerprint(&render(root));
+        self.0.lock().unwrap().insert(uri.to_string(), fp);
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, uri: &str) {
+        self.0.lock().unwrap().remove(uri);
+    }
+
+    fn snapshot(&self) -> Vec<(String, u64)> {
+        self.0.lock().unwrap().iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+
+    fn update(&self, uri: &str, fp: u64) -> bool {
+        match self.0.lock().unwrap().get_mut(uri) {
+            Some(old) if *old != fp => { *old = fp; true }
+            _ => false,
+        }
+    }
+}
+
+/// 轮询已订阅的目录树，变化时通过 `out` 发送通知
+pub fn spawn_watcher(subs: Subscriptions, out: mpsc::UnboundedSender<String>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            for (uri, _) in subs.snapshot() {
+                let Some(root) = root_of(&uri).map(str::to_string) else { continue };
+                let fp = match tokio::task::spawn_blocking(move || fingerprint(&render(&root))).await {
+                    Ok(fp) => fp,
+                    Err(_) => continue,
+                };
+                if subs.update(&uri, fp) {
+                    let note = json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/resources/updated",
+                        "params": { "uri": uri }
+                    });
+                    if out.send(note.to_string()).is_err() { return; }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// project/{src/a.rs, denied-by-test/key, out -> ../outside} 和 outside/secret.txt
+    fn fixture(name: &str) -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("windsurf-mcp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let root = base.join("project");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join(crate::deny_paths::TEST_DENIED)).unwrap();
+        std::fs::create_dir_all(base.join("outside")).unwrap();
+        std::fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(root.join(crate::deny_paths::TEST_DENIED).join("key"), "k\n").unwrap();
+        std::fs::write(base.join("outside/secret.txt"), "s\n").unwrap();
+        std::os::unix::fs::symlink(base.join("outside"), root.join("out")).unwrap();
+        (base.canonicalize().unwrap(), root.canonicalize().unwrap())
+    }
+
+    #[test]
+    fn only_files_inside_the_root_and_not_denied_are_listed() {
+        let (base, root) = fixture("resources-list");
+        let found = Discovered::default();
+        found.remember(&root, &root.join("src/a.rs"));
+        found.remember(&root, &base.join("outside/secret.txt"));
+        found.remember(&root, &root.join("out/secret.txt"));
+        found.remember(&root, &root.join("src/../../outside/secret.txt"));
+        found.remember(&root, &root.join(crate::deny_paths::TEST_DENIED).join("key"));
+        assert_eq!(found.recent_first(), [root.join("src/a.rs")]);
+        assert_eq!(found.generation(), 1);
+
+        let uri = file_uri(&root.join("src/a.rs"));
+        assert_eq!(read(&uri, &found).unwrap()["contents"][0]["text"], "fn a() {}\n");
+        assert!(read(&file_uri(&base.join("outside/secret.txt")), &found).is_err());
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn read_rechecks_the_file() {
+        let (base, root) = fixture("resources-read");
+        let found = Discovered::default();
+        let file = root.join("src/a.rs");
+        found.remember(&root, &file);
+        // 记录之后变成指向项目外的符号链接：规范化后的路径不再是记录的文件
+        std::fs::remove_file(&file).unwrap();
+        std::os::unix::fs::symlink(base.join("outside/secret.txt"), &file).unwrap();
+        assert!(read(&file_uri(&file), &found).is_err());
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
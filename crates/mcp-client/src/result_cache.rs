@@ -0,0 +1,68 @@
+//! 执行器命令结果缓存
+//!
+//! 模型经常在后续轮次重复读取同一文件、列出同一目录。同一次搜索内（执行器共享状态中）按命令参数缓存
+//! readfile / head / tail / readmatch / enclosing / imports / stat / ls 的结果，最近最少使用的先淘汰。
+//! 取用时比对目标文件或目录的修改时间和大小，变化后重新执行；目录只反映直接子项的增删，
+//! 所以 tree（遍历多层子目录）不缓存。
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// 最多缓存的结果数
+const MAX_ENTRIES: usize = 128;
+/// 缓存结果的总字节数上限
+const MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// 可以缓存的命令
+pub const CACHEABLE: &[&str] = &["readfile", "head", "tail", "readmatch", "enclosing", "imports", "stat", "ls"];
+
+/// 目标的修改时间和大小
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamp(SystemTime, u64);
+
+impl Stamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(Self(meta.modified().ok()?, meta.len()))
+    }
+}
+
+#[derive(Default)]
+pub struct ResultCache {
+    /// (命令参数, 目标状态, 结果)，最近使用的在后
+    entries: VecDeque<(String, Stamp, String)>,
+    bytes: usize,
+}
+
+impl ResultCache {
+    /// 目标未变化时返回缓存的结果
+    pub fn get(&mut self, key: &str, stamp: Stamp) -> Option<String> {
+        let i = self.entries.iter().position(|(k, _, _)| k == key)?;
+        let entry = self.entries.remove(i)?;
+        if entry.1 != stamp {
+            self.bytes -= entry.2.len();
+            return None;
+        }
+        let output = entry.2.clone();
+        self.entries.push_back(entry);
+        Some(output)
+    }
+
+    pub fn put(&mut self, key: String, stamp: Stamp, output: String) {
+        if output.len() > MAX_BYTES / 4 {
+            return;
+        }
+        if let Some(i) = self.entries.iter().position(|(k, _, _)| *k == key) {
+            if let Some((_, _, old)) = self.entries.remove(i) {
+                self.bytes -= old.len();
+            }
+        }
+        self.bytes += output.len();
+        self.entries.push_back((key, stamp, output));
+        while self.entries.len() > MAX_ENTRIES || self.bytes > MAX_BYTES {
+            let Some((_, _, old)) = self.entries.pop_front() else { break };
+            self.bytes -= old.len();
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! 下载 ripgrep：`doctor --install-rg`
+//!
+//! 从 ripgrep 的 GitHub release 下载当前平台的预编译包（固定版本 RG_VERSION），按内置的 SHA-256（RG_SHA256）校验后
+//! 解出 rg 可执行文件，放到 $XDG_CACHE_HOME/windsurf-mcp/ripgrep-<版本>/（Windows 为 %LOCALAPPDATA%）。
+//! 系统 PATH 中没有 rg 时 ripgrep::caps 使用这里的副本。
+
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use sha2::{Digest, Sha256};
+
+/// 下载的 ripgrep 版本；升级时同时更新 RG_SHA256
+pub const RG_VERSION: &str = "14.1.1";
+/// RG_VERSION 各 release 目标压缩包的 SHA-256（取自 release 的 .sha256 文件，随代码一起审阅，不在安装时下载）。
+/// 没有条目的目标拒绝安装
+const RG_SHA256: &[(&str, &str)] = &[];
+const RELEASE_URL: &str = "https://github.com/BurntSushi/ripgrep/releases/download";
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+const EXE: &str = if cfg!(windows) { "rg.exe" } else { "rg" };
+
+/// 当前平台的 release 目标；没有预编译包时为 None
+fn target() -> Option<&'static str> {
+    Some(match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => "x86_64-unknown-linux-musl",
+        ("linux", "aarch64") => "aarch64-unknown-linux-gnu",
+        ("linux", "x86") => "i686-unknown-linux-gnu",
+        ("linux", "arm") => "arm-unknown-linux-gnueabihf",
+        ("macos", "x86_64") => "x86_64-apple-darwin",
+        ("macos", "aarch64") => "aarch64-apple-darwin",
+        ("windows", "x86_64") => "x86_64-pc-windows-msvc",
+        ("windows", "x86") => "i686-pc-windows-msvc",
+        ("windows", "aarch64") => "aarch64-pc-windows-msvc",
+        _ => return None,
+    })
+}
+
+/// 安装目录
+fn install_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").filter(|_| cfg!(windows)).map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(base.join("windsurf-mcp").join(format!("ripgrep-{}", RG_VERSION)))
+}
+
+/// 已下载的 rg
+pub fn cached_binary() -> Option<PathBuf> {
+    install_dir().map(|d| d.join(EXE)).filter(|p| p.is_file())
+}
+
+/// 从压缩包中取出 rg 可执行文件
+fn extract(archive: &[u8], zipped: bool) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if zipped {
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive))?;
+        let name = zip.file_names().find(|n| n.rsplit('/').next() == Some(EXE)).map(str::to_string)
+            .ok_or_else(|| anyhow!("{} not found in the archive", EXE))?;
+        zip.by_name(&name)?.read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name().is_some_and(|n| n == EXE) {
+            entry.read_to_end(&mut data)?;
+            return Ok(data);
+        }
+    }
+    bail!("{} not found in the archive", EXE)
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> anyhow::Result<bytes::Bytes> {
+    let resp = client.get(url).send().await.with_context(|| format!("GET {}", url))?;
+    if !resp.status().is_success() {
+        bail!("GET {}: HTTP {}", url, resp.status());
+    }
+    Ok(resp.bytes().await?)
+}
+
+/// 下载并安装 rg，返回可执行文件路径
+pub async fn install() -> anyhow::Result<PathBuf> {
+    let target = target().ok_or_else(|| {
+        anyhow!("no prebuilt ripgrep for {}-{}; install it with your package manager", std::env::consts::OS, std::env::consts::ARCH)
+    })?;
+    let dir = install_dir().ok_or_else(|| anyhow!("cannot determine a cache directory (set XDG_CACHE_HOME)"))?;
+    let expected = RG_SHA256.iter().find(|(t, _)| *t == target).map(|(_, sum)| *sum).ok_or_else(|| {
+        anyhow!("no pinned checksum for ripgrep {} ({}); install it with your package manager", RG_VERSION, target)
+    })?;
+    let zipped = target.contains("windows");
+    let name = format!("ripgrep-{}-{}.{}", RG_VERSION, target, if zipped { "zip" } else { "tar.gz" });
+    let url = format!("{}/{}/{}", RELEASE_URL, RG_VERSION, name);
+
+    let client = reqwest::Client::builder().timeout(DOWNLOAD_TIMEOUT).build()?;
+    let archive = download(&client, &url).await?;
+    let actual: String = Sha256::digest(&archive).iter().map(|b| format!("{:02x}", b)).collect();
+    if actual != expected {
+        bail!("checksum mismatch for {}: expected {}, got {}", name, expected, actual);
+    }
+
+    let binary = tokio::task::spawn_blocking(move || extract(&archive, zipped)).await??;
+    std::fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+    // 先写临时文件再改名，中断时不会留下半个可执行文件
+    let tmp = dir.join(format!("{}.partial", EXE));
+    std::fs::write(&tmp, &binary).with_context(|| format!("write {}", tmp.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o755))?;
+    }
+    let path = dir.join(EXE);
+    std::fs::rename(&tmp, &path).with_context(|| format!("rename to {}", path.display()))?;
+    Ok(path)
+}
@@ -0,0 +1,169 @@
+//! rg 搜索结果：解析 `rg --json`，按文件去重、限额后渲染为文本
+//!
+//! rg 和内置搜索（search_fallback）都产出 RgLine 列表，由 render 统一处理：同一行只保留一次（匹配优先于上下文），
+//! 行数上限按文件公平分配，超出的匹配在该文件末尾注明数量；文件多到放不下时后面的文件只列匹配数，
+//! 再往后的汇总为一行。输出格式与 `rg --no-heading -n` 一致。
+
+use std::collections::btree_map::{BTreeMap, Entry};
+
+use base64::Engine;
+use serde_json::Value;
+
+/// 结果中的一行
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgLine {
+    /// 文件的真实路径
+    pub path: String,
+    /// 行号（1 起）
+    pub line: usize,
+    /// 行内容（不含换行）
+    pub text: String,
+    /// 匹配行；false 为上下文行
+    pub is_match: bool,
+    /// 从本行开始的第一个匹配：(列号（1 起，按字节），匹配文本)
+    pub submatch: Option<(usize, String)>,
+}
+
+/// rg --json 的 {"text": ...} / {"bytes": base64}
+fn arbitrary_data(v: &Value) -> Option<String> {
+    if let Some(text) = v.get("text").and_then(|t| t.as_str()) {
+        return Some(text.to_string());
+    }
+    let bytes = base64::engine::general_purpose::STANDARD.decode(v.get("bytes")?.as_str()?).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// 解析 rg --json 的输出：match 与 context 记录，跨行匹配拆成多行
+pub fn parse(stdout: &str) -> Vec<RgLine> {
+    let mut out = Vec::new();
+    for record in stdout.lines().filter_map(|l| serde_json::from_str::<Value>(l).ok()) {
+        let is_match = match record.get("type").and_then(|t| t.as_str()) {
+            Some("match") => true,
+            Some("context") => false,
+            _ => continue,
+        };
+        let data = &record["data"];
+        let (Some(path), Some(first), Some(lines)) = (
+            arbitrary_data(&data["path"]),
+            data.get("line_number").and_then(|n| n.as_u64()),
+            arbitrary_data(&data["lines"]),
+        ) else {
+            continue;
+        };
+        // 子匹配的偏移相对于 lines 的开头
+        let submatch = data.get("submatches").and_then(|s| s.as_array()).and_then(|s| s.first()).and_then(|m| {
+            Some((m.get("start")?.as_u64()? as usize, arbitrary_data(&m["match"])?))
+        });
+        let mut offset = 0;
+        for (i, raw) in lines.split_inclusive('\n').enumerate() {
+            let end = offset + raw.len();
+            let hit = submatch.as_ref().filter(|(start, _)| is_match && (offset..end).contains(start));
+            out.push(RgLine {
+                path: path.clone(),
+                line: first as usize + i,
+                text: raw.trim_end_matches(['\n', '\r']).to_string(),
+                is_match,
+                submatch: hit.map(|(start, m)| (start - offset + 1, m.clone())),
+            });
+            offset = end;
+        }
+    }
+    out
+}
+
+/// 渲染结果
+pub struct Rendered {
+    pub text: String,
+    /// 输出中保留的带子匹配的行
+    pub matches: Vec<RgLine>,
+}
+
+/// 去重后按 rg 格式渲染，最多约 max_lines 行。`context` 为 true 时不相邻的片段之间用 `--` 分隔
+pub fn render(lines: Vec<RgLine>, context: bool, max_lines: usize) -> Rendered {
+    // 按文件分组（保持首次出现的顺序），同一行匹配优先
+    let mut files: Vec<(String, BTreeMap<usize, RgLine>)> = Vec::new();
+    for l in lines {
+        let idx = match files.iter().position(|(p, _)| *p == l.path) {
+            Some(i) => i,
+            None => {
+                files.push((l.path.clone(), BTreeMap::new()));
+                files.len() - 1
+            }
+        };
+        match files[idx].1.entry(l.line) {
+            Entry::Occupied(mut e) if !e.get().is_match && l.is_match => {
+                e.insert(l);
+            }
+            Entry::Occupied(_) => {}
+            Entry::Vacant(e) => {
+                e.insert(l);
+            }
+        }
+    }
+
+    // 文件太多、每个文件连一行匹配加一行说明都放不下时：前 detail 个文件照常列出匹配，
+    // 之后 listed 个文件只列匹配数，其余文件汇总成一行，模型仍能知道命中分布在哪里
+    let sizes: Vec<usize> = files.iter().map(|(_, ls)| ls.len()).collect();
+    let fits = sizes.iter().sum::<usize>() <= max_lines || files.len() * 2 <= max_lines;
+    let (detail, listed) = if fits {
+        (files.len(), 0)
+    } else {
+        let detail = max_lines / 4;
+        (detail, (max_lines - 2 * detail).saturating_sub(1).min(files.len() - detail))
+    };
+    let rest = files.split_off((detail + listed).min(files.len()));
+    let counted = files.split_off(detail);
+
+    // 每个文件可输出的行数；需要截断时给每个文件的说明行留出位置
+    let room = max_lines.saturating_sub(listed + usize::from(!rest.is_empty()));
+    let sizes = &sizes[..detail];
+    let budget = if sizes.iter().sum::<usize>() <= room { room } else { room.saturating_sub(detail) };
+    let shares = crate::executor::fair_shares(sizes, budget.max(detail));
+
+    let mut out: Vec<String> = Vec::new();
+    let mut matches = Vec::new();
+    for ((path, ls), share) in files.into_iter().zip(shares) {
+        let total = ls.values().filter(|l| l.is_match).count();
+        let mut shown = 0;
+        let mut last: Option<usize> = None;
+        for l in ls.into_values().take(share.max(1)) {
+            if context && last.is_none_or(|n| l.line > n + 1) && !out.is_empty() {
+                out.push("--".into());
+            }
+            let sep = if l.is_match { ':' } else { '-' };
+            out.push(format!("{}{}{}{}{}", path, sep, l.line, sep, l.text));
+            last = Some(l.line);
+            if l.is_match {
+                shown += 1;
+                if l.submatch.is_some() {
+                    matches.push(l);
+                }
+            }
+        }
+        if total > shown {
+            out.push(format!("(+{} more matches in {})", total - shown, path));
+        }
+    }
+    let match_count = |ls: &BTreeMap<usize, RgLine>| ls.values().filter(|l| l.is_match).count();
+    if !counted.is_empty() && context {
+        out.push("--".into());
+    }
+    for (path, ls) in &counted {
+        out.push(format!("{} ({})", path, plural(match_count(ls), "match")));
+    }
+    if !rest.is_empty() {
+        let total: usize = rest.iter().map(|(_, ls)| match_count(ls)).sum();
+        out.push(format!("(+{} in {})", plural(total, "more match"), plural(rest.len(), "more file")));
+    }
+    Rendered { text: out.join("\n"), matches }
+}
+
+/// "1 match" / "3 matches"
+pub fn plural(n: usize, word: &str) -> String {
+    let suffix = match (n, word.ends_with("ch")) {
+        (1, _) => "",
+        (_, true) => "es",
+        _ => "s",
+    };
+    format!("{} {}{}", n, word, suffix)
+}
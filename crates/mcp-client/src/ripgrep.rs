@@ -0,0 +1,134 @@
+//! ripgrep 能力探测
+//!
+//! 在 PATH 中查找 rg，找不到时使用 `doctor --install-rg` 下载的副本。
+//! 首次使用时运行 `rg --version` / `rg --pcre2-version`，缓存路径、版本和可用特性（PCRE2、多行），
+//! 执行器据此调整参数；未安装或版本低于 MIN_VERSION 时 rg / files 命令改用内置搜索（search_fallback）。
+
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// 需要 --sort、--json 与 -U (multiline)
+pub const MIN_VERSION: (u32, u32, u32) = (0, 10, 0);
+
+#[derive(Debug, Clone)]
+pub struct Caps {
+    pub path: String,
+    /// None = 未找到或无法运行
+    pub version: Option<(u32, u32, u32)>,
+    pub pcre2: bool,
+    pub multiline: bool,
+}
+
+static CAPS: OnceLock<Caps> = OnceLock::new();
+
+fn locate() -> String {
+    // 优先使用系统 rg
+    if let Ok(output) = Command::new("which").arg("rg").output() {
+        if output.status.success() {
+            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if !path.is_empty() { return path; }
+        }
+    }
+    // Windows
+    if let Ok(output) = Command::new("where").arg("rg").output() {
+        if output.status.success() {
+            let path = String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("rg").trim().to_string();
+            if !path.is_empty() { return path; }
+        }
+    }
+    // doctor --install-rg 下载的副本
+    if let Some(path) = crate::rg_install::cached_binary() {
+        return path.to_string_lossy().into_owned();
+    }
+    "rg".into()
+}
+
+/// "ripgrep 13.0.0 (rev ...)" → (13, 0, 0)
+fn parse_version(text: &str) -> Option<(u32, u32, u32)> {
+    let first = text.lines().next()?;
+    let ver = first.strip_prefix("ripgrep ")?.split_whitespace().next()?;
+    let mut nums = ver.split(['.', '-']).map(|n| n.parse::<u32>().ok());
+    Some((nums.next()??, nums.next().flatten().unwrap_or(0), nums.next().flatten().unwrap_or(0)))
+}
+
+/// 探测一次（不缓存）
+pub fn probe() -> Caps {
+    let path = locate();
+    let version = Command::new(&path)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| parse_version(&String::from_utf8_lossy(&o.stdout)));
+    // --pcre2-version 从 11.0 起存在，未编译 PCRE2 时退出码非 0
+    let pcre2 = version.is_some_and(|v| v >= (11, 0, 0))
+        && Command::new(&path).arg("--pcre2-version").output().is_ok_and(|o| o.status.success());
+    let at_least = |min: (u32, u32, u32)| version.is_some_and(|v| v >= min);
+    Caps { path, version, pcre2, multiline: at_least((0, 10, 0)) }
+}
+
+/// 探测结果（进程内缓存）
+pub fn caps() -> &'static Caps {
+    CAPS.get_or_init(probe)
+}
+
+fn fmt_version((a, b, c): (u32, u32, u32)) -> String {
+    format!("{}.{}.{}", a, b, c)
+}
+
+impl Caps {
+    /// 不可用原因；None = 可用
+    pub fn unusable(&self) -> Option<String> {
+        match self.version {
+            None => Some(format!("ripgrep (rg) not found or not runnable ({})", self.path)),
+            Some(v) if v < MIN_VERSION => Some(format!(
+                "ripgrep {} at {} is too old (need >= {})",
+                fmt_version(v),
+                self.path,
+                fmt_version(MIN_VERSION)
+            )),
+            Some(_) => None,
+        }
+    }
+
+    /// 一行摘要
+    pub fn summary(&self) -> String {
+        if let Some(why) = self.unusable() {
+            return why;
+        }
+        let mut features = Vec::new();
+        if self.pcre2 { features.push("pcre2"); }
+        if self.multiline { features.push("multiline"); }
+        format!(
+            "ripgrep {} at {} ({})",
+            fmt_version(self.version.unwrap_or_default()),
+            self.path,
+            if features.is_empty() { "no optional features".to_string() } else { features.join(", ") }
+        )
+    }
+
+    /// 按模式内容补充的参数：跨行模式加 -U，环视/反向引用加 -P（PCRE2 可用时）
+    pub fn pattern_flags(&self, pattern: &str) -> Vec<&'static str> {
+        let mut flags = Vec::new();
+        if self.multiline && (pattern.contains("\\n") || pattern.contains('\n')) {
+            flags.push("-U");
+        }
+        let needs_pcre2 = ["(?=", "(?!", "(?<=", "(?<!"].iter().any(|p| pattern.contains(p))
+            || pattern.as_bytes().windows(2).any(|w| w[0] == b'\\' && w[1].is_ascii_digit() && w[1] != b'0');
+        if self.pcre2 && needs_pcre2 {
+            flags.push("-P");
+        }
+        flags
+    }
+}
+
+/// 启动时后台探测并记录结果
+pub fn spawn_probe() {
+    tokio::task::spawn_blocking(|| {
+        let caps = caps();
+        match caps.unusable() {
+            Some(why) => log_warn!("{}; rg-based commands use the built-in search", why),
+            None => log_info!("{}", caps.summary()),
+        }
+    });
+}
@@ -0,0 +1,44 @@
+//! MCP roots：用客户端工作区根目录作为默认 project_path
+//!
+//! 客户端声明 roots 能力时，在 notifications/initialized 和 notifications/roots/list_changed 之后
+//! 发送 roots/list；tools/call 未提供 project_path 时使用第一个根目录，而不是进程的工作目录
+//! （由 IDE 启动时工作目录往往不是项目）。
+
+use serde_json::{json, Value};
+
+pub const REQUEST_ID_PREFIX: &str = "roots-";
+
+/// roots/list 请求
+pub fn request(seq: u64) -> Value {
+    json!({ "jsonrpc": "2.0", "id": format!("{}{}", REQUEST_ID_PREFIX, seq), "method": "roots/list" })
+}
+
+/// roots/list 响应 → 存在的本地目录（非 file:// 根忽略）
+pub fn parse(reply: &Value) -> Vec<String> {
+    let Some(roots) = reply.pointer("/result/roots").and_then(|r| r.as_array()) else {
+        if let Some(err) = reply.get("error") {
+            log_warn!("roots/list failed: {}", err);
+        }
+        return Vec::new();
+    };
+    roots
+        .iter()
+        .filter_map(|r| r.get("uri").and_then(|u| u.as_str()))
+        .filter_map(crate::resources::path_of)
+        .filter(|p| p.is_dir())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect()
+}
+
+/// tools/call 未提供 project_path 时补上
+pub fn apply_default(request: &mut Value, root: &str) {
+    let given = request.pointer("/params/arguments/project_path").and_then(|p| p.as_str()).is_some_and(|p| !p.is_empty());
+    if given {
+        return;
+    }
+    if let Some(args) = request.pointer_mut("/params/arguments").and_then(|a| a.as_object_mut()) {
+        args.insert("project_path".into(), json!(root));
+    } else if let Some(params) = request.get_mut("params").and_then(|p| p.as_object_mut()) {
+        params.insert("arguments".into(), json!({ "project_path": root }));
+    }
+}
@@ -0,0 +1,252 @@
+//! 内置搜索：ripgrep 不可用时 rg / files / count 命令的替代实现
+//!
+//! 用 ignore 遍历（与 rg 相同的忽略规则）+ regex_lite 逐行匹配，结果与解析后的 `rg --json` 相同（rg_results::RgLine），
+//! 由 rg_results::render 统一渲染。
+//! 支持 RgOptions 的上下文、忽略大小写、字面量、整词和跨行选项；不支持 PCRE2 语法（环视、反向引用）。
+//! 含 `\n` 的模式或 multiline 时对整个文件匹配。超过大小上限的文件和二进制文件跳过并计数（Skipped）。
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use ignore::overrides::{Override, OverrideBuilder};
+use tokio_util::sync::CancellationToken;
+
+use crate::executor::RgOptions;
+use crate::rg_results::RgLine;
+
+/// 判定二进制文件时检查的前缀字节数
+const BINARY_PROBE_BYTES: usize = 8192;
+
+/// 结果首行的说明
+pub fn note(why: &str) -> String {
+    format!("({}; results from the built-in search)", why)
+}
+
+/// 未搜索的文件数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Skipped {
+    /// 超过大小上限
+    pub large: usize,
+    /// 二进制文件
+    pub binary: usize,
+}
+
+impl Skipped {
+    /// 结果中的说明行；没有跳过的文件时为 None
+    pub fn note(&self, max_filesize: Option<u64>) -> Option<String> {
+        let files = |n: usize, kind: &str| format!("{} {}file{}", n, kind, if n == 1 { "" } else { "s" });
+        let mut parts = Vec::new();
+        if let Some(max) = max_filesize.filter(|_| self.large > 0) {
+            parts.push(format!("{} larger than {}", files(self.large, ""), crate::repo_sample::human_size(max)));
+        }
+        if self.binary > 0 {
+            parts.push(files(self.binary, "binary "));
+        }
+        (!parts.is_empty()).then(|| format!("(skipped {}; results may be partial)", parts.join(", ")))
+    }
+}
+
+/// rg 搜索时统计 paths 下会被跳过的文件（与 rg 相同的忽略规则和 glob），rg 本身不报告这些数量
+pub fn skipped(paths: &[PathBuf], globs: &[String], max_filesize: Option<u64>, cancel: &CancellationToken) -> Result<Skipped, String> {
+    let mut skipped = Skipped::default();
+    for file in collect(paths, globs, max_filesize, &mut skipped, cancel)? {
+        if cancel.is_cancelled() {
+            return Err("cancelled".into());
+        }
+        if is_binary(&file) {
+            skipped.binary += 1;
+        }
+    }
+    Ok(skipped)
+}
+
+/// 文件开头是否含 NUL 字节（与 rg 的判定相同）
+pub fn is_binary(file: &Path) -> bool {
+    let Ok(f) = std::fs::File::open(file) else { return false };
+    let mut probe = Vec::with_capacity(BINARY_PROBE_BYTES);
+    f.take(BINARY_PROBE_BYTES as u64).read_to_end(&mut probe).is_ok_and(|_| probe.contains(&0))
+}
+
+/// rg 风格的 glob（"!" 开头为排除）→ Override；只有排除项时其余文件照常搜索
+fn overrides(root: &Path, globs: &[String]) -> Result<Override, String> {
+    let mut builder = OverrideBuilder::new(root);
+    for g in globs {
+        builder.add(g).map_err(|e| format!("invalid glob {}: {}", g, e))?;
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// paths 下（目录递归，文件直接使用）符合 globs 的文件，按路径排序；超过 max_filesize 的文件计入 skipped.large
+fn collect(
+    paths: &[PathBuf],
+    globs: &[String],
+    max_filesize: Option<u64>,
+    skipped: &mut Skipped,
+    cancel: &CancellationToken,
+) -> Result<Vec<PathBuf>, String> {
+    let too_large = |path: &Path| max_filesize.is_some_and(|max| std::fs::metadata(path).is_ok_and(|m| m.len() > max));
+    let mut files = Vec::new();
+    for path in paths {
+        // 与 rg 相同：显式给出的文件不受 glob 过滤
+        if path.is_file() {
+            if too_large(path) {
+                skipped.large += 1;
+            } else {
+                files.push(path.clone());
+            }
+            continue;
+        }
+        let mut builder = crate::executor::walk_builder(path, &[], None);
+        builder.overrides(overrides(path, globs)?);
+        for entry in builder.build().flatten() {
+            if cancel.is_cancelled() {
+                return Err("cancelled".into());
+            }
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            if too_large(entry.path()) {
+                skipped.large += 1;
+            } else {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// 列出文件（rg --files 的替代）
+pub fn files(dir: &Path, globs: &[String], cancel: &CancellationToken) -> Result<Vec<PathBuf>, String> {
+    collect(&[dir.to_path_buf()], globs, None, &mut Skipped::default(), cancel)
+}
+
+/// 按 RgOptions 构造正则；第二项为是否对整个文件匹配
+fn regex(pattern: &str, opts: &RgOptions) -> Result<(regex_lite::Regex, bool), String> {
+    let multiline = opts.multiline || (!opts.fixed_strings && (pattern.contains("\\n") || pattern.contains('\n')));
+    let mut source = if opts.fixed_strings { regex_lite::escape(pattern) } else { pattern.to_string() };
+    if opts.word_boundary {
+        source = format!(r"\b(?:{})\b", source);
+    }
+    let flags = [(opts.case_insensitive, "i"), (multiline, "m")].iter().filter(|(on, _)| *on).map(|(_, f)| *f).collect::<String>();
+    if !flags.is_empty() {
+        source = format!("(?{}){}", flags, source);
+    }
+    let re = regex_lite::Regex::new(&source).map_err(|e| format!("regex not supported by the built-in search: {}", e))?;
+    Ok((re, multiline))
+}
+
+/// 读取文本文件；二进制或无法读取时为 None
+fn read_text(file: &Path, cancel: &CancellationToken) -> Result<Option<String>, String> {
+    if cancel.is_cancelled() {
+        return Err("cancelled".into());
+    }
+    let Ok(data) = std::fs::read(file) else { return Ok(None) };
+    if data[..data.len().min(BINARY_PROBE_BYTES)].contains(&0) {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+}
+
+/// 统计匹配行数（rg --count 的替代），只列出有匹配的文件
+pub fn count(
+    paths: &[PathBuf],
+    pattern: &str,
+    globs: &[String],
+    opts: &RgOptions,
+    max_filesize: Option<u64>,
+    cancel: &CancellationToken,
+) -> Result<(Vec<(PathBuf, usize)>, Skipped), String> {
+    let (re, multiline) = regex(pattern, opts)?;
+    let mut skipped = Skipped::default();
+    let mut out = Vec::new();
+    for file in collect(paths, globs, max_filesize, &mut skipped, cancel)? {
+        let Some(text) = read_text(&file, cancel)? else {
+            skipped.binary += 1;
+            continue;
+        };
+        let n = if multiline {
+            multiline_hits(&re, &text, usize::MAX).len()
+        } else {
+            text.lines().filter(|l| re.is_match(l)).count()
+        };
+        if n > 0 {
+            out.push((file, n));
+        }
+    }
+    Ok((out, skipped))
+}
+
+/// 搜索（rg --json --max-count N [-C ctx] 的替代），返回匹配行及上下文行，由 rg_results::render 渲染
+pub fn search(
+    paths: &[PathBuf],
+    pattern: &str,
+    globs: &[String],
+    opts: &RgOptions,
+    max_count: usize,
+    max_filesize: Option<u64>,
+    cancel: &CancellationToken,
+) -> Result<(Vec<RgLine>, Skipped), String> {
+    let (re, multiline) = regex(pattern, opts)?;
+    let (before, after) = opts.around();
+
+    let mut skipped = Skipped::default();
+    let mut out = Vec::new();
+    for file in collect(paths, globs, max_filesize, &mut skipped, cancel)? {
+        let Some(text) = read_text(&file, cancel)? else {
+            skipped.binary += 1;
+            continue;
+        };
+        let lines: Vec<&str> = text.lines().collect();
+        let hits = if multiline { multiline_hits(&re, &text, max_count) } else { line_hits(&re, &lines, max_count) };
+        let path = file.to_string_lossy();
+        for (n, submatch) in &hits {
+            let first = n.saturating_sub(before);
+            let last = (n + after + 1).min(lines.len());
+            for (i, line) in lines.iter().enumerate().take(last).skip(first) {
+                let is_match = i == *n;
+                out.push(RgLine {
+                    path: path.to_string(),
+                    line: i + 1,
+                    text: line.to_string(),
+                    is_match,
+                    submatch: if is_match { submatch.clone() } else { None },
+                });
+            }
+        }
+    }
+    Ok((out, skipped))
+}
+
+/// 匹配的行号（0 起）及行内第一个匹配，最多 max_count 行
+fn line_hits(re: &regex_lite::Regex, lines: &[&str], max_count: usize) -> Vec<(usize, Option<(usize, String)>)> {
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, l)| re.find(l).map(|m| (i, Some((m.start() + 1, m.as_str().to_string())))))
+        .take(max_count)
+        .collect()
+}
+
+/// 跨行匹配：每个匹配覆盖的所有行都算匹配行，子匹配记在起始行上
+fn multiline_hits(re: &regex_lite::Regex, text: &str, max_count: usize) -> Vec<(usize, Option<(usize, String)>)> {
+    let line_start = |offset: usize| text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_of = |offset: usize| text[..offset].matches('\n').count();
+    let mut hits: Vec<(usize, Option<(usize, String)>)> = Vec::new();
+    for m in re.find_iter(text).take(max_count) {
+        let first = line_of(m.start());
+        let last = line_of(m.end().saturating_sub(1).max(m.start()));
+        let submatch = (m.start() - line_start(m.start()) + 1, m.as_str().to_string());
+        for line in first..=last {
+            match hits.last_mut() {
+                Some((l, sub)) if *l == line => {
+                    if line == first && sub.is_none() {
+                        *sub = Some(submatch.clone());
+                    }
+                }
+                _ => hits.push((line, (line == first).then(|| submatch.clone()))),
+            }
+        }
+    }
+    hits
+}
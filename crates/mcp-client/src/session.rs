@@ -0,0 +1,50 @@
+//! 会话状态
+//!
+//! 网络传输（sse / streamable-http / ws / --listen）下一个进程同时服务多个客户端，每个连接或
+//! Mcp-Session-Id 是一个会话：传输名称、额度缓存、分页结果、搜索发现的资源和可续接的搜索对话各自独立，互不覆盖。
+//! 协商的协议版本、roots、最近查询等由会话循环自己持有。
+
+use std::sync::{Arc, OnceLock};
+
+use crate::{conversation, pages, quota, resources};
+
+pub struct Session {
+    pub id: String,
+    transport: &'static str,
+    /// stdio 按首条消息识别出的分帧（stdio-lsp / stdio-line）
+    framing: OnceLock<&'static str>,
+    pub quota: quota::Cache,
+    pub pages: pages::Store,
+    /// 搜索结果中的文件，resources/list 和 resources/read 只看本会话的
+    pub discovered: resources::Discovered,
+    /// fast_context_refine 可续接的搜索对话
+    pub conversations: conversation::Store,
+}
+
+impl Session {
+    pub fn new(transport: &'static str) -> Arc<Self> {
+        Self::with_id(uuid::Uuid::new_v4().simple().to_string(), transport)
+    }
+
+    /// 传输层已分配 id（SSE sessionId、Mcp-Session-Id）时沿用
+    pub fn with_id(id: String, transport: &'static str) -> Arc<Self> {
+        Arc::new(Session {
+            id,
+            transport,
+            framing: OnceLock::new(),
+            quota: quota::Cache::default(),
+            pages: pages::Store::default(),
+            discovered: resources::Discovered::default(),
+            conversations: conversation::Store::default(),
+        })
+    }
+
+    pub fn set_framing(&self, name: &'static str) {
+        self.framing.get_or_init(|| name);
+    }
+
+    /// 上报给 relay 的传输名称
+    pub fn transport(&self) -> &'static str {
+        self.framing.get().copied().unwrap_or(self.transport)
+    }
+}
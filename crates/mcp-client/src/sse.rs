@@ -0,0 +1,219 @@
+//! HTTP + SSE 传输
+//!
+//! `--transport sse [--host 127.0.0.1] [--port 8765] [--auth-token T]`：
+//! GET /sse 建立会话，首先推送 `endpoint` 事件（带 sessionId 的 POST 地址），之后每条响应/通知作为 `message` 事件推送；
+//! POST /message?sessionId=... 提交一条 JSON-RPC 消息，返回 202。每个 SSE 连接是一个独立会话，断开即结束。
+//! 浏览器请求只接受本机 Origin，其余来源需通过 `--allow-origin` 放行（防 DNS rebinding）。
+//! 令牌规则与 ws 传输相同：每个请求都须带 `Authorization: Bearer <token>` 或 `?token=<token>`；未设置令牌时只允许监听回环地址。
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::{header, Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Either, Full, Limited};
+use hyper::body::{Frame, Incoming};
+use hyper_util::rt::TokioIo;
+use tokio::sync::mpsc;
+
+pub const DEFAULT_PORT: u16 = 8765;
+/// 单条消息默认上限（配置 max_message_bytes 可调整）
+pub const MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+type Sessions = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<String>>>>;
+type Body = Either<EventStream, Full<Bytes>>;
+
+/// SSE 事件帧
+pub fn event(name: &str, data: &str) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+/// 连接关闭（响应体被丢弃）时移除会话，会话循环随之退出
+struct SessionGuard {
+    sessions: Sessions,
+    id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if let Ok(mut map) = self.sessions.lock() {
+            map.remove(&self.id);
+        }
+        log_info!("SSE session {} closed", self.id);
+    }
+}
+
+/// SSE 响应体：先发 endpoint 事件，再逐条转发会话输出
+struct EventStream {
+    endpoint: Option<Bytes>,
+    rx: mpsc::UnboundedReceiver<String>,
+    _guard: SessionGuard,
+}
+
+impl hyper::body::Body for EventStream {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if let Some(endpoint) = self.endpoint.take() {
+            return Poll::Ready(Some(Ok(Frame::data(endpoint))));
+        }
+        self.rx
+            .poll_recv(cx)
+            .map(|msg| msg.map(|payload| Ok(Frame::data(event("message", &payload)))))
+    }
+}
+
+#[derive(Clone)]
+struct Server {
+    sessions: Sessions,
+    token: Option<String>,
+    allow_origin: Option<String>,
+    max_message_bytes: usize,
+}
+
+/// 纯文本响应
+pub fn text<B>(status: StatusCode, body: &str) -> Response<Either<B, Full<Bytes>>> {
+    let mut resp = Response::new(Either::Right(Full::new(Bytes::from(body.to_string()))));
+    *resp.status_mut() = status;
+    resp
+}
+
+/// 本机页面或显式放行的来源；无 Origin（非浏览器客户端）总是允许
+pub fn origin_allowed(origin: Option<&str>, allow: Option<&str>) -> bool {
+    let Some(origin) = origin else { return true };
+    if allow.is_some_and(|a| a == "*" || a == origin) {
+        return true;
+    }
+    let host = origin.split("://").nth(1).unwrap_or("");
+    let host = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+fn session_id(query: Option<&str>) -> Option<&str> {
+    query?.split('&').find_map(|kv| kv.strip_prefix("sessionId="))
+}
+
+impl Server {
+    async fn handle(self, req: Request<Incoming>) -> Result<Response<Body>, Infallible> {
+        let origin = req.headers().get(header::ORIGIN).and_then(|o| o.to_str().ok()).map(str::to_string);
+        if !origin_allowed(origin.as_deref(), self.allow_origin.as_deref()) {
+            log_warn!("rejected request from origin {}", origin.unwrap_or_default());
+            return Ok(text(StatusCode::FORBIDDEN, "origin not allowed"));
+        }
+        let authorization = req.headers().get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+        let mut resp = match (req.method(), req.uri().path()) {
+            (&Method::OPTIONS, _) => {
+                let mut r = text(StatusCode::NO_CONTENT, "");
+                let h = r.headers_mut();
+                h.insert(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS".parse().unwrap());
+                h.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, "content-type, authorization".parse().unwrap());
+                r
+            }
+            _ if !crate::ws::authorized(authorization, req.uri().query(), self.token.as_deref()) => {
+                log_warn!("rejected {} {}: invalid or missing token", req.method(), req.uri().path());
+                text(StatusCode::UNAUTHORIZED, "invalid or missing token")
+            }
+            (&Method::GET, "/sse") => self.open_session(),
+            (&Method::POST, "/message") => self.post_message(req).await,
+            _ => text(StatusCode::NOT_FOUND, "not found"),
+        };
+        if let Some(o) = origin.and_then(|o| o.parse().ok()) {
+            resp.headers_mut().insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, o);
+        }
+        Ok(resp)
+    }
+
+    fn open_session(&self) -> Response<Body> {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let (in_tx, in_rx) = mpsc::unbounded_channel();
+        let (out_tx, out_rx) = mpsc::unbounded_channel();
+        if let Ok(mut map) = self.sessions.lock() {
+            map.insert(id.clone(), in_tx);
+        }
+        tokio::spawn(crate::serve_session(in_rx, out_tx, crate::session::Session::with_id(id.clone(), "sse")));
+        log_info!("SSE session {} opened", id);
+
+        let stream = EventStream {
+            endpoint: Some(event("endpoint", &format!("/message?sessionId={}", id))),
+            rx: out_rx,
+            _guard: SessionGuard { sessions: self.sessions.clone(), id },
+        };
+        let mut resp = Response::new(Either::Left(stream));
+        let h = resp.headers_mut();
+        h.insert(header::CONTENT_TYPE, "text/event-stream".parse().unwrap());
+        h.insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
+        resp
+    }
+
+    async fn post_message(&self, req: Request<Incoming>) -> Response<Body> {
+        let Some(id) = session_id(req.uri().query()).map(str::to_string) else {
+            return text(StatusCode::BAD_REQUEST, "missing sessionId");
+        };
+        let Some(tx) = self.sessions.lock().ok().and_then(|m| m.get(&id).cloned()) else {
+            return text(StatusCode::NOT_FOUND, "unknown session");
+        };
+        let body = match Limited::new(req.into_body(), self.max_message_bytes).collect().await {
+            Ok(b) => b.to_bytes(),
+            Err(e) => return text(StatusCode::PAYLOAD_TOO_LARGE, &e.to_string()),
+        };
+        let Ok(message) = String::from_utf8(body.to_vec()) else {
+            return text(StatusCode::BAD_REQUEST, "body is not UTF-8");
+        };
+        if serde_json::from_str::<serde_json::Value>(&message).is_err() {
+            return text(StatusCode::BAD_REQUEST, "body is not JSON");
+        }
+        match tx.send(message) {
+            Ok(()) => text(StatusCode::ACCEPTED, "Accepted"),
+            Err(_) => text(StatusCode::GONE, "session closed"),
+        }
+    }
+}
+
+/// 监听并服务 SSE 客户端，Ctrl-C 退出
+pub async fn run(addr: SocketAddr, token: Option<String>, allow_origin: Option<String>) -> anyhow::Result<()> {
+    crate::ws::ensure_bindable(addr, token.as_deref())?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log_info!("SSE transport listening on http://{}/sse", listener.local_addr()?);
+    crate::ripgrep::spawn_probe();
+
+    let server = Server { sessions: Sessions::default(), token, allow_origin, max_message_bytes: crate::config::load().max_message_bytes() };
+    accept_loop(listener, move |req| server.clone().handle(req)).await;
+    Ok(())
+}
+
+/// HTTP/1 accept 循环，Ctrl-C 时返回
+pub async fn accept_loop<H, F, B>(listener: tokio::net::TcpListener, handler: H)
+where
+    H: Fn(Request<Incoming>) -> F + Clone + Send + 'static,
+    F: std::future::Future<Output = Result<Response<B>, Infallible>> + Send + 'static,
+    B: hyper::body::Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log_warn!("accept error: {}", e);
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let service = hyper::service::service_fn(handler.clone());
+        tokio::spawn(async move {
+            if let Err(e) = hyper::server::conn::http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                log_debug!("connection {}: {}", peer, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,311 @@
+//! Streamable HTTP 传输 (MCP 2025-03-26)
+//!
+//! `--transport http` 或环境变量 WINDSURF_MCP_TRANSPORT=http，单一端点 `/mcp`：
+//! - POST：一条或一批 JSON-RPC 消息。只含通知/响应时返回 202；含请求时按 Accept 返回
+//!   `text/event-stream`（分块推送，全部请求答复后关闭）或 `application/json`。
+//! - initialize 响应带 `Mcp-Session-Id`，之后的请求须携带；未知会话返回 404。
+//! - GET：打开独立的 SSE 流接收服务端主动发出的通知/请求。
+//! - DELETE：结束会话。
+//!
+//! `--auth-token T`（或 WINDSURF_MCP_AUTH_TOKEN）与 ws 传输共用同一检查：每个请求都须带
+//! `Authorization: Bearer <token>` 或 `?token=<token>`；未设置令牌时只允许监听回环地址。
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Either, Full, Limited};
+use hyper::body::{Frame, Incoming};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::sse::{self, event, text};
+
+pub const ENDPOINT: &str = "/mcp";
+const SESSION_HEADER: &str = "mcp-session-id";
+
+type Body = Either<ResponseStream, Full<Bytes>>;
+/// 请求 id (JSON 文本) → 对应 POST 响应流
+type Pending = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<String>>>>;
+type Standalone = Arc<Mutex<Option<mpsc::UnboundedSender<String>>>>;
+
+/// 会话表只持有 inbound 发送端：移出会话表（DELETE）后会话循环随之结束
+struct Session {
+    inbound: mpsc::UnboundedSender<String>,
+    pending: Pending,
+    /// GET 打开的独立流
+    standalone: Standalone,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Arc<Session>>>>;
+
+/// 响应 (无 method) 的 id
+fn response_id(msg: &Value) -> Option<String> {
+    if msg.get("method").is_some() {
+        return None;
+    }
+    msg.get("id").map(|id| id.to_string())
+}
+
+/// 会话输出分发：响应回到发起它的 POST 流，其余消息进入 GET 流（没有时借用任一未完成的 POST 流）
+async fn route(mut out: mpsc::UnboundedReceiver<String>, pending: Pending, standalone: Standalone, sessions: Sessions, id: String) {
+    while let Some(payload) = out.recv().await {
+        let parsed: Value = serde_json::from_str(&payload).unwrap_or(Value::Null);
+        let Ok(mut pending) = pending.lock() else { break };
+        if let Some(tx) = response_id(&parsed).and_then(|rid| pending.remove(&rid)) {
+            let _ = tx.send(payload);
+            continue;
+        }
+        let standalone = standalone.lock().ok().and_then(|s| s.clone());
+        let delivered = standalone.is_some_and(|tx| tx.send(payload.clone()).is_ok())
+            || pending.values().any(|tx| tx.send(payload.clone()).is_ok());
+        if !delivered {
+            log_debug!("session {}: no open stream, dropped {}", id, payload);
+        }
+    }
+    if let Ok(mut map) = sessions.lock() {
+        map.remove(&id);
+    }
+    log_info!("HTTP session {} ended", id);
+}
+
+/// POST 响应流：转发消息直到 `remaining` 个请求全部答复；GET 流 remaining = None，不主动结束
+struct ResponseStream {
+    rx: mpsc::UnboundedReceiver<String>,
+    remaining: Option<usize>,
+}
+
+impl hyper::body::Body for ResponseStream {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if self.remaining == Some(0) {
+            return Poll::Ready(None);
+        }
+        let polled = self.rx.poll_recv(cx);
+        if let Poll::Ready(Some(payload)) = &polled {
+            let is_response = serde_json::from_str::<Value>(payload).is_ok_and(|v| response_id(&v).is_some());
+            if let (true, Some(n)) = (is_response, self.remaining.as_mut()) {
+                *n -= 1;
+            }
+        }
+        polled.map(|msg| msg.map(|payload| Ok(Frame::data(event("message", &payload)))))
+    }
+}
+
+fn event_stream(stream: ResponseStream) -> Response<Body> {
+    let mut resp = Response::new(Either::Left(stream));
+    let h = resp.headers_mut();
+    h.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    h.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    resp
+}
+
+fn json_response(body: &Value) -> Response<Body> {
+    let mut resp = text(StatusCode::OK, &body.to_string());
+    resp.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp
+}
+
+#[derive(Clone)]
+struct Server {
+    sessions: Sessions,
+    token: Option<String>,
+    allow_origin: Option<String>,
+    max_message_bytes: usize,
+}
+
+impl Server {
+    async fn handle(self, req: Request<Incoming>) -> Result<Response<Body>, Infallible> {
+        let origin = req.headers().get(header::ORIGIN).and_then(|o| o.to_str().ok()).map(str::to_string);
+        if !sse::origin_allowed(origin.as_deref(), self.allow_origin.as_deref()) {
+            log_warn!("rejected request from origin {}", origin.unwrap_or_default());
+            return Ok(text(StatusCode::FORBIDDEN, "origin not allowed"));
+        }
+        let authorization = req.headers().get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+        let mut resp = if req.uri().path() != ENDPOINT {
+            text(StatusCode::NOT_FOUND, "not found")
+        } else if req.method() != Method::OPTIONS && !crate::ws::authorized(authorization, req.uri().query(), self.token.as_deref()) {
+            log_warn!("rejected {} {}: invalid or missing token", req.method(), ENDPOINT);
+            text(StatusCode::UNAUTHORIZED, "invalid or missing token")
+        } else {
+            match *req.method() {
+                Method::OPTIONS => {
+                    let mut r = text(StatusCode::NO_CONTENT, "");
+                    let h = r.headers_mut();
+                    h.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, DELETE, OPTIONS"));
+                    h.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("content-type, accept, authorization, mcp-session-id"));
+                    r
+                }
+                Method::POST => self.post(req).await,
+                Method::GET => self.get(&req),
+                Method::DELETE => self.delete(&req),
+                _ => text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"),
+            }
+        };
+        if let Some(o) = origin.and_then(|o| o.parse().ok()) {
+            let h = resp.headers_mut();
+            h.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, o);
+            h.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_static("mcp-session-id"));
+        }
+        Ok(resp)
+    }
+
+    /// 按 Mcp-Session-Id 查找会话；Err 为应返回的状态码和说明
+    fn session(&self, req: &Request<Incoming>) -> Result<(String, Arc<Session>), (StatusCode, &'static str)> {
+        let Some(id) = req.headers().get(SESSION_HEADER).and_then(|v| v.to_str().ok()) else {
+            return Err((StatusCode::BAD_REQUEST, "missing Mcp-Session-Id"));
+        };
+        match self.sessions.lock().ok().and_then(|m| m.get(id).cloned()) {
+            Some(s) => Ok((id.to_string(), s)),
+            None => Err((StatusCode::NOT_FOUND, "unknown session")),
+        }
+    }
+
+    fn open_session(&self) -> (String, Arc<Session>) {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let (in_tx, in_rx) = mpsc::unbounded_channel();
+        let (out_tx, out_rx) = mpsc::unbounded_channel();
+        let session = Arc::new(Session { inbound: in_tx, pending: Pending::default(), standalone: Standalone::default() });
+        if let Ok(mut map) = self.sessions.lock() {
+            map.insert(id.clone(), session.clone());
+        }
+        tokio::spawn(crate::serve_session(in_rx, out_tx, crate::session::Session::with_id(id.clone(), "streamable-http")));
+        tokio::spawn(route(out_rx, session.pending.clone(), session.standalone.clone(), self.sessions.clone(), id.clone()));
+        log_info!("HTTP session {} opened", id);
+        (id, session)
+    }
+
+    async fn post(&self, req: Request<Incoming>) -> Response<Body> {
+        let wants_stream = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|a| a.to_str().ok())
+            .is_some_and(|a| a.contains("text/event-stream"));
+        let has_session = req.headers().contains_key(SESSION_HEADER);
+        let existing = if has_session {
+            match self.session(&req) {
+                Ok(s) => Some(s),
+                Err((status, why)) => return text(status, why),
+            }
+        } else {
+            None
+        };
+
+        let body = match Limited::new(req.into_body(), self.max_message_bytes).collect().await {
+            Ok(b) => b.to_bytes(),
+            Err(e) => return text(StatusCode::PAYLOAD_TOO_LARGE, &e.to_string()),
+        };
+        let messages = match serde_json::from_slice::<Value>(&body) {
+            Ok(Value::Array(batch)) if !batch.is_empty() => batch,
+            Ok(v @ Value::Object(_)) => vec![v],
+            _ => return text(StatusCode::BAD_REQUEST, "body must be a JSON-RPC message or batch"),
+        };
+        let is_initialize = messages.iter().any(|m| m.get("method").and_then(|v| v.as_str()) == Some("initialize"));
+
+        let (id, session, created) = match existing {
+            Some((id, s)) => (id, s, false),
+            None if is_initialize => {
+                let (id, s) = self.open_session();
+                (id, s, true)
+            }
+            None => return text(StatusCode::BAD_REQUEST, "missing Mcp-Session-Id"),
+        };
+
+        let request_ids: Vec<String> = messages
+            .iter()
+            .filter(|m| m.get("method").is_some())
+            .filter_map(|m| m.get("id").map(|i| i.to_string()))
+            .collect();
+        let (tx, rx) = mpsc::unbounded_channel();
+        if let Ok(mut pending) = session.pending.lock() {
+            for rid in &request_ids {
+                pending.insert(rid.clone(), tx.clone());
+            }
+        }
+        drop(tx);
+        for m in &messages {
+            if session.inbound.send(m.to_string()).is_err() {
+                return text(StatusCode::NOT_FOUND, "session closed");
+            }
+        }
+
+        let mut resp = if request_ids.is_empty() {
+            text(StatusCode::ACCEPTED, "")
+        } else if wants_stream {
+            event_stream(ResponseStream { rx, remaining: Some(request_ids.len()) })
+        } else {
+            // 非流式：收齐所有响应后一次返回
+            let mut rx = rx;
+            let mut replies = Vec::new();
+            while replies.len() < request_ids.len() {
+                let Some(payload) = rx.recv().await else { break };
+                let v: Value = serde_json::from_str(&payload).unwrap_or(Value::Null);
+                if response_id(&v).is_some() {
+                    replies.push(v);
+                }
+            }
+            match (messages.len(), replies.len()) {
+                (1, 1) => json_response(&replies[0]),
+                _ => json_response(&Value::Array(replies)),
+            }
+        };
+        if created {
+            if let Ok(v) = HeaderValue::from_str(&id) {
+                resp.headers_mut().insert(SESSION_HEADER, v);
+            }
+        }
+        resp
+    }
+
+    fn get(&self, req: &Request<Incoming>) -> Response<Body> {
+        let accepts_sse = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|a| a.to_str().ok())
+            .is_some_and(|a| a.contains("text/event-stream"));
+        if !accepts_sse {
+            return text(StatusCode::METHOD_NOT_ALLOWED, "GET requires Accept: text/event-stream");
+        }
+        let (_, session) = match self.session(req) {
+            Ok(s) => s,
+            Err((status, why)) => return text(status, why),
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        if let Ok(mut standalone) = session.standalone.lock() {
+            *standalone = Some(tx);
+        }
+        event_stream(ResponseStream { rx, remaining: None })
+    }
+
+    fn delete(&self, req: &Request<Incoming>) -> Response<Body> {
+        match self.session(req) {
+            Ok((id, _)) => {
+                // 丢弃 inbound 发送端后会话循环退出，route 随之结束
+                if let Ok(mut map) = self.sessions.lock() {
+                    map.remove(&id);
+                }
+                text(StatusCode::OK, "")
+            }
+            Err((status, why)) => text(status, why),
+        }
+    }
+}
+
+/// 监听并服务 Streamable HTTP 客户端，Ctrl-C 退出
+pub async fn run(addr: SocketAddr, token: Option<String>, allow_origin: Option<String>) -> anyhow::Result<()> {
+    crate::ws::ensure_bindable(addr, token.as_deref())?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log_info!("streamable HTTP transport listening on http://{}{}", listener.local_addr()?, ENDPOINT);
+    crate::ripgrep::spawn_probe();
+
+    let server = Server { sessions: Sessions::default(), token, allow_origin, max_message_bytes: crate::config::load().max_message_bytes() };
+    sse::accept_loop(listener, move |req| server.clone().handle(req)).await;
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! symbol_search 工具：用 tree-sitter 查找符号定义
+//!
+//! 解析 Rust、Python、Go、JS/TS 文件，返回名称匹配的函数/类型/impl 等定义的路径和完整行范围
+//! （含 Python 装饰器）。不经过 AI，也不依赖 rg。
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::i18n::Locale;
+use crate::outline::{self, Block};
+
+const DEFAULT_MAX_RESULTS: usize = 20;
+const MAX_RESULTS: usize = 100;
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "symbol_search",
+        "description": l.pick(
+            "Find where a symbol (function, method, class, struct, trait, interface, type...) is defined, by parsing Rust, Python, Go and JS/TS with tree-sitter. Returns each definition's path and full line range.",
+            "用 tree-sitter 解析 Rust、Python、Go、JS/TS，查找符号（函数、方法、类、结构体、trait、接口、类型等）的定义位置，返回路径和完整行范围。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": l.pick("Symbol name", "符号名") },
+                "match": {
+                    "type": "string",
+                    "description": l.pick("exact = same name (case-insensitive), prefix = name starts with it, contains = name contains it", "exact = 名称相同（不区分大小写），prefix = 以其开头，contains = 包含"),
+                    "enum": ["exact", "prefix", "contains"],
+                    "default": "exact"
+                },
+                "path": {
+                    "type": "string",
+                    "description": l.pick("Directory to search, relative to project_path (default: whole project)", "搜索的目录，相对 project_path（默认整个项目）"),
+                    "default": ""
+                },
+                "max_results": {
+                    "type": "integer",
+                    "description": l.pick("Max definitions to return (1-100, default 20)", "最多返回的定义数（1-100，默认 20）"),
+                    "default": DEFAULT_MAX_RESULTS,
+                    "minimum": 1,
+                    "maximum": MAX_RESULTS
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Absolute path to project root. Empty = first client root, else cwd.", "项目根目录的绝对路径，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            },
+            "required": ["name"]
+        }
+    })
+}
+
+/// 排序：名称完全相同（区分大小写）的在前，其次按路径
+fn rank(found: &mut [(PathBuf, Block)], name: &str) {
+    found.sort_by(|(pa, a), (pb, b)| {
+        let inexact = |blk: &Block| blk.name.as_deref() != Some(name);
+        inexact(a).cmp(&inexact(b)).then_with(|| pa.cmp(pb)).then(a.start_line.cmp(&b.start_line))
+    });
+}
+
+fn render(root: &Path, found: &[(PathBuf, Block)]) -> String {
+    found
+        .iter()
+        .map(|(path, block)| {
+            let rel = path.strip_prefix(root).unwrap_or(path);
+            format!(
+                "{}:{}-{} {} {}",
+                root.join(rel).display(),
+                block.start_line,
+                block.end_line,
+                block.kind,
+                block.name.as_deref().unwrap_or("")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 执行 symbol_search
+pub async fn call(args: &Value) -> Result<String, String> {
+    let root = PathBuf::from(crate::direct_tools::project_root(args)?);
+    let name = args.get("name").and_then(|n| n.as_str()).map(str::trim).filter(|n| !n.is_empty())
+        .ok_or_else(|| "missing required argument: name".to_string())?
+        .to_string();
+    let mode = args.get("match").and_then(|m| m.as_str()).unwrap_or("exact").to_string();
+    if !matches!(mode.as_str(), "exact" | "prefix" | "contains") {
+        return Err(format!("invalid match: {} (expected exact, prefix or contains)", mode));
+    }
+    let dir = match args.get("path").and_then(|p| p.as_str()).filter(|p| !p.is_empty()) {
+        Some(p) => {
+            let virtual_path = crate::direct_tools::virtual_path(&root, p)?;
+            root.join(virtual_path.trim_start_matches("/codebase").trim_start_matches('/'))
+        }
+        None => root.clone(),
+    };
+    if !dir.is_dir() {
+        return Err(format!("not a directory: {}", dir.display()));
+    }
+    let max = args.get("max_results").and_then(|m| m.as_u64()).map(|m| m as usize)
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .clamp(1, MAX_RESULTS);
+
+    let needle = name.to_lowercase();
+    let search_name = name.clone();
+    let mut found = tokio::task::spawn_blocking(move || {
+        // 多取一些，排序后精确匹配不会被先遍历到的近似结果挤掉
+        outline::find_symbols(&dir, max * 4, |candidate| {
+            let candidate = candidate.to_lowercase();
+            match mode.as_str() {
+                "prefix" => candidate.starts_with(&needle),
+                "contains" => candidate.contains(&needle),
+                _ => candidate == needle,
+            }
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    rank(&mut found, &search_name);
+    found.truncate(max);
+
+    if found.is_empty() {
+        return Ok(format!("No definitions of {} found", name));
+    }
+    Ok(render(&root, &found))
+}
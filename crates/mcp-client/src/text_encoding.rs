@@ -0,0 +1,62 @@
+//! 非 UTF-8 源文件的编码识别
+//!
+//! 读取类命令（readfile / head / tail / readmatch / enclosing / imports）按 BOM（UTF-8、UTF-16）识别编码，
+//! 不是合法 UTF-8 时用 chardetng 猜测（GBK、Shift_JIS、windows-1252 等），转成 UTF-8 后输出，
+//! 并在结果首行注明原编码。开头含 NUL 且没有 BOM 的文件视为二进制，不做转换。
+
+use std::path::Path;
+
+use encoding_rs::Encoding;
+
+/// 判定二进制文件时检查的前缀字节数（与 search_fallback 相同）
+const BINARY_PROBE_BYTES: usize = 8192;
+
+pub struct Decoded {
+    pub text: String,
+    /// 原编码；UTF-8（含带 BOM 的 UTF-8）时为 None
+    pub encoding: Option<&'static str>,
+}
+
+impl Decoded {
+    /// 结果首行的编码说明；UTF-8 时为 None
+    pub fn note(&self) -> Option<String> {
+        self.encoding.map(|e| format!("(encoding: {}, converted to UTF-8)", e))
+    }
+}
+
+pub enum ReadError {
+    NotFound,
+    Binary,
+    Io(std::io::Error),
+}
+
+/// 读取文本文件并转为 UTF-8
+pub fn read(path: &Path) -> Result<Decoded, ReadError> {
+    let data = std::fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => ReadError::NotFound,
+        _ if path.is_dir() => ReadError::NotFound,
+        _ => ReadError::Io(e),
+    })?;
+    decode(data).ok_or(ReadError::Binary)
+}
+
+/// 字节 → UTF-8 文本；二进制时返回 None
+pub fn decode(data: Vec<u8>) -> Option<Decoded> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(&data) {
+        let (text, _) = encoding.decode_without_bom_handling(&data[bom_len..]);
+        let name = (encoding != encoding_rs::UTF_8).then(|| encoding.name());
+        return Some(Decoded { text: text.into_owned(), encoding: name });
+    }
+    if data[..data.len().min(BINARY_PROBE_BYTES)].contains(&0) {
+        return None;
+    }
+    let data = match String::from_utf8(data) {
+        Ok(text) => return Some(Decoded { text, encoding: None }),
+        Err(e) => e.into_bytes(),
+    };
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(&data, true);
+    let encoding = detector.guess(None, true);
+    let (text, _) = encoding.decode_without_bom_handling(&data);
+    Some(Decoded { text: text.into_owned(), encoding: Some(encoding.name()) })
+}
@@ -0,0 +1,92 @@
+//! 工具注册表
+//!
+//! 集中登记每个工具的显示标题和 MCP annotations（readOnlyHint、destructiveHint、idempotentHint、openWorldHint），
+//! tools/list 生成定义后统一填充。未登记的工具按只读、幂等、不访问外部世界处理，标题由名称生成；
+//! 会写文件或依赖外部状态的新工具必须在这里登记。
+
+use serde_json::{json, Value};
+
+use crate::i18n::Locale;
+
+pub struct ToolMeta {
+    pub name: &'static str,
+    /// (en, zh-CN)
+    pub title: (&'static str, &'static str),
+    pub read_only: bool,
+    pub idempotent: bool,
+    pub open_world: bool,
+}
+
+const fn read_only(name: &'static str, en: &'static str, zh: &'static str) -> ToolMeta {
+    ToolMeta { name, title: (en, zh), read_only: true, idempotent: true, open_world: false }
+}
+
+pub const TOOLS: &[ToolMeta] = &[
+    read_only("fast_context_search", "Fast Context Search", "快速上下文搜索"),
+    read_only("multi_repo_search", "Multi-Repository Search", "多仓库搜索"),
+    read_only("read_ranges", "Read Ranges", "读取行范围"),
+    read_only("repo_overview", "Repository Overview", "仓库概览"),
+    read_only("grep_project", "Grep Project", "项目内 grep"),
+    read_only("symbol_search", "Symbol Search", "符号定义搜索"),
+    read_only("codebase_qa", "Codebase Q&A", "代码库问答"),
+    read_only("diff_context_search", "Diff Context Search", "改动上下文搜索"),
+    // 每次调用都会推进保存的对话
+    ToolMeta { name: "fast_context_refine", title: ("Refine Search", "继续搜索"), read_only: true, idempotent: false, open_world: false },
+    read_only("related_tests", "Related Tests", "相关测试"),
+    read_only("dependency_trace", "Dependency Trace", "依赖追踪"),
+    read_only("codebase_stats", "Codebase Stats", "代码库统计"),
+    read_only("git_blame_context", "Git Blame Context", "代码修改历史"),
+    read_only("locate_entrypoints", "Locate Entrypoints", "查找程序入口"),
+    read_only("search_history", "Search History", "搜索历史"),
+    read_only("verify_ranges", "Verify Ranges", "校验结果范围"),
+    read_only("explain_symbol", "Explain Symbol", "解释符号"),
+    read_only("relay_status", "Relay Status", "Relay 状态"),
+    read_only("fetch_result_page", "Fetch Result Page", "获取结果分页"),
+    read_only("rg", "Ripgrep", "Ripgrep 搜索"),
+    read_only("readfile", "Read File", "读取文件"),
+    read_only("tree", "Directory Tree", "目录树"),
+    read_only("glob", "Glob Files", "按模式列出文件"),
+];
+
+/// 由工具名生成标题：fetch_result_page → Fetch Result Page
+fn title_from_name(name: &str) -> String {
+    name.split('_')
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            chars.next().map(|c| c.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 为工具定义填充 title 与 annotations（已有的字段不覆盖）
+pub fn annotate(tools: &mut [Value], l: Locale) {
+    for tool in tools.iter_mut() {
+        let Some(name) = tool.get("name").and_then(|n| n.as_str()).map(str::to_string) else { continue };
+        let (title, annotations) = match TOOLS.iter().find(|m| m.name == name) {
+            Some(m) => (
+                l.pick(m.title.0, m.title.1).to_string(),
+                json!({
+                    "readOnlyHint": m.read_only,
+                    "destructiveHint": false,
+                    "idempotentHint": m.idempotent,
+                    "openWorldHint": m.open_world
+                }),
+            ),
+            None => (
+                title_from_name(&name),
+                json!({ "readOnlyHint": true, "destructiveHint": false, "idempotentHint": true, "openWorldHint": false }),
+            ),
+        };
+        let Some(obj) = tool.as_object_mut() else { continue };
+        obj.entry("title").or_insert_with(|| json!(title));
+        let entry = obj.entry("annotations").or_insert_with(|| json!({}));
+        if let (Some(entry), Some(defaults)) = (entry.as_object_mut(), annotations.as_object()) {
+            entry.entry("title").or_insert_with(|| json!(title));
+            for (k, v) in defaults {
+                entry.entry(k.clone()).or_insert_with(|| v.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! verify_ranges 工具：校验并修正搜索结果中的文件与行范围
+//!
+//! 模型给出的路径可能不存在、行号可能超出文件末尾。这里逐个文件检查：不存在、在项目外或被 deny_paths 禁止的文件丢弃，
+//! 越界的范围截到文件末尾，完全在文件之外的范围丢弃；可选地把范围扩展到包含它的完整函数/类（tree-sitter）。
+//! do_search 在生成结果前对每个文件调用 `check`（不扩展），工具则用于校验任意来源的结果。
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::i18n::Locale;
+
+/// 单次最多校验的文件数
+const MAX_FILES: usize = 100;
+
+/// 一个文件的校验结果
+pub struct Checked {
+    pub ranges: Vec<(u64, u64)>,
+    pub lines: u64,
+    /// 对范围做过的修改，如 "L3-90 → L3-6 (file has 6 lines)"
+    pub notes: Vec<String>,
+}
+
+/// 校验 root 下 rel 文件的行范围；文件不存在或不在项目内时返回 Err(原因)
+pub fn check(root: &Path, rel: &str, ranges: &[(u64, u64)], snap: bool) -> Result<Checked, String> {
+    let virtual_path = crate::direct_tools::virtual_path(root, rel)?;
+    let path = root.join(virtual_path.trim_start_matches("/codebase/"));
+    if !path.is_file() {
+        return Err("not found".into());
+    }
+    // 项目内的符号链接可能指向项目外
+    let real_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    if !path.canonicalize().is_ok_and(|real| real.starts_with(&real_root)) {
+        return Err("outside the project".into());
+    }
+    // deny_paths 禁止的文件不打开
+    if crate::deny_paths::get().denies(root, &path) {
+        return Err("denied by policy".into());
+    }
+    let data = std::fs::read(&path).map_err(|e| e.to_string())?;
+    let lines = (data.iter().filter(|&&b| b == b'\n').count() + usize::from(data.last().is_some_and(|&b| b != b'\n'))) as u64;
+    let source = snap.then(|| String::from_utf8_lossy(&data).into_owned());
+
+    let mut out: Vec<(u64, u64)> = Vec::new();
+    let mut notes = Vec::new();
+    for &(s, e) in ranges {
+        // 起止颠倒时交换，0 视为第 1 行
+        let (start, end) = (s.min(e).max(1), s.max(e).max(1));
+        if start > lines {
+            notes.push(format!("L{}-{} dropped (file has {} lines)", s, e, lines));
+            continue;
+        }
+        let clamped = (start, end.min(lines));
+        let block = source.as_deref().and_then(|src| crate::outline::enclosing_block(&path, src, start as usize));
+        // 只在块包住整个范围时扩展，跨多个函数的范围保持原样
+        let snapped = block.filter(|b| b.start_line as u64 <= clamped.0 && b.end_line as u64 >= clamped.1);
+        let fixed = snapped.as_ref().map(|b| (b.start_line as u64, b.end_line as u64)).unwrap_or(clamped);
+        if fixed != (s, e) {
+            let why = match &snapped {
+                Some(b) => format!("{}{}", b.kind, b.name.as_deref().map(|n| format!(" {}", n)).unwrap_or_default()),
+                None => format!("file has {} lines", lines),
+            };
+            notes.push(format!("L{}-{} → L{}-{} ({})", s, e, fixed.0, fixed.1, why));
+        }
+        out.push(fixed);
+    }
+    // 修正后重叠或相邻的范围合并
+    out.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (s, e) in out {
+        match merged.last_mut() {
+            Some((_, end)) if s <= *end + 1 => *end = (*end).max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    Ok(Checked { ranges: merged, lines, notes })
+}
+
+/// 工具定义
+pub fn definition(l: Locale) -> Value {
+    json!({
+        "name": "verify_ranges",
+        "description": l.pick(
+            "Check a list of result files and line ranges against the project: drops files that do not exist, clamps ranges past the end of a file, and optionally widens ranges to the enclosing function or class. Accepts the structuredContent files of fast_context_search as is.",
+            "对照项目校验结果文件和行范围：丢弃不存在的文件，把超出文件末尾的范围截断，可选地把范围扩展到所在的完整函数或类。可直接传入 fast_context_search 的 structuredContent files。"
+        ),
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "files": {
+                    "type": "array",
+                    "description": l.pick("Files to check (at most {})", "要校验的文件（最多 {} 个）").replace("{}", &MAX_FILES.to_string()),
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "path": { "type": "string", "description": l.pick("Absolute path, path relative to project_path, or /codebase/...", "绝对路径、相对 project_path 的路径或 /codebase/...") },
+                            "ranges": {
+                                "type": "array",
+                                "description": l.pick("Line ranges (1-based, inclusive); empty = whole file", "行范围（从 1 开始，含两端），为空 = 整个文件"),
+                                "items": {
+                                    "type": "object",
+                                    "properties": { "start": { "type": "integer" }, "end": { "type": "integer" } },
+                                    "required": ["start", "end"]
+                                }
+                            }
+                        },
+                        "required": ["path"]
+                    },
+                    "minItems": 1
+                },
+                "snap_to_symbols": {
+                    "type": "boolean",
+                    "description": l.pick("Widen each range to the enclosing function/class (Rust, Python, Go, JS/TS)", "把每个范围扩展到所在的完整函数/类（Rust、Python、Go、JS/TS）"),
+                    "default": false
+                },
+                "project_path": {
+                    "type": "string",
+                    "description": l.pick("Project root the paths belong to. Empty = first client root, else cwd.", "路径所属的项目根目录，留空 = 客户端第一个根目录，否则为当前工作目录"),
+                    "default": ""
+                }
+            },
+            "required": ["files"]
+        }
+    })
+}
+
+/// 执行 verify_ranges
+pub fn call(args: &Value) -> Result<String, String> {
+    let root = crate::direct_tools::project_root(args)?;
+    let root = Path::new(&root);
+    let files = args.get("files").and_then(|f| f.as_array()).filter(|f| !f.is_empty())
+        .ok_or("missing required argument: files")?;
+    if files.len() > MAX_FILES {
+        return Err(format!("too many files: {} (max {})", files.len(), MAX_FILES));
+    }
+    let snap = args.get("snap_to_symbols").and_then(|s| s.as_bool()).unwrap_or(false);
+
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    let mut adjusted = 0;
+    for f in files {
+        let path = f.get("path").and_then(|p| p.as_str()).unwrap_or("");
+        let ranges: Vec<(u64, u64)> = f.get("ranges").and_then(|r| r.as_array()).into_iter().flatten()
+            .filter_map(|r| Some((r.get("start")?.as_u64()?, r.get("end")?.as_u64()?)))
+            .collect();
+        let rel = if path.is_empty() {
+            Err("missing path".to_string())
+        } else {
+            crate::direct_tools::virtual_path(root, path).map(|v| v.trim_start_matches("/codebase/").to_string())
+        };
+        match rel.and_then(|rel| check(root, &rel, &ranges, snap).map(|c| (rel, c))) {
+            Ok((rel, c)) => {
+                let real = root.join(&rel);
+                let spans: Vec<String> = c.ranges.iter().map(|(s, e)| format!("L{}-{}", s, e)).collect();
+                let spans = if spans.is_empty() { format!(" ({} lines)", c.lines) } else { format!(" ({})", spans.join(", ")) };
+                adjusted += usize::from(!c.notes.is_empty());
+                kept.push(format!("  {}{}", real.display(), spans));
+                kept.extend(c.notes.iter().map(|n| format!("    {}", n)));
+            }
+            Err(e) => dropped.push(format!("  {}: {}", path, e)),
+        }
+    }
+
+    let mut out = vec![format!(
+        "Verified {} files: {} kept ({} adjusted), {} dropped.",
+        files.len(),
+        files.len() - dropped.len(),
+        adjusted,
+        dropped.len()
+    )];
+    if !kept.is_empty() {
+        out.push(String::new());
+        out.extend(kept);
+    }
+    if !dropped.is_empty() {
+        out.push(String::new());
+        out.push("Dropped:".into());
+        out.extend(dropped);
+    }
+    Ok(out.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denied_and_outside_files_are_dropped() {
+        let base = std::env::temp_dir().join(format!("windsurf-mcp-verify-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let root = base.join("project");
+        let denied = root.join(crate::deny_paths::TEST_DENIED);
+        std::fs::create_dir_all(&denied).unwrap();
+        std::fs::write(root.join("a.rs"), "1\n2\n3\n").unwrap();
+        std::fs::write(denied.join("key"), "k\n").unwrap();
+        std::fs::write(base.join("outside.rs"), "o\n").unwrap();
+
+        let checked = check(&root, "a.rs", &[(2, 9)], false).unwrap();
+        assert_eq!((checked.ranges, checked.lines), (vec![(2, 3)], 3));
+        let rel = format!("{}/key", crate::deny_paths::TEST_DENIED);
+        assert_eq!(check(&root, &rel, &[(1, 1)], false).err().as_deref(), Some("denied by policy"));
+        assert!(check(&root, "../outside.rs", &[(1, 1)], false).is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(base.join("outside.rs"), root.join("link.rs")).unwrap();
+            assert_eq!(check(&root, "link.rs", &[(1, 1)], false).err().as_deref(), Some("outside the project"));
+        }
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
@@ -1,17 +1,69 @@
 //! Windsurf API 交互层 (standalone, no server deps)
 
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::OnceLock;
+
 use anyhow::Result;
+use http_body_util::BodyExt;
+use serde::Deserialize;
 use uuid::Uuid;
 use super::protocol::*;
 
 const WS_APP: &str = "windsurf";
+const STREAM_METHOD: &str = "GetDevstralStream";
+pub const DEFAULT_UNARY_METHOD: &str = "GetDevstral";
+pub const DEFAULT_FALLBACK_AFTER: u32 = 2;
+
+/// 调用方式：流式 / 非流式 (unary) / 流式失败多次后自动切换到 unary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallMode {
+    #[default]
+    Stream,
+    Unary,
+    Auto,
+}
+
+/// 帧格式：Connect（默认）或 gRPC（部分自建网关只支持 gRPC）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    #[default]
+    Connect,
+    Grpc,
+}
+
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    pub mode: CallMode,
+    /// grpc 时忽略 mode，始终走 gRPC 流式
+    pub framing: Framing,
+    /// unary 调用的方法名
+    pub unary_method: String,
+    /// auto 模式下连续流式失败多少次后切换
+    pub fallback_after: u32,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self { mode: CallMode::Stream, framing: Framing::Connect, unary_method: DEFAULT_UNARY_METHOD.into(), fallback_after: DEFAULT_FALLBACK_AFTER }
+    }
+}
+
+/// 连续流式失败次数；切换到 unary 后本进程内保持
+static STREAM_FAILURES: AtomicU32 = AtomicU32::new(0);
+static UNARY_FALLBACK: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Clone)]
 pub struct WindsurfConfig {
     pub api_base: String,
+    /// relay 凭据中下发，目前未使用
+    #[allow(dead_code)]
     pub auth_base: String,
     pub app_version: String,
     pub ls_version: String,
+    /// relay 凭据中下发，目前未使用
+    #[allow(dead_code)]
     pub model: String,
     pub timeout_ms: u64,
 }
@@ -112,7 +164,7 @@ pub async fn streaming_request(
     proto_bytes: &[u8],
 ) -> Result<Vec<u8>> {
     let frame = connect_frame_encode(proto_bytes);
-    let url = format!("{}/GetDevstralStream", cfg.api_base);
+    let url = format!("{}/{}", cfg.api_base, STREAM_METHOD);
     let trace_id = Uuid::new_v4().to_string().replace("-", "");
     let span_id = &Uuid::new_v4().to_string().replace("-", "")[..16];
 
@@ -142,11 +194,165 @@ pub async fn streaming_request(
     Ok(data.to_vec())
 }
 
-pub fn parse_response(data: &[u8]) -> (String, Option<(String, serde_json::Value)>) {
-    let frames = connect_frame_decode(data);
+/// 非流式调用：application/proto，无帧封装，单条响应
+pub async fn unary_request(
+    client: &reqwest::Client,
+    cfg: &WindsurfConfig,
+    method: &str,
+    proto_bytes: &[u8],
+) -> Result<Vec<u8>> {
+    let url = format!("{}/{}", cfg.api_base, method);
+    let resp = client
+        .post(&url)
+        .header("Content-Type", "application/proto")
+        .header("Connect-Protocol-Version", "1")
+        .header("Content-Encoding", "gzip")
+        .header("Connect-Timeout-Ms", cfg.timeout_ms.to_string())
+        .header("User-Agent", "connect-go/1.18.1 (go1.25.5)")
+        .timeout(std::time::Duration::from_millis(cfg.timeout_ms + 5000))
+        .body(gzip_compress(proto_bytes))
+        .send()
+        .await?;
+
+    let status = resp.status();
+    let data = resp.bytes().await?;
+    if !status.is_success() {
+        // Connect unary 错误体为 JSON {code, message}
+        let detail = serde_json::from_slice::<serde_json::Value>(&data)
+            .ok()
+            .map(|v| format!(": {} {}", v["code"].as_str().unwrap_or(""), v["message"].as_str().unwrap_or("")))
+            .unwrap_or_default();
+        anyhow::bail!("HTTP {}{}", status.as_u16(), detail.trim_end());
+    }
+    Ok(data.to_vec())
+}
+
+/// gRPC 需要 HTTP/2；http:// 网关没有 ALPN，用 prior knowledge 直接走 h2c
+fn grpc_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// gRPC 调用：application/grpc，5 字节前缀帧，状态在 HTTP/2 trailers 中
+pub async fn grpc_request(cfg: &WindsurfConfig, proto_bytes: &[u8]) -> Result<Vec<u8>> {
+    // 帧结构与 Connect 相同：flags(1=压缩) + 长度 + gzip 负载
+    let frame = connect_frame_encode(proto_bytes);
+    let url = format!("{}/{}", cfg.api_base, STREAM_METHOD);
+
+    let resp = grpc_client()
+        .post(&url)
+        .header("Content-Type", "application/grpc")
+        .header("TE", "trailers")
+        .header("grpc-encoding", "gzip")
+        .header("grpc-accept-encoding", "gzip")
+        .header("grpc-timeout", format!("{}m", cfg.timeout_ms))
+        .header("User-Agent", "grpc-go/1.64.0")
+        .timeout(std::time::Duration::from_millis(cfg.timeout_ms + 5000))
+        .body(frame)
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        anyhow::bail!("HTTP {}", resp.status().as_u16());
+    }
+    let resp: http::Response<reqwest::Body> = resp.into();
+    let (parts, body) = resp.into_parts();
+    let collected = body.collect().await?;
+    let trailers = collected.trailers().cloned();
+    let data = collected.to_bytes();
+
+    // trailers-only 响应把状态放在 headers 里
+    let status_of = |h: &http::HeaderMap| {
+        h.get("grpc-status").and_then(|v| v.to_str().ok()).map(|s| {
+            let msg = h.get("grpc-message").and_then(|v| v.to_str().ok()).unwrap_or("");
+            (s.to_string(), msg.to_string())
+        })
+    };
+    let status = trailers.as_ref().and_then(status_of).or_else(|| status_of(&parts.headers));
+    match status {
+        Some((code, _)) if code == "0" => Ok(data.to_vec()),
+        Some((code, msg)) => {
+            let msg = percent_decode(&msg);
+            anyhow::bail!("grpc-status {}{}", code, if msg.is_empty() { String::new() } else { format!(": {}", msg) })
+        }
+        None => anyhow::bail!("missing grpc-status"),
+    }
+}
+
+/// grpc-message 使用百分号编码
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(b) = u8::from_str_radix(&s[i + 1..i + 3], 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// 按 CallOptions 发送请求并解析；auto 模式下流式连续失败后改用 unary
+pub async fn call(
+    client: &reqwest::Client,
+    cfg: &WindsurfConfig,
+    proto_bytes: &[u8],
+    opts: &CallOptions,
+) -> Result<(String, Option<(String, serde_json::Value)>)> {
+    if opts.framing == Framing::Grpc {
+        let data = grpc_request(cfg, proto_bytes).await?;
+        return parse_response(&data).map_err(|e| anyhow::anyhow!("malformed gRPC response: {}", e));
+    }
+
+    let unary = match opts.mode {
+        CallMode::Unary => true,
+        CallMode::Stream => false,
+        CallMode::Auto => UNARY_FALLBACK.load(Ordering::Relaxed),
+    };
+    if unary {
+        let data = unary_request(client, cfg, &opts.unary_method, proto_bytes).await?;
+        return Ok(parse_frames(&[data]));
+    }
+
+    match streaming_request(client, cfg, proto_bytes).await {
+        Ok(data) => {
+            STREAM_FAILURES.store(0, Ordering::Relaxed);
+            parse_response(&data).map_err(|e| anyhow::anyhow!("malformed Connect response: {}", e))
+        }
+        Err(e) if opts.mode == CallMode::Auto => {
+            let failures = STREAM_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
+            if failures < opts.fallback_after.max(1) {
+                return Err(e);
+            }
+            log_warn!("streaming failed {} times ({}), switching to unary", failures, e);
+            UNARY_FALLBACK.store(true, Ordering::Relaxed);
+            let data = unary_request(client, cfg, &opts.unary_method, proto_bytes).await?;
+            Ok(parse_frames(&[data]))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub fn parse_response(data: &[u8]) -> Result<(String, Option<(String, serde_json::Value)>), FrameError> {
+    Ok(parse_frames(&connect_frame_decode(data)?))
+}
+
+/// 解析已解帧的响应消息
+fn parse_frames(frames: &[Vec<u8>]) -> (String, Option<(String, serde_json::Value)>) {
     let mut all_text = String::new();
 
-    for frame_data in &frames {
+    for frame_data in frames {
         if let Ok(text) = std::str::from_utf8(frame_data) {
             if text.starts_with('{') {
                 if let Ok(obj) = serde_json::from_str::<serde_json::Value>(text) {
@@ -172,13 +378,124 @@ pub fn parse_response(data: &[u8]) -> (String, Option<(String, serde_json::Value
     if let Some(parsed) = parse_tool_call(&all_text) {
         return (parsed.0, Some((parsed.1, parsed.2)));
     }
-    eprintln!("[mcp-client] no tool call parsed. all_text length={}, has [TOOL_CALLS]={}", all_text.len(), all_text.contains("[TOOL_CALLS]"));
+    if let Some((text, name, args)) = structured_tool_call(frames) {
+        // 统一走文本格式的解析（含 JSON 修复）
+        if let Some(parsed) = parse_tool_call(&format!("[TOOL_CALLS]{}[ARGS]{}", name, args)) {
+            log_debug!("structured tool call: {}", parsed.1);
+            return (text, Some((parsed.1, parsed.2)));
+        }
+    }
+    log_warn!("no tool call parsed. all_text length={}, has [TOOL_CALLS]={}", all_text.len(), all_text.contains("[TOOL_CALLS]"));
     if all_text.len() < 2000 {
-        eprintln!("[mcp-client] all_text: {}", all_text);
+        log_debug!("all_text: {}", all_text);
     }
     (all_text, None)
 }
 
+/// 结构化工具调用在 protobuf 中的位置：嵌套路径 + 参数字段号
+type ProtoSlot = (Vec<u32>, u32);
+
+/// 结构化工具调用：OpenAI 风格 JSON 帧 (`choices[].delta.tool_calls` / `message.tool_calls`)
+/// 或 protobuf 中的 (名称, JSON 参数) 字段；参数可跨帧分片，按到达顺序拼接。
+/// 返回 (文本, 工具名, 参数原文)
+fn structured_tool_call(frames: &[Vec<u8>]) -> Option<(String, String, String)> {
+    let mut text = String::new();
+    let mut name: Option<String> = None;
+    let mut args = String::new();
+    let mut slot: Option<ProtoSlot> = None;
+
+    for frame in frames {
+        if let Some(obj) = std::str::from_utf8(frame)
+            .ok()
+            .filter(|t| t.trim_start().starts_with('{'))
+            .and_then(|t| serde_json::from_str::<serde_json::Value>(t).ok())
+        {
+            let choices = obj.get("choices").and_then(|c| c.as_array()).cloned().unwrap_or_else(|| vec![obj.clone()]);
+            for choice in &choices {
+                let body = choice.get("delta").or_else(|| choice.get("message")).unwrap_or(choice);
+                if let Some(t) = body.get("content").and_then(|c| c.as_str()) {
+                    text.push_str(t);
+                }
+                // 只取第一个工具调用 (index 0)
+                let Some(call) = body.get("tool_calls").and_then(|c| c.as_array()).and_then(|c| {
+                    c.iter().find(|tc| tc.get("index").and_then(|i| i.as_u64()).unwrap_or(0) == 0)
+                }) else { continue };
+                let func = call.get("function").unwrap_or(call);
+                if let Some(n) = func.get("name").and_then(|n| n.as_str()).filter(|n| !n.is_empty()) {
+                    name.get_or_insert_with(|| n.to_string());
+                }
+                match func.get("arguments") {
+                    Some(serde_json::Value::String(a)) => args.push_str(a),
+                    Some(v @ serde_json::Value::Object(_)) => args.push_str(&v.to_string()),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        let Ok(msg) = RawMessage::parse(frame) else { continue };
+        match &slot {
+            // 后续帧：同一位置只带参数分片
+            Some((path, field)) => {
+                let fragment = path
+                    .iter()
+                    .try_fold(msg, |m, n| m.sub_message(*n))
+                    .and_then(|m| m.get(*field).and_then(|f| f.as_str()).map(str::to_string));
+                if let Some(fragment) = fragment {
+                    args.push_str(&fragment);
+                }
+            }
+            None => {
+                if let Some((path, n, field, a)) = find_proto_call(&msg, &mut Vec::new(), 0) {
+                    name = Some(n);
+                    args.push_str(&a);
+                    slot = Some((path, field));
+                }
+            }
+        }
+    }
+
+    let name = name?;
+    (!args.trim().is_empty()).then(|| (text.trim().to_string(), name, args))
+}
+
+/// 在消息树中查找同时含「标识符形式的名称」和「以 { 开头的参数」两个字符串字段的消息
+fn find_proto_call(msg: &RawMessage, path: &mut Vec<u32>, depth: usize) -> Option<(Vec<u32>, String, u32, String)> {
+    const ROLES: [&str; 4] = ["system", "user", "assistant", "tool"];
+    let is_ident = |s: &str| {
+        !s.is_empty()
+            && s.len() <= 64
+            && s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !ROLES.contains(&s)
+    };
+    let strings: Vec<(u32, &str)> = msg.fields.iter().filter_map(|f| f.as_str().map(|s| (f.number, s))).collect();
+    if let Some((field, a)) = strings.iter().find(|(_, s)| s.trim_start().starts_with('{')) {
+        // 调用 ID 也可能是标识符形式，取字段号离参数最近的一个
+        let name = strings
+            .iter()
+            .filter(|(n, s)| n != field && is_ident(s))
+            .min_by_key(|(n, _)| n.abs_diff(*field));
+        if let Some((_, n)) = name {
+            return Some((path.clone(), n.to_string(), *field, a.to_string()));
+        }
+    }
+    if depth >= 8 {
+        return None;
+    }
+    for f in &msg.fields {
+        let WireValue::Bytes(b) = &f.value else { continue };
+        let Ok(sub) = RawMessage::parse(b) else { continue };
+        path.push(f.number);
+        let found = find_proto_call(&sub, path, depth + 1);
+        path.pop();
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
 fn parse_tool_call(text: &str) -> Option<(String, String, serde_json::Value)> {
     let text = text.replace("</s>", "");
     let idx = text.find("[TOOL_CALLS]")?;
@@ -213,7 +530,7 @@ fn parse_tool_call(text: &str) -> Option<(String, String, serde_json::Value)> {
         for _ in 0..(open - close) {
             repaired.push('}');
         }
-        eprintln!("[mcp-client] repaired JSON: added {} closing braces", open - close);
+        log_info!("repaired JSON: added {} closing braces", open - close);
         if let Ok(args) = serde_json::from_str::<serde_json::Value>(&repaired) {
             return Some((text[..idx].trim().to_string(), name, args));
         }
@@ -247,11 +564,11 @@ fn parse_tool_call(text: &str) -> Option<(String, String, serde_json::Value)> {
         }
     }
     if !salvaged.is_empty() {
-        eprintln!("[mcp-client] salvaged {} commands from malformed JSON", salvaged.len());
+        log_info!("salvaged {} commands from malformed JSON", salvaged.len());
         return Some((text[..idx].trim().to_string(), name, serde_json::Value::Object(salvaged)));
     }
 
-    eprintln!("[mcp-client] tool call JSON parse failed after all repairs");
-    eprintln!("[mcp-client] raw (first 500): {}", &json_str[..json_str.len().min(500)]);
+    log_warn!("tool call JSON parse failed after all repairs");
+    log_debug!("raw (first 500): {}", &json_str[..json_str.len().min(500)]);
     None
 }
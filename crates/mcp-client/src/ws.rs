@@ -0,0 +1,151 @@
+//! WebSocket 传输
+//!
+//! `--transport ws [--host 0.0.0.0] [--port 8765] [--auth-token T]`，端点 `ws://host:port/mcp`。
+//! 每个连接是一个独立会话，一条文本帧 = 一条 JSON-RPC 消息。
+//! 令牌来自 `--auth-token` 或 WINDSURF_MCP_AUTH_TOKEN，客户端以 `Authorization: Bearer <token>`
+//! 或 `?token=<token>` 提供；未设置令牌时只允许监听回环地址。
+
+use std::net::SocketAddr;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::sse;
+
+/// 常数时间比较，避免按字节泄露令牌
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn reject(status: StatusCode, why: &str) -> ErrorResponse {
+    let mut resp = ErrorResponse::new(Some(why.to_string()));
+    *resp.status_mut() = status;
+    resp
+}
+
+/// 握手检查：路径、Origin、令牌
+fn check(req: &Request, token: Option<&str>, allow_origin: Option<&str>) -> Result<(), (StatusCode, &'static str)> {
+    if req.uri().path() != crate::streamable::ENDPOINT {
+        return Err((StatusCode::NOT_FOUND, "not found"));
+    }
+    let origin = req.headers().get("origin").and_then(|o| o.to_str().ok());
+    if !sse::origin_allowed(origin, allow_origin) {
+        return Err((StatusCode::FORBIDDEN, "origin not allowed"));
+    }
+    let authorization = req.headers().get("authorization").and_then(|v| v.to_str().ok());
+    match authorized(authorization, req.uri().query(), token) {
+        true => Ok(()),
+        false => Err((StatusCode::UNAUTHORIZED, "invalid or missing token")),
+    }
+}
+
+/// 令牌检查（ws / sse / http 共用）：`Authorization: Bearer <token>` 或 `?token=<token>`；未设置令牌时总是通过
+pub fn authorized(authorization: Option<&str>, query: Option<&str>, token: Option<&str>) -> bool {
+    let Some(expected) = token else { return true };
+    let bearer = authorization.and_then(|v| v.strip_prefix("Bearer "));
+    let query = query.and_then(|q| q.split('&').find_map(|kv| kv.strip_prefix("token=")));
+    bearer.or(query).is_some_and(|given| token_matches(given, expected))
+}
+
+/// 未设置令牌时只允许监听回环地址
+pub fn ensure_bindable(addr: SocketAddr, token: Option<&str>) -> anyhow::Result<()> {
+    if token.is_none() && !addr.ip().is_loopback() {
+        anyhow::bail!("refusing to listen on {} without --auth-token (or WINDSURF_MCP_AUTH_TOKEN)", addr);
+    }
+    Ok(())
+}
+
+// 握手回调的签名由 tungstenite 决定
+#[allow(clippy::result_large_err)]
+async fn serve_connection(stream: tokio::net::TcpStream, peer: SocketAddr, token: Option<String>, allow_origin: Option<String>) {
+    let max = crate::config::load().max_message_bytes();
+    let limits = WebSocketConfig { max_message_size: Some(max), max_frame_size: Some(max), ..Default::default() };
+    let handshake = tokio_tungstenite::accept_hdr_async_with_config(stream, |req: &Request, resp: Response| {
+        match check(req, token.as_deref(), allow_origin.as_deref()) {
+            Ok(()) => Ok(resp),
+            Err((status, why)) => {
+                log_warn!("WebSocket handshake from {} rejected: {}", peer, why);
+                Err(reject(status, why))
+            }
+        }
+    }, Some(limits));
+    let socket = match handshake.await {
+        Ok(s) => s,
+        Err(e) => {
+            log_warn!("WebSocket handshake from {} failed: {}", peer, e);
+            return;
+        }
+    };
+    log_info!("WebSocket session from {} opened", peer);
+    let (mut sink, mut source) = socket.split();
+    let (in_tx, in_rx) = mpsc::unbounded_channel();
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let session = tokio::spawn(crate::serve_session(in_rx, out_tx, crate::session::Session::new("websocket")));
+
+    loop {
+        tokio::select! {
+            frame = source.next() => match frame {
+                Some(Ok(Message::Text(text))) => {
+                    if in_tx.send(text).is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Binary(bytes))) => match String::from_utf8(bytes) {
+                    Ok(text) => {
+                        if in_tx.send(text).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => log_warn!("WebSocket {}: ignoring non-UTF-8 binary frame", peer),
+                },
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    log_warn!("WebSocket {}: {}", peer, e);
+                    break;
+                }
+            },
+            out = out_rx.recv() => match out {
+                Some(payload) => {
+                    if let Err(e) = sink.send(Message::Text(payload)).await {
+                        log_warn!("WebSocket {}: {}", peer, e);
+                        break;
+                    }
+                }
+                // 会话结束（keepalive 判定断开）
+                None => break,
+            },
+        }
+    }
+    drop(in_tx);
+    let _ = session.await;
+    let _ = sink.close().await;
+    log_info!("WebSocket session from {} closed", peer);
+}
+
+/// 监听并服务 WebSocket 客户端，Ctrl-C 退出
+pub async fn run(addr: SocketAddr, token: Option<String>, allow_origin: Option<String>) -> anyhow::Result<()> {
+    ensure_bindable(addr, token.as_deref())?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log_info!("WebSocket transport listening on ws://{}{}", listener.local_addr()?, crate::streamable::ENDPOINT);
+    crate::ripgrep::spawn_probe();
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log_warn!("accept error: {}", e);
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        tokio::spawn(serve_connection(stream, peer, token.clone(), allow_origin.clone()));
+    }
+    Ok(())
+}
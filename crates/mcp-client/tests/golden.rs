@@ -0,0 +1,237 @@
+//! Golden transcript tests
+//!
+//! Each `tests/golden/*.json` fixture drives the real binary over stdio:
+//! `transcript` steps are `{"send": msg}` (written to stdin), `{"send_raw": text}`
+//! (framed verbatim, for malformed input) or `{"expect": msg}` (next frame read
+//! from stdout, matched as a subset). `backend` lists canned
+//! OpenAI-compatible responses served in order by a mock `/chat/completions`.
+//!
+//! Placeholders: `{project}` = tests/golden/project, `{backend}` = mock base URL;
+//! in expected strings `{*}` matches any text.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::Value;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+/// Replace `{project}` / `{backend}` in every string
+fn substitute(v: &Value, vars: &[(&str, &str)]) -> Value {
+    match v {
+        Value::String(s) => {
+            let mut s = s.clone();
+            for (k, val) in vars {
+                s = s.replace(k, val);
+            }
+            Value::String(s)
+        }
+        Value::Array(a) => Value::Array(a.iter().map(|x| substitute(x, vars)).collect()),
+        Value::Object(o) => Value::Object(o.iter().map(|(k, x)| (k.clone(), substitute(x, vars))).collect()),
+        other => other.clone(),
+    }
+}
+
+/// `{*}` wildcard match
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split("{*}").collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for mid in &parts[1..parts.len() - 1] {
+        match rest.find(mid) {
+            Some(i) => rest = &rest[i + mid.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Objects match as subsets, arrays element-wise with equal length
+fn check(expected: &Value, actual: &Value, path: &str) -> Result<(), String> {
+    match (expected, actual) {
+        (Value::Object(e), Value::Object(a)) => e.iter().try_for_each(|(k, ev)| match a.get(k) {
+            Some(av) => check(ev, av, &format!("{}.{}", path, k)),
+            None => Err(format!("{}: missing key {}", path, k)),
+        }),
+        (Value::Array(e), Value::Array(a)) => {
+            if e.len() != a.len() {
+                return Err(format!("{}: expected {} items, got {}", path, e.len(), a.len()));
+            }
+            e.iter().zip(a).enumerate().try_for_each(|(i, (ev, av))| check(ev, av, &format!("{}[{}]", path, i)))
+        }
+        (Value::String(e), Value::String(a)) if glob_match(e, a) => Ok(()),
+        (e, a) if e == a => Ok(()),
+        (e, a) => Err(format!("{}: expected {}, got {}", path, e, a)),
+    }
+}
+
+/// Mock OpenAI-compatible backend; returns (base_url, remaining responses)
+fn spawn_backend(responses: Vec<Value>) -> (String, Arc<Mutex<VecDeque<Value>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let queue = Arc::new(Mutex::new(VecDeque::from(responses)));
+    let q = queue.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                    break;
+                }
+                if let Some((k, v)) = line.split_once(':') {
+                    if k.eq_ignore_ascii_case("content-length") {
+                        length = v.trim().parse().unwrap_or(0);
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            let _ = reader.read_exact(&mut body);
+            let (status, reply) = match q.lock().unwrap().pop_front() {
+                Some(v) => ("200 OK", v.to_string()),
+                None => ("500 Internal Server Error", r#"{"error":{"message":"no more canned responses"}}"#.to_string()),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                reply.len(),
+                reply
+            );
+        }
+    });
+    (url, queue)
+}
+
+/// stdout → frames (line-delimited or Content-Length)
+fn spawn_frame_reader(stdout: impl Read + Send + 'static, lsp: bool) -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(stdout);
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            let frame = if lsp {
+                let Some(len) = line.trim().strip_prefix("Content-Length:").and_then(|n| n.trim().parse::<usize>().ok()) else { continue };
+                let mut blank = String::new();
+                let _ = reader.read_line(&mut blank);
+                let mut body = vec![0; len];
+                if reader.read_exact(&mut body).is_err() {
+                    return;
+                }
+                String::from_utf8_lossy(&body).into_owned()
+            } else {
+                line.trim().to_string()
+            };
+            if tx.send(frame).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+fn run_fixture(path: &Path) -> Result<(), String> {
+    let fixture: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).map_err(|e| e.to_string())?;
+    let name = path.file_stem().unwrap().to_string_lossy().to_string();
+    let project = golden_dir().join("project");
+    let project = project.to_string_lossy();
+    let lsp = fixture["framing"].as_str() == Some("lsp");
+
+    let responses = fixture["backend"].as_array().cloned().unwrap_or_default();
+    let (backend, remaining) = spawn_backend(responses);
+    let vars = [("{project}", project.as_ref()), ("{backend}", backend.as_str())];
+
+    let config_path = std::env::temp_dir().join(format!("windsurf-mcp-golden-{}-{}.json", name, std::process::id()));
+    std::fs::write(&config_path, substitute(&fixture["config"], &vars).to_string()).unwrap();
+    // search history goes here instead of the real ~/.local/state
+    let state_dir = std::env::temp_dir().join(format!("windsurf-mcp-golden-{}-{}-state", name, std::process::id()));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_windsurf-mcp-client"))
+        .current_dir(project.as_ref())
+        .env("WINDSURF_MCP_CONFIG", &config_path)
+        .env("KEEPALIVE_SECS", "0")
+        .env("RELAY_URL", "http://127.0.0.1:9")
+        .env("XDG_STATE_HOME", &state_dir)
+        .env_remove("WINDSURF_MCP_LANG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let frames = spawn_frame_reader(child.stdout.take().unwrap(), lsp);
+
+    let mut result = Ok(());
+    for (i, step) in fixture["transcript"].as_array().cloned().unwrap_or_default().iter().enumerate() {
+        let payload = match (step.get("send"), step.get("send_raw").and_then(|r| r.as_str())) {
+            (Some(msg), _) => Some(substitute(msg, &vars).to_string()),
+            (None, Some(raw)) => Some(raw.to_string()),
+            _ => None,
+        };
+        if let Some(payload) = payload {
+            let frame = if lsp { format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload) } else { format!("{}\n", payload) };
+            stdin.write_all(frame.as_bytes()).unwrap();
+            stdin.flush().unwrap();
+        } else if let Some(expected) = step.get("expect") {
+            let actual = match frames.recv_timeout(READ_TIMEOUT) {
+                Ok(f) => serde_json::from_str::<Value>(&f).map_err(|e| format!("step {}: bad JSON {}: {}", i, e, f)),
+                Err(_) => Err(format!("step {}: no frame within {:?}", i, READ_TIMEOUT)),
+            };
+            if let Err(e) = actual.and_then(|a| check(&substitute(expected, &vars), &a, "$").map_err(|e| format!("step {}: {}\n  actual: {}", i, e, a))) {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+
+    drop(stdin);
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_file(&config_path);
+    let _ = std::fs::remove_dir_all(&state_dir);
+    result?;
+
+    let left = remaining.lock().unwrap().len();
+    if left > 0 {
+        return Err(format!("{} canned backend responses were never requested", left));
+    }
+    Ok(())
+}
+
+#[test]
+fn golden_transcripts() {
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(golden_dir())
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures in tests/golden");
+
+    let failures: Vec<String> = fixtures
+        .iter()
+        .filter_map(|f| run_fixture(f).err().map(|e| format!("{}: {}", f.file_name().unwrap().to_string_lossy(), e)))
+        .collect();
+    assert!(failures.is_empty(), "golden transcript failures:\n{}", failures.join("\n\n"));
+}
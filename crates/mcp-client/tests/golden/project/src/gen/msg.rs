@@ -0,0 +1,2 @@
+// @generated by protoc. DO NOT EDIT.
+pub struct Msg;
@@ -0,0 +1,6 @@
+pub mod parser;
+
+/// Parse a comma separated list of integers
+pub fn parse_list(input: &str) -> Vec<i64> {
+    parser::split(input).filter_map(|s| s.trim().parse().ok()).collect()
+}
@@ -0,0 +1,4 @@
+/// Split on commas
+pub fn split(input: &str) -> impl Iterator<Item = &str> {
+    input.split(',')
+}
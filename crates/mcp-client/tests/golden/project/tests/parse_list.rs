@@ -0,0 +1,4 @@
+#[test]
+fn parses_numbers() {
+    assert_eq!(golden::parse_list("1, 2,x"), vec![1, 2]);
+}